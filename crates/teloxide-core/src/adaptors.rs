@@ -12,6 +12,13 @@
 #[cfg(feature = "cache_me")]
 pub mod cache_me;
 
+/// [`FileIdCache`] bot adaptor which sends already uploaded files by their
+/// file IDs.
+///
+/// [`FileIdCache`]: file_id_cache::FileIdCache
+#[cfg(feature = "file_id_cache")]
+pub mod file_id_cache;
+
 /// [`Trace`] bot adaptor which traces requests.
 ///
 /// [`Trace`]: trace::Trace
@@ -39,6 +46,8 @@ mod parse_mode;
 pub use cache_me::CacheMe;
 #[cfg(feature = "erased")]
 pub use erased::ErasedRequester;
+#[cfg(feature = "file_id_cache")]
+pub use file_id_cache::FileIdCache;
 #[cfg(feature = "throttle")]
 pub use throttle::Throttle;
 #[cfg(feature = "trace_adaptor")]
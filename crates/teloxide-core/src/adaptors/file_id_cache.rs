@@ -0,0 +1,842 @@
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    fmt::{self, Display},
+    future::{Future, IntoFuture},
+    io, mem,
+    path::PathBuf,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::SystemTime,
+};
+
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::{
+    errors::{ApiError, AsApiError},
+    payloads::{
+        SendAnimation, SendAudio, SendDocument, SendPhoto, SendSticker, SendVideo, SendVideoNote,
+        SendVoice,
+    },
+    requests::{HasPayload, Output, Payload, Request, Requester},
+    types::*,
+};
+
+/// File ID cache.
+///
+/// Telegram allows to send a file which was already uploaded by its file ID,
+/// without uploading it again. This adaptor hashes the content of files sent
+/// with [`send_photo`], [`send_document`], [`send_video`], [`send_audio`],
+/// [`send_animation`], [`send_sticker`], [`send_voice`] and
+/// [`send_video_note`], records the file IDs from the sent messages, and sends
+/// files with the same name, content and method by their file IDs later.
+///
+/// Only files created with [`InputFile::file`] and [`InputFile::memory`] are
+/// cached: files sent by URL or file ID are not uploaded anyway, and the
+/// content of [`InputFile::read`] can't be hashed without consuming it. Note
+/// that a file is read to compute the hash before it's sent. Hashes of files
+/// on disk are remembered by their path, size and modification time, so an
+/// unchanged file isn't read again.
+///
+/// If Telegram rejects a cached file ID, it's removed from the cache and the
+/// file is uploaded again.
+///
+/// File IDs are kept in a [`FileIdStorage`], by default in memory. To keep
+/// them across restarts of the bot, implement [`FileIdStorage`] for a
+/// persistent storage and use [`FileIdCache::with_storage`]. File IDs are
+/// valid only for the bot which received them, and [`FileKey`]s don't include
+/// the bot, so don't share a storage between bots.
+///
+/// ## Examples
+///
+/// ```no_run
+/// use teloxide_core::{
+///     prelude::*,
+///     types::{ChatId, InputFile},
+/// };
+///
+/// # async {
+/// let bot = Bot::new("TOKEN").cache_file_ids();
+///
+/// for chat_id in [ChatId(1), ChatId(2), ChatId(3)] {
+///     // The file is uploaded only once, then it's sent by file ID
+///     bot.send_photo(chat_id, InputFile::file("cat.jpg")).await?;
+/// }
+/// # Ok::<_, teloxide_core::RequestError>(()) };
+/// ```
+///
+/// [`send_photo`]: crate::requests::Requester::send_photo
+/// [`send_document`]: crate::requests::Requester::send_document
+/// [`send_video`]: crate::requests::Requester::send_video
+/// [`send_audio`]: crate::requests::Requester::send_audio
+/// [`send_animation`]: crate::requests::Requester::send_animation
+/// [`send_sticker`]: crate::requests::Requester::send_sticker
+/// [`send_voice`]: crate::requests::Requester::send_voice
+/// [`send_video_note`]: crate::requests::Requester::send_video_note
+#[derive(Debug)]
+pub struct FileIdCache<B, St = InMemFileIdStorage> {
+    bot: B,
+    storage: Arc<St>,
+    hashes: Arc<FileHashes>,
+}
+
+// `#[derive]` requires generics to implement `Clone`, but `St` is wrapped
+// around `Arc`.
+impl<B, St> Clone for FileIdCache<B, St>
+where
+    B: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            bot: self.bot.clone(),
+            storage: Arc::clone(&self.storage),
+            hashes: Arc::clone(&self.hashes),
+        }
+    }
+}
+
+impl<B> FileIdCache<B> {
+    /// Creates new cache, which keeps file IDs in memory.
+    ///
+    /// Note: it's recommended to use [`RequesterExt::cache_file_ids`] instead.
+    ///
+    /// [`RequesterExt::cache_file_ids`]: crate::requests::RequesterExt::cache_file_ids
+    pub fn new(bot: B) -> Self {
+        Self::with_storage(bot, InMemFileIdStorage::new())
+    }
+}
+
+impl<B, St> FileIdCache<B, St> {
+    /// Creates new cache, which keeps file IDs in `storage`.
+    ///
+    /// The storage must not be shared with other bots, see the
+    /// [type-level documentation](Self).
+    pub fn with_storage(bot: B, storage: Arc<St>) -> Self {
+        Self { bot, storage, hashes: Arc::default() }
+    }
+
+    /// Allows to access inner bot
+    pub fn inner(&self) -> &B {
+        &self.bot
+    }
+
+    /// Unwraps inner bot
+    pub fn into_inner(self) -> B {
+        self.bot
+    }
+
+    /// Returns the storage of file IDs.
+    pub fn storage(&self) -> &Arc<St> {
+        &self.storage
+    }
+}
+
+/// A kind of a cached file, i.e. the method which was used to send it.
+///
+/// A file ID can be used only with the same kind of file, e.g. a file sent as
+/// a photo can't be resent as a document by its file ID.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileKind {
+    Photo,
+    Document,
+    Video,
+    Audio,
+    Animation,
+    Sticker,
+    Voice,
+    VideoNote,
+}
+
+impl FileKind {
+    /// Returns the name of this kind in `snake_case`, e.g. `"video_note"`.
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Photo => "photo",
+            Self::Document => "document",
+            Self::Video => "video",
+            Self::Audio => "audio",
+            Self::Animation => "animation",
+            Self::Sticker => "sticker",
+            Self::Voice => "voice",
+            Self::VideoNote => "video_note",
+        }
+    }
+}
+
+impl Display for FileKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A key of a file ID in a [`FileIdStorage`].
+///
+/// The key doesn't include the bot, since a [`FileIdCache`] is used by a
+/// single bot. It's displayed as `{kind}:{hash}` with the hash in lowercase
+/// hex, e.g. `photo:9f86d08…`, which may be used as a key in a database.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FileKey {
+    /// The kind of the file.
+    pub kind: FileKind,
+
+    /// SHA-256 hash of the name and the content of the file.
+    pub hash: [u8; 32],
+}
+
+impl Display for FileKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:", self.kind)?;
+        self.hash.iter().try_for_each(|byte| write!(f, "{byte:02x}"))
+    }
+}
+
+/// A storage of file IDs used by [`FileIdCache`].
+///
+/// A storage keeps file IDs of a single bot: file IDs can't be used by other
+/// bots.
+///
+/// Errors of a storage don't fail requests: they are logged and the file is
+/// uploaded as if it wasn't cached.
+pub trait FileIdStorage {
+    /// An error returned from the storage, it's only logged.
+    type Error: Display + Send;
+
+    /// Returns the file ID recorded for `key`, if any.
+    fn get_file_id(
+        self: Arc<Self>,
+        key: FileKey,
+    ) -> BoxFuture<'static, Result<Option<String>, Self::Error>>;
+
+    /// Records `file_id` for `key`, replacing the previous one.
+    fn set_file_id(
+        self: Arc<Self>,
+        key: FileKey,
+        file_id: String,
+    ) -> BoxFuture<'static, Result<(), Self::Error>>;
+
+    /// Removes the file ID recorded for `key`.
+    ///
+    /// It's called when Telegram rejects the file ID.
+    fn remove_file_id(self: Arc<Self>, key: FileKey)
+        -> BoxFuture<'static, Result<(), Self::Error>>;
+}
+
+/// A memory-based [`FileIdStorage`].
+///
+/// File IDs are lost when the bot is restarted.
+#[derive(Debug, Default)]
+pub struct InMemFileIdStorage {
+    map: Mutex<HashMap<FileKey, String>>,
+}
+
+impl InMemFileIdStorage {
+    #[must_use]
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+}
+
+impl FileIdStorage for InMemFileIdStorage {
+    type Error = Infallible;
+
+    fn get_file_id(
+        self: Arc<Self>,
+        key: FileKey,
+    ) -> BoxFuture<'static, Result<Option<String>, Self::Error>> {
+        let file_id = self.map.lock().unwrap().get(&key).cloned();
+        Box::pin(async move { Ok(file_id) })
+    }
+
+    fn set_file_id(
+        self: Arc<Self>,
+        key: FileKey,
+        file_id: String,
+    ) -> BoxFuture<'static, Result<(), Self::Error>> {
+        self.map.lock().unwrap().insert(key, file_id);
+        Box::pin(async { Ok(()) })
+    }
+
+    fn remove_file_id(
+        self: Arc<Self>,
+        key: FileKey,
+    ) -> BoxFuture<'static, Result<(), Self::Error>> {
+        self.map.lock().unwrap().remove(&key);
+        Box::pin(async { Ok(()) })
+    }
+}
+
+macro_rules! f {
+    ($m:ident $this:ident ($($arg:ident : $T:ty),*)) => {
+        FileIdCacheRequest {
+            req: $this.inner().$m($($arg),*),
+            storage: Arc::clone(&$this.storage),
+            hashes: Arc::clone(&$this.hashes),
+        }
+    };
+}
+
+macro_rules! fty {
+    ($T:ident) => {
+        FileIdCacheRequest<B::$T, St>
+    };
+}
+
+macro_rules! fid {
+    ($m:ident $this:ident ($($arg:ident : $T:ty),*)) => {
+        $this.inner().$m($($arg),*)
+    };
+}
+
+macro_rules! ftyid {
+    ($T:ident) => {
+        B::$T
+    };
+}
+
+impl<B, St> Requester for FileIdCache<B, St>
+where
+    B: Requester,
+    B::Err: AsApiError,
+    B::SendPhoto: Clone + Send + 'static,
+    B::SendDocument: Clone + Send + 'static,
+    B::SendVideo: Clone + Send + 'static,
+    B::SendAudio: Clone + Send + 'static,
+    B::SendAnimation: Clone + Send + 'static,
+    B::SendSticker: Clone + Send + 'static,
+    B::SendVoice: Clone + Send + 'static,
+    B::SendVideoNote: Clone + Send + 'static,
+    St: FileIdStorage + Send + Sync + 'static,
+{
+    type Err = B::Err;
+
+    requester_forward! {
+        send_photo,
+        send_document,
+        send_video,
+        send_audio,
+        send_animation,
+        send_sticker,
+        send_voice,
+        send_video_note,
+        => f, fty
+    }
+
+    requester_forward! {
+        get_me,
+        log_out,
+        close,
+        get_updates,
+        set_webhook,
+        delete_webhook,
+        get_webhook_info,
+        forward_message,
+        forward_messages,
+        copy_message,
+        copy_messages,
+        send_message,
+        send_media_group,
+        send_location,
+        edit_message_live_location,
+        edit_message_live_location_inline,
+        stop_message_live_location,
+        stop_message_live_location_inline,
+        send_venue,
+        send_contact,
+        send_poll,
+        send_dice,
+        send_chat_action,
+        set_message_reaction,
+        get_user_profile_photos,
+        get_file,
+        kick_chat_member,
+        ban_chat_member,
+        unban_chat_member,
+        restrict_chat_member,
+        promote_chat_member,
+        set_chat_administrator_custom_title,
+        ban_chat_sender_chat,
+        unban_chat_sender_chat,
+        set_chat_permissions,
+        export_chat_invite_link,
+        create_chat_invite_link,
+        edit_chat_invite_link,
+        revoke_chat_invite_link,
+        set_chat_photo,
+        delete_chat_photo,
+        set_chat_title,
+        set_chat_description,
+        pin_chat_message,
+        unpin_chat_message,
+        unpin_all_chat_messages,
+        leave_chat,
+        get_chat,
+        get_chat_administrators,
+        get_chat_members_count,
+        get_chat_member_count,
+        get_chat_member,
+        set_chat_sticker_set,
+        delete_chat_sticker_set,
+        get_forum_topic_icon_stickers,
+        create_forum_topic,
+        edit_forum_topic,
+        close_forum_topic,
+        reopen_forum_topic,
+        delete_forum_topic,
+        unpin_all_forum_topic_messages,
+        edit_general_forum_topic,
+        close_general_forum_topic,
+        reopen_general_forum_topic,
+        hide_general_forum_topic,
+        unhide_general_forum_topic,
+        unpin_all_general_forum_topic_messages,
+        answer_callback_query,
+        get_user_chat_boosts,
+        set_my_commands,
+        get_business_connection,
+        get_my_commands,
+        set_my_name,
+        get_my_name,
+        set_my_description,
+        get_my_description,
+        set_my_short_description,
+        get_my_short_description,
+        set_chat_menu_button,
+        get_chat_menu_button,
+        set_my_default_administrator_rights,
+        get_my_default_administrator_rights,
+        delete_my_commands,
+        answer_inline_query,
+        answer_web_app_query,
+        edit_message_text,
+        edit_message_text_inline,
+        edit_message_caption,
+        edit_message_caption_inline,
+        edit_message_media,
+        edit_message_media_inline,
+        edit_message_reply_markup,
+        edit_message_reply_markup_inline,
+        stop_poll,
+        delete_message,
+        delete_messages,
+        get_sticker_set,
+        get_custom_emoji_stickers,
+        upload_sticker_file,
+        create_new_sticker_set,
+        add_sticker_to_set,
+        set_sticker_position_in_set,
+        delete_sticker_from_set,
+        replace_sticker_in_set,
+        set_sticker_set_thumbnail,
+        set_custom_emoji_sticker_set_thumbnail,
+        set_sticker_set_title,
+        delete_sticker_set,
+        set_sticker_emoji_list,
+        set_sticker_keywords,
+        set_sticker_mask_position,
+        send_invoice,
+        create_invoice_link,
+        answer_shipping_query,
+        answer_pre_checkout_query,
+        set_passport_data_errors,
+        send_game,
+        set_game_score,
+        set_game_score_inline,
+        get_game_high_scores,
+        approve_chat_join_request,
+        decline_chat_join_request
+        => fid, ftyid
+    }
+}
+
+download_forward! {
+    B
+    FileIdCache<B, St> [St]
+    { this => this.inner() }
+}
+
+/// Request returned by [`FileIdCache`] methods.
+#[must_use = "Requests are lazy and do nothing unless sent"]
+pub struct FileIdCacheRequest<R, St> {
+    req: R,
+    storage: Arc<St>,
+    hashes: Arc<FileHashes>,
+}
+
+// `#[derive]` requires generics to implement `Clone`, but `St` is wrapped
+// around `Arc`.
+impl<R, St> Clone for FileIdCacheRequest<R, St>
+where
+    R: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            req: self.req.clone(),
+            storage: Arc::clone(&self.storage),
+            hashes: Arc::clone(&self.hashes),
+        }
+    }
+}
+
+/// Future returned by [`FileIdCacheRequest`]s.
+#[pin_project::pin_project]
+pub struct FileIdCacheSend<R: Request>(#[pin] BoxFuture<'static, Result<Output<R>, R::Err>>);
+
+impl<R: Request> Future for FileIdCacheSend<R> {
+    type Output = Result<Output<R>, R::Err>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        this.0.poll(cx)
+    }
+}
+
+impl<R, St> Request for FileIdCacheRequest<R, St>
+where
+    R: Request + Clone + Send + 'static,
+    R::Payload: CachedUpload,
+    R::Err: AsApiError,
+    St: FileIdStorage + Send + Sync + 'static,
+{
+    type Err = R::Err;
+    type Send = FileIdCacheSend<R>;
+    type SendRef = FileIdCacheSend<R>;
+
+    fn send(self) -> Self::Send {
+        FileIdCacheSend(Box::pin(send(self.req, self.storage, self.hashes)))
+    }
+
+    fn send_ref(&self) -> Self::SendRef {
+        // The file of the payload is replaced with a file ID, which requires an owned
+        // request
+        self.clone().send()
+    }
+}
+
+impl<R, St> IntoFuture for FileIdCacheRequest<R, St>
+where
+    Self: Request,
+{
+    type Output = Result<Output<Self>, <Self as Request>::Err>;
+    type IntoFuture = <Self as Request>::Send;
+
+    fn into_future(self) -> Self::IntoFuture {
+        self.send()
+    }
+}
+
+impl<R, St> HasPayload for FileIdCacheRequest<R, St>
+where
+    R: Request,
+{
+    type Payload = R::Payload;
+
+    fn payload_mut(&mut self) -> &mut Self::Payload {
+        self.req.payload_mut()
+    }
+
+    fn payload_ref(&self) -> &Self::Payload {
+        self.req.payload_ref()
+    }
+}
+
+async fn send<R, St>(
+    mut req: R,
+    storage: Arc<St>,
+    hashes: Arc<FileHashes>,
+) -> Result<Message, R::Err>
+where
+    R: Request,
+    R::Payload: CachedUpload,
+    R::Err: AsApiError,
+    St: FileIdStorage,
+{
+    let key = match hashes.hash(req.payload_ref().file()).await {
+        Ok(Some(hash)) => FileKey { kind: R::Payload::KIND, hash },
+        Ok(None) => return req.send().await,
+        Err(err) => {
+            log::warn!("Couldn't hash a file to look up its file ID: {err}");
+            return req.send().await;
+        }
+    };
+
+    match Arc::clone(&storage).get_file_id(key).await {
+        Ok(Some(file_id)) => {
+            let file = mem::replace(req.payload_mut().file_mut(), InputFile::file_id(file_id));
+            match req.send_ref().await {
+                Err(err) if err.as_api_error().is_some_and(is_file_id_error) => {
+                    log::debug!("The file ID of {key} was rejected, uploading the file again");
+                    if let Err(err) = Arc::clone(&storage).remove_file_id(key).await {
+                        log::warn!("Couldn't remove the file ID of {key}: {err}");
+                    }
+                    *req.payload_mut().file_mut() = file;
+                }
+                res => return res,
+            }
+        }
+        Ok(None) => {}
+        Err(err) => log::warn!("Couldn't get the file ID of {key}: {err}"),
+    }
+
+    let message = req.send().await?;
+    if let Some(file_id) = R::Payload::file_id(&message) {
+        if let Err(err) = storage.set_file_id(key, file_id.to_owned()).await {
+            log::warn!("Couldn't record the file ID of {key}: {err}");
+        }
+    }
+
+    Ok(message)
+}
+
+/// Hashes of files on disk by their path, name, size and modification time.
+///
+/// A file which is changed without changing its size and modification time
+/// (e.g. within the precision of the modification time) keeps its old hash.
+#[derive(Debug, Default)]
+struct FileHashes {
+    map: Mutex<HashMap<FileStamp, [u8; 32]>>,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct FileStamp {
+    path: PathBuf,
+    name: Option<String>,
+    len: u64,
+    modified: SystemTime,
+}
+
+impl FileHashes {
+    /// The maximum number of remembered hashes, all of them are forgotten when
+    /// it's exceeded.
+    const CAPACITY: usize = 1024;
+
+    /// Returns the hash of `file`, like [`InputFile::content_hash`], reading
+    /// the file only if it's not remembered.
+    async fn hash(&self, file: &InputFile) -> io::Result<Option<[u8; 32]>> {
+        let Some(path) = file.path() else { return file.content_hash().await };
+
+        let metadata = tokio::fs::metadata(path).await?;
+        let Ok(modified) = metadata.modified() else { return file.content_hash().await };
+        let stamp = FileStamp {
+            path: path.to_owned(),
+            name: file.name().map(ToOwned::to_owned),
+            len: metadata.len(),
+            modified,
+        };
+
+        if let Some(&hash) = self.map.lock().unwrap().get(&stamp) {
+            return Ok(Some(hash));
+        }
+
+        let hash = file.content_hash().await?;
+        if let Some(hash) = hash {
+            let mut map = self.map.lock().unwrap();
+            if map.len() >= Self::CAPACITY {
+                map.clear();
+            }
+            map.insert(stamp, hash);
+        }
+
+        Ok(hash)
+    }
+}
+
+fn is_file_id_error(err: &ApiError) -> bool {
+    matches!(err, ApiError::WrongFileId | ApiError::WrongFileIdOrUrl | ApiError::FileIdInvalid)
+}
+
+trait CachedUpload: Payload<Output = Message> {
+    const KIND: FileKind;
+
+    fn file(&self) -> &InputFile;
+
+    fn file_mut(&mut self) -> &mut InputFile;
+
+    fn file_id(message: &Message) -> Option<&str>;
+}
+
+macro_rules! impl_cached_upload {
+    ($($Payload:ident { $field:ident } => $Kind:ident, |$msg:ident| $file_id:expr;)*) => {
+        $(
+            impl CachedUpload for $Payload {
+                const KIND: FileKind = FileKind::$Kind;
+
+                fn file(&self) -> &InputFile {
+                    &self.$field
+                }
+
+                fn file_mut(&mut self) -> &mut InputFile {
+                    &mut self.$field
+                }
+
+                fn file_id($msg: &Message) -> Option<&str> {
+                    $file_id
+                }
+            }
+        )*
+    };
+}
+
+impl_cached_upload! {
+    // The largest size is the original photo
+    SendPhoto { photo } => Photo, |msg| Some(&msg.photo()?.last()?.file.id);
+    SendDocument { document } => Document, |msg| Some(&msg.document()?.file.id);
+    SendVideo { video } => Video, |msg| Some(&msg.video()?.file.id);
+    SendAudio { audio } => Audio, |msg| Some(&msg.audio()?.file.id);
+    SendAnimation { animation } => Animation, |msg| Some(&msg.animation()?.file.id);
+    SendSticker { sticker } => Sticker, |msg| Some(&msg.sticker()?.file.id);
+    SendVoice { voice } => Voice, |msg| Some(&msg.voice()?.file.id);
+    SendVideoNote { video_note } => VideoNote, |msg| Some(&msg.video_note()?.file.id);
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future::{ready, Ready};
+
+    use super::*;
+    use crate::{types::ChatId, RequestError};
+
+    /// A request which "uploads" a photo, returning a message with a new file
+    /// ID, or rejects the `stale` file ID.
+    #[derive(Clone)]
+    struct FakeRequest {
+        payload: SendPhoto,
+        sent: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl HasPayload for FakeRequest {
+        type Payload = SendPhoto;
+
+        fn payload_mut(&mut self) -> &mut SendPhoto {
+            &mut self.payload
+        }
+
+        fn payload_ref(&self) -> &SendPhoto {
+            &self.payload
+        }
+    }
+
+    impl Request for FakeRequest {
+        type Err = RequestError;
+        type Send = Ready<Result<Message, RequestError>>;
+        type SendRef = Self::Send;
+
+        fn send(self) -> Self::Send {
+            self.send_ref()
+        }
+
+        fn send_ref(&self) -> Self::SendRef {
+            let file = serde_json::to_value(&self.payload.photo).unwrap();
+            let file = file.as_str().unwrap();
+            let mut sent = self.sent.lock().unwrap();
+            let res = match file {
+                "stale" => Err(RequestError::Api(ApiError::WrongFileId)),
+                file if file.starts_with("attach://") => Ok(photo_message(sent.len())),
+                _ => Ok(photo_message(0)),
+            };
+            sent.push(if file.starts_with("attach://") { "upload" } else { file }.to_owned());
+            ready(res)
+        }
+    }
+
+    impl IntoFuture for FakeRequest {
+        type Output = Result<Message, RequestError>;
+        type IntoFuture = Ready<Result<Message, RequestError>>;
+
+        fn into_future(self) -> Self::IntoFuture {
+            self.send()
+        }
+    }
+
+    fn photo_message(n: usize) -> Message {
+        serde_json::from_value(serde_json::json!({
+            "message_id": 1,
+            "date": 1568290622,
+            "chat": { "id": 1, "first_name": "Test", "type": "private" },
+            "photo": [
+                { "file_id": "small", "file_unique_id": "", "width": 90, "height": 90 },
+                { "file_id": format!("id{n}"), "file_unique_id": "", "width": 320, "height": 320 }
+            ]
+        }))
+        .unwrap()
+    }
+
+    async fn send_photo(
+        storage: &Arc<InMemFileIdStorage>,
+        sent: &Arc<Mutex<Vec<String>>>,
+        file: InputFile,
+    ) {
+        let req = FakeRequest { payload: SendPhoto::new(ChatId(1), file), sent: Arc::clone(sent) };
+        FileIdCacheRequest { req, storage: Arc::clone(storage), hashes: Arc::default() }
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn file_ids() {
+        let storage = InMemFileIdStorage::new();
+        let sent = Arc::new(Mutex::new(Vec::new()));
+
+        let cat = || InputFile::memory(&b"cat"[..]).file_name("cat.jpg");
+        send_photo(&storage, &sent, cat()).await;
+        send_photo(&storage, &sent, cat()).await;
+        // Another name
+        send_photo(&storage, &sent, InputFile::memory(&b"cat"[..])).await;
+        // Not uploaded
+        send_photo(&storage, &sent, InputFile::file_id("id")).await;
+
+        assert_eq!(*sent.lock().unwrap(), ["upload", "id0", "upload", "id"]);
+        assert_eq!(storage.map.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn stale_file_id() {
+        let storage = InMemFileIdStorage::new();
+        let sent = Arc::new(Mutex::new(Vec::new()));
+
+        let file = InputFile::memory(&b"dog"[..]);
+        let hash = file.content_hash().await.unwrap().unwrap();
+        let key = FileKey { kind: FileKind::Photo, hash };
+        storage.map.lock().unwrap().insert(key, "stale".to_owned());
+
+        send_photo(&storage, &sent, file.clone()).await;
+        send_photo(&storage, &sent, file).await;
+
+        assert_eq!(*sent.lock().unwrap(), ["stale", "upload", "id1"]);
+        assert_eq!(key.to_string().len(), "photo:".len() + 64);
+    }
+
+    #[tokio::test]
+    async fn file_hashes() {
+        let path =
+            std::env::temp_dir().join(format!("teloxide_file_hashes_{}", std::process::id()));
+        let hashes = FileHashes::default();
+        let file = InputFile::file(&path);
+        let hash = || hashes.hash(&file);
+
+        std::fs::write(&path, "cat").unwrap();
+        let cat = hash().await.unwrap().unwrap();
+        let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+
+        // The file isn't read again if its size and modification time are the same
+        std::fs::write(&path, "dog").unwrap();
+        std::fs::File::options().write(true).open(&path).unwrap().set_modified(modified).unwrap();
+        assert_eq!(hash().await.unwrap(), Some(cat));
+
+        std::fs::write(&path, "fish").unwrap();
+        let fish = hash().await.unwrap().unwrap();
+        assert_ne!(fish, cat);
+        assert_eq!(InputFile::file(&path).content_hash().await.unwrap(), Some(fish));
+
+        // The name is a part of the hash
+        let named = hashes.hash(&InputFile::file(&path).file_name("fish.jpg")).await.unwrap();
+        assert_ne!(named, Some(fish));
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(hash().await.is_err());
+    }
+}
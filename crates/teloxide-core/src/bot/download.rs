@@ -44,4 +44,16 @@ impl Download for Bot {
         .map(|res| res.map_err(crate::errors::hide_token))
         .boxed()
     }
+
+    fn download_file_stream_from(&self, path: &str, offset: u64) -> Self::Stream {
+        net::download_file_stream_from(
+            &self.client,
+            reqwest::Url::clone(&*self.api_url),
+            &self.token,
+            path,
+            offset,
+        )
+        .map(|res| res.map_err(crate::errors::hide_token))
+        .boxed()
+    }
 }
@@ -78,6 +78,21 @@ pub trait AsResponseParameters {
     }
 }
 
+/// A trait for errors which may be caused by an error returned from Telegram.
+pub trait AsApiError {
+    /// Returns the error returned from Telegram, if this error is one.
+    fn as_api_error(&self) -> Option<&ApiError>;
+}
+
+impl AsApiError for crate::RequestError {
+    fn as_api_error(&self) -> Option<&ApiError> {
+        match self {
+            Self::Api(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
 impl AsResponseParameters for crate::RequestError {
     fn response_parameters(&self) -> Option<ResponseParameters> {
         match *self {
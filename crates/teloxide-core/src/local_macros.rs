@@ -372,8 +372,8 @@ macro_rules! impl_payload {
 }
 
 macro_rules! download_forward {
-    ($T:ident $S:ty {$this:ident => $inner:expr}) => {
-        impl<$T: $crate::net::Download> $crate::net::Download for $S {
+    ($T:ident $S:ty $([$($G:ident),*])? {$this:ident => $inner:expr}) => {
+        impl<$T: $crate::net::Download $($(, $G)*)?> $crate::net::Download for $S {
             type Err<'dst> = <$T as $crate::net::Download>::Err<'dst>;
 
             type Fut<'dst> = <$T as $crate::net::Download>::Fut<'dst>;
@@ -397,6 +397,11 @@ macro_rules! download_forward {
                 let $this = self;
                 ($inner).download_file_stream(path)
             }
+
+            fn download_file_stream_from(&self, path: &str, offset: u64) -> Self::Stream {
+                let $this = self;
+                ($inner).download_file_stream_from(path, offset)
+            }
         }
     };
 }
@@ -2,7 +2,10 @@
 
 use std::time::Duration;
 
-pub use self::download::{download_file, download_file_stream, Download};
+pub use self::download::{
+    download_file, download_file_stream, download_file_stream_from, download_progress, Download,
+    DownloadProgress,
+};
 
 pub(crate) use self::{
     request::{request_json, request_multipart},
@@ -90,6 +93,15 @@ fn file_url(base: reqwest::Url, token: &str, file_path: &str) -> reqwest::Url {
     base.join(&format!("file/bot{token}/{file_path}")).expect("failed to format url")
 }
 
+/// Returns the percentage of `done` bytes of `total`, if `total` is known.
+pub(crate) fn percent(done: u64, total: Option<u64>) -> Option<u8> {
+    match total {
+        Some(0) => Some(100),
+        Some(total) => Some((done.min(total) * 100 / total) as u8),
+        None => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::net::*;
@@ -6,7 +6,7 @@ use futures::{
     stream::{once, unfold},
     FutureExt, Stream, StreamExt,
 };
-use reqwest::{Client, Response, Url};
+use reqwest::{header::RANGE, Client, Response, StatusCode, Url};
 use tokio::io::{AsyncWrite, AsyncWriteExt};
 
 use crate::{errors::DownloadError, net::file_url};
@@ -79,6 +79,68 @@ pub trait Download {
     /// [`tokio::fs::File`]: tokio::fs::File
     /// [`download_file`]: Self::download_file
     fn download_file_stream(&self, path: &str) -> Self::Stream;
+
+    /// Download a file from Telegram as [`Stream`], starting at byte `offset`.
+    ///
+    /// This allows to resume an interrupted download: if the stream returned
+    /// an error after `n` bytes, the rest of the file can be downloaded with
+    /// `download_file_stream_from(path, n)`.
+    ///
+    /// To track the progress of a download, see [`download_progress`].
+    ///
+    /// ## Examples
+    ///
+    /// ```no_run
+    /// use futures::StreamExt;
+    /// use teloxide_core::{
+    ///     net::{download_progress, Download},
+    ///     requests::{Request, Requester},
+    ///     Bot,
+    /// };
+    /// use tokio::{fs, io::AsyncWriteExt};
+    ///
+    /// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+    /// let bot = Bot::new("TOKEN");
+    ///
+    /// let file = bot.get_file("*file_id*").await?;
+    /// let mut dst = fs::File::create("/tmp/test.mp4").await?;
+    /// let mut downloaded = 0;
+    /// let mut attempts = 0;
+    /// while downloaded < u64::from(file.size) && attempts < 3 {
+    ///     attempts += 1;
+    ///
+    ///     let stream = bot.download_file_stream_from(&file.path, downloaded);
+    ///     let mut stream = download_progress(stream, downloaded, Some(file.size.into()), |p| {
+    ///         println!("downloading {}%", p.percent().unwrap_or(0));
+    ///     });
+    ///     while let Some(Ok(chunk)) = stream.next().await {
+    ///         dst.write_all(&chunk).await?;
+    ///         downloaded += chunk.len() as u64;
+    ///     }
+    /// }
+    /// # Ok(()) }
+    /// ```
+    ///
+    /// [`download_progress`]: crate::net::download_progress
+    fn download_file_stream_from(&self, path: &str, offset: u64) -> Self::Stream;
+}
+
+/// Progress of a download, see [`download_progress`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DownloadProgress {
+    /// The number of downloaded bytes.
+    pub downloaded: u64,
+
+    /// The size of the file, if known.
+    pub total: Option<u64>,
+}
+
+impl DownloadProgress {
+    /// Returns the downloaded percentage, if the size of the file is known.
+    #[must_use]
+    pub fn percent(&self) -> Option<u8> {
+        super::percent(self.downloaded, self.total)
+    }
 }
 
 /// Download a file from Telegram into `dst`.
@@ -118,16 +180,171 @@ pub fn download_file_stream(
     token: &str,
     path: &str,
 ) -> impl Stream<Item = reqwest::Result<Bytes>> + 'static {
-    client.get(file_url(api_url, token, path)).send().into_stream().flat_map(|res| {
+    download_file_stream_from(client, api_url, token, path, 0)
+}
+
+/// Download a file from Telegram as [`Stream`], starting at byte `offset`.
+///
+/// Note: if you don't need to use a different (from you're bot) client, then
+/// it's recommended to use [`Download::download_file_stream_from`].
+pub fn download_file_stream_from(
+    client: &Client,
+    api_url: Url,
+    token: &str,
+    path: &str,
+    offset: u64,
+) -> impl Stream<Item = reqwest::Result<Bytes>> + 'static {
+    let mut req = client.get(file_url(api_url, token, path));
+    if offset > 0 {
+        req = req.header(RANGE, format!("bytes={offset}-"));
+    }
+
+    req.send().into_stream().flat_map(move |res| {
         match res.and_then(Response::error_for_status) {
-            Ok(res) => Either::Left(unfold(res, |mut res| async {
-                match res.chunk().await {
-                    Err(err) => Some((Err(err), res)),
-                    Ok(Some(c)) => Some((Ok(c), res)),
-                    Ok(None) => None,
-                }
-            })),
+            Ok(res) => {
+                // The server may ignore the range and send the whole file
+                let skip = match res.status() {
+                    StatusCode::PARTIAL_CONTENT => 0,
+                    _ => offset,
+                };
+
+                let chunks = unfold(res, |mut res| async move {
+                    let chunk = res.chunk().await.transpose()?;
+                    Some((chunk, res))
+                });
+                Either::Left(skip_bytes(chunks, skip))
+            }
             Err(err) => Either::Right(once(ready(Err(err)))),
         }
     })
 }
+
+/// Skips the first `skip` bytes of `chunks`.
+fn skip_bytes<S, E>(chunks: S, skip: u64) -> impl Stream<Item = Result<Bytes, E>>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+{
+    chunks
+        .scan(skip, |skip, chunk| {
+            let chunk = chunk.map(|c| {
+                let skipped = (*skip).min(c.len() as u64);
+                *skip -= skipped;
+                c.slice(skipped as usize..)
+            });
+            ready(Some(chunk))
+        })
+        .filter(|chunk| ready(!matches!(chunk, Ok(c) if c.is_empty())))
+}
+
+/// Calls `f` with the progress of a download after each chunk of `stream`.
+///
+/// `downloaded` is the number of bytes downloaded before `stream` (e.g. the
+/// offset passed to [`Download::download_file_stream_from`]), `total` is the
+/// size of the file, e.g. [`File::size`].
+///
+/// [`File::size`]: crate::types::File::size
+pub fn download_progress<S, E, F>(
+    stream: S,
+    mut downloaded: u64,
+    total: Option<u64>,
+    mut f: F,
+) -> impl Stream<Item = Result<Bytes, E>>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+    F: FnMut(DownloadProgress),
+{
+    stream.inspect(move |chunk| {
+        if let Ok(chunk) = chunk {
+            downloaded += chunk.len() as u64;
+            f(DownloadProgress { downloaded, total });
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::stream;
+
+    use super::*;
+    use crate::test_utils::MockServer;
+
+    const FILE: &[u8] = b"0123456789";
+
+    /// Serves `FILE`, sending only the requested range if `ranges` is true.
+    async fn mock_api(ranges: bool) -> Url {
+        let server = MockServer::serve(move |request| {
+            let offset = request
+                .header("range")
+                .and_then(|range| range.strip_prefix("bytes=")?.strip_suffix('-')?.parse().ok())
+                .filter(|_| ranges);
+            match offset {
+                Some(offset) => ("206 Partial Content", FILE[offset..].to_vec()),
+                None => ("200 OK", FILE.to_vec()),
+            }
+        })
+        .await;
+        server.url()
+    }
+
+    async fn download(api_url: Url, offset: u64) -> Vec<u8> {
+        let stream = download_file_stream_from(&Client::new(), api_url, "TOKEN", "file", offset);
+        let chunks: Vec<_> = stream.collect().await;
+        chunks.into_iter().flat_map(|c| c.unwrap()).collect()
+    }
+
+    #[tokio::test]
+    async fn resumption() {
+        let api_url = mock_api(true).await;
+        assert_eq!(download(api_url.clone(), 0).await, FILE);
+        assert_eq!(download(api_url, 4).await, &FILE[4..]);
+    }
+
+    #[tokio::test]
+    async fn resumption_without_ranges() {
+        // The server sends the whole file, so the first bytes are skipped
+        let api_url = mock_api(false).await;
+        assert_eq!(download(api_url.clone(), 4).await, &FILE[4..]);
+        assert_eq!(download(api_url, 10).await, b"");
+    }
+
+    #[tokio::test]
+    async fn chunk_skipping() {
+        let chunks =
+            ["012", "345", "6", "789"].map(|c| Ok::<_, ()>(Bytes::from_static(c.as_bytes())));
+
+        let skip = |n| skip_bytes(stream::iter(chunks.clone()), n).collect::<Vec<_>>();
+        assert_eq!(skip(0).await, chunks);
+        assert_eq!(skip(3).await, chunks[1..]);
+        assert_eq!(
+            skip(4).await,
+            [Ok(Bytes::from_static(b"45")), Ok(Bytes::from_static(b"6")), chunks[3].clone()]
+        );
+        assert_eq!(skip(10).await, []);
+        assert_eq!(skip(20).await, []);
+
+        // Errors are passed through
+        let chunks = [Ok(Bytes::from_static(b"012")), Err(()), Ok(Bytes::from_static(b"345"))];
+        let skipped: Vec<_> = skip_bytes(stream::iter(chunks), 4).collect().await;
+        assert_eq!(skipped, [Err(()), Ok(Bytes::from_static(b"45"))]);
+    }
+
+    #[tokio::test]
+    async fn progress() {
+        let chunks = [Ok(Bytes::from_static(b"012")), Err(()), Ok(Bytes::from_static(b"3456"))];
+
+        let mut reported = Vec::new();
+        let stream = download_progress(stream::iter(chunks), 3, Some(10), |p| reported.push(p));
+        assert_eq!(stream.collect::<Vec<_>>().await.len(), 3);
+
+        assert_eq!(
+            reported,
+            [
+                DownloadProgress { downloaded: 6, total: Some(10) },
+                DownloadProgress { downloaded: 10, total: Some(10) }
+            ]
+        );
+        assert_eq!(reported.iter().map(|p| p.percent()).collect::<Vec<_>>(), [Some(60), Some(100)]);
+        assert_eq!(DownloadProgress { downloaded: 5, total: Some(0) }.percent(), Some(100));
+        assert_eq!(DownloadProgress { downloaded: 5, total: None }.percent(), None);
+    }
+}
@@ -6,6 +6,9 @@ use crate::adaptors::CacheMe;
 #[cfg(feature = "erased")]
 use crate::adaptors::ErasedRequester;
 
+#[cfg(feature = "file_id_cache")]
+use crate::adaptors::FileIdCache;
+
 #[cfg(feature = "trace_adaptor")]
 use crate::adaptors::trace::{Settings, Trace};
 
@@ -24,6 +27,17 @@ pub trait RequesterExt: Requester {
         CacheMe::new(self)
     }
 
+    /// Send already uploaded files by their file IDs, see [`FileIdCache`] for
+    /// more.
+    #[cfg(feature = "file_id_cache")]
+    #[must_use]
+    fn cache_file_ids(self) -> FileIdCache<Self>
+    where
+        Self: Sized,
+    {
+        FileIdCache::new(self)
+    }
+
     /// Erase requester type.
     #[cfg(feature = "erased")]
     #[must_use]
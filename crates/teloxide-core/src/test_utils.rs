@@ -0,0 +1,113 @@
+//! Helpers shared by unit tests.
+
+use std::sync::{Arc, Mutex};
+
+use reqwest::Url;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+
+/// A mock HTTP server which records the requests.
+pub(crate) struct MockServer {
+    url: Url,
+    requests: Arc<Mutex<Vec<MockRequest>>>,
+}
+
+/// A request received by [`MockServer`].
+#[derive(Clone, Debug)]
+pub(crate) struct MockRequest {
+    /// Headers with lowercase names.
+    pub(crate) headers: Vec<(String, String)>,
+    pub(crate) body: Vec<u8>,
+}
+
+impl MockRequest {
+    pub(crate) fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _)| n == name).map(|(_, value)| value.as_str())
+    }
+}
+
+impl MockServer {
+    /// Starts a server which answers every request with the status (e.g. `200
+    /// OK`) and the body returned by `respond`.
+    pub(crate) async fn serve<F>(respond: F) -> Self
+    where
+        F: Fn(&MockRequest) -> (&'static str, Vec<u8>) + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap()).parse().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        tokio::spawn({
+            let requests = Arc::clone(&requests);
+            async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    let mut stream = BufReader::new(stream);
+                    let Some(request) = read_request(&mut stream).await else { continue };
+
+                    let (status, body) = respond(&request);
+                    requests.lock().unwrap().push(request);
+
+                    let headers = format!(
+                        "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                        body.len()
+                    );
+                    let _ = stream.write_all(headers.as_bytes()).await;
+                    let _ = stream.write_all(&body).await;
+                }
+            }
+        });
+
+        Self { url, requests }
+    }
+
+    pub(crate) fn url(&self) -> Url {
+        self.url.clone()
+    }
+
+    /// Returns the requests received so far.
+    pub(crate) fn requests(&self) -> Vec<MockRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+// Reads a request with a body of `Content-Length` bytes or a chunked one.
+async fn read_request(stream: &mut BufReader<TcpStream>) -> Option<MockRequest> {
+    let mut headers = Vec::new();
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if stream.read_line(&mut line).await.ok()? == 0 {
+            return None;
+        }
+        if line == "\r\n" {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.to_lowercase(), value.trim().to_owned()));
+        }
+    }
+
+    let mut request = MockRequest { headers, body: Vec::new() };
+    match request.header("content-length") {
+        Some(len) => {
+            request.body.resize(len.parse().ok()?, 0);
+            stream.read_exact(&mut request.body).await.ok()?;
+        }
+        None if request.header("transfer-encoding") == Some("chunked") => loop {
+            line.clear();
+            stream.read_line(&mut line).await.ok()?;
+            let len = usize::from_str_radix(line.trim(), 16).ok()?;
+            let mut chunk = vec![0; len + 2];
+            stream.read_exact(&mut chunk).await.ok()?;
+            if len == 0 {
+                break;
+            }
+            request.body.extend_from_slice(&chunk[..len]);
+        },
+        None => {}
+    }
+
+    Some(request)
+}
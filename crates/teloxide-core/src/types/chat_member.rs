@@ -2,7 +2,7 @@ use std::ops::Deref;
 
 use serde::{Deserialize, Serialize};
 
-use crate::types::{UntilDate, User};
+use crate::types::{ChatAdministratorRights, UntilDate, User};
 
 /// This object contains information about one member of the chat.
 ///
@@ -566,6 +566,53 @@ impl ChatMemberKind {
             Self::Member | Self::Restricted(_) | Self::Left | Self::Banned(_) => false,
         }
     }
+
+    /// Returns administrator rights of the user.
+    ///
+    /// I.e. returns
+    /// - all the rights, if the user is the owner of the chat
+    /// - the user's privileges, if the user is an administrator in the chat
+    /// - `None` otherwise.
+    #[must_use]
+    pub fn administrator_rights(&self) -> Option<ChatAdministratorRights> {
+        match self {
+            Self::Owner(Owner { is_anonymous, .. }) => Some(ChatAdministratorRights {
+                is_anonymous: *is_anonymous,
+                can_manage_chat: true,
+                can_delete_messages: true,
+                can_manage_video_chats: true,
+                can_restrict_members: true,
+                can_promote_members: true,
+                can_change_info: true,
+                can_invite_users: true,
+                can_post_messages: Some(true),
+                can_edit_messages: Some(true),
+                can_pin_messages: Some(true),
+                can_post_stories: Some(true),
+                can_edit_stories: Some(true),
+                can_delete_stories: Some(true),
+                can_manage_topics: Some(true),
+            }),
+            Self::Administrator(admin) => Some(ChatAdministratorRights {
+                is_anonymous: admin.is_anonymous,
+                can_manage_chat: admin.can_manage_chat,
+                can_delete_messages: admin.can_delete_messages,
+                can_manage_video_chats: admin.can_manage_video_chats,
+                can_restrict_members: admin.can_restrict_members,
+                can_promote_members: admin.can_promote_members,
+                can_change_info: admin.can_change_info,
+                can_invite_users: admin.can_invite_users,
+                can_post_messages: Some(admin.can_post_messages),
+                can_edit_messages: Some(admin.can_edit_messages),
+                can_pin_messages: Some(admin.can_pin_messages),
+                can_post_stories: Some(admin.can_post_stories),
+                can_edit_stories: Some(admin.can_edit_stories),
+                can_delete_stories: Some(admin.can_delete_stories),
+                can_manage_topics: Some(admin.can_manage_topics),
+            }),
+            Self::Member | Self::Restricted(_) | Self::Left | Self::Banned(_) => None,
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
@@ -1,7 +1,7 @@
 use bytes::{Bytes, BytesMut};
 use futures::{
     future::{ready, Either},
-    stream,
+    stream, Stream, TryStreamExt,
 };
 use once_cell::sync::OnceCell;
 use rc_box::ArcBox;
@@ -15,8 +15,8 @@ use tokio::{
 use tokio_util::codec::{Decoder, FramedRead};
 
 use std::{
-    borrow::Cow, convert::Infallible, fmt, future::Future, io, iter, mem, path::PathBuf, pin::Pin,
-    sync::Arc, task,
+    borrow::Cow, convert::Infallible, error::Error, fmt, future::Future, io, iter, mem,
+    path::PathBuf, pin::Pin, sync::Arc, task,
 };
 
 use crate::types::InputSticker;
@@ -29,8 +29,30 @@ pub struct InputFile {
     id: OnceCell<Arc<str>>,
     file_name: Option<Cow<'static, str>>,
     inner: InnerFile,
+    progress: Option<ProgressFn>,
 }
 
+/// Progress of an upload, see [`InputFile::on_progress`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UploadProgress {
+    /// The number of sent bytes.
+    pub sent: u64,
+
+    /// The size of the file, if known.
+    pub total: Option<u64>,
+}
+
+impl UploadProgress {
+    /// Returns the sent percentage, if the size of the file is known.
+    #[must_use]
+    pub fn percent(&self) -> Option<u8> {
+        crate::net::percent(self.sent, self.total)
+    }
+}
+
+#[derive(Clone)]
+struct ProgressFn(Arc<dyn Fn(UploadProgress) + Send + Sync>);
+
 #[derive(Clone)]
 enum InnerFile {
     Read(Read),
@@ -98,6 +120,49 @@ impl InputFile {
         self
     }
 
+    /// Sets a callback which is called with the progress of uploading this
+    /// file.
+    ///
+    /// The progress counts bytes handed to the HTTP client, so it's an
+    /// approximation of the bytes actually sent over the network. The size of
+    /// the file is known for [`InputFile::file`] and [`InputFile::memory`],
+    /// and for [`InputFile::read`] only if the file is sent multiple times
+    /// (and so is read into memory). If the file is sent multiple times (e.g.
+    /// when a request is retried), the progress is reported for every upload.
+    ///
+    /// Files sent by URL or file id are not uploaded, so the callback is never
+    /// called for them.
+    ///
+    /// ## Examples
+    ///
+    /// Report the progress through a [`watch`] channel:
+    ///
+    /// ```
+    /// use teloxide_core::types::{InputFile, UploadProgress};
+    /// use tokio::sync::watch;
+    ///
+    /// let (tx, mut rx) = watch::channel(UploadProgress { sent: 0, total: None });
+    /// let file = InputFile::file("video.mp4").on_progress(move |progress| {
+    ///     tx.send_replace(progress);
+    /// });
+    ///
+    /// // Elsewhere, e.g. to edit a message with the progress:
+    /// // while rx.changed().await.is_ok() {
+    /// //     let percent = rx.borrow().percent();
+    /// //     ...
+    /// // }
+    /// # let _ = (file, &mut rx);
+    /// ```
+    ///
+    /// [`watch`]: tokio::sync::watch
+    pub fn on_progress<F>(mut self, f: F) -> Self
+    where
+        F: Fn(UploadProgress) + Send + Sync + 'static,
+    {
+        self.progress = Some(ProgressFn(Arc::new(f)));
+        self
+    }
+
     /// Creates an `InputFile` from a in-memory bytes.
     ///
     /// Note: in some cases (e.g. sending the same `InputFile` multiple times)
@@ -106,10 +171,10 @@ impl InputFile {
         Self::new(Read(Read::new(Arc::new(TakeCell::new(it)))))
     }
 
-    /// Shorthand for `Self { file_name: None, inner, id: default() }`
-    /// (private because `InnerFile` is private implementation detail)
+    /// Shorthand for `Self { file_name: None, inner, id: default(), progress:
+    /// None }` (private because `InnerFile` is private implementation detail)
     fn new(inner: InnerFile) -> Self {
-        Self { file_name: None, inner, id: OnceCell::new() }
+        Self { file_name: None, inner, id: OnceCell::new(), progress: None }
     }
 
     /// Returns id of this file.
@@ -168,6 +233,59 @@ impl InputFile {
     }
 }
 
+#[cfg(feature = "file_id_cache")]
+impl InputFile {
+    /// Returns the path of this file, if it's a file on disk.
+    pub(crate) fn path(&self) -> Option<&std::path::Path> {
+        match &self.inner {
+            File(path) => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of this file, if it was set with
+    /// [`InputFile::file_name`].
+    pub(crate) fn name(&self) -> Option<&str> {
+        self.file_name.as_deref()
+    }
+
+    /// Returns a SHA-256 hash of the name and the content of this file, or
+    /// `None` if the file is not uploaded (it's an url or a file id) or its
+    /// content can't be read without consuming it ([`InputFile::read`]).
+    pub(crate) async fn content_hash(&self) -> io::Result<Option<[u8; 32]>> {
+        use sha2::{Digest, Sha256};
+
+        let name = match (&self.file_name, &self.inner) {
+            (Some(name), _) => name.clone(),
+            (None, File(path)) => path
+                .file_name()
+                .map_or(Cow::Borrowed(""), |name| Cow::Owned(name.to_string_lossy().into_owned())),
+            (None, _) => Cow::Borrowed(""),
+        };
+
+        let mut hasher = Sha256::new();
+        hasher.update((name.len() as u64).to_le_bytes());
+        hasher.update(name.as_bytes());
+
+        match &self.inner {
+            Url(_) | FileId(_) | Read(_) => return Ok(None),
+            Bytes(data) => hasher.update(data),
+            File(path) => {
+                let mut file = tokio::fs::File::open(path).await?;
+                let mut buf = vec![0; 64 * 1024];
+                loop {
+                    match file.read(&mut buf).await? {
+                        0 => break,
+                        n => hasher.update(&buf[..n]),
+                    }
+                }
+            }
+        }
+
+        Ok(Some(hasher.finalize().into()))
+    }
+}
+
 impl fmt::Debug for InnerFile {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -181,6 +299,12 @@ impl fmt::Debug for InnerFile {
     }
 }
 
+impl fmt::Debug for ProgressFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProgressFn").finish_non_exhaustive()
+    }
+}
+
 impl Serialize for InputFile {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -195,6 +319,7 @@ impl Serialize for InputFile {
 impl InputFile {
     pub(crate) fn into_part(mut self) -> Option<impl Future<Output = Part>> {
         let filename = self.take_or_guess_filename();
+        let progress = self.progress;
 
         match self.inner {
             // Url and FileId are serialized just as strings, they don't need additional parts
@@ -204,9 +329,13 @@ impl InputFile {
                 let fut = async {
                     let body = match tokio::fs::File::open(path_to_file).await {
                         Ok(file) => {
+                            let total = match &progress {
+                                Some(_) => file.metadata().await.ok().map(|m| m.len()),
+                                None => None,
+                            };
                             let file = FramedRead::new(file, BytesDecoder);
 
-                            Body::wrap_stream(file)
+                            progress_body(file, total, progress)
                         }
                         Err(err) => {
                             // explicit type needed for `Bytes: From<?T>` in `wrap_stream`
@@ -221,14 +350,58 @@ impl InputFile {
                 Some(Either::Left(fut))
             }
             Bytes(data) => {
-                let stream = Part::stream(data).file_name(filename);
-                Some(Either::Right(Either::Left(ready(stream))))
+                let part = match progress {
+                    Some(progress) => {
+                        // Split the data into chunks, so that the progress is reported
+                        // while sending them
+                        const CHUNK: usize = 64 * 1024;
+
+                        let len = data.len();
+                        let chunks = (0..len)
+                            .step_by(CHUNK)
+                            .map(move |i| Ok::<_, Infallible>(data.slice(i..len.min(i + CHUNK))));
+                        let body =
+                            progress_body(stream::iter(chunks), Some(len as u64), Some(progress));
+
+                        Part::stream_with_length(body, len as u64)
+                    }
+                    None => Part::stream(data),
+                };
+                Some(Either::Right(Either::Left(ready(part.file_name(filename)))))
             }
-            Read(read) => Some(Either::Right(Either::Right(read.into_part(filename)))),
+            Read(read) => Some(Either::Right(Either::Right(read.into_part(filename, progress)))),
         }
     }
 }
 
+/// Creates a body from `stream`, calling `progress` (if any) after each chunk
+/// is taken from the stream.
+fn progress_body<S, E>(stream: S, total: Option<u64>, progress: Option<ProgressFn>) -> Body
+where
+    S: Stream<Item = Result<Bytes, E>> + Send + 'static,
+    E: Into<Box<dyn Error + Send + Sync>> + 'static,
+{
+    match progress {
+        Some(progress) => Body::wrap_stream(with_progress(stream, total, progress)),
+        None => Body::wrap_stream(stream),
+    }
+}
+
+fn with_progress<S, E>(
+    stream: S,
+    total: Option<u64>,
+    ProgressFn(f): ProgressFn,
+) -> impl Stream<Item = Result<Bytes, E>>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+{
+    let mut sent = 0;
+    stream.inspect_ok(move |chunk| {
+        sent += chunk.len() as u64;
+        f(UploadProgress { sent, total });
+    })
+}
+
 /// Adaptor for `AsyncRead` that allows clonning and converting to
 /// `multipart/form-data`
 #[derive(Clone)]
@@ -246,7 +419,11 @@ impl Read {
         Self { inner: it, buf: Arc::default(), notify: Arc::new(tx), wait: rx }
     }
 
-    pub(crate) async fn into_part(mut self, filename: Cow<'static, str>) -> Part {
+    pub(crate) async fn into_part(
+        mut self,
+        filename: Cow<'static, str>,
+        progress: Option<ProgressFn>,
+    ) -> Part {
         if !self.inner.is_taken() {
             let res = ArcBox::<TakeCell<dyn AsyncRead + Send + Unpin>>::try_from(self.inner);
             match res {
@@ -255,7 +432,7 @@ impl Read {
                 Ok(arc_box) => {
                     let fr = FramedRead::new(ExclusiveArcAsyncRead(arc_box), BytesDecoder);
 
-                    let body = Body::wrap_stream(fr);
+                    let body = progress_body(fr, None, progress);
                     return Part::stream(body).file_name(filename);
                 }
                 // move the arc back into `self`
@@ -265,12 +442,12 @@ impl Read {
 
         // Slow path: either wait until someone will read the whole `dyn AsyncRead` into
         // a buffer, or be the one who reads
-        let body = self.into_shared_body().await;
+        let body = self.into_shared_body(progress).await;
 
         Part::stream(body).file_name(filename)
     }
 
-    async fn into_shared_body(mut self) -> Body {
+    async fn into_shared_body(mut self, progress: Option<ProgressFn>) -> Body {
         match self.inner.take() {
             // Read `dyn AsyncRead` into a buffer
             Some(mut read_ref) => {
@@ -329,7 +506,9 @@ impl Read {
         // unwrap: `OnceCell` is initialized in the match above before sending
         // notification, so at this point it's already initialized.
         match buf.get().unwrap() {
-            Ok(_) => {
+            Ok(chunks) => {
+                let total = chunks.iter().map(|chunk| chunk.len() as u64).sum();
+
                 // We can't use `.iter()` here, because the iterator must capture `buf`
                 let mut i = 0;
                 let iter = iter::from_fn(move || match buf.get().unwrap() {
@@ -343,7 +522,7 @@ impl Read {
                     Err(_) => unreachable!(),
                 });
 
-                Body::wrap_stream(stream::iter(iter))
+                progress_body(stream::iter(iter), Some(total), progress)
             }
 
             Err(err) => {
@@ -425,3 +604,97 @@ impl InputFileLike for InputSticker {
         self.sticker.move_into(into)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use futures::StreamExt;
+
+    use super::*;
+    use crate::test_utils::MockServer;
+
+    #[tokio::test]
+    async fn upload_progress() {
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let file = InputFile::memory(vec![0; 10]).on_progress({
+            let reported = Arc::clone(&reported);
+            move |progress| reported.lock().unwrap().push(progress)
+        });
+
+        let chunks = [Bytes::from_static(&[0; 4]), Bytes::from_static(&[0; 6])];
+        let stream = stream::iter(chunks.map(Ok::<_, Infallible>));
+        let sent: Vec<_> = with_progress(stream, Some(10), file.progress.unwrap()).collect().await;
+        assert_eq!(sent.len(), 2);
+
+        let reported = reported.lock().unwrap();
+        assert_eq!(
+            *reported,
+            [
+                UploadProgress { sent: 4, total: Some(10) },
+                UploadProgress { sent: 10, total: Some(10) }
+            ]
+        );
+        assert_eq!(reported.iter().map(|p| p.percent()).collect::<Vec<_>>(), [Some(40), Some(100)]);
+        assert_eq!(UploadProgress { sent: 1, total: None }.percent(), None);
+    }
+
+    /// Sends `file` as a multipart form and returns the body of the request.
+    async fn upload(file: InputFile) -> Vec<u8> {
+        let server = MockServer::serve(|_| ("200 OK", Vec::new())).await;
+
+        let part = file.into_part().unwrap().await;
+        let form = reqwest::multipart::Form::new().part("file", part);
+        reqwest::Client::new().post(server.url()).multipart(form).send().await.unwrap();
+
+        server.requests().remove(0).body
+    }
+
+    fn contains(body: &[u8], data: &[u8]) -> bool {
+        body.windows(data.len()).any(|w| w == data)
+    }
+
+    #[tokio::test]
+    async fn upload_memory() {
+        let data: Vec<u8> = (0..200_000).map(|i| i as u8).collect();
+
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let file = InputFile::memory(data.clone()).on_progress({
+            let reported = Arc::clone(&reported);
+            move |progress| reported.lock().unwrap().push(progress)
+        });
+        assert!(contains(&upload(file).await, &data));
+
+        // The data is sent in chunks of 64 KiB
+        let sent: Vec<_> = reported.lock().unwrap().iter().map(|p| (p.sent, p.total)).collect();
+        assert_eq!(sent, [65_536, 131_072, 196_608, 200_000].map(|sent| (sent, Some(200_000))));
+    }
+
+    #[tokio::test]
+    async fn upload_file() {
+        let data: Vec<u8> = (0..200_000).map(|i| (i / 7) as u8).collect();
+        let path = std::env::temp_dir().join(format!("teloxide_upload_{}", std::process::id()));
+        tokio::fs::write(&path, &data).await.unwrap();
+
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let file = InputFile::file(&path).on_progress({
+            let reported = Arc::clone(&reported);
+            move |progress| reported.lock().unwrap().push(progress)
+        });
+        let body = upload(file).await;
+        tokio::fs::remove_file(&path).await.unwrap();
+        assert!(contains(&body, &data));
+
+        let reported = reported.lock().unwrap().clone();
+        assert!(reported.len() > 1);
+        assert!(reported.windows(2).all(|w| w[0].sent < w[1].sent));
+        assert!(reported.iter().all(|p| p.total == Some(200_000)));
+        assert_eq!(reported.last().unwrap().sent, 200_000);
+
+        // Without a callback, the file is sent as is
+        tokio::fs::write(&path, &data).await.unwrap();
+        let body = upload(InputFile::file(&path)).await;
+        tokio::fs::remove_file(&path).await.unwrap();
+        assert!(contains(&body, &data));
+    }
+}
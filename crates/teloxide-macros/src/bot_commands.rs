@@ -1,11 +1,15 @@
 use crate::{
-    command::Command, command_enum::CommandEnum, compile_error, fields_parse::impl_parse_args,
-    unzip::Unzip, Result,
+    command::Command,
+    command_enum::CommandEnum,
+    compile_error,
+    fields_parse::{impl_parse_args, subcommand_type, usage, ParserType},
+    unzip::Unzip,
+    Result,
 };
 
 use proc_macro2::TokenStream;
 use quote::{quote, quote_spanned};
-use syn::DeriveInput;
+use syn::{punctuated::Punctuated, Attribute, DeriveInput, Meta, Token};
 
 pub(crate) fn bot_commands_impl(input: DeriveInput) -> Result<TokenStream> {
     let data_enum = get_enum_data(&input)?;
@@ -15,12 +19,23 @@ pub(crate) fn bot_commands_impl(input: DeriveInput) -> Result<TokenStream> {
         .variants
         .iter()
         .map(|variant| {
-            let command = Command::new(&variant.ident.to_string(), &variant.attrs, &command_enum)?;
+            let mut command =
+                Command::new(&variant.ident.to_string(), &variant.attrs, &command_enum)?;
+            if matches!(command.parser, ParserType::Subcommand) && variant.fields.len() != 1 {
+                let attr = subcommand_attr(&variant.attrs).expect("parser is set by the attribute");
+                return Err(syn::Error::new_spanned(
+                    attr,
+                    "subcommand variants must have exactly 1 field",
+                )
+                .into());
+            }
+            command.usage = usage(&variant.fields, &command.parser)?;
+            command.subcommand = subcommand_type(&variant.fields, &command.parser).cloned();
 
             let variant_name = &variant.ident;
             let self_variant = quote! { Self::#variant_name };
 
-            let parse = impl_parse_args(&variant.fields, self_variant, &command.parser);
+            let parse = impl_parse_args(&variant.fields, self_variant, &command.parser)?;
 
             Ok((parse, command))
         })
@@ -42,6 +57,14 @@ pub(crate) fn bot_commands_impl(input: DeriveInput) -> Result<TokenStream> {
     Ok(trait_impl)
 }
 
+/// Returns the `#[command(...)]` attribute that contains `subcommand`.
+fn subcommand_attr(attrs: &[Attribute]) -> Option<&Attribute> {
+    attrs.iter().filter(|attr| attr.path().is_ident("command")).find(|attr| {
+        attr.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)
+            .is_ok_and(|metas| metas.iter().any(|meta| meta.path().is_ident("subcommand")))
+    })
+}
+
 fn impl_commands(infos: &[Command]) -> proc_macro2::TokenStream {
     let commands = infos.iter().filter(|command| command.description_is_enabled()).map(|command| {
         let c = command.get_prefixed_command();
@@ -61,10 +84,14 @@ fn impl_descriptions(infos: &[Command], global: &CommandEnum) -> proc_macro2::To
     let command_descriptions = infos
         .iter()
         .filter(|command| command.description_is_enabled())
-        .map(|command @ Command { prefix, name, aliases, ..}| {
+        .map(|command @ Command { prefix, name, aliases, usage, subcommand, ..}| {
             let description = command.description().unwrap_or_default();
             let aliases = (!command.hidden_aliases).then(|| aliases.clone().map(|(aliases, _)| aliases).unwrap_or_default()).unwrap_or_default();
-            quote! { CommandDescription { prefix: #prefix, command: #name, description: #description, aliases: &[#(#aliases),*]} }
+            let subcommands = match subcommand {
+                Some(ty) => quote! { ::std::option::Option::Some(<#ty as teloxide::utils::command::BotCommands>::descriptions) },
+                None => quote! { ::std::option::Option::None },
+            };
+            quote! { CommandDescription { prefix: #prefix, command: #name, description: #description, aliases: &[#(#aliases),*], usage: #usage, subcommands: #subcommands } }
         });
 
     let warnings = infos.iter().filter_map(|command| command.deprecated_description_off_span()).map(|span| {
@@ -104,6 +131,10 @@ fn impl_parse(
 ) -> proc_macro2::TokenStream {
     let matching_values = infos.iter().map(|c| c.get_prefixed_command());
     let aliases = infos.iter().map(|c| c.get_prefixed_aliases().unwrap_or_default());
+    let names = infos.iter().map(|c| &c.name);
+    let unprefixed_aliases = infos
+        .iter()
+        .map(|c| c.aliases.as_ref().map(|(aliases, _)| aliases.clone()).unwrap_or_default());
 
     quote! {
          fn parse(s: &str, bot_name: &str) -> ::std::result::Result<Self, teloxide::utils::command::ParseError> {
@@ -137,6 +168,27 @@ fn impl_parse(
                    _ => ::std::result::Result::Err(ParseError::UnknownCommand(command.to_owned())),
               }
          }
+
+         fn parse_subcommand(s: &str) -> ::std::result::Result<Self, teloxide::utils::command::ParseError> {
+              use std::str::FromStr;
+              use teloxide::utils::command::ParseError;
+
+              let mut words = s.splitn(2, #command_separator);
+
+              // Unwrap: split iterators always have at least one item
+              let command = words.next().unwrap();
+
+              let args = words.next().unwrap_or("").to_owned();
+              match command {
+                   #(
+                        #names => Ok(#variants_initialization),
+                   )*
+                   #(
+                        c if [#(#unprefixed_aliases),*].contains(&c) => Ok(#variants_initialization),
+                   )*
+                   _ => ::std::result::Result::Err(ParseError::UnknownCommand(command.to_owned())),
+              }
+         }
     }
 }
 
@@ -1,8 +1,11 @@
 use proc_macro2::Span;
 
 use crate::{
-    command_attr::CommandAttrs, command_enum::CommandEnum, error::compile_error_at,
-    fields_parse::ParserType, Result,
+    command_attr::CommandAttrs,
+    command_enum::{fields_only_attr, CommandEnum},
+    error::compile_error_at,
+    fields_parse::ParserType,
+    Result,
 };
 
 pub(crate) struct Command {
@@ -21,6 +24,10 @@ pub(crate) struct Command {
     pub hidden: bool,
     /// Whether the aliases of the command are hidden from the help message.
     pub hidden_aliases: bool,
+    /// Usage of the command arguments, empty if unknown.
+    pub usage: String,
+    /// Type of the subcommand, if this is a `#[command(subcommand)]` variant.
+    pub subcommand: Option<syn::Type>,
 }
 
 impl Command {
@@ -43,8 +50,13 @@ impl Command {
             command_separator: _,
             hide,
             hide_aliases,
+            flag,
+            option,
+            subcommand,
         } = attrs;
 
+        fields_only_attr![flag, option];
+
         let name = match (rename, rename_rule) {
             (Some((rename, _)), None) => rename,
             (Some(_), Some((_, sp))) => {
@@ -58,11 +70,31 @@ impl Command {
         };
 
         let prefix = prefix.map(|(p, _)| p).unwrap_or_else(|| global_options.prefix.clone());
-        let parser = parser.map(|(p, _)| p).unwrap_or_else(|| global_options.parser_type.clone());
+        let parser = match (parser, subcommand) {
+            (Some((_, sp)), Some(_)) => {
+                return Err(compile_error_at(
+                    "`parse_with` can't be applied to a `subcommand` variant",
+                    sp,
+                ))
+            }
+            (Some((p, _)), None) => p,
+            (None, Some(_)) => ParserType::Subcommand,
+            (None, None) => global_options.parser_type.clone(),
+        };
         let hidden = hide.is_some();
         let hidden_aliases = hide_aliases.is_some();
 
-        Ok(Self { prefix, description, parser, name, aliases, hidden, hidden_aliases })
+        Ok(Self {
+            prefix,
+            description,
+            parser,
+            name,
+            aliases,
+            hidden,
+            hidden_aliases,
+            usage: String::new(),
+            subcommand: None,
+        })
     }
 
     pub fn get_prefixed_command(&self) -> String {
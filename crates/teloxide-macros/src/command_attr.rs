@@ -22,6 +22,9 @@ pub(crate) struct CommandAttrs {
     pub command_separator: Option<(String, Span)>,
     pub hide: Option<((), Span)>,
     pub hide_aliases: Option<((), Span)>,
+    pub flag: Option<((), Span)>,
+    pub option: Option<((), Span)>,
+    pub subcommand: Option<((), Span)>,
 }
 
 /// A single k/v attribute for `BotCommands` derive macro.
@@ -51,6 +54,9 @@ enum CommandAttrKind {
     CommandSeparator(String),
     Hide,
     HideAliases,
+    Flag,
+    NamedOption,
+    Subcommand,
 }
 
 impl CommandAttrs {
@@ -72,6 +78,9 @@ impl CommandAttrs {
                 command_separator: None,
                 hide: None,
                 hide_aliases: None,
+                flag: None,
+                option: None,
+                subcommand: None,
             },
             |mut this, attr| {
                 fn insert<T>(opt: &mut Option<(T, Span)>, x: T, sp: Span) -> Result<()> {
@@ -119,6 +128,9 @@ impl CommandAttrs {
                     CommandSeparator(s) => insert(&mut this.command_separator, s, attr.sp),
                     Hide => insert(&mut this.hide, (), attr.sp),
                     HideAliases => insert(&mut this.hide_aliases, (), attr.sp),
+                    Flag => insert(&mut this.flag, (), attr.sp),
+                    NamedOption => insert(&mut this.option, (), attr.sp),
+                    Subcommand => insert(&mut this.subcommand, (), attr.sp),
                 }?;
 
                 Ok(this)
@@ -175,6 +187,9 @@ impl CommandAttr {
                     "command_separator" => CommandSeparator(value.expect_string()?),
                     "hide" => value.expect_none("hide").map(|_| Hide)?,
                     "hide_aliases" => value.expect_none("hide_aliases").map(|_| HideAliases)?,
+                    "flag" => value.expect_none("flag").map(|_| Flag)?,
+                    "option" => value.expect_none("option").map(|_| NamedOption)?,
+                    "subcommand" => value.expect_none("subcommand").map(|_| Subcommand)?,
                     "alias" => Aliases(vec![value.expect_string()?]),
                     "aliases" => Aliases(
                         value
@@ -186,7 +201,8 @@ impl CommandAttr {
                     _ => {
                         return Err(compile_error_at(
                             "unexpected attribute name (expected one of `prefix`, `description`, \
-                             `rename`, `parse_with`, `separator`, `hide`, `alias` and `aliases`",
+                             `rename`, `parse_with`, `separator`, `hide`, `alias`, `aliases`, \
+                             `flag`, `option` and `subcommand`",
                             attr.span(),
                         ))
                     }
@@ -18,6 +18,23 @@ macro_rules! variants_only_attr {
     };
 }
 
+/// Create a if block that checks if the given attribute is applied to
+/// something other than a field, if so, return an error
+macro_rules! fields_only_attr {
+    ($($attr: ident),+) => {
+        $(
+            if let Some((_, sp)) = $attr {
+                return Err(crate::error::compile_error_at(
+                    concat!("`", stringify!($attr), "` attribute can only be applied to *fields* of enum variants"),
+                    sp,
+                ));
+            }
+        )+
+    };
+}
+
+pub(crate) use fields_only_attr;
+
 pub(crate) struct CommandEnum {
     pub prefix: String,
     /// The bool is true if the description contains a doc comment
@@ -41,9 +58,13 @@ impl CommandEnum {
             separator,
             hide,
             hide_aliases,
+            flag,
+            option,
+            subcommand,
         } = attrs;
 
-        variants_only_attr![rename, hide, hide_aliases, aliases];
+        variants_only_attr![rename, hide, hide_aliases, aliases, subcommand];
+        fields_only_attr![flag, option];
 
         let mut parser = parser.map(|(p, _)| p).unwrap_or(ParserType::Default);
 
@@ -1,25 +1,60 @@
-use quote::quote;
-use syn::{Fields, FieldsNamed, FieldsUnnamed, Type};
+use proc_macro2::Span;
+use quote::{format_ident, quote};
+use syn::{spanned::Spanned, Field, Fields, FieldsNamed, FieldsUnnamed, Type};
 
-use crate::{attr::AttrValue, error::Result};
+use crate::{
+    attr::AttrValue,
+    command_attr::CommandAttrs,
+    error::{compile_error_at, Result},
+};
 
 #[derive(Clone)]
 pub(crate) enum ParserType {
     Default,
     Split { separator: Option<String> },
+    Args,
+    Subcommand,
     Custom(syn::Path),
 }
 
+/// An argument of a command parsed with [`ParserType::Args`].
+struct Arg<'a> {
+    /// Name of the argument, as shown to the user.
+    name: String,
+    kind: ArgKind,
+    /// The type the argument is parsed into (`T` for `Option<T>` and
+    /// `Vec<T>`).
+    ty: &'a Type,
+}
+
+enum ArgKind {
+    /// `T`
+    Required,
+    /// `Option<T>`
+    Optional,
+    /// `Vec<T>`
+    Rest,
+    /// `#[command(flag)] bool`
+    Flag,
+    /// `#[command(option)] T`
+    RequiredOption,
+    /// `#[command(option)] Option<T>`
+    Option,
+}
+
 impl ParserType {
     pub fn parse(value: AttrValue) -> Result<Self> {
-        value.expect(r#""default", "split", or a path to a custom parser function"#, |v| match v {
-            AttrValue::Path(p) => Ok(ParserType::Custom(p)),
-            AttrValue::Lit(syn::Lit::Str(ref l)) => match &*l.value() {
-                "default" => Ok(ParserType::Default),
-                "split" => Ok(ParserType::Split { separator: None }),
+        value.expect(r#""default", "split", "args", or a path to a custom parser function"#, |v| {
+            match v {
+                AttrValue::Path(p) => Ok(ParserType::Custom(p)),
+                AttrValue::Lit(syn::Lit::Str(ref l)) => match &*l.value() {
+                    "default" => Ok(ParserType::Default),
+                    "split" => Ok(ParserType::Split { separator: None }),
+                    "args" => Ok(ParserType::Args),
+                    _ => Err(v),
+                },
                 _ => Err(v),
-            },
-            _ => Err(v),
+            }
         })
     }
 }
@@ -28,20 +63,70 @@ pub(crate) fn impl_parse_args(
     fields: &Fields,
     self_variant: proc_macro2::TokenStream,
     parser: &ParserType,
-) -> proc_macro2::TokenStream {
-    match fields {
+) -> Result<proc_macro2::TokenStream> {
+    // Even if they are not used, field attributes must be valid. Only arguments
+    // parsed by `args` must be in a parseable order
+    let args = args_of(fields, matches!(parser, ParserType::Args))?;
+
+    if !matches!(parser, ParserType::Args) {
+        if let Some(arg) = args.iter().find(|arg| arg.is_named()) {
+            return Err(compile_error_at(
+                "`flag` and `option` attributes can only be used with `parse_with = \"args\"`",
+                arg.ty.span(),
+            ));
+        }
+    }
+
+    let res = match fields {
         Fields::Unit => self_variant,
-        Fields::Unnamed(fields) => impl_parse_args_unnamed(fields, self_variant, parser),
-        Fields::Named(named) => impl_parse_args_named(named, self_variant, parser),
+        Fields::Unnamed(fields) => impl_parse_args_unnamed(fields, self_variant, parser, &args),
+        Fields::Named(named) => impl_parse_args_named(named, self_variant, parser, &args),
+    };
+
+    Ok(res)
+}
+
+/// Returns the type of the subcommand if `parser` is
+/// [`ParserType::Subcommand`].
+pub(crate) fn subcommand_type<'a>(fields: &'a Fields, parser: &ParserType) -> Option<&'a Type> {
+    match (parser, fields.iter().next()) {
+        (ParserType::Subcommand, Some(field)) if fields.len() == 1 => Some(&field.ty),
+        _ => None,
     }
 }
 
-pub(crate) fn impl_parse_args_unnamed(
+/// Returns usage of the command arguments, e.g. `<user> [days] [--silent]`.
+///
+/// Usage is only known for [`ParserType::Args`], for other parsers an empty
+/// string is returned.
+pub(crate) fn usage(fields: &Fields, parser: &ParserType) -> Result<String> {
+    if !matches!(parser, ParserType::Args) {
+        return Ok(String::new());
+    }
+
+    let usage = args_of(fields, true)?
+        .iter()
+        .map(|Arg { name, kind, .. }| match kind {
+            ArgKind::Required => format!("<{name}>"),
+            ArgKind::Optional => format!("[{name}]"),
+            ArgKind::Rest => format!("[{name}...]"),
+            ArgKind::Flag => format!("[--{name}]"),
+            ArgKind::RequiredOption => format!("--{name}=<{name}>"),
+            ArgKind::Option => format!("[--{name}=<{name}>]"),
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    Ok(usage)
+}
+
+fn impl_parse_args_unnamed(
     data: &FieldsUnnamed,
     variant: proc_macro2::TokenStream,
     parser_type: &ParserType,
+    args: &[Arg<'_>],
 ) -> proc_macro2::TokenStream {
-    let get_arguments = create_parser(parser_type, data.unnamed.iter().map(|f| &f.ty));
+    let get_arguments = create_parser(parser_type, data.unnamed.iter().map(|f| &f.ty), args);
     let iter = (0..data.unnamed.len()).map(syn::Index::from);
     let mut initialization = quote! {};
     for i in iter {
@@ -56,12 +141,13 @@ pub(crate) fn impl_parse_args_unnamed(
     res
 }
 
-pub(crate) fn impl_parse_args_named(
+fn impl_parse_args_named(
     data: &FieldsNamed,
     variant: proc_macro2::TokenStream,
     parser_type: &ParserType,
+    args: &[Arg<'_>],
 ) -> proc_macro2::TokenStream {
-    let get_arguments = create_parser(parser_type, data.named.iter().map(|f| &f.ty));
+    let get_arguments = create_parser(parser_type, data.named.iter().map(|f| &f.ty), args);
     let i = (0..).map(syn::Index::from);
     let name = data.named.iter().map(|f| f.ident.as_ref().unwrap());
     let res = quote! {
@@ -76,6 +162,7 @@ pub(crate) fn impl_parse_args_named(
 fn create_parser<'a>(
     parser_type: &ParserType,
     mut types: impl ExactSizeIterator<Item = &'a Type>,
+    args: &[Arg<'_>],
 ) -> proc_macro2::TokenStream {
     let function_to_parse = match parser_type {
         ParserType::Default => match types.len() {
@@ -99,6 +186,38 @@ fn create_parser<'a>(
         ParserType::Split { separator } => {
             parser_with_separator(&separator.clone().unwrap_or_else(|| " ".to_owned()), types)
         }
+        ParserType::Args => parser_with_args(args),
+        ParserType::Subcommand => match types.len() {
+            1 => {
+                let ty = types.next().unwrap();
+                quote! {
+                    (
+                        |s: ::std::string::String| {
+                            use teloxide::utils::command::{BotCommands, ParseError};
+
+                            if s.is_empty() {
+                                return ::std::result::Result::Err(ParseError::TooFewArguments {
+                                    expected: 1,
+                                    found: 0,
+                                    message: "Missing subcommand".to_owned(),
+                                });
+                            }
+
+                            match <#ty as BotCommands>::parse_subcommand(&s) {
+                                ::std::result::Result::Ok(res) => ::std::result::Result::Ok((res,)),
+                                ::std::result::Result::Err(ParseError::UnknownCommand(c)) => {
+                                    ::std::result::Result::Err(ParseError::IncorrectFormat(
+                                        format!("Unknown subcommand: {c}").into(),
+                                    ))
+                                }
+                                ::std::result::Result::Err(e) => ::std::result::Result::Err(e),
+                            }
+                        }
+                    )
+                }
+            }
+            _ => unreachable!("the number of subcommand fields is checked in `bot_commands_impl`"),
+        },
         ParserType::Custom(path) => quote! { #path },
     };
 
@@ -152,3 +271,190 @@ fn parser_with_separator<'a>(
 
     res
 }
+
+fn parser_with_args(args: &[Arg<'_>]) -> proc_macro2::TokenStream {
+    let required = args.iter().filter(|arg| matches!(arg.kind, ArgKind::Required)).count();
+    let names_of =
+        |f: fn(&ArgKind) -> bool| args.iter().filter(move |arg| f(&arg.kind)).map(|arg| &arg.name);
+    let flags = names_of(|kind| matches!(kind, ArgKind::Flag));
+    let options = names_of(|kind| matches!(kind, ArgKind::Option | ArgKind::RequiredOption));
+
+    let vars: Vec<_> = (0..args.len()).map(|i| format_ident!("arg{}", i)).collect();
+    let parse = args.iter().map(|Arg { name, kind, ty }| match kind {
+        ArgKind::Required => quote! { command_args.required::<#ty>(#name)? },
+        ArgKind::Optional => quote! { command_args.optional::<#ty>(#name)? },
+        ArgKind::Rest => quote! { command_args.rest::<#ty>(#name)? },
+        ArgKind::Flag => quote! { command_args.flag(#name) },
+        ArgKind::RequiredOption => quote! { command_args.required_option::<#ty>(#name)? },
+        ArgKind::Option => quote! { command_args.option::<#ty>(#name)? },
+    });
+
+    quote! {
+        (
+            |s: ::std::string::String| {
+                let mut command_args = teloxide::utils::command::CommandArgs::new(
+                    &s,
+                    #required,
+                    &[#(#flags),*],
+                    &[#(#options),*],
+                )?;
+
+                #(
+                    let #vars = #parse;
+                )*
+
+                command_args.finish()?;
+
+                ::std::result::Result::Ok((#(#vars,)*))
+            }
+        )
+    }
+}
+
+/// Collects arguments of a variant, checking that they are in a parseable
+/// order for [`ParserType::Args`] if `check_order` is `true`.
+fn args_of(fields: &Fields, check_order: bool) -> Result<Vec<Arg<'_>>> {
+    let mut args = Vec::new();
+    // Span of the last optional or rest positional argument, used to check that
+    // required positional arguments don't follow them
+    let mut optional_sp: Option<Span> = None;
+    let mut rest_sp: Option<Span> = None;
+
+    for (i, field) in fields.iter().enumerate() {
+        let arg = Arg::new(i, field)?;
+
+        if check_order && !arg.is_named() {
+            if rest_sp.is_some() {
+                return Err(compile_error_at(
+                    "positional arguments can't follow a `Vec<_>` argument",
+                    field.span(),
+                ));
+            }
+
+            match arg.kind {
+                ArgKind::Required if optional_sp.is_some() => {
+                    return Err(compile_error_at(
+                        "required arguments can't follow an `Option<_>` argument",
+                        field.span(),
+                    ))
+                }
+                ArgKind::Optional => optional_sp = Some(field.span()),
+                ArgKind::Rest => rest_sp = Some(field.span()),
+                _ => {}
+            }
+        }
+
+        args.push(arg);
+    }
+
+    Ok(args)
+}
+
+impl<'a> Arg<'a> {
+    fn new(i: usize, field: &'a Field) -> Result<Self> {
+        let CommandAttrs {
+            prefix,
+            // Doc comments on fields are allowed
+            description: _,
+            rename_rule,
+            rename,
+            aliases,
+            parser,
+            separator,
+            command_separator,
+            hide,
+            hide_aliases,
+            flag,
+            option,
+            subcommand,
+        } = CommandAttrs::from_attributes(&field.attrs)?;
+
+        let unexpected = [
+            prefix.map(|(_, sp)| sp),
+            rename_rule.map(|(_, sp)| sp),
+            rename.map(|(_, sp)| sp),
+            aliases.map(|(_, sp)| sp),
+            parser.map(|(_, sp)| sp),
+            separator.map(|(_, sp)| sp),
+            command_separator.map(|(_, sp)| sp),
+            hide.map(|(_, sp)| sp),
+            hide_aliases.map(|(_, sp)| sp),
+            subcommand.map(|(_, sp)| sp),
+        ];
+        if let Some(sp) = unexpected.into_iter().flatten().next() {
+            return Err(compile_error_at(
+                "only `flag` and `option` attributes can be applied to fields",
+                sp,
+            ));
+        }
+
+        let ty = &field.ty;
+        let (kind, ty) = match (flag, option, &field.ident) {
+            (Some(_), Some((_, sp)), _) => {
+                return Err(compile_error_at("a field can't be both a flag and an option", sp))
+            }
+            (Some((_, sp)), None, None) | (None, Some((_, sp)), None) => {
+                return Err(compile_error_at(
+                    "`flag` and `option` attributes can only be applied to named fields",
+                    sp,
+                ))
+            }
+            (Some(_), None, Some(_)) if is_bool(ty) => (ArgKind::Flag, ty),
+            (Some(_), None, Some(_)) => {
+                return Err(
+                    syn::Error::new_spanned(ty, "`flag` fields must be of type `bool`").into()
+                )
+            }
+            (None, Some(_), Some(_)) => match generic_arg(ty, "Option") {
+                Some(inner) => (ArgKind::Option, inner),
+                None => (ArgKind::RequiredOption, ty),
+            },
+            (None, None, _) => match (generic_arg(ty, "Option"), generic_arg(ty, "Vec")) {
+                (Some(inner), _) => (ArgKind::Optional, inner),
+                (_, Some(inner)) => (ArgKind::Rest, inner),
+                (None, None) => (ArgKind::Required, ty),
+            },
+        };
+
+        let name = match &field.ident {
+            Some(ident) if kind.is_named() => ident.to_string().replace('_', "-"),
+            Some(ident) => ident.to_string(),
+            None => format!("arg{}", i + 1),
+        };
+
+        Ok(Self { name, kind, ty })
+    }
+
+    fn is_named(&self) -> bool {
+        self.kind.is_named()
+    }
+}
+
+impl ArgKind {
+    /// Returns `true` for `--flag`s and `--option`s.
+    fn is_named(&self) -> bool {
+        matches!(self, Self::Flag | Self::RequiredOption | Self::Option)
+    }
+}
+
+/// Returns `true` if `ty` is `bool` (possibly written as a path, e.g.
+/// `std::primitive::bool`).
+fn is_bool(ty: &Type) -> bool {
+    let Type::Path(syn::TypePath { qself: None, path }) = ty else { return false };
+    path.segments.last().is_some_and(|s| s.ident == "bool" && s.arguments.is_empty())
+}
+
+/// Returns `T` if `ty` is `wrapper<T>` (e.g. `Option<T>`).
+fn generic_arg<'a>(ty: &'a Type, wrapper: &str) -> Option<&'a Type> {
+    let Type::Path(syn::TypePath { qself: None, path }) = ty else { return None };
+    let segment = path.segments.last()?;
+    if segment.ident != wrapper {
+        return None;
+    }
+
+    let syn::PathArguments::AngleBracketed(args) = &segment.arguments else { return None };
+    match args.args.first() {
+        Some(syn::GenericArgument::Type(inner)) if args.args.len() == 1 => Some(inner),
+        _ => None,
+    }
+}
@@ -1,7 +1,15 @@
 use std::str::FromStr;
 
 use chrono::Duration;
-use teloxide::{prelude::*, types::ChatPermissions, utils::command::BotCommands};
+use teloxide::{
+    dispatching::{
+        permissions::{invalidate_admin_cache, AdminCache, AdminRight},
+        HandlerExt,
+    },
+    prelude::*,
+    types::ChatPermissions,
+    utils::command::BotCommands,
+};
 
 // Derive BotCommands to parse text with a command into this enumeration.
 //
@@ -58,19 +66,36 @@ async fn main() {
 
     let bot = teloxide::Bot::from_env();
 
-    Command::repl(bot, action).await;
-}
+    let handler = dptree::entry()
+        // Forget cached administrators of a chat when someone is promoted or demoted there.
+        .branch(invalidate_admin_cache())
+        .branch(
+            Update::filter_message()
+                .filter_command::<Command>()
+                .branch(dptree::case![Command::Help].endpoint(help))
+                .branch(
+                    // Only administrators who can restrict members may use the rest of the
+                    // commands, and the bot must be able to restrict members itself.
+                    dptree::entry()
+                        .filter_has_right::<Bot, _>(|rights| rights.can_restrict_members)
+                        .filter_bot_has_rights::<Bot>(&[AdminRight::RestrictMembers])
+                        .branch(dptree::case![Command::Kick].endpoint(kick_user))
+                        .branch(dptree::case![Command::Ban { time, unit }].endpoint(ban_user))
+                        .branch(dptree::case![Command::Mute { time, unit }].endpoint(mute_user)),
+                ),
+        );
 
-async fn action(bot: Bot, msg: Message, cmd: Command) -> ResponseResult<()> {
-    match cmd {
-        Command::Help => {
-            bot.send_message(msg.chat.id, Command::descriptions().to_string()).await?;
-        }
-        Command::Kick => kick_user(bot, msg).await?,
-        Command::Ban { time, unit } => ban_user(bot, msg, calc_restrict_time(time, unit)).await?,
-        Command::Mute { time, unit } => mute_user(bot, msg, calc_restrict_time(time, unit)).await?,
-    };
+    Dispatcher::builder(bot, handler)
+        // Administrators are requested once per chat, instead of once per update.
+        .dependencies(dptree::deps![AdminCache::new()])
+        .enable_ctrlc_handler()
+        .build()
+        .dispatch()
+        .await;
+}
 
+async fn help(bot: Bot, msg: Message) -> ResponseResult<()> {
+    bot.send_message(msg.chat.id, Command::descriptions().to_string()).await?;
     Ok(())
 }
 
@@ -89,7 +114,8 @@ async fn kick_user(bot: Bot, msg: Message) -> ResponseResult<()> {
 }
 
 // Ban a user with replied message.
-async fn ban_user(bot: Bot, msg: Message, time: Duration) -> ResponseResult<()> {
+async fn ban_user(bot: Bot, msg: Message, (time, unit): (u64, UnitOfTime)) -> ResponseResult<()> {
+    let time = calc_restrict_time(time, unit);
     match msg.reply_to_message() {
         Some(replied) => {
             bot.kick_chat_member(
@@ -108,7 +134,8 @@ async fn ban_user(bot: Bot, msg: Message, time: Duration) -> ResponseResult<()>
 }
 
 // Mute a user with a replied message.
-async fn mute_user(bot: Bot, msg: Message, time: Duration) -> ResponseResult<()> {
+async fn mute_user(bot: Bot, msg: Message, (time, unit): (u64, UnitOfTime)) -> ResponseResult<()> {
+    let time = calc_restrict_time(time, unit);
     match msg.reply_to_message() {
         Some(replied) => {
             bot.restrict_chat_member(
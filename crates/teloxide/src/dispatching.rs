@@ -214,17 +214,33 @@
 //! [`examples/dispatching_features.rs`]: https://github.com/teloxide/teloxide/blob/master/crates/teloxide/examples/dispatching_features.rs
 //! [`Update`]: crate::types::Update
 
+pub mod data;
 pub mod dialogue;
+#[cfg(feature = "scheduler")]
+pub mod scheduler;
 
+mod backpressure;
 mod dispatcher;
-mod distribution;
+pub mod distribution;
 mod filter_ext;
 mod handler_description;
 mod handler_ext;
+mod media_group;
+mod multi_dispatcher;
+pub mod permissions;
+pub mod rate_limit;
+mod webhook_reply;
 
 pub use crate::utils::shutdown_token::{IdleShutdownError, ShutdownToken};
+pub use backpressure::OverflowPolicy;
 pub use dispatcher::{Dispatcher, DispatcherBuilder, UpdateHandler};
 pub use distribution::DefaultKey;
 pub use filter_ext::{MessageFilterExt, UpdateFilterExt};
 pub use handler_description::DpHandlerDescription;
-pub use handler_ext::{filter_command, filter_mention_command, HandlerExt};
+pub use handler_ext::{
+    filter_command, filter_command_with_help, filter_mention_command, HandlerExt,
+};
+pub use media_group::MediaGroup;
+pub use multi_dispatcher::{MultiDispatcher, MultiDispatcherBuilder};
+pub use tokio_util::sync::CancellationToken;
+pub use webhook_reply::{WebhookReplies, WebhookReply};
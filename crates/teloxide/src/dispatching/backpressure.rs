@@ -0,0 +1,207 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    future::Future,
+    sync::{Arc, Mutex, Weak},
+};
+
+use futures::future::BoxFuture;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::types::{Update, UpdateId};
+
+/// What [`Dispatcher`] does with an update when there is no room for it.
+///
+/// This is used both for the queues of workers (see
+/// [`DispatcherBuilder::worker_queue_overflow`]) and for the global limit of
+/// in-flight updates (see [`DispatcherBuilder::in_flight_limit`]).
+///
+/// [`Dispatcher`]: crate::dispatching::Dispatcher
+/// [`DispatcherBuilder::worker_queue_overflow`]: crate::dispatching::DispatcherBuilder::worker_queue_overflow
+/// [`DispatcherBuilder::in_flight_limit`]: crate::dispatching::DispatcherBuilder::in_flight_limit
+#[derive(Clone, Default)]
+pub enum OverflowPolicy {
+    /// Wait until there is room for the update.
+    ///
+    /// While waiting, the dispatcher doesn't receive new updates from the
+    /// update listener, so the backpressure is propagated to the listener
+    /// (and to Telegram, which keeps the updates for up to 24 hours).
+    #[default]
+    Block,
+
+    /// Drop the new update, logging a warning.
+    DropNewest,
+
+    /// Drop the oldest update which is waiting to be processed, logging a
+    /// warning.
+    ///
+    /// If all updates are already being processed, waits like
+    /// [`OverflowPolicy::Block`].
+    DropOldest,
+
+    /// Pass the new update to the hook instead of processing it.
+    Hook(Arc<dyn Fn(Update) -> BoxFuture<'static, ()> + Send + Sync>),
+}
+
+impl OverflowPolicy {
+    /// Creates [`OverflowPolicy::Hook`] from a function.
+    #[must_use]
+    pub fn hook<H, Fut>(hook: H) -> Self
+    where
+        H: Fn(Update) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        Self::Hook(Arc::new(move |upd| Box::pin(hook(upd))))
+    }
+
+    /// Tries to acquire room for a new update.
+    ///
+    /// Returns `None` if the update must not be processed, in which case it
+    /// should be passed to [`OverflowPolicy::reject`].
+    pub(crate) async fn acquire(
+        &self,
+        semaphore: &Arc<Semaphore>,
+        tickets: &mut Tickets,
+    ) -> Option<OwnedSemaphorePermit> {
+        if let Ok(permit) = Arc::clone(semaphore).try_acquire_owned() {
+            return Some(permit);
+        }
+
+        match self {
+            Self::Block => {}
+            Self::DropNewest | Self::Hook(_) => return None,
+            Self::DropOldest => {
+                if let Some(dropped) = tickets.cancel_oldest() {
+                    log::warn!("Dropping update {} due to an overflow", dropped.0);
+                }
+            }
+        }
+
+        Some(Arc::clone(semaphore).acquire_owned().await.expect("The semaphore is never closed"))
+    }
+
+    /// Handles an update rejected by [`OverflowPolicy::acquire`].
+    pub(crate) async fn reject(&self, update: Update) {
+        match self {
+            Self::Hook(hook) => hook(update).await,
+            _ => log::warn!("Dropping update {} due to an overflow", update.id.0),
+        }
+    }
+}
+
+impl fmt::Debug for OverflowPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Block => f.write_str("Block"),
+            Self::DropNewest => f.write_str("DropNewest"),
+            Self::DropOldest => f.write_str("DropOldest"),
+            Self::Hook(_) => f.write_str("Hook(..)"),
+        }
+    }
+}
+
+/// Room taken by an update which is waiting to be processed.
+pub(crate) struct Ticket {
+    update_id: UpdateId,
+    permits: Mutex<Option<Permits>>,
+}
+
+struct Permits {
+    // Room in the worker queue, released when processing starts.
+    queue: OwnedSemaphorePermit,
+    // Room among in-flight updates, released when processing ends.
+    in_flight: Option<OwnedSemaphorePermit>,
+}
+
+impl Ticket {
+    pub(crate) fn new(
+        update_id: UpdateId,
+        queue: OwnedSemaphorePermit,
+        in_flight: Option<OwnedSemaphorePermit>,
+    ) -> Arc<Self> {
+        Arc::new(Self { update_id, permits: Mutex::new(Some(Permits { queue, in_flight })) })
+    }
+
+    /// Marks the update as being processed, releasing its room in the queue.
+    ///
+    /// Returns `None` if the update was dropped, otherwise the returned permit
+    /// must be held until the update is processed.
+    pub(crate) fn start(&self) -> Option<Option<OwnedSemaphorePermit>> {
+        let Permits { queue, in_flight } = self.permits.lock().unwrap().take()?;
+        drop(queue);
+        Some(in_flight)
+    }
+
+    /// Returns `true` if the update is neither being processed nor dropped.
+    pub(crate) fn is_pending(&self) -> bool {
+        self.permits.lock().unwrap().is_some()
+    }
+
+    fn cancel(&self) -> bool {
+        self.permits.lock().unwrap().take().is_some()
+    }
+}
+
+/// Tickets of updates waiting to be processed, from the oldest to the newest.
+#[derive(Default)]
+pub(crate) struct Tickets(VecDeque<Weak<Ticket>>);
+
+impl Tickets {
+    pub(crate) fn push(&mut self, ticket: &Arc<Ticket>) {
+        // Updates are mostly processed in order, so removing processed ones from
+        // the front keeps the queue short.
+        while self.0.front().is_some_and(|t| !t.upgrade().is_some_and(|t| t.is_pending())) {
+            self.0.pop_front();
+        }
+
+        self.0.push_back(Arc::downgrade(ticket));
+    }
+
+    fn cancel_oldest(&mut self) -> Option<UpdateId> {
+        while let Some(ticket) = self.0.pop_front() {
+            if let Some(ticket) = ticket.upgrade().filter(|t| t.cancel()) {
+                return Some(ticket.update_id);
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn drop_oldest() {
+        let semaphore = Arc::new(Semaphore::new(2));
+        let mut tickets = Tickets::default();
+        let policy = OverflowPolicy::DropOldest;
+
+        let mut queued = vec![];
+        for id in 0..3 {
+            let permit = policy.acquire(&semaphore, &mut tickets).await.unwrap();
+            let ticket = Ticket::new(UpdateId(id), permit, None);
+            tickets.push(&ticket);
+            queued.push(ticket);
+        }
+
+        assert!(queued[0].start().is_none());
+        assert!(queued[1].start().is_some());
+        assert!(queued[2].start().is_some());
+        assert_eq!(semaphore.available_permits(), 2);
+    }
+
+    #[tokio::test]
+    async fn drop_newest() {
+        let semaphore = Arc::new(Semaphore::new(1));
+        let mut tickets = Tickets::default();
+        let policy = OverflowPolicy::DropNewest;
+
+        let permit = policy.acquire(&semaphore, &mut tickets).await.unwrap();
+        assert!(policy.acquire(&semaphore, &mut tickets).await.is_none());
+
+        drop(permit);
+        assert!(policy.acquire(&semaphore, &mut tickets).await.is_some());
+    }
+}
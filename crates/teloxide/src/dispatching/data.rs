@@ -0,0 +1,364 @@
+//! Per-user and per-chat data.
+//!
+//! Besides dialogue states, most bots keep some data about users and chats,
+//! such as a language or notification preferences. [`UserData`] and
+//! [`ChatData`] are handles for such data, like [`Dialogue`] is a handle for
+//! a dialogue state. The data is kept in any dialogue [`Storage`], e.g.
+//! [`SqliteStorage`] or [`RedisStorage`], with any [`Serializer`].
+//!
+//! Per-user and per-chat data are kept in two storages, since users and their
+//! private chats have the same IDs. The storages are put into dependencies
+//! wrapped in [`DataStorage`], so that they don't clash with a dialogue
+//! storage of the same type, and the handles are passed to handlers by
+//! [`enter_user_data`] and [`enter_chat_data`]. To keep data apart from
+//! dialogues in one database, open the storages with different
+//! [`StorageNamespace`]s.
+//!
+//! ## Examples
+//!
+//! ```no_run
+//! use teloxide::{
+//!     dispatching::{
+//!         data::{DataStorage, UserData},
+//!         dialogue::InMemStorage,
+//!         HandlerExt,
+//!     },
+//!     prelude::*,
+//! };
+//!
+//! #[derive(Clone, Default)]
+//! struct Settings {
+//!     language: Option<String>,
+//! }
+//!
+//! type SettingsStorage = InMemStorage<Settings>;
+//! type UserSettings = UserData<Settings, SettingsStorage>;
+//!
+//! # async fn run() {
+//! let bot = Bot::from_env();
+//!
+//! let handler = Update::filter_message().enter_user_data::<Settings, SettingsStorage>().endpoint(
+//!     |bot: Bot, settings: UserSettings, msg: Message| async move {
+//!         let language = settings.get_or_default().await?.language;
+//!         bot.send_message(msg.chat.id, format!("Your language: {language:?}")).await?;
+//!         settings.update(|s| s.language = Some("en".to_owned())).await?;
+//!         Ok::<_, Box<dyn std::error::Error + Send + Sync>>(())
+//!     },
+//! );
+//!
+//! Dispatcher::builder(bot, handler)
+//!     .dependencies(dptree::deps![DataStorage::<Settings, SettingsStorage>::new(
+//!         InMemStorage::new(),
+//!         InMemStorage::new(),
+//!     )])
+//!     .build()
+//!     .dispatch()
+//!     .await;
+//! # }
+//! ```
+//!
+//! With persistent storages, e.g.
+//! `SqliteStorage::open_with_namespace(path, Json,
+//! StorageNamespace::new().name("user_settings"))` and the same with
+//! `"chat_settings"`, the settings survive restarts of the bot.
+//!
+//! [`Dialogue`]: crate::dispatching::dialogue::Dialogue
+//! [`SqliteStorage`]: crate::dispatching::dialogue::SqliteStorage
+//! [`RedisStorage`]: crate::dispatching::dialogue::RedisStorage
+//! [`Serializer`]: crate::dispatching::dialogue::Serializer
+//! [`StorageNamespace`]: crate::dispatching::dialogue::StorageNamespace
+
+use std::{fmt::Debug, marker::PhantomData, sync::Arc};
+
+use dptree::{di::DependencyMap, Handler};
+use teloxide_core::types::{ChatId, Update, UserId};
+
+use crate::dispatching::{
+    dialogue::{GetChatId, Storage},
+    DpHandlerDescription,
+};
+
+/// Storages of data of type `D`, put into dependencies for
+/// [`enter_user_data`] and [`enter_chat_data`].
+///
+/// Per-user data is kept under the ID of the user, which is the same as the
+/// ID of the private chat with the user, so per-user and per-chat data are
+/// kept in separate storages.
+#[derive(Debug)]
+pub struct DataStorage<D, S>
+where
+    S: ?Sized,
+{
+    users: Arc<S>,
+    chats: Arc<S>,
+    _phantom: PhantomData<fn() -> D>,
+}
+
+impl<D, S> DataStorage<D, S>
+where
+    S: ?Sized,
+{
+    /// Wraps dialogue storages to keep per-user and per-chat data of type `D`.
+    ///
+    /// The storages must not share keys, e.g. they may be opened with
+    /// different [`StorageNamespace`]s.
+    ///
+    /// [`StorageNamespace`]: crate::dispatching::dialogue::StorageNamespace
+    #[must_use]
+    pub fn new(users: Arc<S>, chats: Arc<S>) -> Arc<Self> {
+        Arc::new(Self { users, chats, _phantom: PhantomData })
+    }
+
+    /// Returns the storage of per-user data.
+    #[must_use]
+    pub fn users(&self) -> &Arc<S> {
+        &self.users
+    }
+
+    /// Returns the storage of per-chat data.
+    #[must_use]
+    pub fn chats(&self) -> &Arc<S> {
+        &self.chats
+    }
+}
+
+macro_rules! data_handle {
+    (
+        $(#[$meta:meta])* $name:ident in $storage:ident,
+        $id:ident: $Id:ident, |$key_id:ident| $key:expr
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug)]
+        pub struct $name<D, S>
+        where
+            S: ?Sized,
+        {
+            storage: Arc<DataStorage<D, S>>,
+            $id: $Id,
+        }
+
+        // `#[derive]` requires generics to implement `Clone`, but they are wrapped
+        // around `Arc`.
+        impl<D, S> Clone for $name<D, S>
+        where
+            S: ?Sized,
+        {
+            fn clone(&self) -> Self {
+                Self { storage: Arc::clone(&self.storage), $id: self.$id }
+            }
+        }
+
+        impl<D, S> $name<D, S>
+        where
+            D: Send + 'static,
+            S: Storage<D> + ?Sized,
+        {
+            #[doc = concat!("Constructs a handle for the data of `", stringify!($id), "`.")]
+            #[must_use]
+            pub fn new(storage: Arc<DataStorage<D, S>>, $id: $Id) -> Self {
+                Self { storage, $id }
+            }
+
+            #[doc = concat!("Returns `", stringify!($id), "` of this handle.")]
+            #[must_use]
+            pub fn $id(&self) -> $Id {
+                self.$id
+            }
+
+            /// Retrieves the data or `None` if there is no data.
+            pub async fn get(&self) -> Result<Option<D>, S::Error> {
+                self.storage.$storage.clone().get_dialogue(self.key()).await
+            }
+
+            /// Like `get`, but returns a default value if there is no data.
+            ///
+            /// Unlike [`Dialogue::get_or_default`], the default value is not
+            /// stored.
+            ///
+            /// [`Dialogue::get_or_default`]: crate::dispatching::dialogue::Dialogue::get_or_default
+            pub async fn get_or_default(&self) -> Result<D, S::Error>
+            where
+                D: Default,
+            {
+                Ok(self.get().await?.unwrap_or_default())
+            }
+
+            /// Replaces the data with `data`.
+            pub async fn set(&self, data: D) -> Result<(), S::Error> {
+                self.storage.$storage.clone().update_dialogue(self.key(), data).await
+            }
+
+            /// Changes the data (or a default value if there is no data) with
+            /// `f` and stores the result.
+            ///
+            /// Note that concurrent updates of the same data may overwrite each
+            /// other.
+            pub async fn update<F>(&self, f: F) -> Result<(), S::Error>
+            where
+                D: Default,
+                F: FnOnce(&mut D),
+            {
+                let mut data = self.get_or_default().await?;
+                f(&mut data);
+                self.set(data).await
+            }
+
+            /// Removes the data from the storage.
+            ///
+            /// Results in an error if there is no data.
+            pub async fn remove(&self) -> Result<(), S::Error> {
+                self.storage.$storage.clone().remove_dialogue(self.key()).await
+            }
+
+            fn key(&self) -> ChatId {
+                let $key_id = self.$id;
+                $key
+            }
+        }
+    };
+}
+
+data_handle! {
+    /// A handle for data of a user.
+    ///
+    /// See the [module-level documentation](self).
+    UserData in users, user_id: UserId, |user_id| ChatId::from(user_id)
+}
+
+data_handle! {
+    /// A handle for data of a chat.
+    ///
+    /// See the [module-level documentation](self).
+    ChatData in chats, chat_id: ChatId, |chat_id| chat_id
+}
+
+/// Passes [`UserData`] of the user who sent an update to handlers.
+///
+/// Updates without a user (e.g. channel posts) are not passed further.
+///
+/// A call to this function is the same as
+/// `dptree::entry().enter_user_data()`.
+///
+/// See [`HandlerExt::enter_user_data`].
+///
+/// ## Dependency requirements
+///
+///  - `Arc<DataStorage<D, S>>`
+///  - [`Update`]
+///
+/// [`HandlerExt::enter_user_data`]: super::HandlerExt::enter_user_data
+#[must_use]
+pub fn enter_user_data<D, S, Output>(
+) -> Handler<'static, DependencyMap, Output, DpHandlerDescription>
+where
+    S: Storage<D> + ?Sized + Send + Sync + 'static,
+    D: Send + Sync + 'static,
+    Output: Send + Sync + 'static,
+{
+    dptree::filter_map(|storage: Arc<DataStorage<D, S>>, upd: Update| {
+        let user_id = upd.from()?.id;
+        Some(UserData::new(storage, user_id))
+    })
+}
+
+/// Passes [`ChatData`] of the chat of an update to handlers.
+///
+/// Updates without a chat are not passed further.
+///
+/// A call to this function is the same as
+/// `dptree::entry().enter_chat_data()`.
+///
+/// See [`HandlerExt::enter_chat_data`].
+///
+/// ## Dependency requirements
+///
+///  - `Arc<DataStorage<D, S>>`
+///  - `Upd`
+///
+/// [`HandlerExt::enter_chat_data`]: super::HandlerExt::enter_chat_data
+#[must_use]
+pub fn enter_chat_data<Upd, D, S, Output>(
+) -> Handler<'static, DependencyMap, Output, DpHandlerDescription>
+where
+    S: Storage<D> + ?Sized + Send + Sync + 'static,
+    D: Send + Sync + 'static,
+    Upd: GetChatId + Clone + Send + Sync + 'static,
+    Output: Send + Sync + 'static,
+{
+    dptree::filter_map(|storage: Arc<DataStorage<D, S>>, upd: Upd| {
+        let chat_id = upd.chat_id()?;
+        Some(ChatData::new(storage, chat_id))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use dptree::deps;
+
+    use super::*;
+    use crate::{dispatching::dialogue::InMemStorage, test_utils::text_message};
+
+    #[derive(Clone, Debug, Default, PartialEq)]
+    struct Settings {
+        language: Option<String>,
+    }
+
+    type Storage = InMemStorage<Settings>;
+
+    #[tokio::test]
+    async fn handles() {
+        let storage = DataStorage::<Settings, _>::new(Storage::new(), Storage::new());
+        let user = UserData::new(Arc::clone(&storage), UserId(1));
+        let chat = ChatData::new(Arc::clone(&storage), ChatId(-1));
+
+        assert_eq!(user.get().await.unwrap(), None);
+        assert_eq!(user.get_or_default().await.unwrap(), Settings::default());
+        assert_eq!(user.get().await.unwrap(), None);
+
+        user.update(|s| s.language = Some("en".to_owned())).await.unwrap();
+        chat.set(Settings { language: Some("de".to_owned()) }).await.unwrap();
+        assert_eq!(user.get().await.unwrap().unwrap().language.as_deref(), Some("en"));
+        assert_eq!(chat.get().await.unwrap().unwrap().language.as_deref(), Some("de"));
+
+        user.remove().await.unwrap();
+        assert_eq!(user.get().await.unwrap(), None);
+        assert!(user.remove().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn private_chat() {
+        let storage = DataStorage::<Settings, _>::new(Storage::new(), Storage::new());
+        // The private chat with a user has the same ID as the user
+        let user = UserData::new(Arc::clone(&storage), UserId(1));
+        let chat = ChatData::new(Arc::clone(&storage), ChatId(1));
+
+        user.set(Settings { language: Some("en".to_owned()) }).await.unwrap();
+        chat.set(Settings { language: Some("de".to_owned()) }).await.unwrap();
+        assert_eq!(user.get().await.unwrap().unwrap().language.as_deref(), Some("en"));
+        assert_eq!(chat.get().await.unwrap().unwrap().language.as_deref(), Some("de"));
+
+        chat.remove().await.unwrap();
+        assert_eq!(user.get().await.unwrap().unwrap().language.as_deref(), Some("en"));
+    }
+
+    #[tokio::test]
+    async fn injection() {
+        let storage = DataStorage::<Settings, _>::new(Storage::new(), Storage::new());
+        let handler = enter_user_data::<Settings, Storage, _>()
+            .chain(enter_chat_data::<Update, Settings, Storage, _>())
+            .endpoint(
+                |user: UserData<Settings, Storage>, chat: ChatData<Settings, Storage>| async move {
+                    user.update(|s| s.language = Some(user.user_id().to_string())).await.unwrap();
+                    chat.update(|s| s.language = Some(chat.chat_id().to_string())).await.unwrap();
+                },
+            );
+
+        let res = handler.dispatch(deps![Arc::clone(&storage), text_message(-2, 3, "hi")]).await;
+        assert!(res.is_break());
+
+        let user = UserData::new(Arc::clone(&storage), UserId(3));
+        let chat = ChatData::new(storage, ChatId(-2));
+        assert_eq!(user.get().await.unwrap().unwrap().language.as_deref(), Some("3"));
+        assert_eq!(chat.get().await.unwrap().unwrap().language.as_deref(), Some("-2"));
+    }
+}
@@ -99,9 +99,15 @@ pub use self::{RedisStorage, RedisStorageError};
 #[cfg(any(feature = "sqlite-storage-nativetls", feature = "sqlite-storage-rustls"))]
 pub use self::{SqliteStorage, SqliteStorageError};
 
-#[cfg(feature = "postgres-storage-nativetls")]
+#[cfg(any(feature = "postgres-storage-nativetls", feature = "postgres-storage-rustls"))]
 pub use self::{PostgresStorage, PostgresStorageError};
 
+#[cfg(any(feature = "mysql-storage-nativetls", feature = "mysql-storage-rustls"))]
+pub use self::{MysqlStorage, MysqlStorageError};
+
+#[cfg(feature = "redb-storage")]
+pub use self::{RedbStorage, RedbStorageError};
+
 pub use get_chat_id::GetChatId;
 pub use storage::*;
 
@@ -201,6 +207,56 @@ where
     pub async fn exit(&self) -> Result<(), S::Error> {
         self.storage.clone().remove_dialogue(self.chat_id).await
     }
+
+    /// Changes the dialogue state with `f`, which is called with the current
+    /// state or `None` if there is no dialogue.
+    ///
+    /// The new state is stored only if the dialogue has not been changed
+    /// concurrently (e.g. by another instance of the bot) since it was read.
+    /// Otherwise, `f` is called again with the changed state, up to 10 times.
+    ///
+    /// See [`CasStorage`].
+    pub async fn transition<F, State>(&self, mut f: F) -> Result<(), TransitionError<S::Error>>
+    where
+        S: CasStorage<D>,
+        F: FnMut(Option<D>) -> State,
+        D: From<State>,
+    {
+        const MAX_ATTEMPTS: u32 = 10;
+
+        for _ in 0..MAX_ATTEMPTS {
+            let (dialogue, version) = self
+                .storage
+                .clone()
+                .get_dialogue_versioned(self.chat_id)
+                .await
+                .map_err(TransitionError::Storage)?
+                .unzip();
+
+            let new_version = self
+                .storage
+                .clone()
+                .update_dialogue_if_version(self.chat_id, version, f(dialogue).into())
+                .await
+                .map_err(TransitionError::Storage)?;
+            if new_version.is_some() {
+                return Ok(());
+            }
+        }
+
+        Err(TransitionError::Conflict)
+    }
+}
+
+/// An error returned from [`Dialogue::transition`].
+#[derive(Debug, thiserror::Error)]
+pub enum TransitionError<E> {
+    #[error("storage error: {0}")]
+    Storage(E),
+
+    /// The dialogue was changed concurrently too many times in a row.
+    #[error("the dialogue was changed concurrently too many times")]
+    Conflict,
 }
 
 /// Enters a dialogue context.
@@ -1,5 +1,6 @@
 pub mod serializer;
 
+mod cached_storage;
 mod in_mem_storage;
 mod trace_storage;
 
@@ -9,27 +10,71 @@ mod redis_storage;
 #[cfg(any(feature = "sqlite-storage-nativetls", feature = "sqlite-storage-rustls"))]
 mod sqlite_storage;
 
-#[cfg(feature = "postgres-storage-nativetls")]
+#[cfg(any(feature = "postgres-storage-nativetls", feature = "postgres-storage-rustls"))]
 mod postgres_storage;
 
-use futures::future::BoxFuture;
+#[cfg(any(feature = "mysql-storage-nativetls", feature = "mysql-storage-rustls"))]
+mod mysql_storage;
+
+#[cfg(feature = "redb-storage")]
+mod redb_storage;
+
+#[cfg(any(
+    feature = "redis-storage",
+    feature = "sqlite-storage-nativetls",
+    feature = "sqlite-storage-rustls",
+    feature = "postgres-storage-nativetls",
+    feature = "postgres-storage-rustls",
+    feature = "mysql-storage-nativetls",
+    feature = "mysql-storage-rustls",
+    feature = "redb-storage"
+))]
+mod namespace;
+
+use futures::{
+    future::{self, BoxFuture},
+    stream::BoxStream,
+    TryStreamExt,
+};
 use teloxide_core::types::ChatId;
 
 pub use self::{
+    cached_storage::CachedStorage,
     in_mem_storage::{InMemStorage, InMemStorageError},
     trace_storage::TraceStorage,
 };
 
 #[cfg(feature = "redis-storage")]
-pub use redis_storage::{RedisStorage, RedisStorageError};
+pub use redis_storage::{RedisPool, RedisPoolConfig, RedisStorage, RedisStorageError};
 pub use serializer::Serializer;
-use std::sync::Arc;
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 #[cfg(any(feature = "sqlite-storage-nativetls", feature = "sqlite-storage-rustls"))]
-pub use sqlite_storage::{SqliteStorage, SqliteStorageError};
+pub use sqlite_storage::{SqlitePool, SqliteStorage, SqliteStorageError};
+
+#[cfg(any(feature = "postgres-storage-nativetls", feature = "postgres-storage-rustls"))]
+pub use postgres_storage::{PgPool, PgPoolOptions, PostgresStorage, PostgresStorageError};
 
-#[cfg(feature = "postgres-storage-nativetls")]
-pub use postgres_storage::{PostgresStorage, PostgresStorageError};
+#[cfg(any(feature = "mysql-storage-nativetls", feature = "mysql-storage-rustls"))]
+pub use mysql_storage::{MySqlPool, MySqlPoolOptions, MysqlStorage, MysqlStorageError};
+
+#[cfg(feature = "redb-storage")]
+pub use redb_storage::{RedbStorage, RedbStorageError};
+
+#[cfg(any(
+    feature = "redis-storage",
+    feature = "sqlite-storage-nativetls",
+    feature = "sqlite-storage-rustls",
+    feature = "postgres-storage-nativetls",
+    feature = "postgres-storage-rustls",
+    feature = "mysql-storage-nativetls",
+    feature = "mysql-storage-rustls",
+    feature = "redb-storage"
+))]
+pub use namespace::StorageNamespace;
 
 /// A storage with an erased error type.
 pub type ErasedStorage<D> =
@@ -40,8 +85,9 @@ pub type ErasedStorage<D> =
 /// You can implement this trait for a structure that communicates with a DB and
 /// be sure that after you restart your bot, all the dialogues won't be lost.
 ///
-/// `Storage` is used only to store dialogue states, i.e. it can't be used as a
-/// generic database.
+/// `Storage` keeps one value per chat, so besides dialogue states it can keep
+/// per-user and per-chat data, see [`dispatching::data`]. It can't be used as
+/// a generic database.
 ///
 /// Currently we support the following storages out of the box:
 ///
@@ -52,6 +98,7 @@ pub type ErasedStorage<D> =
 /// [`InMemStorage`]: crate::dispatching::dialogue::InMemStorage
 /// [`RedisStorage`]: crate::dispatching::dialogue::RedisStorage
 /// [`SqliteStorage`]: crate::dispatching::dialogue::SqliteStorage
+/// [`dispatching::data`]: crate::dispatching::data
 pub trait Storage<D> {
     type Error;
 
@@ -95,6 +142,186 @@ pub trait Storage<D> {
     }
 }
 
+/// A [`Storage`] which can enumerate its dialogues.
+///
+/// This is useful for administration tools, for example to count users who
+/// are in a particular state, to reset dialogues which are stuck in a state
+/// after a deploy, or to move all dialogues to another storage:
+///
+/// ```no_run
+/// # #[cfg(all(feature = "sqlite-storage-nativetls", feature = "postgres-storage-nativetls"))]
+/// # async fn f() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+/// use teloxide::dispatching::dialogue::{
+///     serializer::Json, EnumerableStorage, PostgresStorage, SqliteStorage,
+/// };
+///
+/// let sqlite = SqliteStorage::open("db.sqlite", Json).await?;
+/// let postgres = PostgresStorage::open("postgres://localhost/db", 4, Json).await?;
+///
+/// let dialogues = EnumerableStorage::<String>::export_dialogues(sqlite).await?;
+/// postgres.import_dialogues(dialogues).await?;
+/// # Ok(()) }
+/// ```
+///
+/// All built-in storages implement this trait.
+pub trait EnumerableStorage<D>: Storage<D> {
+    /// Returns a stream of all dialogues along with their chat IDs.
+    ///
+    /// Dialogues are fetched lazily, so dialogues which are updated while the
+    /// stream is consumed may be returned either with the old or the new
+    /// value, or not returned at all if they are added or removed.
+    #[must_use = "Streams are lazy and do nothing unless polled"]
+    fn dialogues(self: Arc<Self>) -> BoxStream<'static, Result<(ChatId, D), Self::Error>>;
+
+    /// Returns the number of dialogues.
+    #[must_use = "Futures are lazy and do nothing unless polled with .await"]
+    fn count_dialogues(self: Arc<Self>) -> BoxFuture<'static, Result<u64, Self::Error>>
+    where
+        D: Send + 'static,
+        Self::Error: Send + 'static,
+    {
+        Box::pin(self.dialogues().try_fold(0, |count, _| future::ready(Ok(count + 1))))
+    }
+
+    /// Removes all dialogues for which `predicate` returns `true`, returning
+    /// the number of removed dialogues.
+    ///
+    /// Stops at the first error, e.g. at a dialogue which cannot be
+    /// deserialized.
+    #[must_use = "Futures are lazy and do nothing unless polled with .await"]
+    fn remove_dialogues_where<F>(
+        self: Arc<Self>,
+        predicate: F,
+    ) -> BoxFuture<'static, Result<u64, Self::Error>>
+    where
+        F: Fn(ChatId, &D) -> bool + Send + 'static,
+        D: Send + 'static,
+        Self: Send + Sync + 'static,
+        Self::Error: Send + 'static,
+    {
+        Box::pin(async move {
+            let chat_ids: Vec<_> = Arc::clone(&self)
+                .dialogues()
+                .try_filter_map(move |(chat_id, dialogue)| {
+                    future::ready(Ok(predicate(chat_id, &dialogue).then_some(chat_id)))
+                })
+                .try_collect()
+                .await?;
+
+            for &chat_id in &chat_ids {
+                Arc::clone(&self).remove_dialogue(chat_id).await?;
+            }
+
+            Ok(chat_ids.len() as u64)
+        })
+    }
+
+    /// Returns all dialogues along with their chat IDs.
+    #[must_use = "Futures are lazy and do nothing unless polled with .await"]
+    #[allow(clippy::type_complexity)]
+    fn export_dialogues(
+        self: Arc<Self>,
+    ) -> BoxFuture<'static, Result<Vec<(ChatId, D)>, Self::Error>>
+    where
+        D: Send + 'static,
+        Self::Error: Send + 'static,
+    {
+        Box::pin(self.dialogues().try_collect())
+    }
+
+    /// Updates dialogues with `dialogues` (e.g. returned from
+    /// [`EnumerableStorage::export_dialogues`] of another storage), returning
+    /// the number of imported dialogues.
+    #[must_use = "Futures are lazy and do nothing unless polled with .await"]
+    fn import_dialogues(
+        self: Arc<Self>,
+        dialogues: Vec<(ChatId, D)>,
+    ) -> BoxFuture<'static, Result<u64, Self::Error>>
+    where
+        D: Send + 'static,
+        Self: Send + Sync + 'static,
+    {
+        Box::pin(async move {
+            let count = dialogues.len() as u64;
+            for (chat_id, dialogue) in dialogues {
+                Arc::clone(&self).update_dialogue(chat_id, dialogue).await?;
+            }
+
+            Ok(count)
+        })
+    }
+}
+
+/// A version of a dialogue in a [`CasStorage`].
+///
+/// Versions are opaque: a version changes with each update of a dialogue,
+/// including updates with [`Storage::update_dialogue`], and a dialogue which
+/// is removed and created again doesn't get its previous versions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DialogueVersion(pub(crate) i64);
+
+impl DialogueVersion {
+    /// Returns the version after `current`, which is the greater of the
+    /// current version plus one and the current time in microseconds.
+    pub(crate) fn next(current: Option<Self>) -> Self {
+        let now = Self::now();
+        match current {
+            Some(Self(current)) if current >= now => Self(current + 1),
+            _ => Self(now),
+        }
+    }
+
+    /// Returns the current time in microseconds.
+    pub(crate) fn now() -> i64 {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        now.as_micros() as i64
+    }
+}
+
+/// A [`Storage`] which supports optimistic concurrency control.
+///
+/// If several instances of a bot (e.g. replicas behind a load balancer)
+/// process updates from the same chat at the same time, they overwrite
+/// changes of each other with [`Storage::update_dialogue`]. Instead, a
+/// dialogue can be read along with its version and updated only if it has
+/// not changed since. [`Dialogue::transition`] does this, retrying on
+/// conflicts.
+///
+/// [`SqliteStorage`], [`PostgresStorage`], [`MysqlStorage`], [`RedisStorage`],
+/// [`RedbStorage`] and [`InMemStorage`] implement this trait.
+///
+/// [`Dialogue::transition`]: crate::dispatching::dialogue::Dialogue::transition
+/// [`SqliteStorage`]: crate::dispatching::dialogue::SqliteStorage
+/// [`PostgresStorage`]: crate::dispatching::dialogue::PostgresStorage
+/// [`MysqlStorage`]: crate::dispatching::dialogue::MysqlStorage
+/// [`RedisStorage`]: crate::dispatching::dialogue::RedisStorage
+/// [`RedbStorage`]: crate::dispatching::dialogue::RedbStorage
+pub trait CasStorage<D>: Storage<D> {
+    /// Returns the dialogue indexed by `chat_id` along with its version.
+    #[must_use = "Futures are lazy and do nothing unless polled with .await"]
+    #[allow(clippy::type_complexity)]
+    fn get_dialogue_versioned(
+        self: Arc<Self>,
+        chat_id: ChatId,
+    ) -> BoxFuture<'static, Result<Option<(D, DialogueVersion)>, Self::Error>>;
+
+    /// Updates the dialogue indexed by `chat_id` with `dialogue` if its
+    /// current version is `expected`, or if there is no dialogue and
+    /// `expected` is `None`.
+    ///
+    /// Returns the new version, or `None` if the dialogue has a different
+    /// version.
+    #[must_use = "Futures are lazy and do nothing unless polled with .await"]
+    fn update_dialogue_if_version(
+        self: Arc<Self>,
+        chat_id: ChatId,
+        expected: Option<DialogueVersion>,
+        dialogue: D,
+    ) -> BoxFuture<'static, Result<Option<DialogueVersion>, Self::Error>>
+    where
+        D: Send + 'static;
+}
+
 struct Eraser<S>(Arc<S>);
 
 impl<D, S> Storage<D> for Eraser<S>
@@ -142,6 +369,7 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::dispatching::dialogue::Dialogue;
 
     #[tokio::test]
     async fn test_erased() {
@@ -153,4 +381,60 @@ mod tests {
         Arc::clone(&erased).remove_dialogue(chat_id).await.unwrap();
         assert_eq!(Arc::clone(&erased).get_dialogue(chat_id).await.unwrap(), None);
     }
+
+    #[tokio::test]
+    async fn test_cas() {
+        let chat_id = ChatId(1);
+        let storage = InMemStorage::new();
+        assert_eq!(Arc::clone(&storage).get_dialogue_versioned(chat_id).await.unwrap(), None);
+
+        let update_if = |expected, dialogue| {
+            Arc::clone(&storage).update_dialogue_if_version(chat_id, expected, dialogue)
+        };
+        let v1 = update_if(None, 1).await.unwrap().unwrap();
+        assert_eq!(update_if(None, 2).await.unwrap(), None);
+        let v2 = update_if(Some(v1), 2).await.unwrap().unwrap();
+        assert_ne!(v1, v2);
+        assert_eq!(update_if(Some(v1), 3).await.unwrap(), None);
+
+        Arc::clone(&storage).update_dialogue(chat_id, 3).await.unwrap();
+        let (dialogue, v3) =
+            Arc::clone(&storage).get_dialogue_versioned(chat_id).await.unwrap().unwrap();
+        assert_eq!(dialogue, 3);
+        assert_ne!(v2, v3);
+
+        let mut calls = 0;
+        Dialogue::new(Arc::clone(&storage), chat_id)
+            .transition(|dialogue: Option<i32>| {
+                calls += 1;
+                if calls == 1 {
+                    // Changes the dialogue concurrently
+                    let update = Arc::clone(&storage).update_dialogue(chat_id, 10);
+                    futures::executor::block_on(update).unwrap();
+                }
+                dialogue.unwrap() + 1
+            })
+            .await
+            .unwrap();
+        assert_eq!(calls, 2);
+        assert_eq!(Arc::clone(&storage).get_dialogue(chat_id).await.unwrap(), Some(11));
+    }
+
+    #[tokio::test]
+    async fn test_enumerable() {
+        let storage = TraceStorage::new(InMemStorage::new());
+        let imported = Arc::clone(&storage)
+            .import_dialogues((0..10).map(|i| (ChatId(i), i * 10)).collect())
+            .await
+            .unwrap();
+        assert_eq!(imported, 10);
+        assert_eq!(Arc::clone(&storage).count_dialogues().await.unwrap(), 10);
+
+        let removed = Arc::clone(&storage).remove_dialogues_where(|_, &d| d >= 50).await.unwrap();
+        assert_eq!(removed, 5);
+
+        let mut dialogues = Arc::clone(&storage).export_dialogues().await.unwrap();
+        dialogues.sort();
+        assert_eq!(dialogues, (0..5).map(|i| (ChatId(i), i * 10)).collect::<Vec<_>>());
+    }
 }
@@ -0,0 +1,529 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Debug,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use futures::{
+    future::BoxFuture,
+    stream::{self, BoxStream},
+    StreamExt, TryStreamExt,
+};
+use teloxide_core::types::ChatId;
+
+use crate::dispatching::dialogue::{EnumerableStorage, Storage};
+
+/// A dialogue storage wrapper which caches dialogues of an underlying storage
+/// in memory.
+///
+/// The cache keeps up to `capacity` recently used dialogues, including the
+/// knowledge that a chat has no dialogue, so [`Storage::get_dialogue`] doesn't
+/// query the underlying storage for them. The least recently used dialogues
+/// are evicted from the cache.
+///
+/// By default, updated and removed dialogues are written through to the
+/// underlying storage right away. A storage created with
+/// [`CachedStorage::write_behind`] only changes the cache and writes changed
+/// dialogues to the underlying storage in batches, periodically. Changed
+/// dialogues are not evicted before they are written. Call
+/// [`CachedStorage::flush`] on shutdown to write the remaining ones, for
+/// example in [`DispatcherBuilder::on_shutdown`]:
+///
+/// ```no_run
+/// # #[cfg(feature = "sqlite-storage-nativetls")]
+/// # async fn f() {
+/// use std::time::Duration;
+/// use teloxide::{
+///     dispatching::dialogue::{serializer::Json, CachedStorage, SqliteStorage},
+///     prelude::*,
+/// };
+///
+/// # #[derive(Clone, Default, serde::Serialize, serde::Deserialize)] enum State { #[default] Start }
+/// let bot = Bot::from_env();
+/// let sqlite = SqliteStorage::open("db.sqlite", Json).await.unwrap();
+/// let storage = CachedStorage::<_, State>::write_behind(sqlite, 10_000, Duration::from_secs(5));
+///
+/// # let handler = dptree::entry().endpoint(|| async { Ok::<_, ()>(()) });
+/// Dispatcher::builder(bot, handler)
+///     .dependencies(dptree::deps![storage.clone()])
+///     .on_shutdown(move || {
+///         let storage = storage.clone();
+///         async move {
+///             if let Err(err) = storage.flush().await {
+///                 log::error!("Failed to flush dialogues: {err}");
+///             }
+///         }
+///     })
+///     .build()
+///     .dispatch()
+///     .await;
+/// # }
+/// ```
+///
+/// ## Consistency
+///
+/// The cache is consistent as long as all changes of the dialogues go through
+/// this storage: don't share the underlying storage with other instances of
+/// the bot or modify it directly. Since [`Dispatcher`] processes updates from
+/// the same chat sequentially (with the default distribution function),
+/// operations with the dialogue of a chat never race with each other.
+///
+/// With write-behind, changes which were not flushed are lost if the process
+/// crashes.
+///
+/// [`Dispatcher`]: crate::dispatching::Dispatcher
+/// [`DispatcherBuilder::on_shutdown`]: crate::dispatching::DispatcherBuilder::on_shutdown
+pub struct CachedStorage<S, D> {
+    inner: Arc<S>,
+    cache: Mutex<Lru<D>>,
+    write_behind: bool,
+    /// Serializes flushes, so a dialogue isn't written by two flushes at once.
+    flush_lock: tokio::sync::Mutex<()>,
+}
+
+impl<S, D> CachedStorage<S, D> {
+    /// Creates a storage which caches up to `capacity` dialogues and writes
+    /// changes through to `inner`.
+    #[must_use]
+    pub fn new(inner: Arc<S>, capacity: usize) -> Arc<Self> {
+        Arc::new(Self {
+            inner,
+            cache: Mutex::new(Lru::new(capacity)),
+            write_behind: false,
+            flush_lock: tokio::sync::Mutex::new(()),
+        })
+    }
+
+    /// Creates a storage which caches up to `capacity` dialogues and writes
+    /// changes to `inner` every `flush_interval`.
+    ///
+    /// Changed dialogues are kept in the cache until they are written, so the
+    /// cache may contain more than `capacity` dialogues.
+    ///
+    /// ## Panics
+    ///
+    /// If called outside of the Tokio runtime, or if `flush_interval` is zero.
+    #[must_use]
+    pub fn write_behind(inner: Arc<S>, capacity: usize, flush_interval: Duration) -> Arc<Self>
+    where
+        S: Storage<D> + Send + Sync + 'static,
+        D: Clone + Send + 'static,
+        <S as Storage<D>>::Error: Debug + Send,
+    {
+        let storage = Arc::new(Self {
+            inner,
+            cache: Mutex::new(Lru::new(capacity)),
+            write_behind: true,
+            flush_lock: tokio::sync::Mutex::new(()),
+        });
+
+        let weak = Arc::downgrade(&storage);
+        let mut interval = tokio::time::interval(flush_interval);
+        tokio::spawn(async move {
+            // The first tick completes immediately
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+
+                let Some(storage) = weak.upgrade() else { break };
+                if let Err(err) = storage.flush().await {
+                    log::error!("Failed to flush dialogues: {err:?}");
+                }
+            }
+        });
+
+        storage
+    }
+
+    /// Writes all changed dialogues to the underlying storage.
+    ///
+    /// Does nothing if the storage writes changes through. Concurrent calls
+    /// wait for each other. If some dialogues fail to be written, the rest are
+    /// written anyway, the failed ones are kept to be written by the next
+    /// flush, and the first error is returned.
+    pub async fn flush(&self) -> Result<(), <S as Storage<D>>::Error>
+    where
+        S: Storage<D> + Send + Sync + 'static,
+        D: Clone + Send + 'static,
+    {
+        let _guard = self.flush_lock.lock().await;
+
+        let dirty: Vec<_> = {
+            let cache = self.cache.lock().unwrap();
+            cache
+                .entries
+                .iter()
+                .filter_map(|(&chat_id, entry)| {
+                    Some((chat_id, entry.dialogue.clone(), entry.dirty?))
+                })
+                .collect()
+        };
+
+        let mut result = Ok(());
+        for (chat_id, dialogue, version) in dirty {
+            match write(&self.inner, chat_id, dialogue).await {
+                // The dialogue may have been changed again while it was being written
+                Ok(()) => self.cache.lock().unwrap().mark_written(chat_id, version),
+                Err(err) => {
+                    if result.is_ok() {
+                        result = Err(err);
+                    }
+                }
+            }
+        }
+
+        self.cache.lock().unwrap().shrink();
+        result
+    }
+}
+
+impl<S, D> Storage<D> for CachedStorage<S, D>
+where
+    S: Storage<D> + Send + Sync + 'static,
+    D: Clone + Send + 'static,
+{
+    type Error = <S as Storage<D>>::Error;
+
+    fn remove_dialogue(
+        self: Arc<Self>,
+        chat_id: ChatId,
+    ) -> BoxFuture<'static, Result<(), Self::Error>>
+    where
+        D: Send + 'static,
+    {
+        Box::pin(async move {
+            if self.write_behind {
+                let mut cache = self.cache.lock().unwrap();
+                if matches!(cache.get(chat_id), Some(Some(_))) {
+                    cache.insert(chat_id, None, true);
+                    return Ok(());
+                }
+            }
+
+            // The underlying storage returns the error if there is no dialogue
+            let result = Arc::clone(&self.inner).remove_dialogue(chat_id).await;
+            let mut cache = self.cache.lock().unwrap();
+            match result {
+                Ok(()) => cache.insert(chat_id, None, false),
+                Err(_) => cache.remove(chat_id),
+            }
+            result
+        })
+    }
+
+    fn update_dialogue(
+        self: Arc<Self>,
+        chat_id: ChatId,
+        dialogue: D,
+    ) -> BoxFuture<'static, Result<(), Self::Error>>
+    where
+        D: Send + 'static,
+    {
+        Box::pin(async move {
+            if self.write_behind {
+                self.cache.lock().unwrap().insert(chat_id, Some(dialogue), true);
+                return Ok(());
+            }
+
+            let result = Arc::clone(&self.inner).update_dialogue(chat_id, dialogue.clone()).await;
+            let mut cache = self.cache.lock().unwrap();
+            match result {
+                Ok(()) => cache.insert(chat_id, Some(dialogue), false),
+                Err(_) => cache.remove(chat_id),
+            }
+            result
+        })
+    }
+
+    fn get_dialogue(
+        self: Arc<Self>,
+        chat_id: ChatId,
+    ) -> BoxFuture<'static, Result<Option<D>, Self::Error>> {
+        Box::pin(async move {
+            if let Some(dialogue) = self.cache.lock().unwrap().get(chat_id) {
+                return Ok(dialogue.clone());
+            }
+
+            let dialogue = Arc::clone(&self.inner).get_dialogue(chat_id).await?;
+            self.cache.lock().unwrap().insert(chat_id, dialogue.clone(), false);
+            Ok(dialogue)
+        })
+    }
+}
+
+impl<S, D> EnumerableStorage<D> for CachedStorage<S, D>
+where
+    S: EnumerableStorage<D> + Send + Sync + 'static,
+    D: Clone + Send + 'static,
+    <S as Storage<D>>::Error: Send,
+{
+    /// Flushes changed dialogues and returns dialogues of the underlying
+    /// storage.
+    fn dialogues(self: Arc<Self>) -> BoxStream<'static, Result<(ChatId, D), Self::Error>> {
+        stream::once(async move {
+            self.flush().await?;
+            Ok(Arc::clone(&self.inner).dialogues())
+        })
+        .try_flatten()
+        .boxed()
+    }
+
+    fn count_dialogues(self: Arc<Self>) -> BoxFuture<'static, Result<u64, Self::Error>>
+    where
+        D: Send + 'static,
+        Self::Error: Send + 'static,
+    {
+        Box::pin(async move {
+            self.flush().await?;
+            Arc::clone(&self.inner).count_dialogues().await
+        })
+    }
+}
+
+async fn write<S, D>(inner: &Arc<S>, chat_id: ChatId, dialogue: Option<D>) -> Result<(), S::Error>
+where
+    S: Storage<D>,
+    D: Send + 'static,
+{
+    match dialogue {
+        Some(dialogue) => Arc::clone(inner).update_dialogue(chat_id, dialogue).await,
+        None => match Arc::clone(inner).remove_dialogue(chat_id).await {
+            // The dialogue may have not been written before it was removed
+            Err(err) => match Arc::clone(inner).get_dialogue(chat_id).await {
+                Ok(None) => Ok(()),
+                _ => Err(err),
+            },
+            Ok(()) => Ok(()),
+        },
+    }
+}
+
+/// A least recently used cache of dialogues, which never evicts changed
+/// dialogues.
+struct Lru<D> {
+    entries: HashMap<ChatId, Entry<D>>,
+    /// Chat IDs of unchanged dialogues, which can be evicted, by the time they
+    /// were used last.
+    evictable: BTreeMap<u64, ChatId>,
+    capacity: usize,
+    clock: u64,
+}
+
+struct Entry<D> {
+    /// `None` if the chat has no dialogue.
+    dialogue: Option<D>,
+    last_used: u64,
+    /// The time of the change which is not written to the underlying storage.
+    dirty: Option<u64>,
+}
+
+impl<D> Lru<D> {
+    fn new(capacity: usize) -> Self {
+        Self { entries: HashMap::new(), evictable: BTreeMap::new(), capacity, clock: 0 }
+    }
+
+    fn get(&mut self, chat_id: ChatId) -> Option<&Option<D>> {
+        self.clock += 1;
+        let entry = self.entries.get_mut(&chat_id)?;
+        if entry.dirty.is_none() {
+            self.evictable.remove(&entry.last_used);
+            self.evictable.insert(self.clock, chat_id);
+        }
+        entry.last_used = self.clock;
+        Some(&entry.dialogue)
+    }
+
+    fn insert(&mut self, chat_id: ChatId, dialogue: Option<D>, dirty: bool) {
+        self.clock += 1;
+        let dirty = dirty.then_some(self.clock);
+        let entry = Entry { dialogue, last_used: self.clock, dirty };
+        if let Some(old) = self.entries.insert(chat_id, entry) {
+            self.evictable.remove(&old.last_used);
+        }
+        if dirty.is_none() {
+            self.evictable.insert(self.clock, chat_id);
+        }
+        self.shrink();
+    }
+
+    fn remove(&mut self, chat_id: ChatId) {
+        if let Some(entry) = self.entries.remove(&chat_id) {
+            self.evictable.remove(&entry.last_used);
+        }
+    }
+
+    /// Marks the dialogue as unchanged if it wasn't changed since the change
+    /// `version` was written.
+    fn mark_written(&mut self, chat_id: ChatId, version: u64) {
+        if let Some(entry) = self.entries.get_mut(&chat_id) {
+            if entry.dirty == Some(version) {
+                entry.dirty = None;
+                self.evictable.insert(entry.last_used, chat_id);
+            }
+        }
+    }
+
+    /// Evicts the least recently used unchanged dialogues until the cache fits
+    /// into the capacity.
+    fn shrink(&mut self) {
+        while self.entries.len() > self.capacity {
+            let Some((_, chat_id)) = self.evictable.pop_first() else { break };
+            self.entries.remove(&chat_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dispatching::dialogue::{InMemStorage, InMemStorageError};
+
+    #[tokio::test]
+    async fn write_through() {
+        let inner = InMemStorage::new();
+        let storage = CachedStorage::new(Arc::clone(&inner), 2);
+
+        Arc::clone(&storage).update_dialogue(ChatId(1), 1).await.unwrap();
+        assert_eq!(Arc::clone(&inner).get_dialogue(ChatId(1)).await.unwrap(), Some(1));
+
+        // Changes which bypass the cache are not visible for cached dialogues
+        Arc::clone(&inner).update_dialogue(ChatId(1), 10).await.unwrap();
+        assert_eq!(Arc::clone(&storage).get_dialogue(ChatId(1)).await.unwrap(), Some(1));
+
+        // The absence of a dialogue is cached too
+        assert_eq!(Arc::clone(&storage).get_dialogue(ChatId(2)).await.unwrap(), None);
+        Arc::clone(&inner).update_dialogue(ChatId(2), 20).await.unwrap();
+        assert_eq!(Arc::clone(&storage).get_dialogue(ChatId(2)).await.unwrap(), None);
+
+        // Evicts the least recently used dialogue, i.e. of chat 2
+        assert_eq!(Arc::clone(&storage).get_dialogue(ChatId(1)).await.unwrap(), Some(1));
+        assert_eq!(Arc::clone(&storage).get_dialogue(ChatId(3)).await.unwrap(), None);
+        assert_eq!(Arc::clone(&storage).get_dialogue(ChatId(2)).await.unwrap(), Some(20));
+
+        Arc::clone(&storage).remove_dialogue(ChatId(2)).await.unwrap();
+        assert_eq!(Arc::clone(&inner).get_dialogue(ChatId(2)).await.unwrap(), None);
+        assert!(Arc::clone(&storage).remove_dialogue(ChatId(2)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn write_behind() {
+        let inner = InMemStorage::new();
+        let storage = CachedStorage::write_behind(Arc::clone(&inner), 1, Duration::from_secs(60));
+
+        Arc::clone(&inner).update_dialogue(ChatId(1), 1).await.unwrap();
+        for chat_id in [ChatId(1), ChatId(2), ChatId(3)] {
+            Arc::clone(&storage).update_dialogue(chat_id, 0).await.unwrap();
+        }
+        Arc::clone(&storage).remove_dialogue(ChatId(1)).await.unwrap();
+        // Removes a dialogue which is not written yet
+        Arc::clone(&storage).remove_dialogue(ChatId(3)).await.unwrap();
+
+        // Changed dialogues are not evicted
+        assert_eq!(Arc::clone(&storage).get_dialogue(ChatId(1)).await.unwrap(), None);
+        assert_eq!(Arc::clone(&storage).get_dialogue(ChatId(2)).await.unwrap(), Some(0));
+        assert_eq!(Arc::clone(&inner).get_dialogue(ChatId(1)).await.unwrap(), Some(1));
+        assert_eq!(Arc::clone(&inner).get_dialogue(ChatId(2)).await.unwrap(), None);
+
+        storage.flush().await.unwrap();
+        assert_eq!(storage.cache.lock().unwrap().entries.len(), 1);
+        let mut dialogues = Arc::clone(&inner).export_dialogues().await.unwrap();
+        dialogues.sort();
+        assert_eq!(dialogues, [(ChatId(2), 0)]);
+    }
+
+    #[tokio::test]
+    async fn concurrent_flushes() {
+        let inner = InMemStorage::new();
+        let storage = CachedStorage::write_behind(Arc::clone(&inner), 1, Duration::from_secs(60));
+
+        for chat_id in [ChatId(1), ChatId(2)] {
+            Arc::clone(&storage).update_dialogue(chat_id, 0).await.unwrap();
+            Arc::clone(&storage).remove_dialogue(chat_id).await.unwrap();
+        }
+        Arc::clone(&storage).update_dialogue(ChatId(3), 3).await.unwrap();
+
+        let (a, b) = tokio::join!(storage.flush(), storage.flush());
+        a.unwrap();
+        b.unwrap();
+        assert_eq!(Arc::clone(&inner).export_dialogues().await.unwrap(), [(ChatId(3), 3)]);
+    }
+
+    #[tokio::test]
+    async fn flush_after_failure() {
+        struct Failing(Arc<InMemStorage<i32>>);
+
+        impl Storage<i32> for Failing {
+            type Error = InMemStorageError;
+
+            fn remove_dialogue(
+                self: Arc<Self>,
+                chat_id: ChatId,
+            ) -> BoxFuture<'static, Result<(), Self::Error>> {
+                Arc::clone(&self.0).remove_dialogue(chat_id)
+            }
+
+            fn update_dialogue(
+                self: Arc<Self>,
+                chat_id: ChatId,
+                dialogue: i32,
+            ) -> BoxFuture<'static, Result<(), Self::Error>> {
+                if chat_id == ChatId(2) {
+                    return Box::pin(async { Err(InMemStorageError::DialogueNotFound) });
+                }
+                Arc::clone(&self.0).update_dialogue(chat_id, dialogue)
+            }
+
+            fn get_dialogue(
+                self: Arc<Self>,
+                chat_id: ChatId,
+            ) -> BoxFuture<'static, Result<Option<i32>, Self::Error>> {
+                Arc::clone(&self.0).get_dialogue(chat_id)
+            }
+        }
+
+        let inner = InMemStorage::new();
+        let storage = CachedStorage::write_behind(
+            Arc::new(Failing(Arc::clone(&inner))),
+            1,
+            Duration::from_secs(60),
+        );
+        for chat_id in [ChatId(1), ChatId(2), ChatId(3)] {
+            Arc::clone(&storage).update_dialogue(chat_id, chat_id.0 as i32).await.unwrap();
+        }
+
+        assert!(storage.flush().await.is_err());
+        let mut dialogues = Arc::clone(&inner).export_dialogues().await.unwrap();
+        dialogues.sort();
+        assert_eq!(dialogues, [(ChatId(1), 1), (ChatId(3), 3)]);
+
+        // The failed dialogue is still changed and is not evicted
+        assert_eq!(storage.cache.lock().unwrap().entries.len(), 1);
+        assert!(storage.cache.lock().unwrap().entries[&ChatId(2)].dirty.is_some());
+        assert!(storage.flush().await.is_err());
+    }
+
+    #[test]
+    fn lru_evicts_only_unchanged() {
+        let mut lru = Lru::new(2);
+        for id in 0..10 {
+            lru.insert(ChatId(id), Some(id), true);
+        }
+        lru.insert(ChatId(10), Some(10), false);
+        lru.insert(ChatId(11), Some(11), false);
+        assert_eq!(lru.entries.len(), 10);
+        assert!(lru.evictable.is_empty());
+
+        // A written dialogue becomes evictable, unless it was changed again
+        lru.insert(ChatId(0), Some(100), true);
+        let version = lru.entries[&ChatId(0)].dirty.unwrap();
+        lru.mark_written(ChatId(1), version);
+        assert!(lru.evictable.is_empty());
+        lru.mark_written(ChatId(0), version);
+        assert_eq!(lru.evictable.len(), 1);
+
+        lru.shrink();
+        assert_eq!(lru.entries.len(), 9);
+        assert!(!lru.entries.contains_key(&ChatId(0)));
+    }
+}
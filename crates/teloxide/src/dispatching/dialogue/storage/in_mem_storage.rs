@@ -1,5 +1,9 @@
-use super::Storage;
-use futures::future::BoxFuture;
+use super::{CasStorage, DialogueVersion, EnumerableStorage, Storage};
+use futures::{
+    future::BoxFuture,
+    stream::{self, BoxStream},
+    StreamExt,
+};
 use std::{collections::HashMap, sync::Arc};
 use teloxide_core::types::ChatId;
 use thiserror::Error;
@@ -21,7 +25,7 @@ pub enum InMemStorageError {
 /// [`super::SqliteStorage`] or implement your own.
 #[derive(Debug)]
 pub struct InMemStorage<D> {
-    map: Mutex<HashMap<ChatId, D>>,
+    map: Mutex<HashMap<ChatId, (D, DialogueVersion)>>,
 }
 
 impl<S> InMemStorage<S> {
@@ -63,7 +67,9 @@ where
         D: Send + 'static,
     {
         Box::pin(async move {
-            self.map.lock().await.insert(chat_id, dialogue);
+            let mut map = self.map.lock().await;
+            let version = DialogueVersion::next(map.get(&chat_id).map(|&(_, version)| version));
+            map.insert(chat_id, (dialogue, version));
             Ok(())
         })
     }
@@ -72,6 +78,62 @@ where
         self: Arc<Self>,
         chat_id: ChatId,
     ) -> BoxFuture<'static, Result<Option<D>, Self::Error>> {
-        Box::pin(async move { Ok(self.map.lock().await.get(&chat_id).map(ToOwned::to_owned)) })
+        Box::pin(async move { Ok(self.map.lock().await.get(&chat_id).map(|(d, _)| d.clone())) })
+    }
+}
+
+impl<D> EnumerableStorage<D> for InMemStorage<D>
+where
+    D: Clone,
+    D: Send + 'static,
+{
+    fn dialogues(self: Arc<Self>) -> BoxStream<'static, Result<(ChatId, D), Self::Error>> {
+        stream::once(async move {
+            let map = self.map.lock().await;
+            let dialogues: Vec<_> =
+                map.iter().map(|(&chat_id, (d, _))| Ok((chat_id, d.clone()))).collect();
+            stream::iter(dialogues)
+        })
+        .flatten()
+        .boxed()
+    }
+
+    fn count_dialogues(self: Arc<Self>) -> BoxFuture<'static, Result<u64, Self::Error>> {
+        Box::pin(async move { Ok(self.map.lock().await.len() as u64) })
+    }
+}
+
+impl<D> CasStorage<D> for InMemStorage<D>
+where
+    D: Clone,
+    D: Send + 'static,
+{
+    fn get_dialogue_versioned(
+        self: Arc<Self>,
+        chat_id: ChatId,
+    ) -> BoxFuture<'static, Result<Option<(D, DialogueVersion)>, Self::Error>> {
+        Box::pin(async move { Ok(self.map.lock().await.get(&chat_id).cloned()) })
+    }
+
+    fn update_dialogue_if_version(
+        self: Arc<Self>,
+        chat_id: ChatId,
+        expected: Option<DialogueVersion>,
+        dialogue: D,
+    ) -> BoxFuture<'static, Result<Option<DialogueVersion>, Self::Error>>
+    where
+        D: Send + 'static,
+    {
+        Box::pin(async move {
+            let mut map = self.map.lock().await;
+            let current = map.get(&chat_id).map(|&(_, version)| version);
+            if current != expected {
+                return Ok(None);
+            }
+
+            let version = DialogueVersion::next(current);
+            map.insert(chat_id, (dialogue, version));
+            Ok(Some(version))
+        })
     }
 }
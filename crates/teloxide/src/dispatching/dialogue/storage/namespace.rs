@@ -0,0 +1,447 @@
+#[cfg(any(
+    feature = "sqlite-storage-nativetls",
+    feature = "sqlite-storage-rustls",
+    feature = "postgres-storage-nativetls",
+    feature = "postgres-storage-rustls",
+    feature = "mysql-storage-nativetls",
+    feature = "mysql-storage-rustls"
+))]
+use futures::{
+    stream::{self, BoxStream},
+    StreamExt, TryStreamExt,
+};
+#[cfg(any(
+    feature = "redis-storage",
+    feature = "sqlite-storage-nativetls",
+    feature = "sqlite-storage-rustls",
+    feature = "postgres-storage-nativetls",
+    feature = "postgres-storage-rustls",
+    feature = "mysql-storage-nativetls",
+    feature = "mysql-storage-rustls"
+))]
+use teloxide_core::types::ChatId;
+use teloxide_core::types::UserId;
+
+/// Where a persistent storage keeps dialogues.
+///
+/// By default, [`RedisStorage`] keeps a dialogue under the bare chat ID as the
+/// key, and [`SqliteStorage`], [`PostgresStorage`], [`MysqlStorage`] and
+/// [`RedbStorage`] keep dialogues in the `teloxide_dialogues` table. So two
+/// bots, or two dialogue types, sharing a database overwrite each other. To
+/// separate them, open the storages with different namespaces:
+///
+///  - [`StorageNamespace::name`] is the prefix of Redis keys
+///    (`{name}:{chat_id}`) or the name of the SQL table,
+///  - [`StorageNamespace::bot_id`] adds the bot ID to Redis keys
+///    (`{name}:{bot_id}:{chat_id}`) or keeps dialogues of different bots in the
+///    same SQL table with a `bot_id` column. Such tables have a different
+///    schema, so the table name with a bot ID is `teloxide_bot_dialogues` by
+///    default and `{name}_bot` for a named namespace. [`RedbStorage`] keeps
+///    dialogues of each bot in a separate `{name}:{bot_id}` table.
+///
+/// ## Migration
+///
+/// Dialogues which were kept in the default namespace can be moved to another
+/// one with `migrate_from_default` of the storages, for example
+/// [`SqliteStorage::migrate_from_default`].
+///
+/// ## Examples
+///
+/// ```
+/// use teloxide::{dispatching::dialogue::StorageNamespace, types::UserId};
+///
+/// let namespace = StorageNamespace::new().name("registration").bot_id(UserId(1234567890));
+/// ```
+///
+/// [`RedisStorage`]: crate::dispatching::dialogue::RedisStorage
+/// [`SqliteStorage`]: crate::dispatching::dialogue::SqliteStorage
+/// [`PostgresStorage`]: crate::dispatching::dialogue::PostgresStorage
+/// [`MysqlStorage`]: crate::dispatching::dialogue::MysqlStorage
+/// [`RedbStorage`]: crate::dispatching::dialogue::RedbStorage
+/// [`SqliteStorage::migrate_from_default`]: crate::dispatching::dialogue::SqliteStorage::migrate_from_default
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct StorageNamespace {
+    name: Option<String>,
+    bot_id: Option<UserId>,
+}
+
+impl StorageNamespace {
+    /// The table which keeps dialogues by default.
+    #[cfg(any(
+        feature = "sqlite-storage-nativetls",
+        feature = "sqlite-storage-rustls",
+        feature = "postgres-storage-nativetls",
+        feature = "postgres-storage-rustls",
+        feature = "mysql-storage-nativetls",
+        feature = "mysql-storage-rustls",
+        feature = "redb-storage"
+    ))]
+    pub(crate) const DEFAULT_TABLE: &'static str = "teloxide_dialogues";
+
+    /// Returns the default namespace.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the name of the namespace.
+    ///
+    /// ## Panics
+    ///
+    /// If `name` is not a valid SQL identifier, i.e. if it is empty, starts
+    /// with a digit or contains characters other than ASCII letters, digits
+    /// and underscores.
+    #[must_use]
+    pub fn name(self, name: impl Into<String>) -> Self {
+        let name = name.into();
+        assert!(
+            name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'),
+            "Invalid storage namespace name: {name:?}"
+        );
+
+        Self { name: Some(name), ..self }
+    }
+
+    /// Sets the ID of the bot whose dialogues are kept in the namespace.
+    #[must_use]
+    pub fn bot_id(self, bot_id: UserId) -> Self {
+        Self { bot_id: Some(bot_id), ..self }
+    }
+
+    /// Returns `true` if this is the default namespace.
+    #[must_use]
+    pub fn is_default(&self) -> bool {
+        self.name.is_none() && self.bot_id.is_none()
+    }
+
+    /// Returns the Redis key of a dialogue.
+    #[cfg(feature = "redis-storage")]
+    pub(crate) fn redis_key(&self, ChatId(chat_id): ChatId) -> String {
+        format!("{}{chat_id}", self.redis_prefix())
+    }
+
+    /// Returns the Redis key of the version of a dialogue.
+    #[cfg(feature = "redis-storage")]
+    pub(crate) fn redis_version_key(&self, chat_id: ChatId) -> String {
+        format!("{}:version", self.redis_key(chat_id))
+    }
+
+    /// Returns the pattern which matches Redis keys of all dialogues (and
+    /// possibly other keys).
+    #[cfg(feature = "redis-storage")]
+    pub(crate) fn redis_pattern(&self) -> String {
+        format!("{}*", self.redis_prefix())
+    }
+
+    /// Returns the chat ID of a dialogue stored under `key`, if `key` is a
+    /// dialogue key of this namespace.
+    #[cfg(feature = "redis-storage")]
+    pub(crate) fn redis_chat_id(&self, key: &str) -> Option<ChatId> {
+        key.strip_prefix(&self.redis_prefix())?.parse().ok().map(ChatId)
+    }
+
+    #[cfg(feature = "redis-storage")]
+    fn redis_prefix(&self) -> String {
+        match (&self.name, self.bot_id) {
+            (None, None) => String::new(),
+            (Some(name), None) => format!("{name}:"),
+            (None, Some(bot_id)) => format!("{bot_id}:"),
+            (Some(name), Some(bot_id)) => format!("{name}:{bot_id}:"),
+        }
+    }
+
+    /// Returns the SQL table.
+    #[cfg(any(
+        feature = "sqlite-storage-nativetls",
+        feature = "sqlite-storage-rustls",
+        feature = "postgres-storage-nativetls",
+        feature = "postgres-storage-rustls",
+        feature = "mysql-storage-nativetls",
+        feature = "mysql-storage-rustls"
+    ))]
+    pub(crate) fn table(&self) -> String {
+        match (&self.name, self.bot_id) {
+            (None, None) => Self::DEFAULT_TABLE.to_owned(),
+            (None, Some(_)) => "teloxide_bot_dialogues".to_owned(),
+            (Some(name), None) => name.clone(),
+            // Tables with the `bot_id` column must not clash with tables without it
+            (Some(name), Some(_)) => format!("{name}_bot"),
+        }
+    }
+
+    /// Returns the name of the [redb] table.
+    ///
+    /// [redb]: https://www.redb.org/
+    #[cfg(feature = "redb-storage")]
+    pub(crate) fn redb_table(&self) -> String {
+        let name = self.name.as_deref().unwrap_or(Self::DEFAULT_TABLE);
+        match self.bot_id {
+            None => name.to_owned(),
+            Some(bot_id) => format!("{name}:{bot_id}"),
+        }
+    }
+
+    /// Returns the `bot_id` column value, if the SQL table has this column.
+    #[cfg(any(
+        feature = "sqlite-storage-nativetls",
+        feature = "sqlite-storage-rustls",
+        feature = "postgres-storage-nativetls",
+        feature = "postgres-storage-rustls",
+        feature = "mysql-storage-nativetls",
+        feature = "mysql-storage-rustls"
+    ))]
+    pub(crate) fn sql_bot_id(&self) -> Option<i64> {
+        self.bot_id.map(|UserId(id)| id as i64)
+    }
+}
+
+/// SQL queries of a dialogue table, shared by [`SqliteStorage`],
+/// [`PostgresStorage`] and [`MysqlStorage`].
+///
+/// `blob` is the type of the dialogue column, `param` makes the n-th query
+/// parameter and `quote` quotes identifiers, so that namespace names which are
+/// reserved words (e.g. `order`) can be used as table names.
+///
+/// The `version` column holds the [`DialogueVersion`] of a dialogue: each
+/// update sets it to the greater of the previous version plus one and the
+/// current time (passed as a parameter), so a dialogue which is removed and
+/// created again doesn't get the previous versions.
+///
+/// [`DialogueVersion`]: crate::dispatching::dialogue::DialogueVersion
+///
+/// [`SqliteStorage`]: crate::dispatching::dialogue::SqliteStorage
+/// [`PostgresStorage`]: crate::dispatching::dialogue::PostgresStorage
+/// [`MysqlStorage`]: crate::dispatching::dialogue::MysqlStorage
+#[cfg(any(
+    feature = "sqlite-storage-nativetls",
+    feature = "sqlite-storage-rustls",
+    feature = "postgres-storage-nativetls",
+    feature = "postgres-storage-rustls",
+    feature = "mysql-storage-nativetls",
+    feature = "mysql-storage-rustls"
+))]
+pub(crate) struct SqlQueries {
+    pub(crate) create: String,
+    pub(crate) get: String,
+    pub(crate) get_versioned: String,
+    pub(crate) update: String,
+    pub(crate) update_if_version: String,
+    pub(crate) insert_if_absent: String,
+    pub(crate) remove: String,
+    pub(crate) page: String,
+    pub(crate) count: String,
+    pub(crate) migrate: String,
+    pub(crate) delete_migrated: String,
+}
+
+#[cfg(any(
+    feature = "sqlite-storage-nativetls",
+    feature = "sqlite-storage-rustls",
+    feature = "postgres-storage-nativetls",
+    feature = "postgres-storage-rustls",
+    feature = "mysql-storage-nativetls",
+    feature = "mysql-storage-rustls"
+))]
+impl SqlQueries {
+    /// The number of dialogues fetched at once by the `page` query.
+    pub(crate) const PAGE_SIZE: i64 = 100;
+
+    pub(crate) fn new(
+        namespace: &StorageNamespace,
+        blob: &str,
+        param: fn(u8) -> String,
+        quote: char,
+    ) -> Self {
+        // Names are checked by `StorageNamespace::name`, so they don't contain quotes
+        let table = format!("{quote}{}{quote}", namespace.table());
+        let default = format!("{quote}{}{quote}", StorageNamespace::DEFAULT_TABLE);
+        let (p1, p2, p3, p4) = (param(1), param(2), param(3), param(4));
+
+        // The bot ID is an integer from the configuration, so it is safe to put it
+        // right into the queries
+        let (create, key, columns, values, bot_filter) = match namespace.sql_bot_id() {
+            None => (
+                format!(
+                    "CREATE TABLE IF NOT EXISTS {table} (\n    chat_id BIGINT PRIMARY KEY,\n    \
+                     dialogue {blob} NOT NULL,\n    version BIGINT NOT NULL DEFAULT 0\n)"
+                ),
+                "chat_id",
+                "chat_id, dialogue",
+                String::new(),
+                None,
+            ),
+            Some(bot_id) => (
+                format!(
+                    "CREATE TABLE IF NOT EXISTS {table} (\n    bot_id BIGINT NOT NULL,\n    \
+                     chat_id BIGINT NOT NULL,\n    dialogue {blob} NOT NULL,\n    version BIGINT \
+                     NOT NULL DEFAULT 0,\n    PRIMARY KEY (bot_id, chat_id)\n)"
+                ),
+                "bot_id, chat_id",
+                "bot_id, chat_id, dialogue",
+                format!("{bot_id}, "),
+                Some(format!("bot_id = {bot_id}")),
+            ),
+        };
+        let filter = bot_filter.as_ref().map(|f| format!("{f} AND ")).unwrap_or_default();
+        let next_version =
+            |version: &str| format!("CASE WHEN {version} >= {p3} THEN {version} + 1 ELSE {p3} END");
+
+        Self {
+            create,
+            get: format!("SELECT dialogue FROM {table} WHERE {filter}chat_id = {p1}"),
+            get_versioned: format!(
+                "SELECT dialogue, version FROM {table} WHERE {filter}chat_id = {p1}"
+            ),
+            update: format!(
+                "INSERT INTO {table} ({columns}, version) VALUES ({values}{p1}, {p2}, {p3})\nON \
+                 CONFLICT({key}) DO UPDATE SET dialogue=excluded.dialogue, version={}",
+                next_version(&format!("{table}.version"))
+            ),
+            update_if_version: format!(
+                "UPDATE {table} SET dialogue = {p2}, version = {} WHERE {filter}chat_id = {p1} \
+                 AND version = {p4} RETURNING version",
+                next_version("version")
+            ),
+            insert_if_absent: format!(
+                "INSERT INTO {table} ({columns}, version) VALUES ({values}{p1}, {p2}, {p3})\nON \
+                 CONFLICT({key}) DO NOTHING RETURNING version"
+            ),
+            remove: format!("DELETE FROM {table} WHERE {filter}chat_id = {p1}"),
+            page: format!(
+                "SELECT chat_id, dialogue FROM {table} WHERE {filter}chat_id > {p1} ORDER BY \
+                 chat_id LIMIT {p2}"
+            ),
+            count: match &bot_filter {
+                None => format!("SELECT COUNT(*) FROM {table}"),
+                Some(f) => format!("SELECT COUNT(*) FROM {table} WHERE {f}"),
+            },
+            // `WHERE true` resolves the parsing ambiguity of `ON CONFLICT` in SQLite
+            migrate: format!(
+                "INSERT INTO {table} ({columns}) SELECT {values}chat_id, dialogue FROM {default} \
+                 WHERE true\nON CONFLICT({key}) DO NOTHING"
+            ),
+            // Only dialogues which are in the namespace now are removed, so dialogues added
+            // to the default table after they were copied aren't lost
+            delete_migrated: match &bot_filter {
+                None => {
+                    format!("DELETE FROM {default} WHERE chat_id IN (SELECT chat_id FROM {table})")
+                }
+                Some(f) => format!(
+                    "DELETE FROM {default} WHERE chat_id IN (SELECT chat_id FROM {table} WHERE \
+                     {f})"
+                ),
+            },
+        }
+    }
+
+    /// Returns a stream of all dialogues of the table, deserialized with
+    /// `deserialize`.
+    ///
+    /// Dialogues are fetched with the `page` query in pages ordered by chat
+    /// IDs, starting after the last chat ID of the previous page.
+    pub(crate) fn dialogues<DB, D, E, F>(
+        &self,
+        pool: sqlx::Pool<DB>,
+        deserialize: F,
+    ) -> BoxStream<'static, Result<(ChatId, D), E>>
+    where
+        DB: sqlx::Database,
+        for<'c> &'c mut DB::Connection: sqlx::Executor<'c, Database = DB>,
+        for<'q> DB::Arguments<'q>: sqlx::IntoArguments<'q, DB>,
+        for<'q> i64: sqlx::Encode<'q, DB> + sqlx::Type<DB>,
+        for<'r> (i64, Vec<u8>): sqlx::FromRow<'r, DB::Row>,
+        D: Send + 'static,
+        E: From<sqlx::Error> + Send + 'static,
+        F: Fn(&[u8]) -> Result<D, E> + Send + Sync + 'static,
+    {
+        let state = (pool, self.page.clone(), deserialize, Some(i64::MIN));
+        stream::try_unfold(state, |(pool, query, deserialize, after)| async move {
+            let Some(after) = after else { return Ok(None) };
+
+            let rows: Vec<(i64, Vec<u8>)> =
+                sqlx::query_as(&query).bind(after).bind(Self::PAGE_SIZE).fetch_all(&pool).await?;
+
+            let next = match rows.last() {
+                Some(&(chat_id, _)) if rows.len() as i64 == Self::PAGE_SIZE => Some(chat_id),
+                _ => None,
+            };
+            let page: Vec<_> = rows
+                .into_iter()
+                .map(|(chat_id, d)| Ok((ChatId(chat_id), deserialize(&d)?)))
+                .collect();
+
+            Ok::<_, E>(Some((stream::iter(page), (pool, query, deserialize, next))))
+        })
+        .try_flatten()
+        .boxed()
+    }
+
+    /// Returns the queries in the MySQL dialect, which has neither `ON
+    /// CONFLICT` nor `RETURNING`.
+    ///
+    /// Parameters of `update_if_version` are the dialogue, the new version,
+    /// the chat ID and the expected version. `update_if_version` and
+    /// `insert_if_absent` don't return the version, so it must be computed by
+    /// the caller. `insert_if_absent` fails with a duplicate key error if
+    /// there is a dialogue.
+    #[cfg(any(feature = "mysql-storage-nativetls", feature = "mysql-storage-rustls"))]
+    pub(crate) fn mysql(namespace: &StorageNamespace) -> Self {
+        let table = format!("`{}`", namespace.table());
+        let default = format!("`{}`", StorageNamespace::DEFAULT_TABLE);
+        let (columns, values, filter, bot_filter) = match namespace.sql_bot_id() {
+            None => ("chat_id, dialogue", String::new(), String::new(), String::new()),
+            Some(bot_id) => (
+                "bot_id, chat_id, dialogue",
+                format!("{bot_id}, "),
+                format!("bot_id = {bot_id} AND "),
+                format!(" WHERE bot_id = {bot_id}"),
+            ),
+        };
+
+        Self {
+            // MySQL evaluates assignments from left to right, so `version` must be updated
+            // before it is replaced
+            update: format!(
+                "INSERT INTO {table} ({columns}, version) VALUES ({values}?, ?, ?) ON DUPLICATE \
+                 KEY UPDATE version = IF({table}.version >= VALUES(version), {table}.version + 1, \
+                 VALUES(version)), dialogue = VALUES(dialogue)"
+            ),
+            update_if_version: format!(
+                "UPDATE {table} SET dialogue = ?, version = ? WHERE {filter}chat_id = ? AND \
+                 version = ?"
+            ),
+            insert_if_absent: format!(
+                "INSERT INTO {table} ({columns}, version) VALUES ({values}?, ?, ?)"
+            ),
+            migrate: format!(
+                "INSERT INTO {table} ({columns}) SELECT {values}chat_id, dialogue FROM {default} \
+                 WHERE chat_id NOT IN (SELECT chat_id FROM {table}{bot_filter})"
+            ),
+            ..Self::new(namespace, "LONGBLOB", |_| "?".to_owned(), '`')
+        }
+    }
+}
+
+#[cfg(all(test, feature = "redis-storage"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redis_keys() {
+        let default = StorageNamespace::new();
+        assert_eq!(default.redis_key(ChatId(-42)), "-42");
+        assert_eq!(default.redis_pattern(), "*");
+        assert_eq!(default.redis_chat_id("-42"), Some(ChatId(-42)));
+        assert_eq!(default.redis_chat_id("registration:42"), None);
+
+        let namespace = StorageNamespace::new().name("registration").bot_id(UserId(1));
+        assert_eq!(namespace.redis_key(ChatId(42)), "registration:1:42");
+        assert_eq!(namespace.redis_pattern(), "registration:1:*");
+        assert_eq!(namespace.redis_chat_id("registration:1:42"), Some(ChatId(42)));
+        assert_eq!(namespace.redis_chat_id("registration:1:rate_limit"), None);
+        assert_eq!(namespace.redis_chat_id("registration:2:42"), None);
+        assert_eq!(namespace.redis_version_key(ChatId(42)), "registration:1:42:version");
+        assert_eq!(namespace.redis_chat_id("registration:1:42:version"), None);
+    }
+}
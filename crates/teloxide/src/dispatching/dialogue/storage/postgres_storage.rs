@@ -5,13 +5,17 @@ use std::{
     sync::Arc,
 };
 
-use futures::future::BoxFuture;
+use futures::{future::BoxFuture, stream::BoxStream};
 use serde::{de::DeserializeOwned, Serialize};
-use sqlx::postgres::{PgPool, PgPoolOptions};
+pub use sqlx::postgres::{PgPool, PgPoolOptions};
 use teloxide_core::types::ChatId;
 use thiserror::Error;
 
-use super::{serializer::Serializer, Storage};
+use super::{
+    namespace::{SqlQueries, StorageNamespace},
+    serializer::Serializer,
+    CasStorage, DialogueVersion, EnumerableStorage, Storage,
+};
 
 /// An error returned from [`PostgresStorage`].
 #[derive(Debug, Error)]
@@ -31,9 +35,14 @@ where
 }
 
 /// A persistent dialogue storage based on [PostgreSQL](https://www.postgresql.org/)
+///
+/// See [`StorageNamespace`] to keep dialogues of several bots or several
+/// dialogue types in one database.
 pub struct PostgresStorage<S> {
     pool: PgPool,
     serializer: S,
+    namespace: StorageNamespace,
+    queries: SqlQueries,
 }
 
 impl<S> PostgresStorage<S> {
@@ -56,14 +65,71 @@ impl<S> PostgresStorage<S> {
         database_url: &str,
         max_connections: u32,
         serializer: S,
+    ) -> Result<Arc<Self>, PostgresStorageError<Infallible>> {
+        Self::open_with_namespace(
+            database_url,
+            max_connections,
+            serializer,
+            StorageNamespace::default(),
+        )
+        .await
+    }
+
+    /// Opens a connection pool like [`PostgresStorage::open`], keeping
+    /// dialogues in `namespace`.
+    pub async fn open_with_namespace(
+        database_url: &str,
+        max_connections: u32,
+        serializer: S,
+        namespace: StorageNamespace,
     ) -> Result<Arc<Self>, PostgresStorageError<Infallible>> {
         let pool =
             PgPoolOptions::new().max_connections(max_connections).connect(database_url).await?;
-        sqlx::query(include_str!("postgres_storage/queries/create_teloxide_dialogues.sql"))
-            .execute(&pool)
-            .await?;
+        Self::open_with_pool(pool, serializer, namespace).await
+    }
 
-        Ok(Arc::new(Self { pool, serializer }))
+    /// Creates a storage which keeps dialogues in `namespace` of the database
+    /// of an existing connection pool, e.g. shared with the rest of the app.
+    ///
+    /// This also allows to configure the pool and connections, see
+    /// [`PgPoolOptions`] and [`PgConnectOptions`].
+    ///
+    /// [`PgConnectOptions`]: sqlx::postgres::PgConnectOptions
+    pub async fn open_with_pool(
+        pool: PgPool,
+        serializer: S,
+        namespace: StorageNamespace,
+    ) -> Result<Arc<Self>, PostgresStorageError<Infallible>> {
+        let queries = SqlQueries::new(&namespace, "BYTEA", |n| format!("${n}"), '"');
+        sqlx::query(&queries.create).execute(&pool).await?;
+        // Tables created before dialogue versions were introduced don't have the column
+        sqlx::query(&format!(
+            "ALTER TABLE \"{}\" ADD COLUMN IF NOT EXISTS version BIGINT NOT NULL DEFAULT 0",
+            namespace.table()
+        ))
+        .execute(&pool)
+        .await?;
+
+        Ok(Arc::new(Self { pool, serializer, namespace, queries }))
+    }
+
+    /// Moves dialogues from the default `teloxide_dialogues` table to the
+    /// namespace of this storage, returning the number of moved dialogues.
+    ///
+    /// Dialogues which already exist in the namespace are kept. Dialogues are
+    /// removed from the default table only if they are in the namespace after
+    /// the migration. The default table must exist.
+    pub async fn migrate_from_default(&self) -> Result<u64, PostgresStorageError<Infallible>> {
+        if self.namespace.table() == StorageNamespace::DEFAULT_TABLE {
+            return Ok(0);
+        }
+
+        let mut tx = self.pool.begin().await?;
+        let moved = sqlx::query(&self.queries.migrate).execute(&mut *tx).await?.rows_affected();
+        sqlx::query(&self.queries.delete_migrated).execute(&mut *tx).await?;
+        tx.commit().await?;
+
+        Ok(moved)
     }
 
     async fn get_dialogue(
@@ -75,13 +141,11 @@ impl<S> PostgresStorage<S> {
             dialogue: Vec<u8>,
         }
 
-        let bytes = sqlx::query_as::<_, DialogueDbRow>(include_str!(
-            "postgres_storage/queries/get_dialogue.sql"
-        ))
-        .bind(chat_id)
-        .fetch_optional(&self.pool)
-        .await?
-        .map(|r| r.dialogue);
+        let bytes = sqlx::query_as::<_, DialogueDbRow>(&self.queries.get)
+            .bind(chat_id)
+            .fetch_optional(&self.pool)
+            .await?
+            .map(|r| r.dialogue);
 
         Ok(bytes)
     }
@@ -106,12 +170,11 @@ where
         D: Send + 'static,
     {
         Box::pin(async move {
-            let deleted_rows_count =
-                sqlx::query(include_str!("postgres_storage/queries/remove_dialogue.sql"))
-                    .bind(chat_id)
-                    .execute(&self.pool)
-                    .await?
-                    .rows_affected();
+            let deleted_rows_count = sqlx::query(&self.queries.remove)
+                .bind(chat_id)
+                .execute(&self.pool)
+                .await?
+                .rows_affected();
 
             if deleted_rows_count == 0 {
                 return Err(PostgresStorageError::DialogueNotFound);
@@ -132,9 +195,10 @@ where
         Box::pin(async move {
             let d =
                 self.serializer.serialize(&dialogue).map_err(PostgresStorageError::SerdeError)?;
-            sqlx::query(include_str!("postgres_storage/queries/update_dialogue.sql"))
+            sqlx::query(&self.queries.update)
                 .bind(chat_id)
                 .bind(d)
+                .bind(DialogueVersion::now())
                 .execute(&self.pool)
                 .await?;
             Ok(())
@@ -154,3 +218,78 @@ where
         })
     }
 }
+
+impl<S, D> CasStorage<D> for PostgresStorage<S>
+where
+    S: Send + Sync + Serializer<D> + 'static,
+    D: Send + Serialize + DeserializeOwned + 'static,
+    <S as Serializer<D>>::Error: Debug + Display,
+{
+    fn get_dialogue_versioned(
+        self: Arc<Self>,
+        ChatId(chat_id): ChatId,
+    ) -> BoxFuture<'static, Result<Option<(D, DialogueVersion)>, Self::Error>> {
+        Box::pin(async move {
+            let row: Option<(Vec<u8>, i64)> = sqlx::query_as(&self.queries.get_versioned)
+                .bind(chat_id)
+                .fetch_optional(&self.pool)
+                .await?;
+
+            row.map(|(d, version)| {
+                let dialogue =
+                    self.serializer.deserialize(&d).map_err(PostgresStorageError::SerdeError)?;
+                Ok((dialogue, DialogueVersion(version)))
+            })
+            .transpose()
+        })
+    }
+
+    fn update_dialogue_if_version(
+        self: Arc<Self>,
+        ChatId(chat_id): ChatId,
+        expected: Option<DialogueVersion>,
+        dialogue: D,
+    ) -> BoxFuture<'static, Result<Option<DialogueVersion>, Self::Error>> {
+        Box::pin(async move {
+            let d =
+                self.serializer.serialize(&dialogue).map_err(PostgresStorageError::SerdeError)?;
+
+            let query = match expected {
+                Some(DialogueVersion(expected)) => sqlx::query_as(&self.queries.update_if_version)
+                    .bind(chat_id)
+                    .bind(d)
+                    .bind(DialogueVersion::now())
+                    .bind(expected),
+                None => sqlx::query_as(&self.queries.insert_if_absent)
+                    .bind(chat_id)
+                    .bind(d)
+                    .bind(DialogueVersion::now()),
+            };
+            let version: Option<(i64,)> = query.fetch_optional(&self.pool).await?;
+
+            Ok(version.map(|(version,)| DialogueVersion(version)))
+        })
+    }
+}
+
+impl<S, D> EnumerableStorage<D> for PostgresStorage<S>
+where
+    S: Send + Sync + Serializer<D> + 'static,
+    D: Send + Serialize + DeserializeOwned + 'static,
+    <S as Serializer<D>>::Error: Debug + Display + Send,
+{
+    fn dialogues(self: Arc<Self>) -> BoxStream<'static, Result<(ChatId, D), Self::Error>> {
+        let this = Arc::clone(&self);
+        self.queries.dialogues(self.pool.clone(), move |d| {
+            this.serializer.deserialize(d).map_err(PostgresStorageError::SerdeError)
+        })
+    }
+
+    fn count_dialogues(self: Arc<Self>) -> BoxFuture<'static, Result<u64, Self::Error>> {
+        Box::pin(async move {
+            let (count,): (i64,) =
+                sqlx::query_as(&self.queries.count).fetch_one(&self.pool).await?;
+            Ok(count as u64)
+        })
+    }
+}
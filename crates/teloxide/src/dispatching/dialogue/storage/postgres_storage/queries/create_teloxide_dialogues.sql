@@ -1,4 +0,0 @@
-CREATE TABLE IF NOT EXISTS teloxide_dialogues (
-    chat_id BIGINT PRIMARY KEY,
-    dialogue BYTEA NOT NULL
-)
\ No newline at end of file
@@ -1 +0,0 @@
-DELETE FROM teloxide_dialogues WHERE chat_id = $1
\ No newline at end of file
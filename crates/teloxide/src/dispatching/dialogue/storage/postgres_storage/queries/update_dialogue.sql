@@ -1,2 +0,0 @@
-INSERT INTO teloxide_dialogues VALUES ($1, $2)
-ON CONFLICT(chat_id) DO UPDATE SET dialogue=excluded.dialogue
\ No newline at end of file
@@ -0,0 +1,351 @@
+use super::{
+    namespace::StorageNamespace, serializer::Serializer, CasStorage, DialogueVersion,
+    EnumerableStorage, Storage,
+};
+use futures::{
+    future::BoxFuture,
+    stream::{self, BoxStream},
+    StreamExt, TryStreamExt,
+};
+use redb::{Database, ReadableTable, ReadableTableMetadata, TableDefinition};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    convert::Infallible,
+    fmt::{Debug, Display},
+    io,
+    ops::Bound,
+    panic,
+    path::Path,
+    sync::Arc,
+};
+use teloxide_core::types::ChatId;
+use thiserror::Error;
+
+/// A dialogue table: chat IDs to versions and serialized dialogues.
+type Table<'a> = TableDefinition<'a, i64, (i64, &'static [u8])>;
+
+/// An error returned from [`RedbStorage`].
+#[derive(Debug, Error)]
+pub enum RedbStorageError<SE>
+where
+    SE: Debug + Display,
+{
+    #[error("dialogue serialization error: {0}")]
+    SerdeError(SE),
+
+    #[error("redb error: {0}")]
+    RedbError(#[from] redb::Error),
+
+    /// Returned from [`RedbStorage::remove_dialogue`].
+    #[error("row not found")]
+    DialogueNotFound,
+}
+
+/// A persistent dialogue storage based on [redb](https://www.redb.org/), an
+/// embedded key-value database written in pure Rust.
+///
+/// Unlike [`SqliteStorage`], it needs neither a C library nor a database
+/// server, and keeps everything in a single file. The file can't be opened by
+/// several processes at once.
+///
+/// See [`StorageNamespace`] to keep dialogues of several bots or several
+/// dialogue types in one database.
+///
+/// [`SqliteStorage`]: crate::dispatching::dialogue::SqliteStorage
+pub struct RedbStorage<S> {
+    db: Arc<Database>,
+    serializer: S,
+    table: String,
+}
+
+impl<S> RedbStorage<S> {
+    /// Opens the database file at `path`, creating it if it doesn't exist.
+    pub async fn open(
+        path: impl AsRef<Path>,
+        serializer: S,
+    ) -> Result<Arc<Self>, RedbStorageError<Infallible>> {
+        Self::open_with_namespace(path, serializer, StorageNamespace::default()).await
+    }
+
+    /// Opens the database file like [`RedbStorage::open`], keeping dialogues in
+    /// `namespace`.
+    pub async fn open_with_namespace(
+        path: impl AsRef<Path>,
+        serializer: S,
+        namespace: StorageNamespace,
+    ) -> Result<Arc<Self>, RedbStorageError<Infallible>> {
+        let path = path.as_ref().to_owned();
+        let db = blocking(move || Ok(Database::create(path)?)).await?;
+
+        Self::open_with_database(Arc::new(db), serializer, namespace).await
+    }
+
+    /// Creates a storage which keeps dialogues in `namespace` of an already
+    /// opened database.
+    ///
+    /// A database file can be opened only once, so this is the way to use
+    /// several namespaces, or a database shared with other code. See also
+    /// [`RedbStorage::database`].
+    pub async fn open_with_database(
+        db: Arc<Database>,
+        serializer: S,
+        namespace: StorageNamespace,
+    ) -> Result<Arc<Self>, RedbStorageError<Infallible>> {
+        let table = namespace.redb_table();
+
+        blocking({
+            let (db, table) = (Arc::clone(&db), table.clone());
+            move || {
+                let tx = db.begin_write()?;
+                tx.open_table(Table::new(&table))?;
+                tx.commit()?;
+                Ok(())
+            }
+        })
+        .await?;
+
+        Ok(Arc::new(Self { db, serializer, table }))
+    }
+
+    /// Returns the database of this storage.
+    #[must_use]
+    pub fn database(&self) -> &Arc<Database> {
+        &self.db
+    }
+
+    /// Moves dialogues from the default `teloxide_dialogues` table to the
+    /// namespace of this storage, returning the number of moved dialogues.
+    ///
+    /// Dialogues which already exist in the namespace are kept.
+    pub async fn migrate_from_default(&self) -> Result<u64, RedbStorageError<Infallible>> {
+        if self.table == StorageNamespace::DEFAULT_TABLE {
+            return Ok(0);
+        }
+
+        let (db, table) = (Arc::clone(&self.db), self.table.clone());
+        let moved = blocking(move || {
+            let tx = db.begin_write()?;
+            let mut moved = 0;
+            {
+                let mut default = tx.open_table(Table::new(StorageNamespace::DEFAULT_TABLE))?;
+                let mut table = tx.open_table(Table::new(&table))?;
+                while let Some((chat_id, value)) = default.pop_first()? {
+                    let chat_id = chat_id.value();
+                    if table.get(chat_id)?.is_none() {
+                        table.insert(chat_id, value.value())?;
+                        moved += 1;
+                    }
+                }
+            }
+            tx.commit()?;
+            Ok(moved)
+        })
+        .await?;
+
+        Ok(moved)
+    }
+
+    fn get(&self, ChatId(chat_id): ChatId) -> Result<Option<(i64, Vec<u8>)>, redb::Error> {
+        let tx = self.db.begin_read()?;
+        let table = tx.open_table(Table::new(&self.table))?;
+        let value = table.get(chat_id)?.map(|value| {
+            let (version, dialogue) = value.value();
+            (version, dialogue.to_owned())
+        });
+        Ok(value)
+    }
+
+    /// Writes `dialogue` if the current version is `expected`, or
+    /// unconditionally if `expected` is `None`, returning the new version.
+    fn update(
+        &self,
+        ChatId(chat_id): ChatId,
+        expected: Option<Option<DialogueVersion>>,
+        dialogue: &[u8],
+    ) -> Result<Option<DialogueVersion>, redb::Error> {
+        let tx = self.db.begin_write()?;
+        let version = {
+            let mut table = tx.open_table(Table::new(&self.table))?;
+            let current = table.get(chat_id)?.map(|value| DialogueVersion(value.value().0));
+            if expected.is_some_and(|expected| expected != current) {
+                return Ok(None);
+            }
+
+            let version = DialogueVersion::next(current);
+            table.insert(chat_id, (version.0, dialogue))?;
+            version
+        };
+        tx.commit()?;
+        Ok(Some(version))
+    }
+}
+
+impl<S, D> Storage<D> for RedbStorage<S>
+where
+    S: Send + Sync + Serializer<D> + 'static,
+    D: Send + Serialize + DeserializeOwned + 'static,
+    <S as Serializer<D>>::Error: Debug + Display,
+{
+    type Error = RedbStorageError<<S as Serializer<D>>::Error>;
+
+    fn remove_dialogue(
+        self: Arc<Self>,
+        ChatId(chat_id): ChatId,
+    ) -> BoxFuture<'static, Result<(), Self::Error>> {
+        Box::pin(async move {
+            let removed = blocking(move || {
+                let tx = self.db.begin_write()?;
+                let removed = tx.open_table(Table::new(&self.table))?.remove(chat_id)?.is_some();
+                tx.commit()?;
+                Ok(removed)
+            })
+            .await?;
+
+            if !removed {
+                return Err(RedbStorageError::DialogueNotFound);
+            }
+
+            Ok(())
+        })
+    }
+
+    fn update_dialogue(
+        self: Arc<Self>,
+        chat_id: ChatId,
+        dialogue: D,
+    ) -> BoxFuture<'static, Result<(), Self::Error>> {
+        Box::pin(async move {
+            let d = self.serializer.serialize(&dialogue).map_err(RedbStorageError::SerdeError)?;
+            blocking(move || self.update(chat_id, None, &d)).await?;
+            Ok(())
+        })
+    }
+
+    fn get_dialogue(
+        self: Arc<Self>,
+        chat_id: ChatId,
+    ) -> BoxFuture<'static, Result<Option<D>, Self::Error>> {
+        Box::pin(async move {
+            let this = Arc::clone(&self);
+            blocking(move || this.get(chat_id))
+                .await?
+                .map(|(_, d)| self.serializer.deserialize(&d).map_err(RedbStorageError::SerdeError))
+                .transpose()
+        })
+    }
+}
+
+impl<S, D> CasStorage<D> for RedbStorage<S>
+where
+    S: Send + Sync + Serializer<D> + 'static,
+    D: Send + Serialize + DeserializeOwned + 'static,
+    <S as Serializer<D>>::Error: Debug + Display,
+{
+    fn get_dialogue_versioned(
+        self: Arc<Self>,
+        chat_id: ChatId,
+    ) -> BoxFuture<'static, Result<Option<(D, DialogueVersion)>, Self::Error>> {
+        Box::pin(async move {
+            let this = Arc::clone(&self);
+            blocking(move || this.get(chat_id))
+                .await?
+                .map(|(version, d)| {
+                    let dialogue =
+                        self.serializer.deserialize(&d).map_err(RedbStorageError::SerdeError)?;
+                    Ok((dialogue, DialogueVersion(version)))
+                })
+                .transpose()
+        })
+    }
+
+    fn update_dialogue_if_version(
+        self: Arc<Self>,
+        chat_id: ChatId,
+        expected: Option<DialogueVersion>,
+        dialogue: D,
+    ) -> BoxFuture<'static, Result<Option<DialogueVersion>, Self::Error>> {
+        Box::pin(async move {
+            let d = self.serializer.serialize(&dialogue).map_err(RedbStorageError::SerdeError)?;
+            let version = blocking(move || self.update(chat_id, Some(expected), &d)).await?;
+            Ok(version)
+        })
+    }
+}
+
+impl<S, D> EnumerableStorage<D> for RedbStorage<S>
+where
+    S: Send + Sync + Serializer<D> + 'static,
+    D: Send + Serialize + DeserializeOwned + 'static,
+    <S as Serializer<D>>::Error: Debug + Display + Send,
+{
+    fn dialogues(self: Arc<Self>) -> BoxStream<'static, Result<(ChatId, D), Self::Error>> {
+        const PAGE_SIZE: usize = 100;
+
+        // Reads dialogues in pages ordered by chat IDs, starting after the last chat
+        // ID of the previous page, so that a read transaction isn't kept open
+        stream::try_unfold((self, Some(Bound::Unbounded)), |(this, after)| async move {
+            let Some(after) = after else { return Ok(None) };
+
+            let rows = blocking({
+                let this = Arc::clone(&this);
+                move || {
+                    let tx = this.db.begin_read()?;
+                    let table = tx.open_table(Table::new(&this.table))?;
+                    let mut rows = Vec::with_capacity(PAGE_SIZE);
+                    for row in table.range((after, Bound::Unbounded))? {
+                        let (chat_id, value) = row?;
+                        rows.push((chat_id.value(), value.value().1.to_owned()));
+                        if rows.len() == PAGE_SIZE {
+                            break;
+                        }
+                    }
+                    Ok(rows)
+                }
+            })
+            .await?;
+
+            let next = match rows.last() {
+                Some(&(chat_id, _)) if rows.len() == PAGE_SIZE => Some(Bound::Excluded(chat_id)),
+                _ => None,
+            };
+            let page: Vec<_> = rows
+                .into_iter()
+                .map(|(chat_id, d)| {
+                    let dialogue =
+                        this.serializer.deserialize(&d).map_err(RedbStorageError::SerdeError)?;
+                    Ok((ChatId(chat_id), dialogue))
+                })
+                .collect();
+
+            Ok::<_, Self::Error>(Some((stream::iter(page), (this, next))))
+        })
+        .try_flatten()
+        .boxed()
+    }
+
+    fn count_dialogues(self: Arc<Self>) -> BoxFuture<'static, Result<u64, Self::Error>> {
+        Box::pin(async move {
+            let count = blocking(move || {
+                let tx = self.db.begin_read()?;
+                let count = tx.open_table(Table::new(&self.table))?.len()?;
+                Ok(count)
+            })
+            .await?;
+            Ok(count)
+        })
+    }
+}
+
+/// Runs a blocking database operation on a separate thread.
+async fn blocking<T, F>(f: F) -> Result<T, redb::Error>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, redb::Error> + Send + 'static,
+{
+    match tokio::task::spawn_blocking(f).await {
+        Ok(res) => res,
+        Err(err) if err.is_panic() => panic::resume_unwind(err.into_panic()),
+        // The task is cancelled if the runtime is shutting down
+        Err(err) => Err(redb::Error::Io(io::Error::other(err))),
+    }
+}
@@ -1,9 +1,18 @@
-use super::{serializer::Serializer, Storage};
+use super::{
+    namespace::StorageNamespace, serializer::Serializer, CasStorage, DialogueVersion,
+    EnumerableStorage, Storage,
+};
 use deadpool_redis::{redis, CreatePoolError, PoolError, Runtime};
-use futures::future::BoxFuture;
+pub use deadpool_redis::{Config as RedisPoolConfig, Pool as RedisPool};
+use futures::{
+    future::BoxFuture,
+    stream::{self, BoxStream},
+    StreamExt, TryStreamExt,
+};
 use redis::AsyncCommands;
 use serde::{de::DeserializeOwned, Serialize};
 use std::{
+    collections::HashSet,
     convert::Infallible,
     fmt::{Debug, Display},
     sync::Arc,
@@ -35,9 +44,13 @@ where
 }
 
 /// A dialogue storage based on [Redis](https://redis.io/).
+///
+/// See [`StorageNamespace`] to keep dialogues of several bots or several
+/// dialogue types in one database.
 pub struct RedisStorage<S> {
-    pool: deadpool_redis::Pool,
+    pool: RedisPool,
     serializer: S,
+    namespace: StorageNamespace,
 }
 
 impl<S> RedisStorage<S> {
@@ -45,13 +58,118 @@ impl<S> RedisStorage<S> {
         url: &str,
         serializer: S,
     ) -> Result<Arc<Self>, RedisStorageError<Infallible>> {
-        let config = deadpool_redis::Config::from_url(url);
+        Self::open_with_namespace(url, serializer, StorageNamespace::default()).await
+    }
+
+    /// Opens a connection pool, keeping dialogues in `namespace`.
+    pub async fn open_with_namespace(
+        url: &str,
+        serializer: S,
+        namespace: StorageNamespace,
+    ) -> Result<Arc<Self>, RedisStorageError<Infallible>> {
+        let config = RedisPoolConfig::from_url(url);
         let pool = config.create_pool(Some(Runtime::Tokio1))?;
 
-        Ok(Arc::new(Self { pool, serializer }))
+        Ok(Self::open_with_pool(pool, serializer, namespace))
+    }
+
+    /// Creates a storage which keeps dialogues in `namespace` of the database
+    /// of an existing connection pool, e.g. shared with the rest of the app.
+    #[must_use]
+    pub fn open_with_pool(
+        pool: RedisPool,
+        serializer: S,
+        namespace: StorageNamespace,
+    ) -> Arc<Self> {
+        Arc::new(Self { pool, serializer, namespace })
+    }
+
+    /// Moves dialogues from the default namespace (keys which are bare chat
+    /// IDs) to the namespace of this storage, returning the number of moved
+    /// dialogues.
+    ///
+    /// Only keys which hold a dialogue of type `D`, i.e. which values can be
+    /// deserialized by the serializer of this storage, are moved, so other
+    /// keys which happen to be integers are left intact. Dialogues which
+    /// already exist in the namespace are kept.
+    pub async fn migrate_from_default<D>(&self) -> Result<u64, RedisStorageError<Infallible>>
+    where
+        S: Serializer<D>,
+    {
+        const MGET_COUNT: usize = 100;
+
+        if self.namespace.is_default() {
+            return Ok(0);
+        }
+
+        let mut conn = self.pool.get().await?;
+
+        let mut chat_ids = Vec::new();
+        let mut keys = conn.scan::<String>().await?;
+        while let Some(key) = keys.next_item().await {
+            if let Ok(chat_id) = key.parse() {
+                chat_ids.push(ChatId(chat_id));
+            }
+        }
+        drop(keys);
+
+        // `MGET` returns `nil` for keys which aren't strings
+        let mut dialogues = Vec::new();
+        for chunk in chat_ids.chunks(MGET_COUNT) {
+            let values: Vec<Option<Vec<u8>>> = redis::cmd("MGET")
+                .arg(chunk.iter().map(|&ChatId(chat_id)| chat_id).collect::<Vec<_>>())
+                .query_async(&mut conn)
+                .await?;
+
+            dialogues.extend(chunk.iter().zip(values).filter_map(|(&chat_id, value)| {
+                self.serializer.deserialize(&value?).ok().map(|_: D| chat_id)
+            }));
+        }
+
+        let mut moved = 0;
+        for chat_id in dialogues {
+            let renamed: bool = redis::cmd("RENAMENX")
+                .arg(chat_id.0)
+                .arg(self.namespace.redis_key(chat_id))
+                .query_async(&mut conn)
+                .await?;
+
+            if renamed {
+                moved += 1;
+            } else {
+                () = conn.del(chat_id.0).await?;
+            }
+            () = conn.del(StorageNamespace::default().redis_version_key(chat_id)).await?;
+        }
+
+        Ok(moved)
     }
 }
 
+/// Sets the dialogue `KEYS[1]` to `ARGV[1]` and its version `KEYS[2]` to the
+/// greater of the previous version plus one and the current time `ARGV[2]`,
+/// returning the new version, but only if the current version is `ARGV[3]` (an
+/// empty string if there is no dialogue). Dialogues without a version have
+/// version 0.
+const UPDATE_IF_VERSION_SCRIPT: &str = r"
+local version = redis.call('GET', KEYS[2])
+if redis.call('EXISTS', KEYS[1]) == 0 then
+    version = ''
+elseif not version then
+    version = '0'
+end
+if version ~= ARGV[3] then
+    return false
+end
+if version ~= '' and tonumber(version) >= tonumber(ARGV[2]) then
+    redis.call('INCR', KEYS[2])
+else
+    redis.call('SET', KEYS[2], ARGV[2])
+end
+redis.call('SET', KEYS[1], ARGV[1])
+return redis.call('GET', KEYS[2])
+";
+
 impl<S, D> Storage<D> for RedisStorage<S>
 where
     S: Send + Sync + Serializer<D> + 'static,
@@ -62,13 +180,20 @@ where
 
     fn remove_dialogue(
         self: Arc<Self>,
-        ChatId(chat_id): ChatId,
+        chat_id: ChatId,
     ) -> BoxFuture<'static, Result<(), Self::Error>> {
         Box::pin(async move {
             let mut conn = self.pool.get().await?;
 
-            let deleted_rows_count =
-                redis::pipe().atomic().del(chat_id).query_async(&mut conn).await?;
+            let key = self.namespace.redis_key(chat_id);
+            let version_key = self.namespace.redis_version_key(chat_id);
+            let deleted_rows_count = redis::pipe()
+                .atomic()
+                .del(key)
+                .del(version_key)
+                .ignore()
+                .query_async(&mut conn)
+                .await?;
 
             if let redis::Value::Array(values) = deleted_rows_count {
                 // False positive
@@ -87,29 +212,150 @@ where
 
     fn update_dialogue(
         self: Arc<Self>,
-        ChatId(chat_id): ChatId,
+        chat_id: ChatId,
         dialogue: D,
     ) -> BoxFuture<'static, Result<(), Self::Error>> {
         Box::pin(async move {
             let dialogue =
                 self.serializer.serialize(&dialogue).map_err(RedisStorageError::SerdeError)?;
-            () = self.pool.get().await?.set::<_, Vec<u8>, _>(chat_id, dialogue).await?;
+            // Versions are only compared for equality, so the current time is a new
+            // version unless another update happens in the same microsecond
+            () = redis::pipe()
+                .atomic()
+                .set(self.namespace.redis_key(chat_id), dialogue)
+                .ignore()
+                .set(self.namespace.redis_version_key(chat_id), DialogueVersion::now())
+                .ignore()
+                .query_async(&mut self.pool.get().await?)
+                .await?;
             Ok(())
         })
     }
 
     fn get_dialogue(
         self: Arc<Self>,
-        ChatId(chat_id): ChatId,
+        chat_id: ChatId,
     ) -> BoxFuture<'static, Result<Option<D>, Self::Error>> {
         Box::pin(async move {
             self.pool
                 .get()
                 .await?
-                .get::<_, Option<Vec<u8>>>(chat_id)
+                .get::<_, Option<Vec<u8>>>(self.namespace.redis_key(chat_id))
                 .await?
                 .map(|d| self.serializer.deserialize(&d).map_err(RedisStorageError::SerdeError))
                 .transpose()
         })
     }
 }
+
+impl<S, D> CasStorage<D> for RedisStorage<S>
+where
+    S: Send + Sync + Serializer<D> + 'static,
+    D: Send + Serialize + DeserializeOwned + 'static,
+    <S as Serializer<D>>::Error: Debug + Display,
+{
+    fn get_dialogue_versioned(
+        self: Arc<Self>,
+        chat_id: ChatId,
+    ) -> BoxFuture<'static, Result<Option<(D, DialogueVersion)>, Self::Error>> {
+        Box::pin(async move {
+            let (dialogue, version): (Option<Vec<u8>>, Option<i64>) = redis::cmd("MGET")
+                .arg(self.namespace.redis_key(chat_id))
+                .arg(self.namespace.redis_version_key(chat_id))
+                .query_async(&mut self.pool.get().await?)
+                .await?;
+
+            dialogue
+                .map(|d| {
+                    let dialogue =
+                        self.serializer.deserialize(&d).map_err(RedisStorageError::SerdeError)?;
+                    Ok((dialogue, DialogueVersion(version.unwrap_or(0))))
+                })
+                .transpose()
+        })
+    }
+
+    fn update_dialogue_if_version(
+        self: Arc<Self>,
+        chat_id: ChatId,
+        expected: Option<DialogueVersion>,
+        dialogue: D,
+    ) -> BoxFuture<'static, Result<Option<DialogueVersion>, Self::Error>> {
+        Box::pin(async move {
+            let dialogue =
+                self.serializer.serialize(&dialogue).map_err(RedisStorageError::SerdeError)?;
+            let expected = expected.map(|DialogueVersion(v)| v.to_string()).unwrap_or_default();
+
+            let version: Option<i64> = redis::cmd("EVAL")
+                .arg(UPDATE_IF_VERSION_SCRIPT)
+                .arg(2)
+                .arg(self.namespace.redis_key(chat_id))
+                .arg(self.namespace.redis_version_key(chat_id))
+                .arg(dialogue)
+                .arg(DialogueVersion::now())
+                .arg(expected)
+                .query_async(&mut self.pool.get().await?)
+                .await?;
+
+            Ok(version.map(DialogueVersion))
+        })
+    }
+}
+
+impl<S, D> EnumerableStorage<D> for RedisStorage<S>
+where
+    S: Send + Sync + Serializer<D> + 'static,
+    D: Send + Serialize + DeserializeOwned + 'static,
+    <S as Serializer<D>>::Error: Debug + Display + Send,
+{
+    /// Scans keys of the namespace, so the stream may be slow if the database
+    /// contains a lot of other keys. Note that in the default namespace all
+    /// keys which are integers are considered dialogues.
+    fn dialogues(self: Arc<Self>) -> BoxStream<'static, Result<(ChatId, D), Self::Error>> {
+        const SCAN_COUNT: usize = 100;
+
+        // `SCAN` may return a key more than once, so returned chat IDs are remembered
+        let state = (self, Some(0u64), HashSet::new());
+        stream::try_unfold(state, |(this, cursor, mut seen)| async move {
+            let Some(cursor) = cursor else { return Ok(None) };
+
+            let mut conn = this.pool.get().await?;
+            let (cursor, keys): (u64, Vec<String>) = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(this.namespace.redis_pattern())
+                .arg("COUNT")
+                .arg(SCAN_COUNT)
+                .query_async(&mut conn)
+                .await?;
+
+            let (chat_ids, keys): (Vec<_>, Vec<_>) = keys
+                .into_iter()
+                .filter_map(|key| Some((this.namespace.redis_chat_id(&key)?, key)))
+                .filter(|&(chat_id, _)| seen.insert(chat_id))
+                .unzip();
+            let values: Vec<Option<Vec<u8>>> = if keys.is_empty() {
+                Vec::new()
+            } else {
+                redis::cmd("MGET").arg(keys).query_async(&mut conn).await?
+            };
+
+            // A dialogue may be removed after `SCAN`
+            let page: Vec<_> = chat_ids
+                .into_iter()
+                .zip(values)
+                .filter_map(|(chat_id, d)| Some((chat_id, d?)))
+                .map(|(chat_id, d)| {
+                    let dialogue =
+                        this.serializer.deserialize(&d).map_err(RedisStorageError::SerdeError)?;
+                    Ok((chat_id, dialogue))
+                })
+                .collect();
+
+            let cursor = (cursor != 0).then_some(cursor);
+            Ok::<_, Self::Error>(Some((stream::iter(page), (this, cursor, seen))))
+        })
+        .try_flatten()
+        .boxed()
+    }
+}
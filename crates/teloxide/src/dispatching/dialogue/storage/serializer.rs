@@ -1,5 +1,10 @@
 //! Various serializers for dialogue storages.
 
+use std::{
+    borrow::Cow,
+    fmt::{Debug, Display},
+};
+
 use serde::{de::DeserializeOwned, ser::Serialize};
 
 /// A serializer for memory storages.
@@ -71,3 +76,279 @@ where
         bincode::deserialize(data)
     }
 }
+
+/// A serializer which tags dialogues with a version and migrates dialogues of
+/// older versions.
+///
+/// Once you change the dialogue type, dialogues which were serialized before
+/// cannot be deserialized anymore. `Versioned` stores the version of the
+/// dialogue type along with the serialized dialogue and keeps migrations from
+/// each version to the next one: the first [`Versioned::migration`] migrates
+/// from version 0 to version 1, the second from version 1 to version 2, and
+/// so on. The current version is the number of migrations. Dialogues which
+/// were serialized without `Versioned` have version 0.
+///
+/// Dialogues are migrated when they are read, and written with the current
+/// version when they are updated.
+///
+/// By default, a dialogue which cannot be deserialized (or has a version
+/// newer than the current one) is an error. Use
+/// [`Versioned::reset_invalid_state`] to reset such dialogues to the default
+/// value or [`Versioned::on_invalid_state`] to decide what to do in a hook.
+///
+/// ## Examples
+///
+/// ```
+/// use serde::{Deserialize, Serialize};
+/// use teloxide::dispatching::dialogue::serializer::{Json, Serializer, Versioned};
+///
+/// // The dialogue type as it was before
+/// #[derive(Serialize, Deserialize)]
+/// enum StateV0 {
+///     Start,
+///     ReceiveName,
+/// }
+///
+/// #[derive(Clone, Default, Serialize, Deserialize, PartialEq, Debug)]
+/// enum State {
+///     #[default]
+///     Start,
+///     ReceiveName {
+///         attempts: u32,
+///     },
+/// }
+///
+/// let serializer = Versioned::new(Json)
+///     .migration(|old: StateV0| match old {
+///         StateV0::Start => State::Start,
+///         StateV0::ReceiveName => State::ReceiveName { attempts: 0 },
+///     })
+///     .reset_invalid_state();
+///
+/// let state: State = serializer.deserialize(br#""ReceiveName""#).unwrap();
+/// assert_eq!(state, State::ReceiveName { attempts: 0 });
+/// ```
+///
+/// ## Format
+///
+/// The serialized dialogue is prefixed with the bytes `FF 54 58 56` and the
+/// version as a little-endian `u32`. Data without this prefix has version 0.
+pub struct Versioned<D, S>
+where
+    S: Serializer<D>,
+{
+    serializer: S,
+    migrations: Vec<Migration<S, <S as Serializer<D>>::Error>>,
+    on_invalid_state: Option<InvalidStateHook<D, <S as Serializer<D>>::Error>>,
+}
+
+type Migration<S, E> = Box<dyn Fn(&S, &[u8]) -> Result<Vec<u8>, E> + Send + Sync>;
+
+type InvalidStateHook<D, E> = Box<dyn Fn(&[u8], &VersionedError<E>) -> Option<D> + Send + Sync>;
+
+const VERSION_TAG: [u8; 4] = [0xFF, b'T', b'X', b'V'];
+
+/// An error returned from [`Versioned`].
+#[derive(Debug, thiserror::Error)]
+pub enum VersionedError<E> {
+    #[error("dialogue serialization error: {0}")]
+    Serializer(E),
+
+    /// The dialogue has a version which is newer than the current one.
+    #[error("unknown dialogue version {version}, the current version is {current}")]
+    UnknownVersion { version: u32, current: u32 },
+
+    /// The dialogue cannot be deserialized to be migrated from `version`.
+    #[error("failed to migrate a dialogue from version {version}: {error}")]
+    Migration { version: u32, error: E },
+}
+
+impl<D, S> Versioned<D, S>
+where
+    S: Serializer<D>,
+{
+    /// Creates a serializer of version 0 on top of `serializer`.
+    #[must_use]
+    pub fn new(serializer: S) -> Self {
+        Self { serializer, migrations: Vec::new(), on_invalid_state: None }
+    }
+
+    /// Adds a migration from the current version to the next one.
+    ///
+    /// `Old` is the dialogue type of the current version, and `New` is the
+    /// dialogue type of the next version, so `New` of the last migration is
+    /// `D`.
+    #[must_use]
+    pub fn migration<Old, New, F>(mut self, f: F) -> Self
+    where
+        S: Serializer<Old, Error = <S as Serializer<D>>::Error>
+            + Serializer<New, Error = <S as Serializer<D>>::Error>,
+        F: Fn(Old) -> New + Send + Sync + 'static,
+    {
+        self.migrations.push(Box::new(move |serializer: &S, data: &[u8]| {
+            let old = Serializer::<Old>::deserialize(serializer, data)?;
+            Serializer::<New>::serialize(serializer, &f(old))
+        }));
+        self
+    }
+
+    /// Resets dialogues which cannot be deserialized to the default value.
+    #[must_use]
+    pub fn reset_invalid_state(self) -> Self
+    where
+        D: Default,
+        <S as Serializer<D>>::Error: Debug + Display,
+    {
+        self.on_invalid_state(|_, error| {
+            log::warn!("Resetting the dialogue which cannot be deserialized: {error}");
+            Some(D::default())
+        })
+    }
+
+    /// Calls `hook` with the data and the error if a dialogue cannot be
+    /// deserialized.
+    ///
+    /// If the hook returns `Some(dialogue)`, the dialogue is used instead,
+    /// otherwise the error is returned.
+    #[must_use]
+    pub fn on_invalid_state<F>(self, hook: F) -> Self
+    where
+        F: Fn(&[u8], &VersionedError<<S as Serializer<D>>::Error>) -> Option<D>
+            + Send
+            + Sync
+            + 'static,
+    {
+        Self { on_invalid_state: Some(Box::new(hook)), ..self }
+    }
+
+    /// Returns the current version, i.e. the number of migrations.
+    #[must_use]
+    pub fn version(&self) -> u32 {
+        self.migrations.len() as u32
+    }
+
+    fn deserialize_versioned(
+        &self,
+        data: &[u8],
+    ) -> Result<D, VersionedError<<S as Serializer<D>>::Error>> {
+        let (version, data) =
+            match data.strip_prefix(&VERSION_TAG).and_then(|data| data.split_first_chunk()) {
+                Some((version, data)) => (u32::from_le_bytes(*version), data),
+                None => (0, data),
+            };
+
+        let current = self.version();
+        if version > current {
+            return Err(VersionedError::UnknownVersion { version, current });
+        }
+
+        let mut data = Cow::Borrowed(data);
+        for (version, migration) in self.migrations.iter().enumerate().skip(version as usize) {
+            data =
+                Cow::Owned(migration(&self.serializer, &data).map_err(|error| {
+                    VersionedError::Migration { version: version as u32, error }
+                })?);
+        }
+
+        self.serializer.deserialize(&data).map_err(VersionedError::Serializer)
+    }
+}
+
+impl<D, S> Serializer<D> for Versioned<D, S>
+where
+    S: Serializer<D>,
+    <S as Serializer<D>>::Error: Debug + Display,
+{
+    type Error = VersionedError<<S as Serializer<D>>::Error>;
+
+    fn serialize(&self, val: &D) -> Result<Vec<u8>, Self::Error> {
+        let mut data = VERSION_TAG.to_vec();
+        data.extend(self.version().to_le_bytes());
+        data.extend(self.serializer.serialize(val).map_err(VersionedError::Serializer)?);
+        Ok(data)
+    }
+
+    fn deserialize(&self, data: &[u8]) -> Result<D, Self::Error> {
+        self.deserialize_versioned(data).or_else(|error| {
+            match self.on_invalid_state.as_ref().and_then(|hook| hook(data, &error)) {
+                Some(dialogue) => Ok(dialogue),
+                None => Err(error),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize)]
+    struct V0 {
+        name: String,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct V1 {
+        name: String,
+        age: u8,
+    }
+
+    #[derive(Serialize, Deserialize, Default, Debug, PartialEq)]
+    struct V2 {
+        first_name: String,
+        age: u8,
+    }
+
+    fn serializer() -> Versioned<V2, Json> {
+        Versioned::new(Json)
+            .migration(|V0 { name }| V1 { name, age: 18 })
+            .migration(|V1 { name, age }| V2 { first_name: name, age })
+    }
+
+    #[test]
+    fn migrations() {
+        let serializer = serializer();
+        assert_eq!(serializer.version(), 2);
+
+        // Serialized without `Versioned`
+        let v0 = serde_json::to_vec(&V0 { name: "Alice".to_owned() }).unwrap();
+        let expected = V2 { first_name: "Alice".to_owned(), age: 18 };
+        assert_eq!(serializer.deserialize(&v0).unwrap(), expected);
+
+        let v1 = Versioned::new(Json)
+            .migration(|V0 { name }| V1 { name, age: 18 })
+            .serialize(&V1 { name: "Bob".to_owned(), age: 30 })
+            .unwrap();
+        let expected = V2 { first_name: "Bob".to_owned(), age: 30 };
+        assert_eq!(serializer.deserialize(&v1).unwrap(), expected);
+
+        let v2 = serializer.serialize(&expected).unwrap();
+        assert_eq!(v2[..8], [0xFF, b'T', b'X', b'V', 2, 0, 0, 0]);
+        assert_eq!(serializer.deserialize(&v2).unwrap(), expected);
+    }
+
+    #[test]
+    fn invalid_state() {
+        let v3 = Versioned::<_, Json>::new(Json)
+            .migration(|V0 { name }| V1 { name, age: 18 })
+            .migration(|V1 { name, age }| V2 { first_name: name, age })
+            .migration(|v2: V2| v2)
+            .serialize(&V2::default())
+            .unwrap();
+
+        let error = serializer().deserialize(&v3).unwrap_err();
+        assert!(matches!(error, VersionedError::UnknownVersion { version: 3, current: 2 }));
+        let error = serializer().deserialize(b"[]").unwrap_err();
+        assert!(matches!(error, VersionedError::Migration { version: 0, .. }));
+
+        assert_eq!(serializer().reset_invalid_state().deserialize(&v3).unwrap(), V2::default());
+
+        let serializer = serializer().on_invalid_state(|data, error| match error {
+            VersionedError::Migration { .. } => None,
+            _ => Some(V2 { first_name: format!("{} bytes", data.len()), age: 0 }),
+        });
+        assert_eq!(serializer.deserialize(&v3).unwrap().first_name, format!("{} bytes", v3.len()));
+        assert!(serializer.deserialize(b"[]").is_err());
+    }
+}
@@ -1,7 +1,12 @@
-use super::{serializer::Serializer, Storage};
-use futures::future::BoxFuture;
+use super::{
+    namespace::{SqlQueries, StorageNamespace},
+    serializer::Serializer,
+    CasStorage, DialogueVersion, EnumerableStorage, Storage,
+};
+use futures::{future::BoxFuture, stream::BoxStream};
 use serde::{de::DeserializeOwned, Serialize};
-use sqlx::{sqlite::SqlitePool, Executor};
+pub use sqlx::sqlite::SqlitePool;
+use sqlx::Executor;
 use std::{
     convert::Infallible,
     fmt::{Debug, Display},
@@ -12,9 +17,14 @@ use teloxide_core::types::ChatId;
 use thiserror::Error;
 
 /// A persistent dialogue storage based on [SQLite](https://www.sqlite.org/).
+///
+/// See [`StorageNamespace`] to keep dialogues of several bots or several
+/// dialogue types in one database.
 pub struct SqliteStorage<S> {
     pool: SqlitePool,
     serializer: S,
+    namespace: StorageNamespace,
+    queries: SqlQueries,
 }
 
 /// An error returned from [`SqliteStorage`].
@@ -38,20 +48,65 @@ impl<S> SqliteStorage<S> {
     pub async fn open(
         path: &str,
         serializer: S,
+    ) -> Result<Arc<Self>, SqliteStorageError<Infallible>> {
+        Self::open_with_namespace(path, serializer, StorageNamespace::default()).await
+    }
+
+    /// Opens the database, keeping dialogues in `namespace`.
+    pub async fn open_with_namespace(
+        path: &str,
+        serializer: S,
+        namespace: StorageNamespace,
     ) -> Result<Arc<Self>, SqliteStorageError<Infallible>> {
         let pool = SqlitePool::connect(format!("sqlite:{path}?mode=rwc").as_str()).await?;
-        sqlx::query(
-            "
-CREATE TABLE IF NOT EXISTS teloxide_dialogues (
-    chat_id BIGINT PRIMARY KEY,
-    dialogue BLOB NOT NULL
-);
-        ",
-        )
-        .execute(&pool)
-        .await?;
+        Self::open_with_pool(pool, serializer, namespace).await
+    }
+
+    /// Creates a storage which keeps dialogues in `namespace` of the database
+    /// of an existing connection pool, e.g. shared with the rest of the app.
+    pub async fn open_with_pool(
+        pool: SqlitePool,
+        serializer: S,
+        namespace: StorageNamespace,
+    ) -> Result<Arc<Self>, SqliteStorageError<Infallible>> {
+        let queries = SqlQueries::new(&namespace, "BLOB", |n| format!("?{n}"), '"');
+        sqlx::query(&queries.create).execute(&pool).await?;
+
+        // Tables created before dialogue versions were introduced don't have the column
+        let table = namespace.table();
+        let (has_version,): (bool,) =
+            sqlx::query_as("SELECT COUNT(*) > 0 FROM pragma_table_info(?1) WHERE name = 'version'")
+                .bind(&table)
+                .fetch_one(&pool)
+                .await?;
+        if !has_version {
+            sqlx::query(&format!(
+                "ALTER TABLE \"{table}\" ADD COLUMN version BIGINT NOT NULL DEFAULT 0"
+            ))
+            .execute(&pool)
+            .await?;
+        }
+
+        Ok(Arc::new(Self { pool, serializer, namespace, queries }))
+    }
+
+    /// Moves dialogues from the default `teloxide_dialogues` table to the
+    /// namespace of this storage, returning the number of moved dialogues.
+    ///
+    /// Dialogues which already exist in the namespace are kept. Dialogues are
+    /// removed from the default table only if they are in the namespace after
+    /// the migration. The default table must exist.
+    pub async fn migrate_from_default(&self) -> Result<u64, SqliteStorageError<Infallible>> {
+        if self.namespace.table() == StorageNamespace::DEFAULT_TABLE {
+            return Ok(0);
+        }
+
+        let mut tx = self.pool.begin().await?;
+        let moved = sqlx::query(&self.queries.migrate).execute(&mut *tx).await?.rows_affected();
+        sqlx::query(&self.queries.delete_migrated).execute(&mut *tx).await?;
+        tx.commit().await?;
 
-        Ok(Arc::new(Self { pool, serializer }))
+        Ok(moved)
     }
 }
 
@@ -69,12 +124,11 @@ where
         ChatId(chat_id): ChatId,
     ) -> BoxFuture<'static, Result<(), Self::Error>> {
         Box::pin(async move {
-            let deleted_rows_count =
-                sqlx::query("DELETE FROM teloxide_dialogues WHERE chat_id = ?")
-                    .bind(chat_id)
-                    .execute(&self.pool)
-                    .await?
-                    .rows_affected();
+            let deleted_rows_count = sqlx::query(&self.queries.remove)
+                .bind(chat_id)
+                .execute(&self.pool)
+                .await?
+                .rows_affected();
 
             if deleted_rows_count == 0 {
                 return Err(SqliteStorageError::DialogueNotFound);
@@ -96,14 +150,10 @@ where
                 .acquire()
                 .await?
                 .execute(
-                    sqlx::query(
-                        "
-            INSERT INTO teloxide_dialogues VALUES (?, ?)
-            ON CONFLICT(chat_id) DO UPDATE SET dialogue=excluded.dialogue
-                                ",
-                    )
-                    .bind(chat_id)
-                    .bind(d),
+                    sqlx::query(&self.queries.update)
+                        .bind(chat_id)
+                        .bind(d)
+                        .bind(DialogueVersion::now()),
                 )
                 .await?;
             Ok(())
@@ -115,7 +165,7 @@ where
         chat_id: ChatId,
     ) -> BoxFuture<'static, Result<Option<D>, Self::Error>> {
         Box::pin(async move {
-            get_dialogue(&self.pool, chat_id)
+            get_dialogue(&self.pool, &self.queries.get, chat_id)
                 .await?
                 .map(|d| self.serializer.deserialize(&d).map_err(SqliteStorageError::SerdeError))
                 .transpose()
@@ -123,8 +173,83 @@ where
     }
 }
 
+impl<S, D> CasStorage<D> for SqliteStorage<S>
+where
+    S: Send + Sync + Serializer<D> + 'static,
+    D: Send + Serialize + DeserializeOwned + 'static,
+    <S as Serializer<D>>::Error: Debug + Display,
+{
+    fn get_dialogue_versioned(
+        self: Arc<Self>,
+        ChatId(chat_id): ChatId,
+    ) -> BoxFuture<'static, Result<Option<(D, DialogueVersion)>, Self::Error>> {
+        Box::pin(async move {
+            let row: Option<(Vec<u8>, i64)> = sqlx::query_as(&self.queries.get_versioned)
+                .bind(chat_id)
+                .fetch_optional(&self.pool)
+                .await?;
+
+            row.map(|(d, version)| {
+                let dialogue =
+                    self.serializer.deserialize(&d).map_err(SqliteStorageError::SerdeError)?;
+                Ok((dialogue, DialogueVersion(version)))
+            })
+            .transpose()
+        })
+    }
+
+    fn update_dialogue_if_version(
+        self: Arc<Self>,
+        ChatId(chat_id): ChatId,
+        expected: Option<DialogueVersion>,
+        dialogue: D,
+    ) -> BoxFuture<'static, Result<Option<DialogueVersion>, Self::Error>> {
+        Box::pin(async move {
+            let d = self.serializer.serialize(&dialogue).map_err(SqliteStorageError::SerdeError)?;
+
+            let query = match expected {
+                Some(DialogueVersion(expected)) => sqlx::query_as(&self.queries.update_if_version)
+                    .bind(chat_id)
+                    .bind(d)
+                    .bind(DialogueVersion::now())
+                    .bind(expected),
+                None => sqlx::query_as(&self.queries.insert_if_absent)
+                    .bind(chat_id)
+                    .bind(d)
+                    .bind(DialogueVersion::now()),
+            };
+            let version: Option<(i64,)> = query.fetch_optional(&self.pool).await?;
+
+            Ok(version.map(|(version,)| DialogueVersion(version)))
+        })
+    }
+}
+
+impl<S, D> EnumerableStorage<D> for SqliteStorage<S>
+where
+    S: Send + Sync + Serializer<D> + 'static,
+    D: Send + Serialize + DeserializeOwned + 'static,
+    <S as Serializer<D>>::Error: Debug + Display + Send,
+{
+    fn dialogues(self: Arc<Self>) -> BoxStream<'static, Result<(ChatId, D), Self::Error>> {
+        let this = Arc::clone(&self);
+        self.queries.dialogues(self.pool.clone(), move |d| {
+            this.serializer.deserialize(d).map_err(SqliteStorageError::SerdeError)
+        })
+    }
+
+    fn count_dialogues(self: Arc<Self>) -> BoxFuture<'static, Result<u64, Self::Error>> {
+        Box::pin(async move {
+            let (count,): (i64,) =
+                sqlx::query_as(&self.queries.count).fetch_one(&self.pool).await?;
+            Ok(count as u64)
+        })
+    }
+}
+
 async fn get_dialogue(
     pool: &SqlitePool,
+    query: &str,
     ChatId(chat_id): ChatId,
 ) -> Result<Option<Vec<u8>>, sqlx::Error> {
     #[derive(sqlx::FromRow)]
@@ -132,13 +257,45 @@ async fn get_dialogue(
         dialogue: Vec<u8>,
     }
 
-    let bytes = sqlx::query_as::<_, DialogueDbRow>(
-        "SELECT dialogue FROM teloxide_dialogues WHERE chat_id = ?",
-    )
-    .bind(chat_id)
-    .fetch_optional(pool)
-    .await?
-    .map(|r| r.dialogue);
+    let bytes = sqlx::query_as::<_, DialogueDbRow>(query)
+        .bind(chat_id)
+        .fetch_optional(pool)
+        .await?
+        .map(|r| r.dialogue);
 
     Ok(bytes)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dispatching::dialogue::serializer::Json;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    #[tokio::test]
+    async fn add_version_column() {
+        // Every connection to an in-memory database has its own database
+        let pool = SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await;
+        let pool = pool.unwrap();
+        pool.execute(
+            "CREATE TABLE teloxide_dialogues (chat_id BIGINT PRIMARY KEY, dialogue BLOB NOT NULL)",
+        )
+        .await
+        .unwrap();
+        pool.execute(r#"INSERT INTO teloxide_dialogues VALUES (1, '"old"')"#).await.unwrap();
+
+        let storage = SqliteStorage::open_with_pool(pool.clone(), Json, StorageNamespace::new())
+            .await
+            .unwrap();
+        // Does nothing if the column exists
+        SqliteStorage::open_with_pool(pool, Json, StorageNamespace::new()).await.unwrap();
+
+        let versioned = Arc::clone(&storage).get_dialogue_versioned(ChatId(1)).await.unwrap();
+        assert_eq!(versioned, Some(("old".to_owned(), DialogueVersion(0))));
+        Arc::clone(&storage).update_dialogue(ChatId(1), "new".to_owned()).await.unwrap();
+        assert_eq!(
+            Arc::clone(&storage).get_dialogue(ChatId(1)).await.unwrap(),
+            Some("new".to_owned())
+        );
+    }
+}
@@ -1,9 +1,9 @@
 use std::{fmt::Debug, sync::Arc};
 
-use futures::future::BoxFuture;
+use futures::{future::BoxFuture, stream::BoxStream};
 use teloxide_core::types::ChatId;
 
-use crate::dispatching::dialogue::Storage;
+use crate::dispatching::dialogue::{CasStorage, DialogueVersion, EnumerableStorage, Storage};
 
 /// A dialogue storage wrapper which logs all actions performed on an underlying
 /// storage.
@@ -67,3 +67,63 @@ where
         <S as Storage<D>>::get_dialogue(self.inner.clone(), chat_id)
     }
 }
+
+impl<S, D> EnumerableStorage<D> for TraceStorage<S>
+where
+    D: Debug,
+    S: EnumerableStorage<D> + Send + Sync + 'static,
+{
+    fn dialogues(self: Arc<Self>) -> BoxStream<'static, Result<(ChatId, D), Self::Error>> {
+        log::trace!("Requested all dialogues");
+        <S as EnumerableStorage<D>>::dialogues(self.inner.clone())
+    }
+
+    fn count_dialogues(self: Arc<Self>) -> BoxFuture<'static, Result<u64, Self::Error>>
+    where
+        D: Send + 'static,
+        Self::Error: Send + 'static,
+    {
+        log::trace!("Requested the number of dialogues");
+        <S as EnumerableStorage<D>>::count_dialogues(self.inner.clone())
+    }
+}
+
+impl<S, D> CasStorage<D> for TraceStorage<S>
+where
+    D: Debug,
+    S: CasStorage<D> + Send + Sync + 'static,
+{
+    fn get_dialogue_versioned(
+        self: Arc<Self>,
+        chat_id: ChatId,
+    ) -> BoxFuture<'static, Result<Option<(D, DialogueVersion)>, Self::Error>> {
+        log::trace!("Requested a dialogue #{} with its version", chat_id);
+        <S as CasStorage<D>>::get_dialogue_versioned(self.inner.clone(), chat_id)
+    }
+
+    fn update_dialogue_if_version(
+        self: Arc<Self>,
+        chat_id: ChatId,
+        expected: Option<DialogueVersion>,
+        dialogue: D,
+    ) -> BoxFuture<'static, Result<Option<DialogueVersion>, Self::Error>>
+    where
+        D: Send + 'static,
+    {
+        Box::pin(async move {
+            let to = format!("{dialogue:#?}");
+            let version = <S as CasStorage<D>>::update_dialogue_if_version(
+                self.inner.clone(),
+                chat_id,
+                expected,
+                dialogue,
+            )
+            .await?;
+            match version {
+                Some(_) => log::trace!("Updated a dialogue #{}: {:#?}", chat_id, to),
+                None => log::trace!("Not updated a dialogue #{}: version mismatch", chat_id),
+            }
+            Ok(version)
+        })
+    }
+}
@@ -1,12 +1,14 @@
 use crate::{
     dispatching::{
-        distribution::default_distribution_function, DefaultKey, DpHandlerDescription,
-        ShutdownToken,
+        backpressure::{OverflowPolicy, Ticket, Tickets},
+        distribution::{default_distribution_function, DistributionFn},
+        media_group::{MediaGroup, PendingMediaGroup},
+        DefaultKey, DpHandlerDescription, ShutdownToken, WebhookReplies, WebhookReply,
     },
-    error_handlers::{ErrorHandler, LoggingErrorHandler},
+    error_handlers::{ErrorHandler, HandlerPanic, LoggingErrorHandler, UpdateErrorHandler},
     requests::{Request, Requester},
     stop::StopToken,
-    types::{Update, UpdateKind},
+    types::{Me, Update, UpdateKind},
     update_listeners::{self, UpdateListener},
 };
 
@@ -14,22 +16,28 @@ use dptree::di::{DependencyMap, DependencySupplier};
 use either::Either;
 use futures::{
     future::{self, BoxFuture},
-    stream::FuturesUnordered,
+    stream::{self, FuturesUnordered},
     FutureExt as _, StreamExt as _,
 };
-use tokio_stream::wrappers::ReceiverStream;
+use tokio::sync::{Notify, OwnedSemaphorePermit, Semaphore};
+use tokio_util::sync::CancellationToken;
+
+#[cfg(feature = "scheduler")]
+use crate::dispatching::scheduler::{JobHandler, Scheduler};
 
 use std::{
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
     fmt::Debug,
     future::Future,
     hash::Hash,
     ops::{ControlFlow, Deref},
+    panic::AssertUnwindSafe,
     pin::pin,
     sync::{
         atomic::{AtomicBool, AtomicU32, Ordering},
-        Arc,
+        Arc, Mutex,
     },
+    time::Duration,
 };
 
 /// The builder for [`Dispatcher`].
@@ -42,10 +50,22 @@ pub struct DispatcherBuilder<R, Err, Key> {
     handler: Arc<UpdateHandler<Err>>,
     default_handler: DefaultHandler,
     error_handler: Arc<dyn ErrorHandler<Err> + Send + Sync>,
+    update_error_handler: Option<Arc<dyn UpdateErrorHandler<Err> + Send + Sync>>,
     ctrlc_handler: bool,
-    distribution_f: fn(&Update) -> Option<Key>,
+    sigterm_handler: bool,
+    distribution_f: DistributionFunction<Key>,
     worker_queue_size: usize,
     stack_size: usize,
+    media_group_debounce: Option<Duration>,
+    webhook_replies: Option<WebhookReplies>,
+    worker_queue_overflow: OverflowPolicy,
+    concurrency_limit: Option<usize>,
+    in_flight_limit: Option<(usize, OverflowPolicy)>,
+    shutdown_timeout: Option<Duration>,
+    on_shutdown: Option<ShutdownCallback>,
+    on_panic: Option<PanicHook>,
+    #[cfg(feature = "scheduler")]
+    scheduler: Option<(Scheduler, Arc<JobHandler<Err>>)>,
 }
 
 impl<R, Err, Key> DispatcherBuilder<R, Err, Key>
@@ -76,11 +96,33 @@ where
     /// Specifies a handler that will be called on a handler error.
     ///
     /// By default, it is [`LoggingErrorHandler`].
+    ///
+    /// If [`update_error_handler`] is set, this handler only handles errors of
+    /// scheduled jobs.
+    ///
+    /// [`update_error_handler`]: DispatcherBuilder::update_error_handler
     #[must_use]
     pub fn error_handler(self, handler: Arc<dyn ErrorHandler<Err> + Send + Sync>) -> Self {
         Self { error_handler: handler, ..self }
     }
 
+    /// Specifies a handler that will be called on a handler error (or panic)
+    /// with the update that caused it and the dependencies, instead of
+    /// [`error_handler`].
+    ///
+    /// See also [`AdminChatErrorHandler`], which reports errors to an admin
+    /// chat.
+    ///
+    /// [`error_handler`]: DispatcherBuilder::error_handler
+    /// [`AdminChatErrorHandler`]: crate::error_handlers::AdminChatErrorHandler
+    #[must_use]
+    pub fn update_error_handler(
+        self,
+        handler: Arc<dyn UpdateErrorHandler<Err> + Send + Sync>,
+    ) -> Self {
+        Self { update_error_handler: Some(handler), ..self }
+    }
+
     /// Specifies dependencies that can be used inside of handlers.
     ///
     /// By default, there is no dependencies.
@@ -98,14 +140,169 @@ where
         Self { ctrlc_handler: true, ..self }
     }
 
+    /// Enables the `SIGTERM` handler that [`shutdown`]s dispatching.
+    ///
+    /// `SIGTERM` is what container runtimes (e.g. Docker or Kubernetes) and
+    /// service managers send to stop an application, so this is useful for
+    /// deployments.
+    ///
+    /// [`shutdown`]: ShutdownToken::shutdown
+    #[cfg(all(feature = "ctrlc_handler", unix))]
+    #[must_use]
+    pub fn enable_sigterm_handler(self) -> Self {
+        Self { sigterm_handler: true, ..self }
+    }
+
+    /// Specifies how long to wait for workers to process the remaining
+    /// updates when shutting down.
+    ///
+    /// After the timeout, the remaining workers are aborted, which drops the
+    /// futures of running handlers. Handlers can learn that shutdown has
+    /// started earlier, see [`Dispatcher::dispatch`].
+    ///
+    /// By default, the dispatcher waits for all updates to be processed.
+    #[must_use]
+    pub fn shutdown_timeout(self, timeout: Duration) -> Self {
+        Self { shutdown_timeout: Some(timeout), ..self }
+    }
+
+    /// Specifies a callback that will be called when dispatching is shut down,
+    /// after all workers finished or were aborted.
+    ///
+    /// This is a good place to persist in-memory state or to flush buffers.
+    #[must_use]
+    pub fn on_shutdown<F, Fut>(self, callback: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        Self { on_shutdown: Some(Arc::new(move || Box::pin(callback()))), ..self }
+    }
+
+    /// Specifies a hook that will be called when a handler panics, for example
+    /// to tell the user that something went wrong.
+    ///
+    /// Panics of handlers are caught, so a panic only affects the update that
+    /// caused it, and updates from the same chat are processed further. The
+    /// panic is passed to [`ErrorHandler::handle_panic`] of the error handler
+    /// first, and then to the hook. Panics of the error handler and of the
+    /// default handler are not caught.
+    #[must_use]
+    pub fn on_panic<F, Fut>(self, hook: F) -> Self
+    where
+        F: Fn(HandlerPanic) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        Self { on_panic: Some(Arc::new(move |panic| Box::pin(hook(panic)))), ..self }
+    }
+
+    /// Runs jobs of `scheduler` with `handler`.
+    ///
+    /// Jobs are passed to `handler` as [`Job`] along with the same dependencies
+    /// as updates (see [`Dispatcher::dispatch`]), except for those specific to
+    /// updates. Errors are passed to the error handler of the dispatcher.
+    ///
+    /// `scheduler` is also available to update handlers as a dependency. Jobs
+    /// stop running when dispatching is shut down. Only one process may run
+    /// jobs of the same store. See [`scheduler`] for the details.
+    ///
+    /// [`Job`]: crate::dispatching::scheduler::Job
+    /// [`scheduler`]: crate::dispatching::scheduler
+    #[cfg(feature = "scheduler")]
+    #[must_use]
+    pub fn scheduler(self, scheduler: Scheduler, handler: JobHandler<Err>) -> Self {
+        Self { scheduler: Some((scheduler, Arc::new(handler))), ..self }
+    }
+
     /// Specifies size of the queue for workers.
     ///
+    /// Each [distribution key] has its own worker, which processes updates one
+    /// by one. Updates that wait to be processed are stored in the queue of the
+    /// worker. What happens when the queue is full is specified by
+    /// [`DispatcherBuilder::worker_queue_overflow`].
+    ///
     /// By default it's 64.
+    ///
+    /// ## Panics
+    ///
+    /// If `size` is 0.
+    ///
+    /// [distribution key]: DispatcherBuilder::distribution_function
     #[must_use]
+    #[track_caller]
     pub fn worker_queue_size(self, size: usize) -> Self {
+        assert_ne!(size, 0, "worker queue size can't be 0");
+
         Self { worker_queue_size: size, ..self }
     }
 
+    /// Specifies what happens with an update when the queue of its worker is
+    /// full (see [`DispatcherBuilder::worker_queue_size`]).
+    ///
+    /// By default, it's [`OverflowPolicy::Block`], that is, the dispatcher
+    /// stops receiving updates until there is room in the queue.
+    #[must_use]
+    pub fn worker_queue_overflow(self, policy: OverflowPolicy) -> Self {
+        Self { worker_queue_overflow: policy, ..self }
+    }
+
+    /// Limits the number of updates processed at the same time, across all
+    /// workers.
+    ///
+    /// Updates over the limit wait in the queues of their workers.
+    ///
+    /// By default, the number of concurrently processed updates is not limited.
+    ///
+    /// ## Panics
+    ///
+    /// If `limit` is 0.
+    #[must_use]
+    #[track_caller]
+    pub fn concurrency_limit(self, limit: usize) -> Self {
+        assert_ne!(limit, 0, "concurrency limit can't be 0");
+
+        Self { concurrency_limit: Some(limit), ..self }
+    }
+
+    /// Limits the number of in-flight updates, that is updates that are
+    /// received by the dispatcher but not yet processed, including updates in
+    /// the queues of workers.
+    ///
+    /// When the limit is reached, a new update is handled according to
+    /// `policy`. Together with [`DispatcherBuilder::concurrency_limit`], this
+    /// bounds memory and task usage under a burst of updates from many chats.
+    ///
+    /// By default, the number of in-flight updates is only limited by the
+    /// sizes of worker queues.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use teloxide::{
+    ///     dispatching::{Dispatcher, OverflowPolicy},
+    ///     dptree, Bot,
+    /// };
+    ///
+    /// let bot = Bot::new("TOKEN");
+    /// let handler = dptree::entry() /* ... */;
+    /// let dp = Dispatcher::builder(bot, handler)
+    ///     .concurrency_limit(64)
+    ///     .in_flight_limit(1024, OverflowPolicy::DropOldest)
+    ///     .build();
+    /// # let _: Dispatcher<_, (), _> = dp;
+    /// ```
+    ///
+    /// ## Panics
+    ///
+    /// If `limit` is 0.
+    #[must_use]
+    #[track_caller]
+    pub fn in_flight_limit(self, limit: usize, policy: OverflowPolicy) -> Self {
+        assert_ne!(limit, 0, "in-flight limit can't be 0");
+
+        Self { in_flight_limit: Some((limit, policy)), ..self }
+    }
+
     /// Specifies the stack size available to the dispatcher.
     ///
     /// By default, it's 8 * 1024 * 1024 bytes (8 MiB).
@@ -114,6 +311,71 @@ where
         Self { stack_size: size, ..self }
     }
 
+    /// Enables aggregation of media groups (albums).
+    ///
+    /// When a user sends an album (or one is posted in a channel or sent in a
+    /// business chat), each of its items arrives as a separate message with
+    /// the same [`Message::media_group_id`]. Albums are collected from
+    /// [`UpdateKind::Message`], [`UpdateKind::ChannelPost`] and
+    /// [`UpdateKind::BusinessMessage`] updates, but not from edited messages.
+    /// With this option, the dispatcher collects such messages until no new
+    /// items of the group arrive for `debounce`, and runs the handler only
+    /// once, for the update with the first message of the group. All
+    /// messages of the group are available to handlers via
+    /// [`UpdateFilterExt::filter_media_group`] (or
+    /// as an `Option<MediaGroup>` dependency, which is `None` for other
+    /// updates).
+    ///
+    /// The media group is processed in place of its first message, so the
+    /// order of updates with the same [distribution key] is preserved: updates
+    /// received after the first item of the group wait until the group is
+    /// collected and handled.
+    ///
+    /// Telegram usually sends all items of an album within a fraction of a
+    /// second, so a debounce of around 500 milliseconds is enough in most
+    /// cases.
+    ///
+    /// By default, media groups are not aggregated.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use teloxide::{dispatching::MediaGroup, prelude::*};
+    ///
+    /// let bot = Bot::new("TOKEN");
+    /// let handler = Update::filter_media_group().endpoint(|bot: Bot, group: MediaGroup| async move {
+    ///     let chat_id = group.messages[0].chat.id;
+    ///     let text = format!("Got an album of {} items", group.messages.len());
+    ///     bot.send_message(chat_id, text).await?;
+    ///     respond(())
+    /// });
+    /// let dp = Dispatcher::builder(bot, handler)
+    ///     .aggregate_media_groups(Duration::from_millis(500))
+    ///     .build();
+    /// # let _ = dp;
+    /// ```
+    ///
+    /// [`Message::media_group_id`]: crate::types::Message::media_group_id
+    /// [`UpdateFilterExt::filter_media_group`]: crate::dispatching::UpdateFilterExt::filter_media_group
+    /// [distribution key]: DispatcherBuilder::distribution_function
+    #[must_use]
+    pub fn aggregate_media_groups(self, debounce: Duration) -> Self {
+        Self { media_group_debounce: Some(debounce), ..self }
+    }
+
+    /// Allows handlers to answer updates received by a webhook in the HTTP
+    /// response, via the [`WebhookReply`] dependency.
+    ///
+    /// `replies` must also be passed to the webhook listener. See
+    /// [`WebhookReplies`] for the details.
+    ///
+    /// By default, [`WebhookReply`] always sends requests as usual.
+    #[must_use]
+    pub fn webhook_replies(self, replies: WebhookReplies) -> Self {
+        Self { webhook_replies: Some(replies), ..self }
+    }
+
     /// Specifies the distribution function that decides how updates are grouped
     /// before execution.
     ///
@@ -144,17 +406,61 @@ where
     /// This pair nicely with dialogue system, which has state attached to
     /// chats.
     ///
+    /// ## Built-in distribution functions
+    ///
+    /// The [`distribution`] module provides functions for common cases, each
+    /// documenting its ordering guarantees:
+    ///
+    ///  - [`distribution::by_chat`] groups updates by chat, like the default
+    ///    one;
+    ///  - [`distribution::by_user`] groups updates by user;
+    ///  - [`distribution::by_chat_and_thread`] groups updates by forum topic;
+    ///  - [`distribution::by_inline_query_sender`] groups only inline queries,
+    ///    by their sender;
+    ///  - [`distribution::sequential`] processes all updates sequentially.
+    ///
+    /// Since `f` can be a closure, it can also capture some state, for example
+    /// a cache to look up the key. The type of the closure argument must be
+    /// specified, i.e. `|upd: &Update| ...`. A shared
+    /// `Arc<dyn Fn(&Update) -> Option<K> + Send + Sync>` can be passed as well,
+    /// see [`DistributionFn`]. To look up the key in the dependencies of the
+    /// dispatcher, use [`DispatcherBuilder::distribution_function_with_deps`].
+    ///
     /// ## Examples
     ///
     /// Grouping updates by user who caused this update to happen:
     ///
     /// ```
-    /// use teloxide::{dispatching::Dispatcher, dptree, Bot};
+    /// use teloxide::{
+    ///     dispatching::{distribution, Dispatcher},
+    ///     dptree, Bot,
+    /// };
+    ///
+    /// let bot = Bot::new("TOKEN");
+    /// let handler = dptree::entry() /* ... */;
+    /// let dp = Dispatcher::builder(bot, handler).distribution_function(distribution::by_user).build();
+    /// # let _: Dispatcher<_, (), _> = dp;
+    /// ```
+    ///
+    /// Grouping updates by a tenant, looked up in a shared map:
+    ///
+    /// ```
+    /// use std::{collections::HashMap, sync::Arc};
+    /// use teloxide::{
+    ///     dispatching::Dispatcher,
+    ///     dptree,
+    ///     types::{ChatId, Update},
+    ///     Bot,
+    /// };
+    ///
+    /// let tenants: Arc<HashMap<ChatId, u64>> = Arc::new(HashMap::new());
     ///
     /// let bot = Bot::new("TOKEN");
     /// let handler = dptree::entry() /* ... */;
     /// let dp = Dispatcher::builder(bot, handler)
-    ///     .distribution_function(|upd| upd.from().map(|user| user.id))
+    ///     .distribution_function(move |upd: &Update| {
+    ///         upd.chat().and_then(|chat| tenants.get(&chat.id).copied())
+    ///     })
     ///     .build();
     /// # let _: Dispatcher<_, (), _> = dp;
     /// ```
@@ -162,20 +468,68 @@ where
     /// Not grouping updates at all, always processing updates concurrently:
     ///
     /// ```
-    /// use teloxide::{dispatching::Dispatcher, dptree, Bot};
+    /// use teloxide::{dispatching::Dispatcher, dptree, types::Update, Bot};
     ///
     /// let bot = Bot::new("TOKEN");
     /// let handler = dptree::entry() /* ... */;
-    /// let dp = Dispatcher::builder(bot, handler).distribution_function(|_| None::<()>).build();
+    /// let dp =
+    ///     Dispatcher::builder(bot, handler).distribution_function(|_: &Update| None::<()>).build();
     /// # let _: Dispatcher<_, (), _> = dp;
     /// ```
+    ///
+    /// [`distribution`]: crate::dispatching::distribution
+    /// [`distribution::by_chat`]: crate::dispatching::distribution::by_chat
+    /// [`distribution::by_user`]: crate::dispatching::distribution::by_user
+    /// [`distribution::by_chat_and_thread`]: crate::dispatching::distribution::by_chat_and_thread
+    /// [`distribution::by_inline_query_sender`]: crate::dispatching::distribution::by_inline_query_sender
+    /// [`distribution::sequential`]: crate::dispatching::distribution::sequential
+    /// [`DistributionFn`]: crate::dispatching::distribution::DistributionFn
     #[must_use]
-    pub fn distribution_function<K>(
-        self,
-        f: fn(&Update) -> Option<K>,
-    ) -> DispatcherBuilder<R, Err, K>
+    pub fn distribution_function<K, F>(self, f: F) -> DispatcherBuilder<R, Err, K>
     where
         K: Hash + Eq,
+        F: DistributionFn<K>,
+    {
+        self.distribution_function_with_deps(move |update, _| f.key(update))
+    }
+
+    /// Specifies a function that determines how updates are grouped for
+    /// sequential processing, with access to the dependencies of the
+    /// dispatcher.
+    ///
+    /// The same as [`DispatcherBuilder::distribution_function`], but `f` also
+    /// receives the dependencies, for example to look up the key in a shared
+    /// state.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use std::{collections::HashMap, sync::Arc};
+    /// use teloxide::{
+    ///     dispatching::Dispatcher,
+    ///     dptree::{self, di::DependencySupplier},
+    ///     types::ChatId,
+    ///     Bot,
+    /// };
+    ///
+    /// type Tenants = HashMap<ChatId, u64>;
+    ///
+    /// let bot = Bot::new("TOKEN");
+    /// let handler = dptree::entry() /* ... */;
+    /// let dp = Dispatcher::builder(bot, handler)
+    ///     .dependencies(dptree::deps![Tenants::new()])
+    ///     .distribution_function_with_deps(|upd, deps| {
+    ///         let tenants: Arc<Tenants> = deps.get();
+    ///         upd.chat().and_then(|chat| tenants.get(&chat.id).copied())
+    ///     })
+    ///     .build();
+    /// # let _: Dispatcher<_, (), _> = dp;
+    /// ```
+    #[must_use]
+    pub fn distribution_function_with_deps<K, F>(self, f: F) -> DispatcherBuilder<R, Err, K>
+    where
+        K: Hash + Eq,
+        F: Fn(&Update, &DependencyMap) -> Option<K> + Send + Sync + 'static,
     {
         let Self {
             bot,
@@ -183,10 +537,22 @@ where
             handler,
             default_handler,
             error_handler,
+            update_error_handler,
             ctrlc_handler,
+            sigterm_handler,
             distribution_f: _,
             worker_queue_size,
             stack_size,
+            media_group_debounce,
+            webhook_replies,
+            worker_queue_overflow,
+            concurrency_limit,
+            in_flight_limit,
+            shutdown_timeout,
+            on_shutdown,
+            on_panic,
+            #[cfg(feature = "scheduler")]
+            scheduler,
         } = self;
 
         DispatcherBuilder {
@@ -195,10 +561,22 @@ where
             handler,
             default_handler,
             error_handler,
+            update_error_handler,
             ctrlc_handler,
-            distribution_f: f,
+            sigterm_handler,
+            distribution_f: Arc::new(f),
             worker_queue_size,
             stack_size,
+            media_group_debounce,
+            webhook_replies,
+            worker_queue_overflow,
+            concurrency_limit,
+            in_flight_limit,
+            shutdown_timeout,
+            on_shutdown,
+            on_panic,
+            #[cfg(feature = "scheduler")]
+            scheduler,
         }
     }
 
@@ -211,25 +589,58 @@ where
             handler,
             default_handler,
             error_handler,
+            update_error_handler,
             distribution_f,
             worker_queue_size,
             ctrlc_handler,
+            sigterm_handler,
             stack_size,
+            media_group_debounce,
+            webhook_replies,
+            worker_queue_overflow,
+            concurrency_limit,
+            in_flight_limit,
+            shutdown_timeout,
+            on_shutdown,
+            on_panic,
+            #[cfg(feature = "scheduler")]
+            scheduler,
         } = self;
 
         // If the `ctrlc_handler` feature is not enabled, don't emit a warning.
-        let _ = ctrlc_handler;
+        let _ = (ctrlc_handler, sigterm_handler);
+
+        let update_error_handler = update_error_handler.unwrap_or_else(|| {
+            Arc::new(ErrorHandlerAdaptor(Arc::clone(&error_handler)))
+                as Arc<dyn UpdateErrorHandler<Err> + Send + Sync>
+        });
 
         let dp = Dispatcher {
             bot,
             dependencies,
             handler,
             default_handler,
+            #[cfg(feature = "scheduler")]
             error_handler,
+            update_error_handler,
             state: ShutdownToken::new(),
             distribution_f,
             worker_queue_size,
             stack_size,
+            media_group_debounce,
+            media_groups: HashMap::new(),
+            webhook_replies,
+            worker_queue_overflow,
+            concurrency_limit: concurrency_limit.map(|limit| Arc::new(Semaphore::new(limit))),
+            in_flight_limit: in_flight_limit
+                .map(|(limit, policy)| (Arc::new(Semaphore::new(limit)), policy)),
+            in_flight_tickets: Tickets::default(),
+            shutdown_timeout,
+            on_shutdown,
+            on_panic,
+            cancellation_token: CancellationToken::new(),
+            #[cfg(feature = "scheduler")]
+            scheduler,
             workers: HashMap::new(),
             default_worker: None,
             current_number_of_active_workers: Default::default(),
@@ -239,9 +650,11 @@ where
         #[cfg(feature = "ctrlc_handler")]
         {
             if ctrlc_handler {
-                let mut dp = dp;
                 dp.setup_ctrlc_handler_inner();
-                return dp;
+            }
+            #[cfg(unix)]
+            if sigterm_handler {
+                dp.setup_sigterm_handler_inner();
             }
         }
 
@@ -270,34 +683,131 @@ pub struct Dispatcher<R, Err, Key> {
     handler: Arc<UpdateHandler<Err>>,
     default_handler: DefaultHandler,
 
-    distribution_f: fn(&Update) -> Option<Key>,
+    distribution_f: DistributionFunction<Key>,
     worker_queue_size: usize,
     stack_size: usize,
     current_number_of_active_workers: Arc<AtomicU32>,
     max_number_of_active_workers: Arc<AtomicU32>,
-    // Tokio TX channel parts associated with chat IDs that consume updates sequentially.
+    media_group_debounce: Option<Duration>,
+    // Media groups that are being collected, by their IDs.
+    media_groups: HashMap<String, Arc<PendingMediaGroup>>,
+    webhook_replies: Option<WebhookReplies>,
+    worker_queue_overflow: OverflowPolicy,
+    concurrency_limit: Option<Arc<Semaphore>>,
+    in_flight_limit: Option<(Arc<Semaphore>, OverflowPolicy)>,
+    // Updates which hold permits of `in_flight_limit`.
+    in_flight_tickets: Tickets,
+    shutdown_timeout: Option<Duration>,
+    on_shutdown: Option<ShutdownCallback>,
+    on_panic: Option<PanicHook>,
+    // Cancelled when shutdown starts, recreated for each dispatching.
+    cancellation_token: CancellationToken,
+    #[cfg(feature = "scheduler")]
+    scheduler: Option<(Scheduler, Arc<JobHandler<Err>>)>,
+    // Workers associated with distribution keys that consume updates sequentially.
     workers: HashMap<Key, Worker>,
-    // The default TX part that consume updates concurrently.
+    // The default worker that consumes updates concurrently.
     default_worker: Option<Worker>,
 
+    // Handles errors of scheduled jobs.
+    #[cfg(feature = "scheduler")]
     error_handler: Arc<dyn ErrorHandler<Err> + Send + Sync>,
+    update_error_handler: Arc<dyn UpdateErrorHandler<Err> + Send + Sync>,
 
     state: ShutdownToken,
 }
 
 struct Worker {
-    tx: tokio::sync::mpsc::Sender<Update>,
+    updates: Arc<WorkerQueue>,
     handle: tokio::task::JoinHandle<()>,
     is_waiting: Arc<AtomicBool>,
+    // Room in the queue of the worker, see `DispatcherBuilder::worker_queue_size`.
+    queue: Arc<Semaphore>,
+    tickets: Tickets,
+}
+
+impl Worker {
+    // Closes the queue of the worker, returning the handle to wait for the
+    // worker to process the rest of its updates.
+    fn stop(self) -> tokio::task::JoinHandle<()> {
+        self.updates.close();
+        self.handle
+    }
+}
+
+// Updates waiting to be processed by a worker.
+//
+// Unlike a channel, it forgets updates dropped due to an overflow without
+// waiting for the worker to get to them, so it never holds more updates than
+// `DispatcherBuilder::worker_queue_size`.
+#[derive(Default)]
+struct WorkerQueue {
+    updates: Mutex<VecDeque<QueuedUpdate>>,
+    notify: Notify,
+    closed: AtomicBool,
+}
+
+impl WorkerQueue {
+    fn push(&self, queued: QueuedUpdate) {
+        let mut updates = self.updates.lock().unwrap();
+        updates.retain(|queued| {
+            let pending = queued.ticket.is_pending();
+            if let (false, Some(group)) = (pending, &queued.media_group) {
+                group.close();
+            }
+            pending
+        });
+        updates.push_back(queued);
+        drop(updates);
+
+        self.notify.notify_one();
+    }
+
+    // Returns `None` when the queue is closed and empty.
+    async fn pop(&self) -> Option<QueuedUpdate> {
+        loop {
+            // Registers for a notification before checking, so that it isn't missed
+            let notified = self.notify.notified();
+            if let Some(queued) = self.updates.lock().unwrap().pop_front() {
+                return Some(queued);
+            }
+            if self.closed.load(Ordering::Acquire) {
+                return None;
+            }
+            notified.await;
+        }
+    }
+
+    // The worker stops once it processes the updates left in the queue.
+    fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+        self.notify.notify_one();
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.updates.lock().unwrap().len()
+    }
 }
 
-// TODO: it is allowed to return message as response on telegram request in
-// webhooks, so we can allow this too. See more there: https://core.telegram.org/bots/api#making-requests-when-getting-updates
+// An update along with the data the dispatcher collected for it.
+struct QueuedUpdate {
+    update: Update,
+    media_group: Option<Arc<PendingMediaGroup>>,
+    webhook_reply: WebhookReply,
+    ticket: Arc<Ticket>,
+}
 
 /// A handler that processes updates from Telegram.
 pub type UpdateHandler<Err> =
     dptree::Handler<'static, DependencyMap, Result<(), Err>, DpHandlerDescription>;
 
+type DistributionFunction<Key> = Arc<dyn Fn(&Update, &DependencyMap) -> Option<Key> + Send + Sync>;
+
+type ShutdownCallback = Arc<dyn Fn() -> BoxFuture<'static, ()> + Send + Sync>;
+
+type PanicHook = Arc<dyn Fn(HandlerPanic) -> BoxFuture<'static, ()> + Send + Sync>;
+
 type DefaultHandler = Arc<dyn Fn(Arc<Update>) -> BoxFuture<'static, ()> + Send + Sync>;
 
 impl<R, Err> Dispatcher<R, Err, DefaultKey>
@@ -323,10 +833,22 @@ where
                 Box::pin(async {})
             }),
             error_handler: LoggingErrorHandler::new(),
+            update_error_handler: None,
             ctrlc_handler: false,
+            sigterm_handler: false,
             worker_queue_size: DEFAULT_WORKER_QUEUE_SIZE,
-            distribution_f: default_distribution_function,
+            distribution_f: Arc::new(|update, _| default_distribution_function(update)),
             stack_size: DEFAULT_STACK_SIZE,
+            media_group_debounce: None,
+            webhook_replies: None,
+            worker_queue_overflow: OverflowPolicy::Block,
+            concurrency_limit: None,
+            in_flight_limit: None,
+            shutdown_timeout: None,
+            on_shutdown: None,
+            on_panic: None,
+            #[cfg(feature = "scheduler")]
+            scheduler: None,
         }
     }
 }
@@ -348,7 +870,16 @@ where
     ///
     ///  - Your bot passed to [`Dispatcher::builder`];
     ///  - An update from Telegram;
-    ///  - [`crate::types::Me`] (can be used in [`HandlerExt::filter_command`]).
+    ///  - [`crate::types::Me`] (can be used in [`HandlerExt::filter_command`]);
+    ///  - `Option<`[`MediaGroup`]`>` (see
+    ///    [`DispatcherBuilder::aggregate_media_groups`]);
+    ///  - [`WebhookReply`] (see [`DispatcherBuilder::webhook_replies`]);
+    ///  - [`CancellationToken`], which is cancelled when shutdown starts, so
+    ///    that long-running handlers can stop early (see also
+    ///    [`DispatcherBuilder::shutdown_timeout`]);
+    ///  - [`Scheduler`] (if set with [`DispatcherBuilder::scheduler`]).
+    ///
+    /// [`Scheduler`]: crate::dispatching::scheduler::Scheduler
     ///
     /// [`HandlerExt::filter_command`]: crate::dispatching::HandlerExt::filter_command
     pub async fn dispatch(&mut self)
@@ -400,15 +931,7 @@ where
     {
         // FIXME: there should be a way to check if dependency is already inserted
         let me = self.bot.get_me().send().await?;
-        self.dependencies.insert(me);
-        self.dependencies.insert(self.bot.clone());
-
-        let description = self.handler.description();
-        let allowed_updates = description.allowed_updates();
-        log::debug!("hinting allowed updates: {:?}", allowed_updates);
-        update_listener.hint_allowed_updates(&mut allowed_updates.into_iter());
-
-        let stop_token = Some(update_listener.stop_token());
+        let stop_token = self.prepare(&mut update_listener, me);
 
         // We create a new Tokio runtime in order to set the correct stack size. We do
         // it a scoped thread because Tokio runtimes cannot be nested. We need a scoped
@@ -434,6 +957,41 @@ where
         Ok(())
     }
 
+    /// Same as [`Dispatcher::try_dispatch_with_listener`], but runs on the
+    /// current runtime instead of a new one, so
+    /// [`DispatcherBuilder::stack_size`] has no effect, and uses `me` instead
+    /// of calling `get_me`.
+    pub(crate) async fn dispatch_with_listener_in_place<UListener, Eh>(
+        &mut self,
+        mut update_listener: UListener,
+        update_listener_error_handler: Arc<Eh>,
+        me: Me,
+    ) where
+        UListener: UpdateListener + Send,
+        Eh: ErrorHandler<UListener::Err> + Send + Sync,
+        UListener::Err: Debug,
+    {
+        let stop_token = self.prepare(&mut update_listener, me);
+        self.start_listening(update_listener, update_listener_error_handler, stop_token).await;
+    }
+
+    /// Inserts the bot-specific dependencies and hints `update_listener`
+    /// which updates are handled. Returns the stop token of the listener.
+    fn prepare<UListener>(&mut self, update_listener: &mut UListener, me: Me) -> Option<StopToken>
+    where
+        UListener: UpdateListener,
+    {
+        self.dependencies.insert(me);
+        self.dependencies.insert(self.bot.clone());
+
+        let description = self.handler.description();
+        let allowed_updates = description.allowed_updates();
+        log::debug!("hinting allowed updates: {:?}", allowed_updates);
+        update_listener.hint_allowed_updates(&mut allowed_updates.into_iter());
+
+        Some(update_listener.stop_token())
+    }
+
     async fn start_listening<'a, UListener, Eh>(
         &'a mut self,
         mut update_listener: UListener,
@@ -446,6 +1004,24 @@ where
     {
         self.state.start_dispatching();
 
+        // Workers clone dependencies when spawned, and all workers are stopped at the
+        // end of dispatching, so each dispatching gets a fresh token.
+        self.cancellation_token = CancellationToken::new();
+        self.dependencies.insert(self.cancellation_token.clone());
+
+        #[cfg(feature = "scheduler")]
+        let scheduler = self.scheduler.clone().map(|(scheduler, handler)| {
+            self.dependencies.insert(scheduler.clone());
+            tokio::spawn(scheduler.run(
+                handler,
+                self.dependencies.clone(),
+                Arc::clone(&self.error_handler),
+                self.cancellation_token.clone(),
+            ))
+        });
+        #[cfg(not(feature = "scheduler"))]
+        let scheduler = None;
+
         let stream = update_listener.as_stream();
         tokio::pin!(stream);
 
@@ -466,6 +1042,7 @@ where
                     if self.state.is_shutting_down() {
                         if let Some(token) = stop_token.take() {
                             log::debug!("Start shutting down dispatching...");
+                            self.cancellation_token.cancel();
                             token.stop();
                         }
                     }
@@ -473,15 +1050,44 @@ where
             }
         }
 
-        self.workers
+        self.cancellation_token.cancel();
+
+        let mut handles = self
+            .workers
             .drain()
-            .map(|(_chat_id, worker)| worker.handle)
-            .chain(self.default_worker.take().map(|worker| worker.handle))
-            .collect::<FuturesUnordered<_>>()
-            .for_each(|res| async {
+            .map(|(_key, worker)| worker.stop())
+            .chain(self.default_worker.take().map(Worker::stop))
+            .chain(scheduler)
+            .collect::<FuturesUnordered<_>>();
+
+        let drain = async {
+            while let Some(res) = handles.next().await {
                 res.expect("Failed to wait for a worker.");
-            })
-            .await;
+            }
+        };
+        let drained = match self.shutdown_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, drain).await.is_ok(),
+            None => {
+                drain.await;
+                true
+            }
+        };
+
+        if !drained {
+            log::warn!("Shutdown timeout has elapsed, aborting {} worker(s)", handles.len());
+            handles.iter().for_each(|handle| handle.abort());
+            handles
+                .for_each(|res| async {
+                    if let Err(err) = res {
+                        assert!(err.is_cancelled(), "Failed to wait for a worker: {err}");
+                    }
+                })
+                .await;
+        }
+
+        if let Some(on_shutdown) = &self.on_shutdown {
+            on_shutdown().await;
+        }
 
         self.state.done();
     }
@@ -505,18 +1111,31 @@ where
                     return;
                 }
 
-                let worker = match (self.distribution_f)(&upd) {
+                // If an update is added to a media group, it is not handled by itself, so
+                // the webhook doesn't need to wait for a reply.
+                let webhook_reply = match &self.webhook_replies {
+                    Some(replies) => replies.take(upd.id),
+                    None => WebhookReply::default(),
+                };
+
+                let ControlFlow::Continue(media_group) = self.collect_media_group(&upd) else {
+                    return;
+                };
+
+                let worker = match (self.distribution_f)(&upd, &self.dependencies) {
                     Some(key) => self.workers.entry(key).or_insert_with(|| {
                         let deps = self.dependencies.clone();
                         let handler = Arc::clone(&self.handler);
                         let default_handler = Arc::clone(&self.default_handler);
-                        let error_handler = Arc::clone(&self.error_handler);
+                        let error_handler = Arc::clone(&self.update_error_handler);
 
                         spawn_worker(
                             deps,
                             handler,
                             default_handler,
                             error_handler,
+                            self.on_panic.clone(),
+                            self.concurrency_limit.clone(),
                             Arc::clone(&self.current_number_of_active_workers),
                             Arc::clone(&self.max_number_of_active_workers),
                             self.worker_queue_size,
@@ -526,24 +1145,103 @@ where
                         let deps = self.dependencies.clone();
                         let handler = Arc::clone(&self.handler);
                         let default_handler = Arc::clone(&self.default_handler);
-                        let error_handler = Arc::clone(&self.error_handler);
+                        let error_handler = Arc::clone(&self.update_error_handler);
 
                         spawn_default_worker(
                             deps,
                             handler,
                             default_handler,
                             error_handler,
+                            self.on_panic.clone(),
+                            self.concurrency_limit.clone(),
                             self.worker_queue_size,
                         )
                     }),
                 };
 
-                worker.tx.send(upd).await.expect("TX is dead");
+                let Some(queue) =
+                    self.worker_queue_overflow.acquire(&worker.queue, &mut worker.tickets).await
+                else {
+                    let policy = self.worker_queue_overflow.clone();
+                    return self.reject(upd, media_group, &policy).await;
+                };
+                let in_flight = match &self.in_flight_limit {
+                    Some((semaphore, policy)) => {
+                        match policy.acquire(semaphore, &mut self.in_flight_tickets).await {
+                            Some(permit) => Some(permit),
+                            None => {
+                                let policy = policy.clone();
+                                return self.reject(upd, media_group, &policy).await;
+                            }
+                        }
+                    }
+                    None => None,
+                };
+
+                let ticket = Ticket::new(upd.id, queue, in_flight);
+                worker.tickets.push(&ticket);
+                if self.in_flight_limit.is_some() {
+                    self.in_flight_tickets.push(&ticket);
+                }
+
+                let queued = QueuedUpdate { update: upd, media_group, webhook_reply, ticket };
+                worker.updates.push(queued);
             }
             Err(err) => err_handler.clone().handle_error(err).await,
         }
     }
 
+    /// Adds a message to the media group it belongs to, if media groups are
+    /// aggregated.
+    ///
+    /// Returns `Break` if the message was added to a group which is already
+    /// sent to a worker, and `Continue` with the group to be sent along with
+    /// the update otherwise.
+    fn collect_media_group(
+        &mut self,
+        update: &Update,
+    ) -> ControlFlow<(), Option<Arc<PendingMediaGroup>>> {
+        let Some(debounce) = self.media_group_debounce else {
+            return ControlFlow::Continue(None);
+        };
+        let (UpdateKind::Message(message)
+        | UpdateKind::ChannelPost(message)
+        | UpdateKind::BusinessMessage(message)) = &update.kind
+        else {
+            return ControlFlow::Continue(None);
+        };
+        let Some(id) = message.media_group_id() else {
+            return ControlFlow::Continue(None);
+        };
+
+        if self.media_groups.get(id).is_some_and(|group| group.push(message)) {
+            return ControlFlow::Break(());
+        }
+
+        // Either this is the first message of the group, or the group was already
+        // handled and the message arrived too late. In both cases, start a new group.
+        self.media_groups.retain(|_, group| !group.is_closed());
+        let group = Arc::new(PendingMediaGroup::new(id.to_owned(), message.clone(), debounce));
+        self.media_groups.insert(id.to_owned(), Arc::clone(&group));
+
+        ControlFlow::Continue(Some(group))
+    }
+
+    async fn reject(
+        &mut self,
+        update: Update,
+        media_group: Option<Arc<PendingMediaGroup>>,
+        policy: &OverflowPolicy,
+    ) {
+        // The rest of the media group would be added to the dropped update otherwise
+        if let Some(group) = media_group {
+            group.close();
+            self.media_groups.remove(group.id());
+        }
+
+        policy.reject(update).await;
+    }
+
     async fn remove_inactive_workers_if_needed(&mut self) {
         let workers = self.workers.len();
         let max = self.max_number_of_active_workers.load(Ordering::Relaxed) as usize;
@@ -561,7 +1259,7 @@ where
             .workers
             .iter()
             .filter(|(_, worker)| {
-                worker.tx.capacity() == self.worker_queue_size
+                worker.queue.available_permits() == self.worker_queue_size
                     && worker.is_waiting.load(Ordering::Relaxed)
             })
             .map(|(k, _)| k)
@@ -569,13 +1267,9 @@ where
             .collect::<Vec<_>>()
             .into_iter()
             .map(|key| {
-                let Worker { tx, handle, .. } = self.workers.remove(&key).unwrap();
-
-                // Close channel, worker should stop almost immediately
-                // (it's been supposedly waiting on the channel)
-                drop(tx);
-
-                handle
+                // The worker should stop almost immediately (it's been supposedly
+                // waiting on the queue)
+                self.workers.remove(&key).unwrap().stop()
             });
 
         for handle in handles {
@@ -595,7 +1289,7 @@ where
 
 impl<R, Err, Key> Dispatcher<R, Err, Key> {
     #[cfg(feature = "ctrlc_handler")]
-    fn setup_ctrlc_handler_inner(&mut self) {
+    fn setup_ctrlc_handler_inner(&self) {
         let token = self.state.clone();
         tokio::spawn(async move {
             loop {
@@ -614,13 +1308,40 @@ impl<R, Err, Key> Dispatcher<R, Err, Key> {
             }
         });
     }
+
+    #[cfg(all(feature = "ctrlc_handler", unix))]
+    fn setup_sigterm_handler_inner(&self) {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let token = self.state.clone();
+        let mut sigterm = signal(SignalKind::terminate()).expect("Failed to listen for SIGTERM");
+        tokio::spawn(async move {
+            while sigterm.recv().await.is_some() {
+                match token.shutdown() {
+                    Ok(f) => {
+                        log::info!("SIGTERM received, trying to shutdown the dispatcher...");
+                        f.await;
+                        log::info!("dispatcher is shutdown...");
+                    }
+                    Err(_) => {
+                        log::info!(
+                            "SIGTERM received, the dispatcher isn't running, ignoring the signal"
+                        )
+                    }
+                }
+            }
+        });
+    }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn spawn_worker<Err>(
     deps: DependencyMap,
     handler: Arc<UpdateHandler<Err>>,
     default_handler: DefaultHandler,
-    error_handler: Arc<dyn ErrorHandler<Err> + Send + Sync>,
+    error_handler: Arc<dyn UpdateErrorHandler<Err> + Send + Sync>,
+    on_panic: Option<PanicHook>,
+    concurrency_limit: Option<Arc<Semaphore>>,
     current_number_of_active_workers: Arc<AtomicU32>,
     max_number_of_active_workers: Arc<AtomicU32>,
     queue_size: usize,
@@ -628,14 +1349,15 @@ fn spawn_worker<Err>(
 where
     Err: Send + Sync + 'static,
 {
-    let (tx, mut rx) = tokio::sync::mpsc::channel(queue_size);
+    let updates = Arc::new(WorkerQueue::default());
     let is_waiting = Arc::new(AtomicBool::new(true));
     let is_waiting_local = Arc::clone(&is_waiting);
 
     let deps = Arc::new(deps);
 
+    let queue_local = Arc::clone(&updates);
     let handle = tokio::spawn(async move {
-        while let Some(update) = rx.recv().await {
+        while let Some(queued) = queue_local.pop().await {
             is_waiting_local.store(false, Ordering::Relaxed);
             {
                 let current = current_number_of_active_workers.fetch_add(1, Ordering::Relaxed) + 1;
@@ -646,62 +1368,156 @@ where
             let handler = Arc::clone(&handler);
             let default_handler = Arc::clone(&default_handler);
             let error_handler = Arc::clone(&error_handler);
-
-            handle_update(update, deps, handler, default_handler, error_handler).await;
+            let on_panic = on_panic.clone();
+            let concurrency_permit = acquire_permit(concurrency_limit.clone()).await;
+
+            handle_update(
+                queued,
+                deps,
+                handler,
+                default_handler,
+                error_handler,
+                on_panic,
+                concurrency_permit,
+            )
+            .await;
 
             current_number_of_active_workers.fetch_sub(1, Ordering::Relaxed);
             is_waiting_local.store(true, Ordering::Relaxed);
         }
     });
 
-    Worker { tx, handle, is_waiting }
+    let queue = Arc::new(Semaphore::new(queue_size));
+    Worker { updates, handle, is_waiting, queue, tickets: Tickets::default() }
 }
 
 fn spawn_default_worker<Err>(
     deps: DependencyMap,
     handler: Arc<UpdateHandler<Err>>,
     default_handler: DefaultHandler,
-    error_handler: Arc<dyn ErrorHandler<Err> + Send + Sync>,
+    error_handler: Arc<dyn UpdateErrorHandler<Err> + Send + Sync>,
+    on_panic: Option<PanicHook>,
+    concurrency_limit: Option<Arc<Semaphore>>,
     queue_size: usize,
 ) -> Worker
 where
     Err: Send + Sync + 'static,
 {
-    let (tx, rx) = tokio::sync::mpsc::channel(queue_size);
+    let updates = Arc::new(WorkerQueue::default());
 
     let deps = Arc::new(deps);
 
-    let handle = tokio::spawn(ReceiverStream::new(rx).for_each_concurrent(None, move |update| {
+    // The permit is acquired before the handler future is created, so that
+    // the concurrency limit also bounds the number of futures in flight
+    let stream = stream::unfold(Arc::clone(&updates), |updates| async move {
+        let queued = updates.pop().await?;
+        Some((queued, updates))
+    })
+    .then(move |queued| acquire_permit(concurrency_limit.clone()).map(|permit| (queued, permit)));
+    let handle = tokio::spawn(stream.for_each_concurrent(None, move |(queued, permit)| {
         let deps = Arc::clone(&deps);
         let handler = Arc::clone(&handler);
         let default_handler = Arc::clone(&default_handler);
         let error_handler = Arc::clone(&error_handler);
+        let on_panic = on_panic.clone();
 
-        handle_update(update, deps, handler, default_handler, error_handler)
+        handle_update(queued, deps, handler, default_handler, error_handler, on_panic, permit)
     }));
 
-    Worker { tx, handle, is_waiting: Arc::new(AtomicBool::new(true)) }
+    Worker {
+        updates,
+        handle,
+        is_waiting: Arc::new(AtomicBool::new(true)),
+        queue: Arc::new(Semaphore::new(queue_size)),
+        tickets: Tickets::default(),
+    }
+}
+
+/// Waits for a permit of the concurrency limit, if there is one.
+async fn acquire_permit(limit: Option<Arc<Semaphore>>) -> Option<OwnedSemaphorePermit> {
+    match limit {
+        Some(semaphore) => {
+            Some(semaphore.acquire_owned().await.expect("The semaphore is never closed"))
+        }
+        None => None,
+    }
 }
 
 async fn handle_update<Err>(
-    update: Update,
+    QueuedUpdate { update, media_group, webhook_reply, ticket }: QueuedUpdate,
     deps: Arc<DependencyMap>,
     handler: Arc<UpdateHandler<Err>>,
     default_handler: DefaultHandler,
-    error_handler: Arc<dyn ErrorHandler<Err> + Send + Sync>,
+    error_handler: Arc<dyn UpdateErrorHandler<Err> + Send + Sync>,
+    on_panic: Option<PanicHook>,
+    _concurrency_permit: Option<OwnedSemaphorePermit>,
 ) where
     Err: Send + Sync + 'static,
 {
-    let mut deps = deps.deref().clone();
-    deps.insert(update);
-
-    match handler.dispatch(deps).await {
-        ControlFlow::Break(Ok(())) => {}
-        ControlFlow::Break(Err(err)) => error_handler.clone().handle_error(err).await,
-        ControlFlow::Continue(deps) => {
+    // The update could be dropped while waiting in the queue
+    let Some(_in_flight_permit) = ticket.start() else {
+        if let Some(group) = media_group {
+            group.close();
+        }
+        return;
+    };
+
+    let media_group: Option<MediaGroup> = match media_group {
+        Some(group) => Some(group.collect().await),
+        None => None,
+    };
+
+    // The handler consumes the dependencies, so the error handler gets a copy
+    // of the dependencies without the rest of the update data. Dependencies are
+    // stored in `Arc`s, so the update itself is shared, not cloned.
+    let mut error_deps = deps.deref().clone();
+    error_deps.insert(update);
+    let update: Arc<Update> = error_deps.get();
+
+    let mut handler_deps = error_deps.clone();
+    handler_deps.insert(media_group);
+    handler_deps.insert(webhook_reply);
+
+    // Only panics of the handler itself are caught, the error handler and the
+    // default handler are not guarded
+    match AssertUnwindSafe(handler.dispatch(handler_deps)).catch_unwind().await {
+        Ok(ControlFlow::Break(Ok(()))) => {}
+        Ok(ControlFlow::Break(Err(err))) => {
+            error_handler.handle_update_error(err, update, error_deps).await
+        }
+        Ok(ControlFlow::Continue(deps)) => {
             let update = deps.get();
             (default_handler)(update).await;
         }
+        Err(payload) => {
+            let panic = HandlerPanic::new(update, payload);
+            Arc::clone(&error_handler).handle_update_panic(panic.clone(), error_deps).await;
+            if let Some(on_panic) = on_panic {
+                on_panic(panic).await;
+            }
+        }
+    }
+}
+
+/// Makes [`ErrorHandler`] an [`UpdateErrorHandler`], ignoring the update.
+struct ErrorHandlerAdaptor<Err>(Arc<dyn ErrorHandler<Err> + Send + Sync>);
+
+impl<Err> UpdateErrorHandler<Err> for ErrorHandlerAdaptor<Err> {
+    fn handle_update_error(
+        self: Arc<Self>,
+        error: Err,
+        _: Arc<Update>,
+        _: DependencyMap,
+    ) -> BoxFuture<'static, ()> {
+        Arc::clone(&self.0).handle_error(error)
+    }
+
+    fn handle_update_panic(
+        self: Arc<Self>,
+        panic: HandlerPanic,
+        _: DependencyMap,
+    ) -> BoxFuture<'static, ()> {
+        Arc::clone(&self.0).handle_panic(panic)
     }
 }
 
@@ -718,6 +1534,7 @@ mod tests {
     use teloxide_core::Bot;
 
     use super::*;
+    use crate::test_utils::{self, text_message, MockApi};
 
     #[tokio::test]
     async fn test_tokio_spawn() {
@@ -733,4 +1550,288 @@ mod tests {
         .await
         .unwrap();
     }
+
+    #[test]
+    #[should_panic(expected = "worker queue size can't be 0")]
+    fn zero_worker_queue_size() {
+        let _ = Dispatcher::<_, Infallible, _>::builder(Bot::new(""), dptree::entry())
+            .worker_queue_size(0);
+    }
+
+    #[tokio::test]
+    async fn shutdown_timeout() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        use crate::{stop::mk_stop_token, types::Update, update_listeners::StatefulListener};
+
+        let listener = StatefulListener::new(
+            vec![text_message(250918540, 250918540, "hi")],
+            |updates: &mut Vec<Update>| {
+                let updates: Vec<_> = updates.drain(..).map(Ok::<_, Infallible>).collect();
+                futures::stream::iter(updates)
+            },
+            |_: &mut _| mk_stop_token().0,
+        );
+
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let shut_down = Arc::new(AtomicBool::new(false));
+
+        let handler = dptree::endpoint({
+            let cancelled = Arc::clone(&cancelled);
+            move |token: CancellationToken| {
+                let cancelled = Arc::clone(&cancelled);
+                async move {
+                    token.cancelled().await;
+                    cancelled.store(true, Ordering::SeqCst);
+                    // Ignore the cancellation, so that the worker is aborted.
+                    future::pending::<Result<(), Infallible>>().await
+                }
+            }
+        });
+
+        Dispatcher::builder(Bot::new("").set_api_url(MockApi::get_me(1).await.url()), handler)
+            .shutdown_timeout(Duration::from_millis(50))
+            .on_shutdown({
+                let shut_down = Arc::clone(&shut_down);
+                move || {
+                    let shut_down = Arc::clone(&shut_down);
+                    async move { shut_down.store(true, Ordering::SeqCst) }
+                }
+            })
+            .build()
+            .dispatch_with_listener(listener, LoggingErrorHandler::new())
+            .await;
+
+        assert!(cancelled.load(Ordering::SeqCst));
+        assert!(shut_down.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn bounded_queues() {
+        use crate::types::{Update, UpdateId};
+
+        // Handlers are stuck until the gate is opened
+        let gate = Arc::new(Semaphore::new(0));
+        let handled = Arc::new(Mutex::new(Vec::new()));
+
+        let handler = dptree::endpoint(
+            |update: Update, gate: Arc<Semaphore>, handled: Arc<Mutex<Vec<UpdateId>>>| async move {
+                gate.acquire().await.unwrap().forget();
+                handled.lock().unwrap().push(update.id);
+                Ok::<_, Infallible>(())
+            },
+        );
+        let mut dp = Dispatcher::builder(Bot::new(""), handler)
+            .dependencies(dptree::deps![Arc::clone(&gate), Arc::clone(&handled)])
+            .worker_queue_size(2)
+            .worker_queue_overflow(OverflowPolicy::DropOldest)
+            .concurrency_limit(1)
+            .in_flight_limit(3, OverflowPolicy::DropOldest)
+            .build();
+
+        let listener_errors = LoggingErrorHandler::new();
+        for id in 0..1000 {
+            // Updates from two chats go to two workers
+            let update = test_utils::update(serde_json::json!({
+                "update_id": id,
+                "message": {
+                    "message_id": id,
+                    "date": 1567927221,
+                    "chat": { "id": id % 2, "first_name": "Test", "type": "private" },
+                    "text": "hi"
+                }
+            }));
+            dp.process_update(Ok::<_, Infallible>(update), &listener_errors).await;
+            tokio::task::yield_now().await;
+
+            for worker in dp.workers.values() {
+                assert!(worker.updates.len() <= 2);
+            }
+        }
+
+        gate.add_permits(1000);
+        for (_, worker) in dp.workers.drain() {
+            worker.stop().await.unwrap();
+        }
+
+        // Only the updates which were in flight at the end are processed, the
+        // rest are dropped
+        let handled = handled.lock().unwrap();
+        assert!(!handled.is_empty() && handled.len() <= 3, "{handled:?}");
+        assert!(handled.contains(&UpdateId(999)));
+    }
+
+    #[tokio::test]
+    async fn channel_post_media_groups() {
+        use crate::dispatching::MediaGroup;
+
+        let groups = Arc::new(Mutex::new(Vec::<usize>::new()));
+        let handler = dptree::endpoint(
+            |group: Option<MediaGroup>, groups: Arc<Mutex<Vec<usize>>>| async move {
+                groups.lock().unwrap().push(group.map_or(0, |group| group.messages.len()));
+                Ok::<_, Infallible>(())
+            },
+        );
+        let mut dp = Dispatcher::builder(Bot::new(""), handler)
+            .dependencies(dptree::deps![Arc::clone(&groups)])
+            .aggregate_media_groups(Duration::from_millis(50))
+            .build();
+
+        let listener_errors = LoggingErrorHandler::new();
+        for id in 0..3 {
+            let update = test_utils::update(serde_json::json!({
+                "update_id": id,
+                "channel_post": {
+                    "message_id": id,
+                    "date": 1567927221,
+                    "chat": { "id": -1001, "title": "Channel", "type": "channel" },
+                    "media_group_id": "12543417770506682",
+                    "photo": [{
+                        "file_id": "AgADAgAD",
+                        "file_unique_id": "AQAD",
+                        "width": 90,
+                        "height": 60
+                    }]
+                }
+            }));
+            dp.process_update(Ok::<_, Infallible>(update), &listener_errors).await;
+        }
+
+        for (_, worker) in dp.workers.drain() {
+            worker.stop().await.unwrap();
+        }
+        assert_eq!(*groups.lock().unwrap(), [3]);
+    }
+
+    #[tokio::test]
+    async fn panic_isolation() {
+        use std::sync::{
+            atomic::{AtomicU32, Ordering},
+            Mutex,
+        };
+
+        use crate::{
+            stop::mk_stop_token,
+            types::{Update, UpdateId},
+            update_listeners::StatefulListener,
+        };
+
+        struct Panics(Mutex<Vec<HandlerPanic>>);
+
+        impl ErrorHandler<Infallible> for Panics {
+            fn handle_error(self: Arc<Self>, _: Infallible) -> BoxFuture<'static, ()> {
+                Box::pin(async {})
+            }
+
+            fn handle_panic(self: Arc<Self>, panic: HandlerPanic) -> BoxFuture<'static, ()> {
+                self.0.lock().unwrap().push(panic);
+                Box::pin(async {})
+            }
+        }
+
+        let mut update = text_message(250918540, 250918540, "hi");
+        update.id = UpdateId(2);
+        let listener = StatefulListener::new(
+            vec![text_message(250918540, 250918540, "panic"), update],
+            |updates: &mut Vec<Update>| {
+                let updates: Vec<_> = updates.drain(..).map(Ok::<_, Infallible>).collect();
+                futures::stream::iter(updates)
+            },
+            |_: &mut _| mk_stop_token().0,
+        );
+
+        let handled = Arc::new(AtomicU32::new(0));
+        let hooked = Arc::new(AtomicU32::new(0));
+        let panics = Arc::new(Panics(Mutex::new(Vec::new())));
+
+        let handler = dptree::endpoint({
+            let handled = Arc::clone(&handled);
+            move |update: Update| {
+                let handled = Arc::clone(&handled);
+                async move {
+                    if let UpdateKind::Message(message) = update.kind {
+                        assert_ne!(message.text(), Some("panic"), "oops");
+                    }
+                    handled.fetch_add(1, Ordering::SeqCst);
+                    Ok::<_, Infallible>(())
+                }
+            }
+        });
+
+        Dispatcher::builder(Bot::new("").set_api_url(MockApi::get_me(1).await.url()), handler)
+            .error_handler(panics.clone())
+            .on_panic({
+                let hooked = Arc::clone(&hooked);
+                move |panic| {
+                    let hooked = Arc::clone(&hooked);
+                    async move {
+                        assert_eq!(panic.update.id.0, 1);
+                        hooked.fetch_add(1, Ordering::SeqCst);
+                    }
+                }
+            })
+            .build()
+            .dispatch_with_listener(listener, LoggingErrorHandler::new())
+            .await;
+
+        assert_eq!(handled.load(Ordering::SeqCst), 1);
+        assert_eq!(hooked.load(Ordering::SeqCst), 1);
+
+        let panics = panics.0.lock().unwrap();
+        assert_eq!(panics.len(), 1);
+        assert_eq!(panics[0].update.id.0, 1);
+        assert!(panics[0].message.contains("oops"), "{}", panics[0].message);
+    }
+
+    #[tokio::test]
+    async fn update_error_handler() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        use crate::{
+            stop::mk_stop_token,
+            types::{Me, Update, UpdateId},
+            update_listeners::StatefulListener,
+        };
+
+        let mut update = text_message(250918540, 250918540, "hi");
+        update.id = UpdateId(7);
+        let listener = StatefulListener::new(
+            vec![update],
+            |updates: &mut Vec<Update>| {
+                let updates: Vec<_> = updates.drain(..).map(Ok::<_, Infallible>).collect();
+                futures::stream::iter(updates)
+            },
+            |_: &mut _| mk_stop_token().0,
+        );
+
+        let handled = Arc::new(AtomicBool::new(false));
+
+        let handler = dptree::endpoint(|| async { Err::<(), _>("oops") });
+
+        Dispatcher::builder(Bot::new("").set_api_url(MockApi::get_me(1).await.url()), handler)
+            .update_error_handler(Arc::new({
+                let handled = Arc::clone(&handled);
+                move |err: &'static str, update: Arc<Update>, deps: DependencyMap| {
+                    let handled = Arc::clone(&handled);
+                    async move {
+                        assert_eq!(err, "oops");
+                        assert_eq!(update.id.0, 7);
+
+                        // The update is shared with the dependencies, not cloned
+                        let update_dep: Arc<Update> = deps.get();
+                        assert!(Arc::ptr_eq(&update, &update_dep));
+                        let me: Arc<Me> = deps.get();
+                        assert_eq!(me.username(), "bot1");
+                        let _: Arc<Bot> = deps.get();
+
+                        handled.store(true, Ordering::SeqCst);
+                    }
+                }
+            }))
+            .build()
+            .dispatch_with_listener(listener, LoggingErrorHandler::new())
+            .await;
+
+        assert!(handled.load(Ordering::SeqCst));
+    }
 }
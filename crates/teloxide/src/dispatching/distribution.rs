@@ -1,4 +1,40 @@
-use teloxide_core::types::{ChatId, Update};
+//! Built-in distribution functions.
+//!
+//! A distribution function decides which updates are processed sequentially,
+//! see [`DispatcherBuilder::distribution_function`]. Updates with the same key
+//! are processed one by one, in the order they were received, while updates
+//! with different keys (or with no key at all) are processed concurrently.
+//!
+//! [`DispatcherBuilder::distribution_function`]: crate::dispatching::DispatcherBuilder::distribution_function
+
+use std::sync::Arc;
+
+use teloxide_core::types::{ChatId, ThreadId, Update, UpdateKind, UserId};
+
+/// A function which returns the distribution key of an update.
+///
+/// It is implemented for functions and closures taking `&Update`, and for
+/// shared `Arc<dyn Fn(&Update) -> Option<K> + Send + Sync>` ones.
+pub trait DistributionFn<K>: Send + Sync + 'static {
+    /// Returns the distribution key of `update`, or `None` if it can be
+    /// processed concurrently with any other update.
+    fn key(&self, update: &Update) -> Option<K>;
+}
+
+impl<K, F> DistributionFn<K> for F
+where
+    F: Fn(&Update) -> Option<K> + Send + Sync + 'static,
+{
+    fn key(&self, update: &Update) -> Option<K> {
+        self(update)
+    }
+}
+
+impl<K: 'static> DistributionFn<K> for Arc<dyn Fn(&Update) -> Option<K> + Send + Sync> {
+    fn key(&self, update: &Update) -> Option<K> {
+        self(update)
+    }
+}
 
 /// Default distribution key for dispatching.
 #[derive(Debug, Hash, PartialEq, Eq, Clone)]
@@ -7,3 +43,128 @@ pub struct DefaultKey(ChatId);
 pub(crate) fn default_distribution_function(update: &Update) -> Option<DefaultKey> {
     update.chat().map(|c| c.id).map(DefaultKey)
 }
+
+/// Groups updates by chat.
+///
+/// Updates from the same chat are processed sequentially. Updates without a
+/// chat (e.g. inline queries) are processed concurrently. This is the default
+/// behaviour and works well with [dialogues], which are attached to chats.
+///
+/// [dialogues]: crate::dispatching::dialogue
+#[must_use]
+pub fn by_chat(update: &Update) -> Option<ChatId> {
+    update.chat().map(|c| c.id)
+}
+
+/// Groups updates by the user who caused them.
+///
+/// Updates from the same user are processed sequentially, even if they come
+/// from different chats, so two users writing in one group are processed
+/// concurrently. Updates without a user (e.g. channel posts) are processed
+/// concurrently.
+#[must_use]
+pub fn by_user(update: &Update) -> Option<UserId> {
+    update.from().map(|user| user.id)
+}
+
+/// Groups updates by chat and forum topic.
+///
+/// Updates from the same topic of a forum are processed sequentially, while
+/// different topics of the same forum are processed concurrently. Updates
+/// from chats that aren't forums are grouped by chat, like with [`by_chat`].
+/// Updates without a chat are processed concurrently.
+#[must_use]
+pub fn by_chat_and_thread(update: &Update) -> Option<(ChatId, Option<ThreadId>)> {
+    let chat_id = update.chat()?.id;
+
+    let message = match &update.kind {
+        UpdateKind::Message(m)
+        | UpdateKind::EditedMessage(m)
+        | UpdateKind::ChannelPost(m)
+        | UpdateKind::EditedChannelPost(m)
+        | UpdateKind::BusinessMessage(m)
+        | UpdateKind::EditedBusinessMessage(m) => Some(m),
+        UpdateKind::CallbackQuery(q) => q.message.as_ref().and_then(|m| m.regular_message()),
+        _ => None,
+    };
+    let thread_id = message.filter(|m| m.is_topic_message).and_then(|m| m.thread_id);
+
+    Some((chat_id, thread_id))
+}
+
+/// Groups inline queries and chosen inline results by their sender.
+///
+/// Inline queries from the same user are processed sequentially, so a handler
+/// of an older query doesn't race with a newer one. All other updates are
+/// processed concurrently.
+#[must_use]
+pub fn by_inline_query_sender(update: &Update) -> Option<UserId> {
+    match &update.kind {
+        UpdateKind::InlineQuery(q) => Some(q.from.id),
+        UpdateKind::ChosenInlineResult(r) => Some(r.from.id),
+        _ => None,
+    }
+}
+
+/// Processes all updates sequentially, in the order they were received.
+///
+/// This gives the strongest ordering guarantees, at the cost of processing
+/// only one update at a time.
+#[must_use]
+pub fn sequential(_update: &Update) -> Option<()> {
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use teloxide_core::types::MessageId;
+
+    use super::*;
+    use crate::test_utils::update;
+
+    #[test]
+    fn keys() {
+        let topic_message = update(serde_json::json!({
+            "update_id": 1,
+            "message": {
+                "message_id": 5,
+                "message_thread_id": 4,
+                "is_topic_message": true,
+                "date": 1675229140,
+                "chat": { "id": -1001847508954i64, "is_forum": true, "title": "forum", "type": "supergroup" },
+                "from": { "id": 1253681278, "is_bot": false, "first_name": "User" },
+                "text": "blah"
+            }
+        }));
+        let inline_query = update(serde_json::json!({
+            "update_id": 2,
+            "inline_query": {
+                "id": "1",
+                "from": { "id": 1253681278, "is_bot": false, "first_name": "User" },
+                "query": "",
+                "offset": ""
+            }
+        }));
+
+        let chat_id = ChatId(-1001847508954);
+        let user_id = UserId(1253681278);
+
+        assert_eq!(by_chat(&topic_message), Some(chat_id));
+        assert_eq!(by_user(&topic_message), Some(user_id));
+        assert_eq!(
+            by_chat_and_thread(&topic_message),
+            Some((chat_id, Some(ThreadId(MessageId(4)))))
+        );
+        assert_eq!(by_inline_query_sender(&topic_message), None);
+        assert_eq!(sequential(&topic_message), Some(()));
+
+        assert_eq!(by_chat(&inline_query), None);
+        assert_eq!(by_chat_and_thread(&inline_query), None);
+        assert_eq!(by_inline_query_sender(&inline_query), Some(user_id));
+
+        type Shared = Arc<dyn Fn(&Update) -> Option<UserId> + Send + Sync>;
+        let shared: Shared = Arc::new(by_user);
+        assert_eq!(DistributionFn::key(&shared, &topic_message), Some(user_id));
+        assert_eq!(DistributionFn::key(&by_chat, &topic_message), Some(chat_id));
+    }
+}
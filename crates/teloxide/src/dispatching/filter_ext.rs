@@ -5,15 +5,19 @@
 use dptree::{di::DependencyMap, Handler};
 
 use crate::{
-    dispatching::DpHandlerDescription,
+    dispatching::{DpHandlerDescription, MediaGroup},
     types::{AllowedUpdate, Message, Update, UpdateKind},
 };
 
 macro_rules! define_ext {
-    ($ext_name:ident, $for_ty:ty => $( ($func:ident, $proj_fn:expr, $fn_doc:expr $(, $Allowed:ident)? ) ,)*) => {
+    (
+        $ext_name:ident, $for_ty:ty => $( ($func:ident, $proj_fn:expr, $fn_doc:expr $(, $Allowed:ident)? ) ,)*
+        $( extra { $($extra_sig:tt)* } { $($extra_impl:tt)* } )?
+    ) => {
         #[doc = concat!("Filter methods for [`", stringify!($for_ty), "`].")]
         pub trait $ext_name<Out>: private::Sealed {
             $( define_ext!(@sig $func, $fn_doc); )*
+            $( $($extra_sig)* )?
         }
 
         impl<Out> $ext_name<Out> for $for_ty
@@ -21,6 +25,7 @@ macro_rules! define_ext {
             Out: Send + Sync + 'static,
         {
             $( define_ext!(@impl $for_ty, $func, $proj_fn $(, $Allowed )? ); )*
+            $( $($extra_impl)* )?
         }
     };
 
@@ -134,7 +139,7 @@ define_message_ext! {
 }
 
 macro_rules! define_update_ext {
-    ($( ($func:ident, $kind:path, $Allowed:ident) ,)*) => {
+    ($( ($func:ident, $kind:path, $Allowed:ident) ,)* $( extra $($extra:tt)* )?) => {
         define_ext! {
             UpdateFilterExt, Update =>
             $((
@@ -146,6 +151,7 @@ macro_rules! define_update_ext {
                 concat!("Filters out [`", stringify!($kind), "`] objects."),
                 $Allowed
             ),)*
+            $( extra $($extra)* )?
         }
     }
 }
@@ -173,4 +179,25 @@ define_update_ext! {
     (filter_chat_join_request, UpdateKind::ChatJoinRequest, ChatJoinRequest),
     (filter_chat_boost, UpdateKind::ChatBoost, ChatBoost),
     (filter_removed_chat_boost, UpdateKind::RemovedChatBoost, RemovedChatBoost),
+    extra {
+        /// Filters out media groups (albums) collected by the dispatcher.
+        ///
+        /// Passes [`MediaGroup`] forwards. Media groups are only collected if
+        /// [`DispatcherBuilder::aggregate_media_groups`] is enabled.
+        ///
+        /// ## Dependency requirements
+        ///
+        ///  - `Option<`[`MediaGroup`]`>` (added by [`Dispatcher`])
+        ///
+        /// [`Dispatcher`]: crate::dispatching::Dispatcher
+        /// [`DispatcherBuilder::aggregate_media_groups`]: crate::dispatching::DispatcherBuilder::aggregate_media_groups
+        fn filter_media_group() -> Handler<'static, DependencyMap, Out, DpHandlerDescription>;
+    } {
+        fn filter_media_group() -> Handler<'static, DependencyMap, Out, DpHandlerDescription> {
+            dptree::filter_map_with_description(
+                DpHandlerDescription::of(AllowedUpdate::Message),
+                |media_group: Option<MediaGroup>| media_group,
+            )
+        }
+    }
 }
@@ -1,10 +1,14 @@
 use crate::{
     dispatching::{
         dialogue::{GetChatId, Storage},
+        permissions::AdminRight,
+        rate_limit::RateLimit,
         DpHandlerDescription,
     },
-    types::{Me, Message},
-    utils::command::BotCommands,
+    payloads::SendMessageSetters,
+    requests::{Request, Requester},
+    types::{ChatAdministratorRights, Me, Message, ReplyParameters},
+    utils::command::{BotCommands, ParseError},
 };
 use dptree::{di::DependencyMap, Handler};
 
@@ -35,6 +39,149 @@ pub trait HandlerExt<Output> {
     where
         C: BotCommands + Send + Sync + 'static;
 
+    /// Returns a handler that accepts a parsed command `C`, replying with help
+    /// for the command if its arguments are incorrect.
+    ///
+    /// Unlike [`filter_command`], which silently skips messages that fail to
+    /// parse, this replies with the parsing error and the help for the
+    /// command (see [`CommandDescriptions::command_help`]) when the command is
+    /// known, but [`BotCommands::parse`] fails for another reason, for example
+    /// with [`ParseError::TooFewArguments`] or [`ParseError::IncorrectFormat`].
+    /// After replying, the rest of the chain is not executed, just like with
+    /// any other filter.
+    ///
+    /// `R` is the type of the bot used to reply, usually [`Bot`].
+    ///
+    /// ## Dependency requirements
+    ///
+    ///  - `R`
+    ///  - [`crate::types::Message`]
+    ///  - [`crate::types::Me`]
+    ///
+    /// [`filter_command`]: HandlerExt::filter_command
+    /// [`CommandDescriptions::command_help`]: crate::utils::command::CommandDescriptions::command_help
+    /// [`Bot`]: crate::Bot
+    #[must_use]
+    fn filter_command_with_help<C, R>(self) -> Self
+    where
+        C: BotCommands + Send + Sync + 'static,
+        R: Requester + Clone + Send + Sync + 'static;
+
+    /// Returns a handler that accepts a payload of `/start <payload>` decoded
+    /// with `codec`.
+    ///
+    /// See [`deep_linking::filter_start_payload`].
+    ///
+    /// ## Dependency requirements
+    ///
+    ///  - [`crate::types::Message`]
+    ///  - [`crate::types::Me`]
+    ///
+    /// [`deep_linking::filter_start_payload`]: crate::utils::deep_linking::filter_start_payload
+    #[cfg(feature = "deep-linking")]
+    #[must_use]
+    fn filter_start_payload<T>(self, codec: crate::utils::deep_linking::PayloadCodec) -> Self
+    where
+        T: serde::de::DeserializeOwned + Send + Sync + 'static;
+
+    /// Returns a handler that accepts updates within `limit`, handling the
+    /// rest according to [`OnExceeded`].
+    ///
+    /// `R` is the type of the bot, used to reply to rejected updates. See
+    /// [`rate_limit`] for the details.
+    ///
+    /// ## Dependency requirements
+    ///
+    ///  - `R`
+    ///  - [`crate::types::Update`]
+    ///
+    /// [`OnExceeded`]: super::rate_limit::OnExceeded
+    /// [`rate_limit`]: super::rate_limit
+    #[must_use]
+    fn rate_limit<R>(self, limit: RateLimit) -> Self
+    where
+        R: Requester + Clone + Send + Sync + 'static;
+
+    /// Returns a handler that accepts updates from administrators (including
+    /// the owner) of the chat, and messages sent on behalf of the chat by
+    /// anonymous administrators.
+    ///
+    /// `R` is the type of the bot, used to request chat administrators. See
+    /// [`permissions`] for the details.
+    ///
+    /// ## Dependency requirements
+    ///
+    ///  - `R`
+    ///  - [`AdminCache`]
+    ///  - [`crate::types::Update`]
+    ///
+    /// [`permissions`]: super::permissions
+    /// [`AdminCache`]: super::permissions::AdminCache
+    #[must_use]
+    fn filter_chat_admin<R>(self) -> Self
+    where
+        R: Requester + Clone + Send + Sync + 'static;
+
+    /// Returns a handler that accepts updates from administrators whose rights
+    /// satisfy `has_right`, for example
+    /// `|rights: &ChatAdministratorRights| rights.can_restrict_members`.
+    ///
+    /// The owner of the chat has all the rights. See [`permissions`] for the
+    /// details.
+    ///
+    /// ## Dependency requirements
+    ///
+    ///  - `R`
+    ///  - [`AdminCache`]
+    ///  - [`crate::types::Update`]
+    ///
+    /// [`permissions`]: super::permissions
+    /// [`AdminCache`]: super::permissions::AdminCache
+    #[must_use]
+    fn filter_has_right<R, F>(self, has_right: F) -> Self
+    where
+        R: Requester + Clone + Send + Sync + 'static,
+        F: Fn(&ChatAdministratorRights) -> bool + Send + Sync + 'static;
+
+    /// Returns a handler that accepts updates from the owner of the chat.
+    ///
+    /// See [`permissions`] for the details.
+    ///
+    /// ## Dependency requirements
+    ///
+    ///  - `R`
+    ///  - [`AdminCache`]
+    ///  - [`crate::types::Update`]
+    ///
+    /// [`permissions`]: super::permissions
+    /// [`AdminCache`]: super::permissions::AdminCache
+    #[must_use]
+    fn filter_owner<R>(self) -> Self
+    where
+        R: Requester + Clone + Send + Sync + 'static;
+
+    /// Returns a handler that accepts updates from chats where the bot has all
+    /// the `rights`.
+    ///
+    /// Otherwise, logs a [`MissingRights`] warning and replies with the list
+    /// of missing rights. Updates from private chats and updates without a
+    /// chat are accepted. See [`permissions`] for the details.
+    ///
+    /// ## Dependency requirements
+    ///
+    ///  - `R`
+    ///  - [`AdminCache`]
+    ///  - [`crate::types::Update`]
+    ///  - [`crate::types::Me`]
+    ///
+    /// [`permissions`]: super::permissions
+    /// [`AdminCache`]: super::permissions::AdminCache
+    /// [`MissingRights`]: super::permissions::MissingRights
+    #[must_use]
+    fn filter_bot_has_rights<R>(self, rights: &[AdminRight]) -> Self
+    where
+        R: Requester + Clone + Send + Sync + 'static;
+
     /// Passes [`Dialogue<D, S>`] and `D` as handler dependencies.
     ///
     /// It does so by the following steps:
@@ -60,6 +207,39 @@ pub trait HandlerExt<Output> {
         <S as Storage<D>>::Error: Debug + Send,
         D: Default + Send + Sync + 'static,
         Upd: GetChatId + Clone + Send + Sync + 'static;
+
+    /// Passes [`UserData<D, S>`] of the user who sent an update as a handler
+    /// dependency. If the update has no user, the rest of the chain will not
+    /// be executed.
+    ///
+    /// ## Dependency requirements
+    ///
+    ///  - `Arc<DataStorage<D, S>>`
+    ///  - [`crate::types::Update`]
+    ///
+    /// [`UserData<D, S>`]: super::data::UserData
+    #[must_use]
+    fn enter_user_data<D, S>(self) -> Self
+    where
+        S: Storage<D> + ?Sized + Send + Sync + 'static,
+        D: Send + Sync + 'static;
+
+    /// Passes [`ChatData<D, S>`] of the chat of an update as a handler
+    /// dependency. If the update has no chat ID ([`GetChatId::chat_id`]
+    /// returns `None`), the rest of the chain will not be executed.
+    ///
+    /// ## Dependency requirements
+    ///
+    ///  - `Arc<DataStorage<D, S>>`
+    ///  - `Upd`
+    ///
+    /// [`ChatData<D, S>`]: super::data::ChatData
+    #[must_use]
+    fn enter_chat_data<Upd, D, S>(self) -> Self
+    where
+        S: Storage<D> + ?Sized + Send + Sync + 'static,
+        D: Send + Sync + 'static,
+        Upd: GetChatId + Clone + Send + Sync + 'static;
 }
 
 impl<Output> HandlerExt<Output> for Handler<'static, DependencyMap, Output, DpHandlerDescription>
@@ -80,6 +260,58 @@ where
         self.chain(filter_mention_command::<C, Output>())
     }
 
+    fn filter_command_with_help<C, R>(self) -> Self
+    where
+        C: BotCommands + Send + Sync + 'static,
+        R: Requester + Clone + Send + Sync + 'static,
+    {
+        self.chain(filter_command_with_help::<C, R, Output>())
+    }
+
+    #[cfg(feature = "deep-linking")]
+    fn filter_start_payload<T>(self, codec: crate::utils::deep_linking::PayloadCodec) -> Self
+    where
+        T: serde::de::DeserializeOwned + Send + Sync + 'static,
+    {
+        self.chain(crate::utils::deep_linking::filter_start_payload::<T, Output>(codec))
+    }
+
+    fn rate_limit<R>(self, limit: RateLimit) -> Self
+    where
+        R: Requester + Clone + Send + Sync + 'static,
+    {
+        self.chain(super::rate_limit::rate_limit::<R, Output>(limit))
+    }
+
+    fn filter_chat_admin<R>(self) -> Self
+    where
+        R: Requester + Clone + Send + Sync + 'static,
+    {
+        self.chain(super::permissions::filter_chat_admin::<R, Output>())
+    }
+
+    fn filter_has_right<R, F>(self, has_right: F) -> Self
+    where
+        R: Requester + Clone + Send + Sync + 'static,
+        F: Fn(&ChatAdministratorRights) -> bool + Send + Sync + 'static,
+    {
+        self.chain(super::permissions::filter_has_right::<R, F, Output>(has_right))
+    }
+
+    fn filter_owner<R>(self) -> Self
+    where
+        R: Requester + Clone + Send + Sync + 'static,
+    {
+        self.chain(super::permissions::filter_owner::<R, Output>())
+    }
+
+    fn filter_bot_has_rights<R>(self, rights: &[AdminRight]) -> Self
+    where
+        R: Requester + Clone + Send + Sync + 'static,
+    {
+        self.chain(super::permissions::filter_bot_has_rights::<R, Output>(rights))
+    }
+
     fn enter_dialogue<Upd, S, D>(self) -> Self
     where
         S: Storage<D> + ?Sized + Send + Sync + 'static,
@@ -89,6 +321,23 @@ where
     {
         self.chain(super::dialogue::enter::<Upd, S, D, Output>())
     }
+
+    fn enter_user_data<D, S>(self) -> Self
+    where
+        S: Storage<D> + ?Sized + Send + Sync + 'static,
+        D: Send + Sync + 'static,
+    {
+        self.chain(super::data::enter_user_data::<D, S, Output>())
+    }
+
+    fn enter_chat_data<Upd, D, S>(self) -> Self
+    where
+        S: Storage<D> + ?Sized + Send + Sync + 'static,
+        D: Send + Sync + 'static,
+        Upd: GetChatId + Clone + Send + Sync + 'static,
+    {
+        self.chain(super::data::enter_chat_data::<Upd, D, S, Output>())
+    }
 }
 
 /// Returns a handler that accepts a parsed command `C`.
@@ -148,10 +397,60 @@ where
     })
 }
 
+/// Returns a handler that accepts a parsed command `C`, replying with help for
+/// the command if its arguments are incorrect.
+///
+/// A call to this function is the same as
+/// `dptree::entry().filter_command_with_help::<C, R>()`.
+///
+/// See [`HandlerExt::filter_command_with_help`].
+///
+/// ## Dependency requirements
+///
+///  - `R`
+///  - [`crate::types::Message`]
+///  - [`crate::types::Me`]
+#[must_use]
+pub fn filter_command_with_help<C, R, Output>(
+) -> Handler<'static, DependencyMap, Output, DpHandlerDescription>
+where
+    C: BotCommands + Send + Sync + 'static,
+    R: Requester + Clone + Send + Sync + 'static,
+    Output: Send + Sync + 'static,
+{
+    dptree::filter_map_async(move |bot: R, message: Message, me: Me| async move {
+        let bot_name = me.user.username.as_deref().expect("Bots must have a username");
+        let text = message.text()?;
+
+        let error = match C::parse(text, bot_name) {
+            Ok(command) => return Some(command),
+            Err(ParseError::UnknownCommand(_) | ParseError::WrongBotName(_)) => return None,
+            Err(error) => error,
+        };
+
+        let descriptions = C::descriptions();
+        let help = descriptions.command_help(text).unwrap_or_else(|| descriptions.to_string());
+        let res = bot
+            .send_message(message.chat.id, format!("{error}\n\n{help}"))
+            .reply_parameters(ReplyParameters::new(message.id))
+            .send()
+            .await;
+
+        if let Err(err) = res {
+            log::error!("Failed to reply with a command help: {err}");
+        }
+
+        None
+    })
+}
+
 #[cfg(test)]
 #[cfg(feature = "macros")]
 mod tests {
-    use crate::{self as teloxide, dispatching::UpdateFilterExt, utils::command::BotCommands};
+    use crate::{
+        self as teloxide, dispatching::UpdateFilterExt, test_utils::MockApi,
+        utils::command::BotCommands,
+    };
     use chrono::DateTime;
     use dptree::deps;
     use teloxide_core::types::{
@@ -166,6 +465,10 @@ mod tests {
     #[command(rename_rule = "lowercase")]
     enum Cmd {
         Test,
+        #[command(description = "ban a user", parse_with = "args")]
+        Ban {
+            user: String,
+        },
     }
 
     fn make_update(text: String) -> Update {
@@ -300,4 +603,59 @@ mod tests {
         let result = h.dispatch(deps![update, me.clone()]).await;
         assert!(result.is_continue());
     }
+
+    #[tokio::test]
+    async fn test_filter_command_with_help() {
+        let h = dptree::entry().branch(
+            Update::filter_message()
+                .filter_command_with_help::<Cmd, crate::Bot>()
+                .endpoint(|| async {}),
+        );
+        let me = make_me();
+        let bot = crate::Bot::new("token");
+
+        let update = make_update("/test".to_owned());
+        let result = h.dispatch(deps![update, me.clone(), bot.clone()]).await;
+        assert!(result.is_break());
+
+        let update = make_update("/unknown".to_owned());
+        let result = h.dispatch(deps![update, me.clone(), bot.clone()]).await;
+        assert!(result.is_continue());
+
+        let update = make_update("/test@".to_owned() + "SomeOtherBot");
+        let result = h.dispatch(deps![update, me.clone(), bot.clone()]).await;
+        assert!(result.is_continue());
+    }
+
+    #[tokio::test]
+    async fn test_filter_command_with_help_replies() {
+        let h = dptree::entry().branch(
+            Update::filter_message().filter_command_with_help::<Cmd, crate::Bot>().endpoint(
+                |cmd: Cmd| async move {
+                    assert!(matches!(cmd, Cmd::Ban { user } if user == "john"));
+                },
+            ),
+        );
+        let me = make_me();
+        let api = MockApi::serve(
+            r#"{"ok":true,"result":{"message_id":1,"date":0,"chat":{"id":1,"type":"private"},"text":""}}"#,
+        )
+        .await;
+        let bot = crate::Bot::new("token").set_api_url(api.url());
+
+        let update = make_update("/ban".to_owned());
+        let result = h.dispatch(deps![update, me.clone(), bot.clone()]).await;
+        assert!(result.is_continue());
+        assert_eq!(api.requests().len(), 1);
+        assert_eq!(
+            api.requests()[0].body["text"],
+            "Too few arguments (expected 1, found 0, message = 'Missing argument `user`')\n\n/ban \
+             <user> — ban a user"
+        );
+
+        let update = make_update("/ban john".to_owned());
+        let result = h.dispatch(deps![update, me.clone(), bot.clone()]).await;
+        assert!(result.is_break());
+        assert_eq!(api.requests().len(), 1);
+    }
 }
@@ -0,0 +1,139 @@
+use std::{mem, sync::Mutex, time::Duration};
+
+use tokio::time::Instant;
+
+use crate::types::Message;
+
+/// Messages of a media group (album), collected by [`Dispatcher`].
+///
+/// See [`DispatcherBuilder::aggregate_media_groups`].
+///
+/// [`Dispatcher`]: crate::dispatching::Dispatcher
+/// [`DispatcherBuilder::aggregate_media_groups`]: crate::dispatching::DispatcherBuilder::aggregate_media_groups
+#[derive(Clone, Debug)]
+pub struct MediaGroup {
+    /// The identifier of the media group, see [`Message::media_group_id`].
+    pub id: String,
+
+    /// Messages of the media group, in the order they were received.
+    pub messages: Vec<Message>,
+}
+
+/// A media group which is still being collected.
+///
+/// The first message of a media group is sent to a worker along with its
+/// `PendingMediaGroup`, so that the group keeps the position of its first
+/// message among updates with the same distribution key. The rest of the
+/// messages are pushed into the group until the worker closes it.
+pub(crate) struct PendingMediaGroup {
+    id: String,
+    debounce: Duration,
+    state: Mutex<PendingState>,
+}
+
+struct PendingState {
+    messages: Vec<Message>,
+    deadline: Instant,
+    is_closed: bool,
+}
+
+impl PendingMediaGroup {
+    pub(crate) fn new(id: String, first: Message, debounce: Duration) -> Self {
+        Self {
+            id,
+            debounce,
+            state: Mutex::new(PendingState {
+                messages: vec![first],
+                deadline: Instant::now() + debounce,
+                is_closed: false,
+            }),
+        }
+    }
+
+    /// Adds a message to the group, postponing its deadline.
+    ///
+    /// Returns `false` if the group is already closed.
+    pub(crate) fn push(&self, message: &Message) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.is_closed {
+            return false;
+        }
+
+        state.messages.push(message.clone());
+        state.deadline = Instant::now() + self.debounce;
+        true
+    }
+
+    pub(crate) fn is_closed(&self) -> bool {
+        self.state.lock().unwrap().is_closed
+    }
+
+    /// Closes the group without collecting it, when its first message is
+    /// dropped.
+    pub(crate) fn close(&self) {
+        self.state.lock().unwrap().is_closed = true;
+    }
+
+    pub(crate) fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Waits until no messages were added to the group for the debounce
+    /// window, then closes it.
+    pub(crate) async fn collect(&self) -> MediaGroup {
+        loop {
+            let deadline = {
+                let mut state = self.state.lock().unwrap();
+                if state.deadline <= Instant::now() {
+                    state.is_closed = true;
+                    let messages = mem::take(&mut state.messages);
+                    return MediaGroup { id: self.id.clone(), messages };
+                }
+
+                state.deadline
+            };
+
+            tokio::time::sleep_until(deadline).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn photo(id: i32) -> Message {
+        serde_json::from_value(serde_json::json!({
+            "message_id": id,
+            "chat": { "id": 250918540, "first_name": "Test", "type": "private" },
+            "date": 1567927221,
+            "media_group_id": "12543417770506682",
+            "photo": [{
+                "file_id": "AgADAgAD",
+                "file_unique_id": "AQAD",
+                "width": 90,
+                "height": 60
+            }]
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn collect_media_group() {
+        let debounce = Duration::from_millis(50);
+        let group = PendingMediaGroup::new("12543417770506682".to_owned(), photo(1), debounce);
+
+        assert!(group.push(&photo(2)));
+        tokio::time::sleep(debounce / 2).await;
+        assert!(group.push(&photo(3)));
+        assert!(!group.is_closed());
+
+        let collected = group.collect().await;
+        assert_eq!(collected.id, "12543417770506682");
+        assert_eq!(collected.messages.iter().map(|m| m.id.0).collect::<Vec<_>>(), [1, 2, 3]);
+
+        // Messages arriving after the group is handled start a new one.
+        assert!(group.is_closed());
+        assert!(!group.push(&photo(4)));
+    }
+}
@@ -0,0 +1,463 @@
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    sync::{Arc, Mutex},
+};
+
+use dptree::di::DependencyMap;
+use futures::{stream::FuturesUnordered, StreamExt as _};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    dispatching::{DefaultKey, Dispatcher, DispatcherBuilder, ShutdownToken, UpdateHandler},
+    error_handlers::{ErrorHandler, LoggingErrorHandler},
+    requests::{Request, Requester},
+    types::{Me, UserId},
+    update_listeners::{self, UpdateListener},
+};
+
+/// The builder for [`MultiDispatcher`].
+pub struct MultiDispatcherBuilder<R, Err> {
+    handler: UpdateHandler<Err>,
+    dependencies: DependencyMap,
+    error_handler: Arc<dyn ErrorHandler<Err> + Send + Sync>,
+    configure: Configure<R, Err>,
+    ctrlc_handler: bool,
+}
+
+type Configure<R, Err> = Arc<
+    dyn Fn(DispatcherBuilder<R, Err, DefaultKey>) -> DispatcherBuilder<R, Err, DefaultKey>
+        + Send
+        + Sync,
+>;
+
+impl<R, Err> MultiDispatcherBuilder<R, Err>
+where
+    R: Requester + Clone + Send + Sync + 'static,
+    Err: Debug + Send + Sync + 'static,
+{
+    /// Specifies dependencies that will be shared by all bots.
+    ///
+    /// See [`DispatcherBuilder::dependencies`].
+    #[must_use]
+    pub fn dependencies(self, dependencies: DependencyMap) -> Self {
+        Self { dependencies, ..self }
+    }
+
+    /// Specifies a handler that will be called on a handler error.
+    ///
+    /// By default, it is [`LoggingErrorHandler`].
+    #[must_use]
+    pub fn error_handler(self, handler: Arc<dyn ErrorHandler<Err> + Send + Sync>) -> Self {
+        Self { error_handler: handler, ..self }
+    }
+
+    /// Specifies a function that configures the dispatcher of each bot.
+    ///
+    /// The bot, the handler, the dependencies and the error handler are
+    /// already set when the function is called. Don't enable signal
+    /// handlers of individual dispatchers, use
+    /// [`MultiDispatcherBuilder::enable_ctrlc_handler`] instead.
+    ///
+    /// ## Examples
+    ///
+    /// ```no_run
+    /// use std::{convert::Infallible, time::Duration};
+    /// use teloxide::{dispatching::MultiDispatcher, prelude::*};
+    ///
+    /// let dispatcher = MultiDispatcher::<Bot, Infallible>::builder(dptree::entry())
+    ///     .configure(|dp| dp.shutdown_timeout(Duration::from_secs(10)))
+    ///     .build();
+    /// ```
+    #[must_use]
+    pub fn configure<F>(self, configure: F) -> Self
+    where
+        F: Fn(DispatcherBuilder<R, Err, DefaultKey>) -> DispatcherBuilder<R, Err, DefaultKey>
+            + Send
+            + Sync
+            + 'static,
+    {
+        Self { configure: Arc::new(configure), ..self }
+    }
+
+    /// Enables the `^C` handler that [`shutdown`]s all bots.
+    ///
+    /// [`shutdown`]: MultiDispatcher::shutdown
+    #[cfg(feature = "ctrlc_handler")]
+    #[must_use]
+    pub fn enable_ctrlc_handler(self) -> Self {
+        Self { ctrlc_handler: true, ..self }
+    }
+
+    /// Constructs [`MultiDispatcher`].
+    #[must_use]
+    pub fn build(self) -> MultiDispatcher<R, Err> {
+        let Self { handler, dependencies, error_handler, configure, ctrlc_handler } = self;
+
+        let dp = MultiDispatcher {
+            inner: Arc::new(Inner {
+                handler,
+                dependencies,
+                error_handler,
+                configure,
+                bots: Mutex::new(HashMap::new()),
+                shutdown: CancellationToken::new(),
+            }),
+        };
+
+        // If the `ctrlc_handler` feature is not enabled, don't emit a warning.
+        let _ = ctrlc_handler;
+
+        #[cfg(feature = "ctrlc_handler")]
+        if ctrlc_handler {
+            let shutdown = dp.inner.shutdown.clone();
+            tokio::spawn(async move {
+                tokio::signal::ctrl_c().await.expect("Failed to listen for ^C");
+                log::info!("^C received, trying to shutdown all bots...");
+                shutdown.cancel();
+            });
+        }
+
+        dp
+    }
+}
+
+/// A dispatcher of updates for multiple bots.
+///
+/// All bots share the same handler tree, dependencies and error handler. Each
+/// bot is run by its own [`Dispatcher`], so handlers receive [`Me`] and the
+/// bot (`R`) of the bot which received the update, along with the other
+/// dependencies listed in [`Dispatcher::dispatch`].
+///
+/// All bots run as tasks on the current Tokio runtime, so
+/// [`DispatcherBuilder::stack_size`] has no effect here. Bots can be added and
+/// removed at runtime, the dispatcher is cheap to clone, so it can be passed to
+/// handlers as a dependency.
+///
+/// ## Examples
+///
+/// ```no_run
+/// use std::convert::Infallible;
+/// use teloxide::{dispatching::MultiDispatcher, prelude::*, types::Me};
+///
+/// # async fn run() -> Result<(), teloxide::RequestError> {
+/// let handler = Update::filter_message().endpoint(|bot: Bot, me: Me, msg: Message| async move {
+///     bot.send_message(msg.chat.id, format!("Hi, I'm @{}", me.username())).await?;
+///     respond(())
+/// });
+///
+/// let dispatcher = MultiDispatcher::builder(handler).enable_ctrlc_handler().build();
+///
+/// for token in ["TOKEN_1", "TOKEN_2"] {
+///     dispatcher.add_bot(Bot::new(token)).await?;
+/// }
+///
+/// dispatcher.dispatch().await;
+/// # Ok(()) }
+/// ```
+pub struct MultiDispatcher<R, Err> {
+    inner: Arc<Inner<R, Err>>,
+}
+
+struct Inner<R, Err> {
+    handler: UpdateHandler<Err>,
+    dependencies: DependencyMap,
+    error_handler: Arc<dyn ErrorHandler<Err> + Send + Sync>,
+    configure: Configure<R, Err>,
+    bots: Mutex<HashMap<UserId, RunningBot>>,
+    shutdown: CancellationToken,
+}
+
+struct RunningBot {
+    token: ShutdownToken,
+    handle: JoinHandle<()>,
+}
+
+impl<R, Err> Clone for MultiDispatcher<R, Err> {
+    fn clone(&self) -> Self {
+        Self { inner: Arc::clone(&self.inner) }
+    }
+}
+
+impl<R, Err> MultiDispatcher<R, Err>
+where
+    R: Requester + Clone + Send + Sync + 'static,
+    Err: Debug + Send + Sync + 'static,
+{
+    /// Constructs a new [`MultiDispatcherBuilder`] with `handler`.
+    #[must_use]
+    pub fn builder(handler: UpdateHandler<Err>) -> MultiDispatcherBuilder<R, Err> {
+        MultiDispatcherBuilder {
+            handler,
+            dependencies: DependencyMap::new(),
+            error_handler: LoggingErrorHandler::new(),
+            configure: Arc::new(|dp| dp),
+            ctrlc_handler: false,
+        }
+    }
+
+    /// Starts dispatching updates of `bot`, received with long polling.
+    ///
+    /// Returns information about the bot. If the bot is already running, it
+    /// is restarted.
+    pub async fn add_bot(&self, bot: R) -> Result<Me, R::Err>
+    where
+        <R as Requester>::GetUpdates: Send,
+    {
+        let me = bot.get_me().send().await?;
+        let listener = update_listeners::polling_default(bot.clone()).await;
+        let error_handler =
+            LoggingErrorHandler::with_custom_text("An error from the update listener");
+
+        self.start(bot, me.clone(), listener, error_handler).await;
+        Ok(me)
+    }
+
+    /// Starts dispatching updates of `bot`, received with `update_listener`.
+    ///
+    /// Returns information about the bot. If the bot is already running, it
+    /// is restarted.
+    pub async fn add_bot_with_listener<UListener, Eh>(
+        &self,
+        bot: R,
+        update_listener: UListener,
+        update_listener_error_handler: Arc<Eh>,
+    ) -> Result<Me, R::Err>
+    where
+        UListener: UpdateListener + Send + 'static,
+        Eh: ErrorHandler<UListener::Err> + Send + Sync + 'static,
+        UListener::Err: Debug + Send,
+    {
+        let me = bot.get_me().send().await?;
+        self.start(bot, me.clone(), update_listener, update_listener_error_handler).await;
+        Ok(me)
+    }
+
+    /// Stops dispatching updates of the bot with the identifier `id`, waiting
+    /// until its updates are processed.
+    ///
+    /// Returns `false` if there was no such bot.
+    pub async fn remove_bot(&self, id: UserId) -> bool {
+        let bot = self.inner.bots.lock().unwrap().remove(&id);
+        match bot {
+            Some(bot) => {
+                bot.stop().await;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns identifiers of running bots.
+    #[must_use]
+    pub fn bots(&self) -> Vec<UserId> {
+        self.inner.bots.lock().unwrap().keys().copied().collect()
+    }
+
+    /// Waits until [`MultiDispatcher::shutdown`] is called (or `^C` is
+    /// received, see [`MultiDispatcherBuilder::enable_ctrlc_handler`]), then
+    /// stops all bots.
+    pub async fn dispatch(&self) {
+        self.inner.shutdown.cancelled().await;
+        self.stop_all().await;
+    }
+
+    /// Stops all bots concurrently, waiting until their updates are
+    /// processed.
+    ///
+    /// Bots added after this call are stopped immediately.
+    pub async fn shutdown(&self) {
+        self.inner.shutdown.cancel();
+        self.stop_all().await;
+    }
+
+    async fn start<UListener, Eh>(
+        &self,
+        bot: R,
+        me: Me,
+        update_listener: UListener,
+        update_listener_error_handler: Arc<Eh>,
+    ) where
+        UListener: UpdateListener + Send + 'static,
+        Eh: ErrorHandler<UListener::Err> + Send + Sync + 'static,
+        UListener::Err: Debug + Send,
+    {
+        let Inner { handler, dependencies, error_handler, configure, .. } = &*self.inner;
+
+        let builder = Dispatcher::builder(bot, handler.clone())
+            .dependencies(dependencies.clone())
+            .error_handler(Arc::clone(error_handler));
+        let mut dp = configure(builder).build();
+
+        let token = dp.shutdown_token();
+        // The dispatcher doesn't need to call `get_me` again
+        let handle = tokio::spawn({
+            let me = me.clone();
+            async move {
+                dp.dispatch_with_listener_in_place(
+                    update_listener,
+                    update_listener_error_handler,
+                    me,
+                )
+                .await
+            }
+        });
+        let running = RunningBot { token, handle };
+
+        let previous = self.inner.bots.lock().unwrap().insert(me.id, running);
+        if let Some(previous) = previous {
+            log::info!("Restarting bot @{}", me.username());
+            previous.stop().await;
+        }
+
+        if self.inner.shutdown.is_cancelled() {
+            self.stop_all().await;
+        }
+    }
+
+    async fn stop_all(&self) {
+        let bots: Vec<_> = self.inner.bots.lock().unwrap().drain().map(|(_, bot)| bot).collect();
+
+        bots.into_iter()
+            .map(RunningBot::stop)
+            .collect::<FuturesUnordered<_>>()
+            .for_each(|()| async {})
+            .await;
+    }
+}
+
+impl RunningBot {
+    async fn stop(self) {
+        let Self { token, handle } = self;
+
+        // The returned future isn't awaited, since the dispatcher could finish
+        // before it is polled. Waiting for the task is enough.
+        if token.shutdown().is_err() {
+            // The dispatcher hasn't started dispatching yet (or has already
+            // stopped), so there are no updates to wait for.
+            handle.abort();
+        }
+
+        if let Err(err) = handle.await {
+            assert!(err.is_cancelled(), "Failed to wait for a dispatcher: {err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use futures::StreamExt as _;
+    use teloxide_core::Bot;
+
+    use super::*;
+    use crate::{
+        stop::{mk_stop_token, StopFlag, StopToken},
+        test_utils::{text_message, MockApi},
+        types::Update,
+        update_listeners::StatefulListener,
+    };
+
+    // A listener which receives `updates`, then doesn't receive any updates
+    // until it is stopped.
+    fn listener(updates: Vec<Update>) -> impl UpdateListener<Err = Infallible> + Send + 'static {
+        let (token, flag) = mk_stop_token();
+        StatefulListener::new(
+            (updates, token, flag),
+            |(updates, _, flag): &mut (Vec<Update>, StopToken, StopFlag)| {
+                futures::stream::iter(std::mem::take(updates).into_iter().map(Ok))
+                    .chain(futures::stream::pending())
+                    .take_until(flag.clone())
+            },
+            |(_, token, _): &mut (Vec<Update>, StopToken, StopFlag)| token.clone(),
+        )
+    }
+
+    #[tokio::test]
+    async fn add_and_remove_bots() {
+        let dispatcher = MultiDispatcher::<Bot, Infallible>::builder(dptree::entry()).build();
+        let api = MockApi::get_me(1).await;
+        let bot = Bot::new("").set_api_url(api.url());
+
+        let me = dispatcher
+            .add_bot_with_listener(bot.clone(), listener(vec![]), LoggingErrorHandler::new())
+            .await
+            .unwrap();
+        assert_eq!(dispatcher.bots(), [me.id]);
+
+        // Restarts the bot
+        dispatcher
+            .add_bot_with_listener(bot.clone(), listener(vec![]), LoggingErrorHandler::new())
+            .await
+            .unwrap();
+        assert_eq!(dispatcher.bots(), [me.id]);
+
+        assert!(dispatcher.remove_bot(me.id).await);
+        assert!(!dispatcher.remove_bot(me.id).await);
+
+        dispatcher
+            .add_bot_with_listener(bot, listener(vec![]), LoggingErrorHandler::new())
+            .await
+            .unwrap();
+        tokio::task::yield_now().await;
+
+        let handle = tokio::spawn({
+            let dispatcher = dispatcher.clone();
+            async move { dispatcher.dispatch().await }
+        });
+        dispatcher.shutdown().await;
+        handle.await.unwrap();
+        assert!(dispatcher.bots().is_empty());
+        // `getMe` is requested once per added bot
+        assert!(api.requests().iter().all(|r| r.method == "GetMe"));
+        assert_eq!(api.requests().len(), 3);
+    }
+
+    // `#[tokio::test]` uses a single-threaded runtime, so this checks that bots
+    // don't block the threads of the runtime.
+    #[tokio::test]
+    async fn bots_share_current_runtime() {
+        let handled = Arc::new(AtomicUsize::new(0));
+        let handler = dptree::endpoint({
+            let handled = Arc::clone(&handled);
+            move || {
+                handled.fetch_add(1, Ordering::SeqCst);
+                async { Ok(()) }
+            }
+        });
+        let dispatcher = MultiDispatcher::<Bot, Infallible>::builder(handler).build();
+
+        let mut apis = Vec::new();
+        for id in [1, 2, 3] {
+            let api = MockApi::get_me(id).await;
+            let bot = Bot::new("").set_api_url(api.url());
+            let updates = vec![text_message(250918540, 250918540, "hi")];
+            dispatcher
+                .add_bot_with_listener(bot, listener(updates), LoggingErrorHandler::new())
+                .await
+                .unwrap();
+            apis.push(api);
+        }
+        assert_eq!(dispatcher.bots().len(), 3);
+
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while handled.load(Ordering::SeqCst) < 3 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("Bots didn't handle their updates");
+
+        dispatcher.shutdown().await;
+        assert!(dispatcher.bots().is_empty());
+        for api in apis {
+            assert_eq!(api.requests().len(), 1);
+        }
+    }
+}
@@ -0,0 +1,680 @@
+//! Permission filters for admin-only handlers.
+//!
+//! Checking whether a user is an administrator with [`get_chat_member`] costs a
+//! request per update. Instead, the filters from this module use
+//! [`AdminCache`], which keeps the results of [`get_chat_administrators`] for
+//! each chat:
+//!
+//!  - [`HandlerExt::filter_chat_admin`] accepts updates from administrators
+//!    (including the owner) of the chat,
+//!  - [`HandlerExt::filter_has_right`] accepts updates from administrators with
+//!    particular [`ChatAdministratorRights`],
+//!  - [`HandlerExt::filter_owner`] accepts updates from the owner of the chat,
+//!  - [`HandlerExt::filter_bot_has_rights`] accepts updates from chats where
+//!    the bot itself has the required [`AdminRight`]s, replying with the list
+//!    of missing rights otherwise.
+//!
+//! [`AdminCache`] must be passed as a dependency. Cached administrators expire
+//! after [`AdminCache::with_ttl`] and are invalidated by
+//! [`invalidate_admin_cache`], which should be the first branch of the handler
+//! tree: it makes the dispatcher receive [`ChatMember`] and [`MyChatMember`]
+//! updates and forgets a chat whenever someone is promoted or demoted there.
+//!
+//! Updates from private chats and updates without a chat or a user are never
+//! accepted by the administrator filters.
+//!
+//! ## Examples
+//!
+//! ```no_run
+//! use teloxide::{
+//!     dispatching::{
+//!         permissions::{invalidate_admin_cache, AdminCache, AdminRight},
+//!         HandlerExt,
+//!     },
+//!     prelude::*,
+//! };
+//!
+//! # async {
+//! let bot = Bot::from_env();
+//!
+//! let handler = dptree::entry().branch(invalidate_admin_cache()).branch(
+//!     Update::filter_message()
+//!         .filter_has_right::<Bot, _>(|rights| rights.can_restrict_members)
+//!         .filter_bot_has_rights::<Bot>(&[AdminRight::RestrictMembers])
+//!         .endpoint(|bot: Bot, msg: Message| async move {
+//!             bot.send_message(msg.chat.id, "Restricting...").await?;
+//!             respond(())
+//!         }),
+//! );
+//!
+//! Dispatcher::builder(bot, handler)
+//!     .dependencies(dptree::deps![AdminCache::new()])
+//!     .build()
+//!     .dispatch()
+//!     .await;
+//! # };
+//! ```
+//!
+//! [`get_chat_member`]: crate::requests::Requester::get_chat_member
+//! [`get_chat_administrators`]: crate::requests::Requester::get_chat_administrators
+//! [`HandlerExt::filter_chat_admin`]: crate::dispatching::HandlerExt::filter_chat_admin
+//! [`HandlerExt::filter_has_right`]: crate::dispatching::HandlerExt::filter_has_right
+//! [`HandlerExt::filter_owner`]: crate::dispatching::HandlerExt::filter_owner
+//! [`HandlerExt::filter_bot_has_rights`]: crate::dispatching::HandlerExt::filter_bot_has_rights
+//! [`ChatMember`]: crate::types::UpdateKind::ChatMember
+//! [`MyChatMember`]: crate::types::UpdateKind::MyChatMember
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use dptree::{di::DependencyMap, Handler};
+
+use crate::{
+    dispatching::{DpHandlerDescription, UpdateFilterExt},
+    payloads::SendMessageSetters,
+    requests::{Request, Requester},
+    types::{
+        ChatAdministratorRights, ChatId, ChatMember, ChatMemberUpdated, Me, ReplyParameters,
+        Update, UpdateKind, UserId,
+    },
+};
+
+/// A cache of chat administrators, see the [module-level docs].
+///
+/// Cloning the cache is cheap, clones share the cached administrators.
+///
+/// [module-level docs]: self
+#[derive(Clone)]
+pub struct AdminCache {
+    chats: Arc<Mutex<HashMap<ChatId, CachedAdmins>>>,
+    ttl: Duration,
+}
+
+struct CachedAdmins {
+    admins: Arc<[ChatMember]>,
+    fetched_at: Instant,
+}
+
+impl AdminCache {
+    /// Creates a cache which keeps administrators for 10 minutes.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_ttl(Duration::from_secs(10 * 60))
+    }
+
+    /// Creates a cache which keeps administrators for `ttl`.
+    ///
+    /// The cache is also invalidated by [`invalidate_admin_cache`], so `ttl`
+    /// only matters for chats where the bot doesn't receive [`ChatMember`]
+    /// updates, i.e. where the bot isn't an administrator itself.
+    ///
+    /// [`ChatMember`]: crate::types::UpdateKind::ChatMember
+    #[must_use]
+    pub fn with_ttl(ttl: Duration) -> Self {
+        Self { chats: Arc::default(), ttl }
+    }
+
+    /// Returns administrators of the chat, requesting them with
+    /// [`get_chat_administrators`] if they aren't cached.
+    ///
+    /// [`get_chat_administrators`]: crate::requests::Requester::get_chat_administrators
+    pub async fn administrators<R>(
+        &self,
+        bot: &R,
+        chat_id: ChatId,
+    ) -> Result<Arc<[ChatMember]>, R::Err>
+    where
+        R: Requester,
+    {
+        if let Some(cached) = self.chats.lock().unwrap().get(&chat_id) {
+            if cached.fetched_at.elapsed() < self.ttl {
+                return Ok(Arc::clone(&cached.admins));
+            }
+        }
+
+        let admins: Arc<[ChatMember]> = bot.get_chat_administrators(chat_id).send().await?.into();
+
+        let mut chats = self.chats.lock().unwrap();
+        chats.retain(|_, cached| cached.fetched_at.elapsed() < self.ttl);
+        chats.insert(
+            chat_id,
+            CachedAdmins { admins: Arc::clone(&admins), fetched_at: Instant::now() },
+        );
+
+        Ok(admins)
+    }
+
+    /// Returns the user if they are an administrator (or the owner) of the
+    /// chat.
+    pub async fn administrator<R>(
+        &self,
+        bot: &R,
+        chat_id: ChatId,
+        user_id: UserId,
+    ) -> Result<Option<ChatMember>, R::Err>
+    where
+        R: Requester,
+    {
+        let admins = self.administrators(bot, chat_id).await?;
+        Ok(admins.iter().find(|admin| admin.user.id == user_id).cloned())
+    }
+
+    /// Returns `rights` which the user doesn't have in the chat.
+    ///
+    /// If the user isn't an administrator, all `rights` are returned.
+    pub async fn missing_rights<R>(
+        &self,
+        bot: &R,
+        chat_id: ChatId,
+        user_id: UserId,
+        rights: &[AdminRight],
+    ) -> Result<Vec<AdminRight>, R::Err>
+    where
+        R: Requester,
+    {
+        let granted = self
+            .administrator(bot, chat_id, user_id)
+            .await?
+            .and_then(|admin| admin.kind.administrator_rights());
+
+        Ok(rights
+            .iter()
+            .copied()
+            .filter(|right| !granted.as_ref().is_some_and(|granted| right.is_granted(granted)))
+            .collect())
+    }
+
+    /// Forgets administrators of the chat.
+    pub fn invalidate(&self, chat_id: ChatId) {
+        self.chats.lock().unwrap().remove(&chat_id);
+    }
+
+    /// Forgets administrators of all chats.
+    pub fn clear(&self) {
+        self.chats.lock().unwrap().clear();
+    }
+
+    /// Forgets administrators of the chat if the update promotes or demotes
+    /// someone, or changes rights of an administrator.
+    pub fn observe(&self, update: &ChatMemberUpdated) {
+        if update.old_chat_member.is_privileged() || update.new_chat_member.is_privileged() {
+            self.invalidate(update.chat.id);
+        }
+    }
+}
+
+impl Default for AdminCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for AdminCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AdminCache")
+            .field("chats", &self.chats.lock().unwrap().len())
+            .field("ttl", &self.ttl)
+            .finish()
+    }
+}
+
+/// An administrator right, see [`ChatAdministratorRights`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AdminRight {
+    /// [`ChatAdministratorRights::can_manage_chat`].
+    ManageChat,
+    /// [`ChatAdministratorRights::can_delete_messages`].
+    DeleteMessages,
+    /// [`ChatAdministratorRights::can_manage_video_chats`].
+    ManageVideoChats,
+    /// [`ChatAdministratorRights::can_restrict_members`].
+    RestrictMembers,
+    /// [`ChatAdministratorRights::can_promote_members`].
+    PromoteMembers,
+    /// [`ChatAdministratorRights::can_change_info`].
+    ChangeInfo,
+    /// [`ChatAdministratorRights::can_invite_users`].
+    InviteUsers,
+    /// [`ChatAdministratorRights::can_post_messages`].
+    PostMessages,
+    /// [`ChatAdministratorRights::can_edit_messages`].
+    EditMessages,
+    /// [`ChatAdministratorRights::can_pin_messages`].
+    PinMessages,
+    /// [`ChatAdministratorRights::can_post_stories`].
+    PostStories,
+    /// [`ChatAdministratorRights::can_edit_stories`].
+    EditStories,
+    /// [`ChatAdministratorRights::can_delete_stories`].
+    DeleteStories,
+    /// [`ChatAdministratorRights::can_manage_topics`].
+    ManageTopics,
+}
+
+impl AdminRight {
+    /// Returns `true` if `rights` include this right.
+    #[must_use]
+    pub fn is_granted(self, rights: &ChatAdministratorRights) -> bool {
+        match self {
+            Self::ManageChat => rights.can_manage_chat,
+            Self::DeleteMessages => rights.can_delete_messages,
+            Self::ManageVideoChats => rights.can_manage_video_chats,
+            Self::RestrictMembers => rights.can_restrict_members,
+            Self::PromoteMembers => rights.can_promote_members,
+            Self::ChangeInfo => rights.can_change_info,
+            Self::InviteUsers => rights.can_invite_users,
+            Self::PostMessages => rights.can_post_messages.unwrap_or(false),
+            Self::EditMessages => rights.can_edit_messages.unwrap_or(false),
+            Self::PinMessages => rights.can_pin_messages.unwrap_or(false),
+            Self::PostStories => rights.can_post_stories.unwrap_or(false),
+            Self::EditStories => rights.can_edit_stories.unwrap_or(false),
+            Self::DeleteStories => rights.can_delete_stories.unwrap_or(false),
+            Self::ManageTopics => rights.can_manage_topics.unwrap_or(false),
+        }
+    }
+}
+
+impl fmt::Display for AdminRight {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::ManageChat => "manage the chat",
+            Self::DeleteMessages => "delete messages",
+            Self::ManageVideoChats => "manage video chats",
+            Self::RestrictMembers => "ban users",
+            Self::PromoteMembers => "add new admins",
+            Self::ChangeInfo => "change chat info",
+            Self::InviteUsers => "invite users via link",
+            Self::PostMessages => "post messages",
+            Self::EditMessages => "edit messages of others",
+            Self::PinMessages => "pin messages",
+            Self::PostStories => "post stories",
+            Self::EditStories => "edit stories of others",
+            Self::DeleteStories => "delete stories of others",
+            Self::ManageTopics => "manage topics",
+        })
+    }
+}
+
+/// Administrator rights which the bot is missing in a chat.
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+#[error("the bot is missing administrator rights in chat {chat_id}: {}", list_rights(.rights))]
+pub struct MissingRights {
+    /// The chat.
+    pub chat_id: ChatId,
+
+    /// The missing rights.
+    pub rights: Vec<AdminRight>,
+}
+
+fn list_rights(rights: &[AdminRight]) -> String {
+    rights.iter().map(AdminRight::to_string).collect::<Vec<_>>().join(", ")
+}
+
+/// Returns a handler that invalidates [`AdminCache`] on [`ChatMember`] and
+/// [`MyChatMember`] updates.
+///
+/// The handler never accepts updates, so it should be the first branch of the
+/// handler tree.
+///
+/// ## Dependency requirements
+///
+///  - [`AdminCache`]
+///  - [`crate::types::Update`]
+///
+/// [`ChatMember`]: crate::types::UpdateKind::ChatMember
+/// [`MyChatMember`]: crate::types::UpdateKind::MyChatMember
+#[must_use]
+pub fn invalidate_admin_cache<Output>(
+) -> Handler<'static, DependencyMap, Output, DpHandlerDescription>
+where
+    Output: Send + Sync + 'static,
+{
+    let observe = |cache: AdminCache, update: ChatMemberUpdated| {
+        cache.observe(&update);
+        false
+    };
+
+    dptree::entry()
+        .branch(Update::filter_chat_member().filter(observe))
+        .branch(Update::filter_my_chat_member().filter(observe))
+}
+
+/// Returns a handler that accepts updates from administrators (including the
+/// owner) of the chat.
+///
+/// A call to this function is the same as
+/// `dptree::entry().filter_chat_admin::<R>()`.
+///
+/// See [`HandlerExt::filter_chat_admin`].
+///
+/// ## Dependency requirements
+///
+///  - `R`
+///  - [`AdminCache`]
+///  - [`crate::types::Update`]
+///
+/// [`HandlerExt::filter_chat_admin`]: crate::dispatching::HandlerExt::filter_chat_admin
+#[must_use]
+pub fn filter_chat_admin<R, Output>(
+) -> Handler<'static, DependencyMap, Output, DpHandlerDescription>
+where
+    R: Requester + Clone + Send + Sync + 'static,
+    Output: Send + Sync + 'static,
+{
+    dptree::filter_async(|bot: R, cache: AdminCache, update: Update| async move {
+        is_sent_on_behalf_of_chat(&update) || sender(&bot, &cache, &update).await.is_some()
+    })
+}
+
+/// Returns a handler that accepts updates from administrators whose rights
+/// satisfy `has_right`.
+///
+/// A call to this function is the same as
+/// `dptree::entry().filter_has_right::<R, _>(has_right)`.
+///
+/// See [`HandlerExt::filter_has_right`].
+///
+/// ## Dependency requirements
+///
+///  - `R`
+///  - [`AdminCache`]
+///  - [`crate::types::Update`]
+///
+/// [`HandlerExt::filter_has_right`]: crate::dispatching::HandlerExt::filter_has_right
+#[must_use]
+pub fn filter_has_right<R, F, Output>(
+    has_right: F,
+) -> Handler<'static, DependencyMap, Output, DpHandlerDescription>
+where
+    R: Requester + Clone + Send + Sync + 'static,
+    F: Fn(&ChatAdministratorRights) -> bool + Send + Sync + 'static,
+    Output: Send + Sync + 'static,
+{
+    let has_right = Arc::new(has_right);
+    dptree::filter_async(move |bot: R, cache: AdminCache, update: Update| {
+        let has_right = Arc::clone(&has_right);
+        async move {
+            sender(&bot, &cache, &update)
+                .await
+                .and_then(|admin| admin.kind.administrator_rights())
+                .is_some_and(|rights| has_right(&rights))
+        }
+    })
+}
+
+/// Returns a handler that accepts updates from the owner of the chat.
+///
+/// A call to this function is the same as
+/// `dptree::entry().filter_owner::<R>()`.
+///
+/// See [`HandlerExt::filter_owner`].
+///
+/// ## Dependency requirements
+///
+///  - `R`
+///  - [`AdminCache`]
+///  - [`crate::types::Update`]
+///
+/// [`HandlerExt::filter_owner`]: crate::dispatching::HandlerExt::filter_owner
+#[must_use]
+pub fn filter_owner<R, Output>() -> Handler<'static, DependencyMap, Output, DpHandlerDescription>
+where
+    R: Requester + Clone + Send + Sync + 'static,
+    Output: Send + Sync + 'static,
+{
+    dptree::filter_async(|bot: R, cache: AdminCache, update: Update| async move {
+        sender(&bot, &cache, &update).await.is_some_and(|admin| admin.is_owner())
+    })
+}
+
+/// Returns a handler that accepts updates from chats where the bot has all
+/// the `rights`, replying with the list of missing rights otherwise.
+///
+/// A call to this function is the same as
+/// `dptree::entry().filter_bot_has_rights::<R>(rights)`.
+///
+/// See [`HandlerExt::filter_bot_has_rights`].
+///
+/// ## Dependency requirements
+///
+///  - `R`
+///  - [`AdminCache`]
+///  - [`crate::types::Update`]
+///  - [`crate::types::Me`]
+///
+/// [`HandlerExt::filter_bot_has_rights`]: crate::dispatching::HandlerExt::filter_bot_has_rights
+#[must_use]
+pub fn filter_bot_has_rights<R, Output>(
+    rights: &[AdminRight],
+) -> Handler<'static, DependencyMap, Output, DpHandlerDescription>
+where
+    R: Requester + Clone + Send + Sync + 'static,
+    Output: Send + Sync + 'static,
+{
+    let rights: Arc<[AdminRight]> = rights.into();
+    dptree::filter_async(move |bot: R, cache: AdminCache, update: Update, me: Me| {
+        let rights = Arc::clone(&rights);
+        async move {
+            let Some(chat) = update.chat().filter(|chat| !chat.is_private()) else {
+                return true;
+            };
+
+            let missing = match cache.missing_rights(&bot, chat.id, me.id, &rights).await {
+                Ok(missing) if missing.is_empty() => return true,
+                Ok(missing) => MissingRights { chat_id: chat.id, rights: missing },
+                Err(err) => {
+                    log::error!("Couldn't get administrators of chat {}: {err}", chat.id);
+                    return false;
+                }
+            };
+            log::warn!("{missing}");
+
+            let text = format!(
+                "I need the following administrator rights to do this: {}.",
+                list_rights(&missing.rights)
+            );
+            let res = match &update.kind {
+                UpdateKind::Message(message) => {
+                    bot.send_message(chat.id, text)
+                        .reply_parameters(ReplyParameters::new(message.id))
+                        .send()
+                        .await
+                }
+                _ => bot.send_message(chat.id, text).send().await,
+            };
+            if let Err(err) = res {
+                log::error!("Failed to reply with missing rights: {err}");
+            }
+
+            false
+        }
+    })
+}
+
+/// Returns the sender of the update if they are an administrator of the chat.
+async fn sender<R>(bot: &R, cache: &AdminCache, update: &Update) -> Option<ChatMember>
+where
+    R: Requester,
+{
+    let chat = update.chat().filter(|chat| !chat.is_private())?;
+    let user = update.from()?;
+
+    match cache.administrator(bot, chat.id, user.id).await {
+        Ok(admin) => admin,
+        Err(err) => {
+            log::error!("Couldn't get administrators of chat {}: {err}", chat.id);
+            None
+        }
+    }
+}
+
+/// Returns `true` if the update is a message sent on behalf of the chat itself,
+/// i.e. by an anonymous administrator or in a channel.
+fn is_sent_on_behalf_of_chat(update: &Update) -> bool {
+    match &update.kind {
+        UpdateKind::Message(message)
+        | UpdateKind::EditedMessage(message)
+        | UpdateKind::ChannelPost(message)
+        | UpdateKind::EditedChannelPost(message) => {
+            message.sender_chat.as_ref().is_some_and(|sender| sender.id == message.chat.id)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dptree::deps;
+    use teloxide_core::Bot;
+
+    use super::*;
+    use crate::test_utils::{me, text_message, MockApi};
+
+    const ADMINISTRATORS: &str = r#"{"ok":true,"result":[
+        {"user":{"id":1,"is_bot":false,"first_name":"Owner"},"status":"creator","is_anonymous":false},
+        {"user":{"id":2,"is_bot":false,"first_name":"Admin"},"status":"administrator","is_anonymous":false,"can_be_edited":false,"can_manage_chat":true,"can_change_info":false,"can_delete_messages":false,"can_manage_video_chats":false,"can_invite_users":false,"can_restrict_members":true,"can_promote_members":false},
+        {"user":{"id":4,"is_bot":true,"first_name":"Bot","username":"bot"},"status":"administrator","is_anonymous":false,"can_be_edited":false,"can_manage_chat":true,"can_change_info":false,"can_delete_messages":true,"can_manage_video_chats":false,"can_invite_users":false,"can_restrict_members":false,"can_promote_members":false}
+    ]}"#;
+
+    #[tokio::test]
+    async fn filters() {
+        let api = MockApi::serve(ADMINISTRATORS).await;
+        let bot = Bot::new("").set_api_url(api.url());
+        let cache = AdminCache::new();
+
+        let accepts = |handler: Handler<'static, DependencyMap, (), DpHandlerDescription>| {
+            let (bot, cache) = (bot.clone(), cache.clone());
+            move |user_id| {
+                let handler = handler.clone();
+                let deps =
+                    deps![bot.clone(), cache.clone(), text_message(-1001, user_id, "/ban"), me(4)];
+                async move { handler.dispatch(deps).await.is_break() }
+            }
+        };
+
+        let admin = accepts(filter_chat_admin::<Bot, _>().endpoint(|| async {}));
+        assert!(admin(1).await);
+        assert!(admin(2).await);
+        assert!(!admin(3).await);
+
+        let restrict = accepts(
+            filter_has_right::<Bot, _, _>(|rights| rights.can_restrict_members)
+                .endpoint(|| async {}),
+        );
+        assert!(restrict(1).await);
+        assert!(restrict(2).await);
+        assert!(!restrict(3).await);
+
+        let owner = accepts(filter_owner::<Bot, _>().endpoint(|| async {}));
+        assert!(owner(1).await);
+        assert!(!owner(2).await);
+
+        let bot_deletes = accepts(
+            filter_bot_has_rights::<Bot, _>(&[AdminRight::DeleteMessages]).endpoint(|| async {}),
+        );
+        assert!(bot_deletes(1).await);
+
+        assert_eq!(api.requests().len(), 1);
+
+        let bot_restricts = accepts(
+            filter_bot_has_rights::<Bot, _>(&[
+                AdminRight::DeleteMessages,
+                AdminRight::RestrictMembers,
+            ])
+            .endpoint(|| async {}),
+        );
+        // The reply is answered with the mock response, which is an error for
+        // `sendMessage`, but the update is rejected anyway
+        assert!(!bot_restricts(1).await);
+
+        cache.invalidate(ChatId(-1001));
+        assert!(admin(1).await);
+        assert_eq!(api.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn missing_rights() {
+        let api = MockApi::serve(ADMINISTRATORS).await;
+        let bot = Bot::new("").set_api_url(api.url());
+        let cache = AdminCache::new();
+
+        let missing = cache
+            .missing_rights(
+                &bot,
+                ChatId(-1001),
+                UserId(4),
+                &[AdminRight::DeleteMessages, AdminRight::RestrictMembers, AdminRight::PinMessages],
+            )
+            .await
+            .unwrap();
+        assert_eq!(missing, [AdminRight::RestrictMembers, AdminRight::PinMessages]);
+
+        let missing = MissingRights { chat_id: ChatId(-1001), rights: missing };
+        assert_eq!(
+            missing.to_string(),
+            "the bot is missing administrator rights in chat -1001: ban users, pin messages"
+        );
+
+        let missing =
+            cache.missing_rights(&bot, ChatId(-1001), UserId(3), &[AdminRight::ManageChat]).await;
+        assert_eq!(missing.unwrap(), [AdminRight::ManageChat]);
+    }
+
+    #[tokio::test]
+    async fn invalidation() {
+        let api = MockApi::serve(ADMINISTRATORS).await;
+        let bot = Bot::new("").set_api_url(api.url());
+        let cache = AdminCache::new();
+        let handler = invalidate_admin_cache::<()>();
+
+        let member_updated = |new_member: &str| -> Update {
+            serde_json::from_str(&format!(
+                r#"{{
+                    "update_id": 2,
+                    "chat_member": {{
+                        "chat": {{ "id": -1001, "title": "Group", "type": "supergroup" }},
+                        "from": {{ "id": 1, "is_bot": false, "first_name": "Owner" }},
+                        "date": 1567927221,
+                        "old_chat_member": {{
+                            "user": {{ "id": 3, "is_bot": false, "first_name": "Test" }},
+                            "status": "member"
+                        }},
+                        "new_chat_member": {new_member}
+                    }}
+                }}"#
+            ))
+            .unwrap()
+        };
+
+        assert!(cache.administrator(&bot, ChatId(-1001), UserId(3)).await.unwrap().is_none());
+        assert_eq!(api.requests().len(), 1);
+
+        // A member leaving the chat doesn't change administrators
+        let left = member_updated(
+            r#"{ "user": { "id": 3, "is_bot": false, "first_name": "Test" }, "status": "left" }"#,
+        );
+        let result = handler.dispatch(deps![cache.clone(), left]).await;
+        assert!(result.is_continue());
+        cache.administrators(&bot, ChatId(-1001)).await.unwrap();
+        assert_eq!(api.requests().len(), 1);
+
+        // A promotion does, so administrators are requested again
+        let promoted = member_updated(
+            r#"{
+                "user": { "id": 3, "is_bot": false, "first_name": "Test" },
+                "status": "administrator", "is_anonymous": false, "can_be_edited": false,
+                "can_manage_chat": true, "can_change_info": false, "can_delete_messages": false,
+                "can_manage_video_chats": false, "can_invite_users": false,
+                "can_restrict_members": false, "can_promote_members": false
+            }"#,
+        );
+        let result = handler.dispatch(deps![cache.clone(), promoted]).await;
+        assert!(result.is_continue());
+        cache.administrators(&bot, ChatId(-1001)).await.unwrap();
+        assert_eq!(api.requests().len(), 2);
+    }
+}
@@ -0,0 +1,454 @@
+//! Handler-level rate limiting.
+//!
+//! [`HandlerExt::rate_limit`] limits how often a handler accepts updates from
+//! the same user (or chat, or any other key), protecting expensive handlers
+//! from flooding. Unlike [`Throttle`], which limits requests sent by the bot,
+//! this limits updates received by the bot.
+//!
+//! Limits use [token bucket] semantics: a bucket holds up to `max` tokens, an
+//! accepted update takes one token, and the bucket is refilled with `max`
+//! tokens per `window`. So a user can make a burst of `max` requests, and
+//! then one request per `window / max`.
+//!
+//! Buckets are kept in a [`RateLimitStore`], [`InMemRateLimitStore`] by
+//! default. To share limits between several instances of a bot, use
+//! [`RedisRateLimitStore`].
+//!
+//! ## Rejected updates
+//!
+//! A rate limit is a filter: an update which exceeds it is handled according
+//! to [`OnExceeded`], and then the rest of the branch is skipped, like with
+//! any other filter. So the update is passed to the next sibling branch, and
+//! eventually to the default handler of the dispatcher. To keep rejected
+//! updates away from other handlers, put the rate limit before all branches
+//! it guards, e.g. `dptree::entry().rate_limit::<Bot>(limit).branch(...)`, or
+//! make the rate-limited branch the last one.
+//!
+//! ## Examples
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use teloxide::{
+//!     dispatching::{
+//!         rate_limit::{OnExceeded, RateLimit},
+//!         HandlerExt,
+//!     },
+//!     prelude::*,
+//! };
+//!
+//! let limit = RateLimit::per_user(3, Duration::from_secs(60))
+//!     .scope("search")
+//!     .on_exceeded(OnExceeded::ReplyOnce("Too many searches, try again in {seconds}s".into()));
+//!
+//! let handler = Update::filter_message().rate_limit::<Bot>(limit).endpoint(
+//!     |bot: Bot, msg: Message| async move {
+//!         bot.send_message(msg.chat.id, "Searching...").await?;
+//!         respond(())
+//!     },
+//! );
+//! ```
+//!
+//! [`HandlerExt::rate_limit`]: crate::dispatching::HandlerExt::rate_limit
+//! [`Throttle`]: crate::adaptors::Throttle
+//! [token bucket]: https://en.wikipedia.org/wiki/Token_bucket
+//! [`RedisRateLimitStore`]: crate::dispatching::rate_limit::RedisRateLimitStore
+
+mod in_mem_store;
+
+#[cfg(feature = "redis-storage")]
+mod redis_store;
+
+use std::{fmt, future::Future, sync::Arc, time::Duration};
+
+use dptree::{di::DependencyMap, Handler};
+use futures::future::BoxFuture;
+
+use crate::{
+    dispatching::DpHandlerDescription,
+    requests::{Request, Requester},
+    types::Update,
+};
+
+pub use in_mem_store::InMemRateLimitStore;
+
+#[cfg(feature = "redis-storage")]
+pub use redis_store::{RedisRateLimitStore, RedisRateLimitStoreError};
+
+/// A rate limit store with an erased error type.
+pub type ErasedRateLimitStore =
+    dyn RateLimitStore<Error = Box<dyn std::error::Error + Send + Sync>> + Send + Sync;
+
+/// Parameters of a token bucket.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Bucket {
+    /// The maximum number of tokens in the bucket.
+    pub capacity: u32,
+
+    /// The time in which an empty bucket is refilled.
+    pub window: Duration,
+}
+
+/// A result of [`RateLimitStore::take_token`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Decision {
+    /// A token was taken, the update is accepted.
+    Allowed,
+
+    /// The bucket is empty, the update is rejected.
+    Limited(Exceeded),
+}
+
+/// Information about a rejected update.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Exceeded {
+    /// The time after which the next update will be accepted.
+    pub retry_after: Duration,
+
+    /// `true` if this is the first rejected update since the last accepted
+    /// one.
+    pub first: bool,
+}
+
+/// A store of token buckets.
+pub trait RateLimitStore {
+    type Error;
+
+    /// Takes a token from the bucket identified by `key`, creating a full
+    /// bucket if there is no such bucket.
+    ///
+    /// This must be atomic, i.e. concurrent calls with the same key must not
+    /// take the same token.
+    #[must_use = "Futures are lazy and do nothing unless polled with .await"]
+    fn take_token(
+        self: Arc<Self>,
+        key: String,
+        bucket: Bucket,
+    ) -> BoxFuture<'static, Result<Decision, Self::Error>>;
+
+    /// Erases [`Self::Error`] to [`std::error::Error`].
+    #[must_use]
+    fn erase(self: Arc<Self>) -> Arc<ErasedRateLimitStore>
+    where
+        Self: Sized + Send + Sync + 'static,
+        Self::Error: std::error::Error + Send + Sync + 'static,
+    {
+        Arc::new(Eraser(self))
+    }
+}
+
+struct Eraser<S>(Arc<S>);
+
+impl<S> RateLimitStore for Eraser<S>
+where
+    S: RateLimitStore + Send + Sync + 'static,
+    S::Error: std::error::Error + Send + Sync + 'static,
+{
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+
+    fn take_token(
+        self: Arc<Self>,
+        key: String,
+        bucket: Bucket,
+    ) -> BoxFuture<'static, Result<Decision, Self::Error>> {
+        Box::pin(
+            async move { Arc::clone(&self.0).take_token(key, bucket).await.map_err(|e| e.into()) },
+        )
+    }
+}
+
+/// What to do when an update exceeds a [`RateLimit`].
+///
+/// In all cases the rest of the rate-limited branch is not executed, but
+/// sibling branches still get the update, see the [module-level docs].
+///
+/// [module-level docs]: self#rejected-updates
+#[derive(Clone, Default)]
+pub enum OnExceeded {
+    /// Ignore the update silently.
+    #[default]
+    Ignore,
+
+    /// Reply to the first rejected update with a message, ignoring the rest.
+    ///
+    /// `{seconds}` in the message is replaced with the number of seconds
+    /// until the next update is accepted. Updates without a chat (e.g. inline
+    /// queries) are ignored.
+    ReplyOnce(String),
+
+    /// Pass the rejected update to the hook.
+    Call(Arc<dyn Fn(Update, Exceeded) -> BoxFuture<'static, ()> + Send + Sync>),
+}
+
+impl OnExceeded {
+    /// Creates [`OnExceeded::Call`] from a function.
+    #[must_use]
+    pub fn call<H, Fut>(hook: H) -> Self
+    where
+        H: Fn(Update, Exceeded) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        Self::Call(Arc::new(move |upd, exceeded| Box::pin(hook(upd, exceeded))))
+    }
+}
+
+impl fmt::Debug for OnExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ignore => f.write_str("Ignore"),
+            Self::ReplyOnce(text) => f.debug_tuple("ReplyOnce").field(text).finish(),
+            Self::Call(_) => f.write_str("Call(..)"),
+        }
+    }
+}
+
+type KeyFn = Arc<dyn Fn(&Update) -> Option<String> + Send + Sync>;
+
+/// A limit of updates accepted by a handler, see the [module-level docs].
+///
+/// [module-level docs]: self
+#[derive(Clone)]
+pub struct RateLimit {
+    bucket: Bucket,
+    scope: String,
+    key: KeyFn,
+    on_exceeded: OnExceeded,
+    store: Arc<ErasedRateLimitStore>,
+}
+
+impl RateLimit {
+    /// Creates a limit of `max` updates per `window` for every user.
+    ///
+    /// Updates without a user (e.g. channel posts) aren't limited.
+    ///
+    /// ## Panics
+    ///
+    /// If `max` is 0 or `window` is zero.
+    #[must_use]
+    #[track_caller]
+    pub fn per_user(max: u32, window: Duration) -> Self {
+        Self::new(max, window, |upd: &Update| upd.from().map(|user| user.id))
+    }
+
+    /// Creates a limit of `max` updates per `window` for every chat.
+    ///
+    /// Updates without a chat (e.g. inline queries) aren't limited.
+    ///
+    /// ## Panics
+    ///
+    /// If `max` is 0 or `window` is zero.
+    #[must_use]
+    #[track_caller]
+    pub fn per_chat(max: u32, window: Duration) -> Self {
+        Self::new(max, window, |upd: &Update| upd.chat().map(|chat| chat.id))
+    }
+
+    /// Creates a limit of `max` updates per `window` for every key returned by
+    /// `key`.
+    ///
+    /// Updates for which `key` returns `None` aren't limited. Functions from
+    /// [`distribution`] can be used as keys.
+    ///
+    /// ## Panics
+    ///
+    /// If `max` is 0 or `window` is zero.
+    ///
+    /// [`distribution`]: crate::dispatching::distribution
+    #[must_use]
+    #[track_caller]
+    pub fn new<F, K>(max: u32, window: Duration, key: F) -> Self
+    where
+        F: Fn(&Update) -> Option<K> + Send + Sync + 'static,
+        K: fmt::Debug,
+    {
+        assert_ne!(max, 0, "max can't be 0");
+        assert!(!window.is_zero(), "window can't be zero");
+
+        Self {
+            bucket: Bucket { capacity: max, window },
+            scope: String::new(),
+            key: Arc::new(move |upd| key(upd).map(|k| format!("{k:?}"))),
+            on_exceeded: OnExceeded::Ignore,
+            store: InMemRateLimitStore::new().erase(),
+        }
+    }
+
+    /// Sets the scope of the limit.
+    ///
+    /// Limits with different scopes have separate buckets even if they share
+    /// a store, so every limited handler (e.g. every command) should have
+    /// its own scope.
+    #[must_use]
+    pub fn scope(self, scope: impl Into<String>) -> Self {
+        Self { scope: scope.into(), ..self }
+    }
+
+    /// Sets what to do when the limit is exceeded, [`OnExceeded::Ignore`] by
+    /// default.
+    #[must_use]
+    pub fn on_exceeded(self, on_exceeded: OnExceeded) -> Self {
+        Self { on_exceeded, ..self }
+    }
+
+    /// Sets the store of buckets, a new [`InMemRateLimitStore`] by default.
+    #[must_use]
+    pub fn store<S>(self, store: Arc<S>) -> Self
+    where
+        S: RateLimitStore + Send + Sync + 'static,
+        S::Error: std::error::Error + Send + Sync + 'static,
+    {
+        Self { store: store.erase(), ..self }
+    }
+
+    /// Checks whether `update` is within the limit, handling it according to
+    /// [`OnExceeded`] otherwise.
+    async fn check<R>(&self, bot: R, update: Update) -> bool
+    where
+        R: Requester,
+    {
+        let Some(key) = (self.key)(&update) else {
+            return true;
+        };
+        let key = format!("{}:{key}", self.scope);
+
+        let exceeded = match Arc::clone(&self.store).take_token(key, self.bucket).await {
+            Ok(Decision::Allowed) => return true,
+            Ok(Decision::Limited(exceeded)) => exceeded,
+            Err(err) => {
+                // It's better to serve a flood than to ignore everyone
+                log::error!("Couldn't check a rate limit: {err}");
+                return true;
+            }
+        };
+
+        match &self.on_exceeded {
+            OnExceeded::Ignore => {
+                log::debug!("Ignoring update {} due to a rate limit", update.id.0);
+            }
+            OnExceeded::ReplyOnce(text) => {
+                if let (true, Some(chat)) = (exceeded.first, update.chat()) {
+                    let seconds = exceeded.retry_after.as_secs_f64().ceil().to_string();
+                    let text = text.replace("{seconds}", &seconds);
+                    if let Err(err) = bot.send_message(chat.id, text).send().await {
+                        log::error!("Failed to reply to a rate-limited update: {err}");
+                    }
+                }
+            }
+            OnExceeded::Call(hook) => hook(update, exceeded).await,
+        }
+
+        false
+    }
+}
+
+impl fmt::Debug for RateLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RateLimit")
+            .field("bucket", &self.bucket)
+            .field("scope", &self.scope)
+            .field("on_exceeded", &self.on_exceeded)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Returns a handler that accepts updates within `limit`.
+///
+/// A call to this function is the same as
+/// `dptree::entry().rate_limit::<R>(limit)`.
+///
+/// See [`HandlerExt::rate_limit`].
+///
+/// ## Dependency requirements
+///
+///  - `R`
+///  - [`crate::types::Update`]
+///
+/// [`HandlerExt::rate_limit`]: crate::dispatching::HandlerExt::rate_limit
+#[must_use]
+pub fn rate_limit<R, Output>(
+    limit: RateLimit,
+) -> Handler<'static, DependencyMap, Output, DpHandlerDescription>
+where
+    R: Requester + Clone + Send + Sync + 'static,
+    Output: Send + Sync + 'static,
+{
+    let limit = Arc::new(limit);
+    dptree::filter_async(move |bot: R, update: Update| {
+        let limit = Arc::clone(&limit);
+        async move { limit.check(bot, update).await }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use dptree::deps;
+    use teloxide_core::Bot;
+
+    use super::*;
+    use crate::test_utils::text_message;
+
+    #[tokio::test]
+    async fn limit_per_user() {
+        let hooked = Arc::new(AtomicU32::new(0));
+        let limit =
+            RateLimit::per_user(2, Duration::from_secs(60)).on_exceeded(OnExceeded::call({
+                let hooked = Arc::clone(&hooked);
+                move |_, exceeded| {
+                    let hooked = Arc::clone(&hooked);
+                    async move {
+                        assert!(exceeded.retry_after <= Duration::from_secs(30));
+                        hooked.fetch_add(exceeded.first.into(), Ordering::SeqCst);
+                    }
+                }
+            }));
+        let handler = rate_limit::<Bot, _>(limit).endpoint(|| async {});
+
+        let accepted = |user_id: u64| {
+            let handler = handler.clone();
+            let update = text_message(user_id as i64, user_id, "/search");
+            async move { handler.dispatch(deps![Bot::new(""), update]).await.is_break() }
+        };
+
+        assert!(accepted(1).await);
+        assert!(accepted(1).await);
+        assert!(!accepted(1).await);
+        assert!(!accepted(1).await);
+        assert!(accepted(2).await);
+
+        assert_eq!(hooked.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn rejected_updates_fall_through() {
+        let limited = Arc::new(AtomicU32::new(0));
+        let fallback = Arc::new(AtomicU32::new(0));
+
+        let counter = |count: &Arc<AtomicU32>| {
+            let count = Arc::clone(count);
+            move || {
+                count.fetch_add(1, Ordering::SeqCst);
+                async {}
+            }
+        };
+        let handler = dptree::entry()
+            .branch(
+                rate_limit::<Bot, _>(RateLimit::per_user(1, Duration::from_secs(60)))
+                    .endpoint(counter(&limited)),
+            )
+            .branch(dptree::endpoint(counter(&fallback)));
+
+        for _ in 0..3 {
+            let update = text_message(1, 1, "/search");
+            assert!(handler.dispatch(deps![Bot::new(""), update]).await.is_break());
+        }
+        assert_eq!(limited.load(Ordering::SeqCst), 1);
+        assert_eq!(fallback.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "window can't be zero")]
+    fn zero_window() {
+        let _ = RateLimit::per_user(1, Duration::ZERO);
+    }
+}
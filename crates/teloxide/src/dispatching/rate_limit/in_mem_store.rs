@@ -0,0 +1,88 @@
+use std::{collections::HashMap, convert::Infallible, sync::Arc, time::Duration};
+
+use futures::future::BoxFuture;
+use tokio::{sync::Mutex, time::Instant};
+
+use super::{Bucket, Decision, Exceeded, RateLimitStore};
+
+/// A rate limit store based on [`std::collections::HashMap`].
+///
+/// ## Note
+/// Limits are not shared between several instances of a bot. If you need
+/// that, you should use e.g. [`super::RedisRateLimitStore`] or implement your
+/// own.
+#[derive(Debug, Default)]
+pub struct InMemRateLimitStore {
+    buckets: Mutex<Buckets>,
+}
+
+#[derive(Debug, Default)]
+struct Buckets {
+    map: HashMap<String, State>,
+    // The map is pruned when it grows twice since the last pruning
+    pruned_len: usize,
+}
+
+#[derive(Debug)]
+struct State {
+    tokens: f64,
+    updated: Instant,
+    window: Duration,
+    notified: bool,
+}
+
+impl InMemRateLimitStore {
+    #[must_use]
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+}
+
+impl RateLimitStore for InMemRateLimitStore {
+    type Error = Infallible;
+
+    fn take_token(
+        self: Arc<Self>,
+        key: String,
+        Bucket { capacity, window }: Bucket,
+    ) -> BoxFuture<'static, Result<Decision, Self::Error>> {
+        Box::pin(async move {
+            let now = Instant::now();
+            let mut buckets = self.buckets.lock().await;
+
+            if buckets.map.len() >= (buckets.pruned_len * 2).max(1024) {
+                // Buckets which are refilled completely are the same as new ones
+                buckets.map.retain(|_, state| now - state.updated < state.window);
+                buckets.pruned_len = buckets.map.len();
+            }
+
+            let state = buckets.map.entry(key).or_insert(State {
+                tokens: capacity.into(),
+                updated: now,
+                window,
+                notified: false,
+            });
+
+            // Tokens per second
+            let rate = f64::from(capacity) / window.as_secs_f64();
+            let elapsed = (now - state.updated).as_secs_f64();
+            state.tokens = (state.tokens + elapsed * rate).min(capacity.into());
+            state.updated = now;
+            state.window = window;
+
+            if state.tokens >= 1.0 {
+                state.tokens -= 1.0;
+                state.notified = false;
+                return Ok(Decision::Allowed);
+            }
+
+            // `rate` is 0 or infinite for an empty capacity or window, which
+            // `RateLimit` doesn't allow, but custom buckets can still be used
+            let retry_after =
+                Duration::try_from_secs_f64((1.0 - state.tokens) / rate).unwrap_or(Duration::MAX);
+            let exceeded = Exceeded { retry_after, first: !state.notified };
+            state.notified = true;
+            Ok(Decision::Limited(exceeded))
+        })
+    }
+}
@@ -0,0 +1,109 @@
+use std::{sync::Arc, time::Duration};
+
+use deadpool_redis::{redis, CreatePoolError, PoolError, Runtime};
+use futures::future::BoxFuture;
+use thiserror::Error;
+
+use super::{Bucket, Decision, Exceeded, RateLimitStore};
+
+/// Refills and takes a token from a bucket atomically.
+///
+/// Returns `{allowed, retry_after_ms, first}`.
+const TAKE_TOKEN: &str = r"
+local capacity = tonumber(ARGV[1])
+local window = tonumber(ARGV[2])
+local now = tonumber(ARGV[3])
+
+local state = redis.call('HMGET', KEYS[1], 'tokens', 'updated', 'notified')
+local tokens = tonumber(state[1]) or capacity
+local updated = tonumber(state[2]) or now
+local notified = state[3] == '1'
+
+local rate = capacity / window
+tokens = math.min(capacity, tokens + math.max(0, now - updated) * rate)
+
+local allowed = tokens >= 1
+local first = false
+if allowed then
+    tokens = tokens - 1
+    notified = false
+else
+    first = not notified
+    notified = true
+end
+
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'updated', ARGV[3], 'notified', notified and '1' or '0')
+redis.call('PEXPIRE', KEYS[1], window)
+
+if allowed then
+    return {1, 0, 0}
+end
+return {0, math.ceil((1 - tokens) / rate), first and 1 or 0}
+";
+
+/// An error returned from [`RedisRateLimitStore`].
+#[derive(Debug, Error)]
+pub enum RedisRateLimitStoreError {
+    #[error("error from Redis: {0}")]
+    RedisError(#[from] redis::RedisError),
+
+    #[error("error creating redis pool: {0}")]
+    CreatePoolError(#[from] CreatePoolError),
+
+    #[error("redis pool error: {0}")]
+    PoolError(#[from] PoolError),
+}
+
+/// A rate limit store based on [Redis](https://redis.io/).
+///
+/// Limits are shared by all instances of a bot using the same Redis. Time is
+/// measured by the instances, so their clocks should be synchronized.
+pub struct RedisRateLimitStore {
+    pool: deadpool_redis::Pool,
+}
+
+impl RedisRateLimitStore {
+    pub async fn open(url: &str) -> Result<Arc<Self>, RedisRateLimitStoreError> {
+        let config = deadpool_redis::Config::from_url(url);
+        let pool = config.create_pool(Some(Runtime::Tokio1))?;
+
+        Ok(Arc::new(Self { pool }))
+    }
+}
+
+impl RateLimitStore for RedisRateLimitStore {
+    type Error = RedisRateLimitStoreError;
+
+    fn take_token(
+        self: Arc<Self>,
+        key: String,
+        Bucket { capacity, window }: Bucket,
+    ) -> BoxFuture<'static, Result<Decision, Self::Error>> {
+        Box::pin(async move {
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64;
+            let window = window.as_millis().max(1) as u64;
+
+            let (allowed, retry_after, first): (bool, u64, bool) = redis::cmd("EVAL")
+                .arg(TAKE_TOKEN)
+                .arg(1)
+                .arg(format!("teloxide_rate_limit:{key}"))
+                .arg(capacity)
+                .arg(window)
+                .arg(now)
+                .query_async(&mut self.pool.get().await?)
+                .await?;
+
+            if allowed {
+                return Ok(Decision::Allowed);
+            }
+
+            Ok(Decision::Limited(Exceeded {
+                retry_after: Duration::from_millis(retry_after),
+                first,
+            }))
+        })
+    }
+}
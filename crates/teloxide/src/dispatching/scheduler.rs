@@ -0,0 +1,600 @@
+//! Scheduled and delayed jobs.
+//!
+//! Bots often need to do something later: send a reminder, delete a message
+//! after a while, or post a digest every morning. [`Scheduler`] runs such
+//! jobs as part of [`Dispatcher`], so they have access to the bot and all the
+//! dependencies, and they are stopped together with dispatching.
+//!
+//! A [`Job`] is identified by a name and carries serialized data. Jobs are
+//! kept in a [`JobStore`], so with a persistent store they survive restarts.
+//! The following stores are supported out of the box:
+//!
+//!  - [`InMemJobStore`] -- a store based on [`std::collections::HashMap`].
+//!  - [`RedisJobStore`] -- a Redis-based store.
+//!  - [`SqliteJobStore`] -- an SQLite-based persistent store.
+//!  - [`PostgresJobStore`] -- a PostgreSQL-based persistent store.
+//!
+//! When a job is due, [`Dispatcher`] passes it to the job handler (see
+//! [`DispatcherBuilder::scheduler`]) along with the usual dependencies, so
+//! jobs are handled with [`dptree`] just like updates.
+//!
+//! Due jobs are not claimed atomically, so only one process may run jobs of a
+//! store, otherwise a job can run more than once. Other processes can still
+//! schedule and cancel jobs in the same store.
+//!
+//! ## Examples
+//!
+//! ```no_run
+//! # #[cfg(feature = "scheduler")]
+//! # async fn run() {
+//! use std::time::Duration;
+//! use teloxide::{
+//!     dispatching::scheduler::{self, InMemJobStore, Job, Scheduler},
+//!     prelude::*,
+//! };
+//!
+//! let bot = Bot::from_env();
+//! let scheduler = Scheduler::new(InMemJobStore::new());
+//!
+//! let handler = Update::filter_message().endpoint(
+//!     |bot: Bot, msg: Message, scheduler: Scheduler| async move {
+//!         let job = Job::after("remind", Duration::from_secs(60 * 60)).with_data(&msg.chat.id)?;
+//!         scheduler.schedule(job).await?;
+//!         bot.send_message(msg.chat.id, "I'll remind you in an hour").await?;
+//!         Ok(())
+//!     },
+//! );
+//!
+//! let jobs = dptree::entry().branch(scheduler::filter_job::<ChatId, _>("remind").endpoint(
+//!     |bot: Bot, chat_id: ChatId| async move {
+//!         bot.send_message(chat_id, "Reminder!").await?;
+//!         Ok(())
+//!     },
+//! ));
+//!
+//! Dispatcher::<_, Box<dyn std::error::Error + Send + Sync>, _>::builder(bot, handler)
+//!     .scheduler(scheduler, jobs)
+//!     .build()
+//!     .dispatch()
+//!     .await;
+//! # }
+//! ```
+//!
+//! [`Dispatcher`]: crate::dispatching::Dispatcher
+//! [`DispatcherBuilder::scheduler`]: crate::dispatching::DispatcherBuilder::scheduler
+//! [`RedisJobStore`]: crate::dispatching::scheduler::RedisJobStore
+//! [`SqliteJobStore`]: crate::dispatching::scheduler::SqliteJobStore
+//! [`PostgresJobStore`]: crate::dispatching::scheduler::PostgresJobStore
+
+mod job_store;
+
+use std::{
+    fmt::Debug,
+    pin::pin,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use chrono::{DateTime, Utc};
+use dptree::{
+    di::{DependencyMap, DependencySupplier},
+    Handler,
+};
+use futures::future;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use thiserror::Error;
+use tokio::{sync::Notify, task::JoinSet};
+use tokio_util::sync::CancellationToken;
+
+use crate::{dispatching::DpHandlerDescription, error_handlers::ErrorHandler};
+
+pub use job_store::{ErasedJobStore, InMemJobStore, JobStore};
+
+#[cfg(feature = "redis-storage")]
+pub use job_store::{RedisJobStore, RedisJobStoreError};
+
+#[cfg(any(feature = "sqlite-storage-nativetls", feature = "sqlite-storage-rustls"))]
+pub use job_store::{SqliteJobStore, SqliteJobStoreError};
+
+#[cfg(any(feature = "postgres-storage-nativetls", feature = "postgres-storage-rustls"))]
+pub use job_store::{PostgresJobStore, PostgresJobStoreError};
+
+/// A handler of jobs, see [`DispatcherBuilder::scheduler`].
+///
+/// [`DispatcherBuilder::scheduler`]: crate::dispatching::DispatcherBuilder::scheduler
+pub type JobHandler<Err> =
+    dptree::Handler<'static, DependencyMap, Result<(), Err>, DpHandlerDescription>;
+
+/// How long the scheduler sleeps at most before checking the store again.
+///
+/// Jobs can be added to a persistent store by other processes, which the
+/// scheduler isn't notified about.
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+
+/// How long the scheduler sleeps after the store fails for the first time in a
+/// row. The delay doubles with each failure, up to [`MAX_SLEEP`].
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+
+/// An identifier of a [`Job`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct JobId(pub String);
+
+impl JobId {
+    /// Generates a new unique identifier.
+    #[must_use]
+    pub fn generate() -> Self {
+        static COUNTER: AtomicU32 = AtomicU32::new(0);
+
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+        let counter = COUNTER.fetch_add(1, Ordering::Relaxed);
+        Self(format!("{nanos:x}-{counter:x}"))
+    }
+}
+
+/// When a [`Job`] runs.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Schedule {
+    /// The job runs once, then it is removed from the store.
+    Once,
+
+    /// The job runs according to a cron expression, see [`Job::cron`].
+    Cron(String),
+}
+
+/// A job to be run by [`Scheduler`].
+///
+/// When the job is due, it is passed to the job handler as a dependency.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Job {
+    id: JobId,
+    name: String,
+    schedule: Schedule,
+    next_run: DateTime<Utc>,
+    // JSON, so that it doesn't depend on the serializer of the store.
+    data: Vec<u8>,
+}
+
+impl Job {
+    /// Creates a job which runs once at `time`.
+    #[must_use]
+    pub fn at(name: impl Into<String>, time: DateTime<Utc>) -> Self {
+        Self {
+            id: JobId::generate(),
+            name: name.into(),
+            schedule: Schedule::Once,
+            next_run: time,
+            data: b"null".to_vec(),
+        }
+    }
+
+    /// Creates a job which runs once after `delay`.
+    #[must_use]
+    pub fn after(name: impl Into<String>, delay: Duration) -> Self {
+        let delay = chrono::Duration::from_std(delay).unwrap_or(chrono::Duration::MAX);
+        let time = Utc::now().checked_add_signed(delay).unwrap_or(DateTime::<Utc>::MAX_UTC);
+        Self::at(name, time)
+    }
+
+    /// Creates a job which runs according to a cron `expression`.
+    ///
+    /// The expression has fields for seconds, minutes, hours, days of month,
+    /// months, days of week and, optionally, years, all in UTC. For example,
+    /// `"0 0 9 * * Mon-Fri"` means "at 9:00 on weekdays". See the [`cron`]
+    /// crate for the details.
+    ///
+    /// [`cron`]: https://docs.rs/cron
+    pub fn cron(name: impl Into<String>, expression: &str) -> Result<Self, cron::error::Error> {
+        let next_run = next_cron_run(expression, Utc::now())?.unwrap_or(DateTime::<Utc>::MAX_UTC);
+        Ok(Self { schedule: Schedule::Cron(expression.to_owned()), ..Self::at(name, next_run) })
+    }
+
+    /// Sets the identifier of the job.
+    ///
+    /// Scheduling a job replaces a job with the same identifier, so a fixed
+    /// identifier is useful for jobs which are scheduled on every start of a
+    /// bot, e.g. periodic digests. By default, a unique identifier is
+    /// generated.
+    #[must_use]
+    pub fn with_id(self, id: impl Into<String>) -> Self {
+        Self { id: JobId(id.into()), ..self }
+    }
+
+    /// Sets the data of the job, see [`Job::data`].
+    pub fn with_data<T>(self, data: &T) -> Result<Self, serde_json::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        Ok(Self { data: serde_json::to_vec(data)?, ..self })
+    }
+
+    /// Returns the identifier of the job.
+    #[must_use]
+    pub fn id(&self) -> &JobId {
+        &self.id
+    }
+
+    /// Returns the name of the job.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the schedule of the job.
+    #[must_use]
+    pub fn schedule(&self) -> &Schedule {
+        &self.schedule
+    }
+
+    /// Returns when the job runs next time.
+    #[must_use]
+    pub fn next_run(&self) -> DateTime<Utc> {
+        self.next_run
+    }
+
+    /// Deserializes the data of the job, set with [`Job::with_data`].
+    ///
+    /// A job without data has the data `()`.
+    pub fn data<T>(&self) -> Result<T, serde_json::Error>
+    where
+        T: DeserializeOwned,
+    {
+        serde_json::from_slice(&self.data)
+    }
+
+    /// Returns the job with its next run after `now`, or `None` if the job
+    /// shouldn't run anymore.
+    fn next(&self, now: DateTime<Utc>) -> Option<Self> {
+        let Schedule::Cron(expression) = &self.schedule else {
+            return None;
+        };
+
+        match next_cron_run(expression, now) {
+            Ok(next_run) => Some(Self { next_run: next_run?, ..self.clone() }),
+            Err(err) => {
+                log::error!("Invalid cron expression of job {}: {err}", self.id.0);
+                None
+            }
+        }
+    }
+}
+
+fn next_cron_run(
+    expression: &str,
+    after: DateTime<Utc>,
+) -> Result<Option<DateTime<Utc>>, cron::error::Error> {
+    Ok(cron::Schedule::from_str(expression)?.after(&after).next())
+}
+
+/// Filters jobs with the name `name`, deserializing and passing their data
+/// forwards.
+///
+/// Jobs with data which can't be deserialized into `T` are logged and
+/// ignored.
+#[must_use]
+pub fn filter_job<T, Output>(
+    name: &'static str,
+) -> Handler<'static, DependencyMap, Output, DpHandlerDescription>
+where
+    T: DeserializeOwned + Send + Sync + 'static,
+    Output: Send + Sync + 'static,
+{
+    dptree::filter_map(move |job: Job| {
+        if job.name != name {
+            return None;
+        }
+
+        job.data::<T>()
+            .map_err(|err| log::error!("Couldn't deserialize data of job {}: {err}", job.id.0))
+            .ok()
+    })
+}
+
+/// An error returned from [`Scheduler`].
+#[derive(Debug, Error)]
+pub enum SchedulerError {
+    #[error("job store error: {0}")]
+    Store(#[from] Box<dyn std::error::Error + Send + Sync>),
+}
+
+/// A handle to schedule jobs.
+///
+/// It is passed to handlers as a dependency when set with
+/// [`DispatcherBuilder::scheduler`]. See the [module-level docs] for the
+/// details.
+///
+/// [`DispatcherBuilder::scheduler`]: crate::dispatching::DispatcherBuilder::scheduler
+/// [module-level docs]: self
+#[derive(Clone)]
+pub struct Scheduler {
+    store: Arc<ErasedJobStore>,
+    wakeup: Arc<Notify>,
+}
+
+impl Scheduler {
+    /// Creates a scheduler which keeps jobs in `store`.
+    #[must_use]
+    pub fn new<S>(store: Arc<S>) -> Self
+    where
+        S: JobStore + Send + Sync + 'static,
+        S::Error: std::error::Error + Send + Sync + 'static,
+    {
+        Self { store: store.erase(), wakeup: <_>::default() }
+    }
+
+    /// Schedules `job`, replacing a job with the same identifier.
+    pub async fn schedule(&self, job: Job) -> Result<JobId, SchedulerError> {
+        let id = job.id.clone();
+        Arc::clone(&self.store).save_job(job).await?;
+        // Stores a permit if the scheduler isn't waiting at the moment
+        self.wakeup.notify_one();
+        Ok(id)
+    }
+
+    /// Cancels the job with the identifier `id`.
+    ///
+    /// Returns `false` if there was no such job.
+    pub async fn cancel(&self, id: &JobId) -> Result<bool, SchedulerError> {
+        Ok(Arc::clone(&self.store).remove_job(id.clone()).await?)
+    }
+
+    /// Returns the job with the identifier `id`.
+    pub async fn get(&self, id: &JobId) -> Result<Option<Job>, SchedulerError> {
+        Ok(Arc::clone(&self.store).get_job(id.clone()).await?)
+    }
+
+    /// Runs due jobs until `stop` is cancelled, then waits for running jobs.
+    ///
+    /// Jobs are rescheduled (or removed) before they run, so a job which is
+    /// interrupted, e.g. by a crash, isn't run again. A job which can't be
+    /// rescheduled isn't run, and the store is retried with a backoff, so that
+    /// the job doesn't run over and over while the store is failing.
+    pub(crate) async fn run<Err>(
+        self,
+        handler: Arc<JobHandler<Err>>,
+        dependencies: DependencyMap,
+        error_handler: Arc<dyn ErrorHandler<Err> + Send + Sync>,
+        stop: CancellationToken,
+    ) where
+        Err: Send + Sync + 'static,
+    {
+        let mut running = JoinSet::new();
+        let mut backoff = None;
+
+        while !stop.is_cancelled() {
+            let now = Utc::now();
+            let mut failed = false;
+            match Arc::clone(&self.store).due_jobs(now).await {
+                Ok(jobs) => {
+                    for job in jobs {
+                        if !self.reschedule(&job, now).await {
+                            failed = true;
+                            continue;
+                        }
+
+                        let handler = Arc::clone(&handler);
+                        let error_handler = Arc::clone(&error_handler);
+                        let mut deps = dependencies.clone();
+                        deps.insert(job);
+                        running.spawn(run_job(handler, deps, error_handler));
+                    }
+                }
+                Err(err) => {
+                    log::error!("Couldn't get due jobs: {err}");
+                    failed = true;
+                }
+            }
+
+            while running.try_join_next().is_some() {}
+
+            let mut sleep = match Arc::clone(&self.store).next_run().await {
+                Ok(Some(next_run)) => (next_run - Utc::now()).to_std().unwrap_or_default(),
+                Ok(None) => MAX_SLEEP,
+                Err(err) => {
+                    log::error!("Couldn't get the next run of jobs: {err}");
+                    MAX_SLEEP
+                }
+            };
+
+            // Jobs which failed to be rescheduled are still due
+            backoff = failed.then(|| {
+                backoff.map_or(MIN_BACKOFF, |backoff: Duration| (backoff * 2).min(MAX_SLEEP))
+            });
+            if let Some(backoff) = backoff {
+                sleep = sleep.max(backoff);
+            }
+
+            let sleep = pin!(tokio::time::sleep(sleep.min(MAX_SLEEP)));
+            let notified = pin!(self.wakeup.notified());
+            let cancelled = pin!(stop.cancelled());
+            future::select(sleep, future::select(notified, cancelled)).await;
+        }
+
+        while running.join_next().await.is_some() {}
+    }
+
+    /// Returns `false` if the job couldn't be rescheduled.
+    async fn reschedule(&self, job: &Job, now: DateTime<Utc>) -> bool {
+        let res = match job.next(now) {
+            Some(next) => Arc::clone(&self.store).save_job(next).await,
+            None => Arc::clone(&self.store).remove_job(job.id.clone()).await.map(drop),
+        };
+
+        match res {
+            Ok(()) => true,
+            Err(err) => {
+                log::error!("Couldn't reschedule job {}: {err}", job.id.0);
+                false
+            }
+        }
+    }
+}
+
+async fn run_job<Err>(
+    handler: Arc<JobHandler<Err>>,
+    deps: DependencyMap,
+    error_handler: Arc<dyn ErrorHandler<Err> + Send + Sync>,
+) where
+    Err: Send + Sync + 'static,
+{
+    match handler.dispatch(deps).await {
+        std::ops::ControlFlow::Break(Ok(())) => {}
+        std::ops::ControlFlow::Break(Err(err)) => error_handler.handle_error(err).await,
+        std::ops::ControlFlow::Continue(deps) => {
+            let job: Arc<Job> = deps.get();
+            log::warn!("Unhandled job {} ({})", job.id.0, job.name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::error_handlers::LoggingErrorHandler;
+
+    #[test]
+    fn job() {
+        let job = Job::cron("digest", "0 0 9 * * *").unwrap().with_id("digest");
+        assert_eq!(job.id(), &JobId("digest".to_owned()));
+        assert_eq!(job.next_run().format("%H:%M:%S").to_string(), "09:00:00");
+
+        let next = job.next(job.next_run()).unwrap();
+        assert_eq!(next.next_run() - job.next_run(), chrono::Duration::days(1));
+
+        assert!(Job::cron("digest", "every day").is_err());
+
+        let job = Job::after("remind", Duration::from_secs(60)).with_data(&42).unwrap();
+        assert_eq!(job.data::<i32>().unwrap(), 42);
+        assert!(job.next(Utc::now()).is_none());
+        assert!(Job::at("remind", Utc::now()).data::<()>().is_ok());
+    }
+
+    #[tokio::test]
+    async fn run_jobs() {
+        let store = InMemJobStore::new();
+        let scheduler = Scheduler::new(Arc::clone(&store));
+        let stop = CancellationToken::new();
+
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let handler = dptree::entry().branch(filter_job::<i32, _>("remind").endpoint({
+            let received = Arc::clone(&received);
+            move |n: i32| {
+                let received = Arc::clone(&received);
+                async move {
+                    received.lock().unwrap().push(n);
+                    Ok::<_, ()>(())
+                }
+            }
+        }));
+
+        let task = tokio::spawn(scheduler.clone().run(
+            Arc::new(handler),
+            DependencyMap::new(),
+            LoggingErrorHandler::new(),
+            stop.clone(),
+        ));
+
+        let later = scheduler
+            .schedule(Job::after("remind", Duration::from_millis(100)).with_data(&2).unwrap())
+            .await
+            .unwrap();
+        scheduler
+            .schedule(Job::after("remind", Duration::from_millis(20)).with_data(&1).unwrap())
+            .await
+            .unwrap();
+        let cancelled = scheduler
+            .schedule(Job::after("remind", Duration::from_millis(50)).with_data(&3).unwrap())
+            .await
+            .unwrap();
+        assert!(scheduler.cancel(&cancelled).await.unwrap());
+
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        stop.cancel();
+        task.await.unwrap();
+
+        assert_eq!(*received.lock().unwrap(), [1, 2]);
+        assert_eq!(scheduler.get(&later).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn failed_reschedule() {
+        use std::io;
+
+        use futures::future::BoxFuture;
+
+        // A store which can't save or remove jobs
+        struct ReadOnly {
+            inner: Arc<InMemJobStore>,
+            due_calls: AtomicU32,
+        }
+
+        impl JobStore for ReadOnly {
+            type Error = io::Error;
+
+            fn save_job(self: Arc<Self>, _: Job) -> BoxFuture<'static, Result<(), Self::Error>> {
+                Box::pin(async { Err(io::Error::other("read-only")) })
+            }
+
+            fn remove_job(
+                self: Arc<Self>,
+                _: JobId,
+            ) -> BoxFuture<'static, Result<bool, Self::Error>> {
+                Box::pin(async { Err(io::Error::other("read-only")) })
+            }
+
+            fn get_job(
+                self: Arc<Self>,
+                id: JobId,
+            ) -> BoxFuture<'static, Result<Option<Job>, Self::Error>> {
+                Box::pin(async move { Ok(Arc::clone(&self.inner).get_job(id).await.unwrap()) })
+            }
+
+            fn due_jobs(
+                self: Arc<Self>,
+                now: DateTime<Utc>,
+            ) -> BoxFuture<'static, Result<Vec<Job>, Self::Error>> {
+                self.due_calls.fetch_add(1, Ordering::Relaxed);
+                Box::pin(async move { Ok(Arc::clone(&self.inner).due_jobs(now).await.unwrap()) })
+            }
+
+            fn next_run(
+                self: Arc<Self>,
+            ) -> BoxFuture<'static, Result<Option<DateTime<Utc>>, Self::Error>> {
+                Box::pin(async move { Ok(Arc::clone(&self.inner).next_run().await.unwrap()) })
+            }
+        }
+
+        let inner = InMemJobStore::new();
+        Arc::clone(&inner).save_job(Job::after("remind", Duration::ZERO)).await.unwrap();
+        let store = Arc::new(ReadOnly { inner, due_calls: AtomicU32::new(0) });
+
+        let runs = Arc::new(AtomicU32::new(0));
+        let handler = dptree::endpoint({
+            let runs = Arc::clone(&runs);
+            move || {
+                runs.fetch_add(1, Ordering::Relaxed);
+                async { Ok::<_, ()>(()) }
+            }
+        });
+
+        let stop = CancellationToken::new();
+        let task = tokio::spawn(Scheduler::new(Arc::clone(&store)).run(
+            Arc::new(handler),
+            DependencyMap::new(),
+            LoggingErrorHandler::new(),
+            stop.clone(),
+        ));
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        stop.cancel();
+        task.await.unwrap();
+
+        // The job is not run, and the store is not polled in a loop
+        assert_eq!(runs.load(Ordering::Relaxed), 0);
+        assert_eq!(store.due_calls.load(Ordering::Relaxed), 1);
+    }
+}
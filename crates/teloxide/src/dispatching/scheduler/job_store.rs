@@ -0,0 +1,111 @@
+mod in_mem_job_store;
+
+#[cfg(feature = "redis-storage")]
+mod redis_job_store;
+
+#[cfg(any(feature = "sqlite-storage-nativetls", feature = "sqlite-storage-rustls"))]
+mod sqlite_job_store;
+
+#[cfg(any(feature = "postgres-storage-nativetls", feature = "postgres-storage-rustls"))]
+mod postgres_job_store;
+
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+
+use super::{Job, JobId};
+
+pub use in_mem_job_store::InMemJobStore;
+
+#[cfg(feature = "redis-storage")]
+pub use redis_job_store::{RedisJobStore, RedisJobStoreError};
+
+#[cfg(any(feature = "sqlite-storage-nativetls", feature = "sqlite-storage-rustls"))]
+pub use sqlite_job_store::{SqliteJobStore, SqliteJobStoreError};
+
+#[cfg(any(feature = "postgres-storage-nativetls", feature = "postgres-storage-rustls"))]
+pub use postgres_job_store::{PostgresJobStore, PostgresJobStoreError};
+
+/// A job store with an erased error type.
+pub type ErasedJobStore =
+    dyn JobStore<Error = Box<dyn std::error::Error + Send + Sync>> + Send + Sync;
+
+/// A store of scheduled [`Job`]s.
+///
+/// Like dialogue [`Storage`]s, persistent stores use a [`Serializer`] to
+/// encode jobs.
+///
+/// [`Storage`]: crate::dispatching::dialogue::Storage
+/// [`Serializer`]: crate::dispatching::dialogue::Serializer
+pub trait JobStore {
+    type Error;
+
+    /// Saves `job`, replacing a job with the same identifier.
+    #[must_use = "Futures are lazy and do nothing unless polled with .await"]
+    fn save_job(self: Arc<Self>, job: Job) -> BoxFuture<'static, Result<(), Self::Error>>;
+
+    /// Removes the job with the identifier `id`.
+    ///
+    /// Returns `false` if there was no such job.
+    #[must_use = "Futures are lazy and do nothing unless polled with .await"]
+    fn remove_job(self: Arc<Self>, id: JobId) -> BoxFuture<'static, Result<bool, Self::Error>>;
+
+    /// Returns the job with the identifier `id`.
+    #[must_use = "Futures are lazy and do nothing unless polled with .await"]
+    fn get_job(self: Arc<Self>, id: JobId) -> BoxFuture<'static, Result<Option<Job>, Self::Error>>;
+
+    /// Returns jobs which should run at `now` or earlier, the earliest first.
+    #[must_use = "Futures are lazy and do nothing unless polled with .await"]
+    fn due_jobs(
+        self: Arc<Self>,
+        now: DateTime<Utc>,
+    ) -> BoxFuture<'static, Result<Vec<Job>, Self::Error>>;
+
+    /// Returns the earliest [`Job::next_run`] of all jobs.
+    #[must_use = "Futures are lazy and do nothing unless polled with .await"]
+    fn next_run(self: Arc<Self>) -> BoxFuture<'static, Result<Option<DateTime<Utc>>, Self::Error>>;
+
+    /// Erases [`Self::Error`] to [`std::error::Error`].
+    #[must_use]
+    fn erase(self: Arc<Self>) -> Arc<ErasedJobStore>
+    where
+        Self: Sized + Send + Sync + 'static,
+        Self::Error: std::error::Error + Send + Sync + 'static,
+    {
+        Arc::new(Eraser(self))
+    }
+}
+
+struct Eraser<S>(Arc<S>);
+
+impl<S> JobStore for Eraser<S>
+where
+    S: JobStore + Send + Sync + 'static,
+    S::Error: std::error::Error + Send + Sync + 'static,
+{
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+
+    fn save_job(self: Arc<Self>, job: Job) -> BoxFuture<'static, Result<(), Self::Error>> {
+        Box::pin(async move { Arc::clone(&self.0).save_job(job).await.map_err(|e| e.into()) })
+    }
+
+    fn remove_job(self: Arc<Self>, id: JobId) -> BoxFuture<'static, Result<bool, Self::Error>> {
+        Box::pin(async move { Arc::clone(&self.0).remove_job(id).await.map_err(|e| e.into()) })
+    }
+
+    fn get_job(self: Arc<Self>, id: JobId) -> BoxFuture<'static, Result<Option<Job>, Self::Error>> {
+        Box::pin(async move { Arc::clone(&self.0).get_job(id).await.map_err(|e| e.into()) })
+    }
+
+    fn due_jobs(
+        self: Arc<Self>,
+        now: DateTime<Utc>,
+    ) -> BoxFuture<'static, Result<Vec<Job>, Self::Error>> {
+        Box::pin(async move { Arc::clone(&self.0).due_jobs(now).await.map_err(|e| e.into()) })
+    }
+
+    fn next_run(self: Arc<Self>) -> BoxFuture<'static, Result<Option<DateTime<Utc>>, Self::Error>> {
+        Box::pin(async move { Arc::clone(&self.0).next_run().await.map_err(|e| e.into()) })
+    }
+}
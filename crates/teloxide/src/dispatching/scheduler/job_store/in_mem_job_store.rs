@@ -0,0 +1,61 @@
+use std::{collections::HashMap, convert::Infallible, sync::Arc};
+
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use tokio::sync::Mutex;
+
+use super::JobStore;
+use crate::dispatching::scheduler::{Job, JobId};
+
+/// A job store based on [`std::collections::HashMap`].
+///
+/// ## Note
+/// All your jobs will be lost after you restart your bot. If you need to
+/// store them somewhere on a drive, you should use e.g.
+/// [`super::SqliteJobStore`] or implement your own.
+#[derive(Debug, Default)]
+pub struct InMemJobStore {
+    map: Mutex<HashMap<JobId, Job>>,
+}
+
+impl InMemJobStore {
+    #[must_use]
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+}
+
+impl JobStore for InMemJobStore {
+    type Error = Infallible;
+
+    fn save_job(self: Arc<Self>, job: Job) -> BoxFuture<'static, Result<(), Self::Error>> {
+        Box::pin(async move {
+            self.map.lock().await.insert(job.id.clone(), job);
+            Ok(())
+        })
+    }
+
+    fn remove_job(self: Arc<Self>, id: JobId) -> BoxFuture<'static, Result<bool, Self::Error>> {
+        Box::pin(async move { Ok(self.map.lock().await.remove(&id).is_some()) })
+    }
+
+    fn get_job(self: Arc<Self>, id: JobId) -> BoxFuture<'static, Result<Option<Job>, Self::Error>> {
+        Box::pin(async move { Ok(self.map.lock().await.get(&id).cloned()) })
+    }
+
+    fn due_jobs(
+        self: Arc<Self>,
+        now: DateTime<Utc>,
+    ) -> BoxFuture<'static, Result<Vec<Job>, Self::Error>> {
+        Box::pin(async move {
+            let mut jobs: Vec<_> =
+                self.map.lock().await.values().filter(|job| job.next_run <= now).cloned().collect();
+            jobs.sort_by_key(|job| job.next_run);
+            Ok(jobs)
+        })
+    }
+
+    fn next_run(self: Arc<Self>) -> BoxFuture<'static, Result<Option<DateTime<Utc>>, Self::Error>> {
+        Box::pin(async move { Ok(self.map.lock().await.values().map(|job| job.next_run).min()) })
+    }
+}
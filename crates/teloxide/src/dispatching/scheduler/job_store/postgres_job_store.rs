@@ -0,0 +1,143 @@
+use std::{
+    convert::Infallible,
+    fmt::{Debug, Display},
+    sync::Arc,
+};
+
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use sqlx::{
+    postgres::{PgPool, PgPoolOptions},
+    Executor,
+};
+use thiserror::Error;
+
+use super::JobStore;
+use crate::dispatching::{
+    dialogue::Serializer,
+    scheduler::{Job, JobId},
+};
+
+/// An error returned from [`PostgresJobStore`].
+#[derive(Debug, Error)]
+pub enum PostgresJobStoreError<SE>
+where
+    SE: Debug + Display,
+{
+    #[error("job serialization error: {0}")]
+    SerdeError(SE),
+
+    #[error("postgres error: {0}")]
+    PostgresError(#[from] sqlx::Error),
+}
+
+/// A persistent job store based on [PostgreSQL](https://www.postgresql.org/).
+pub struct PostgresJobStore<S> {
+    pool: PgPool,
+    serializer: S,
+}
+
+impl<S> PostgresJobStore<S> {
+    /// Opens a connection pool to the [Postgres](https://www.postgresql.org/) database and creates the table
+    /// for storing jobs.
+    ///
+    /// The parameters are the same as of [`PostgresStorage::open`].
+    ///
+    /// [`PostgresStorage::open`]: crate::dispatching::dialogue::PostgresStorage::open
+    pub async fn open(
+        database_url: &str,
+        max_connections: u32,
+        serializer: S,
+    ) -> Result<Arc<Self>, PostgresJobStoreError<Infallible>> {
+        let pool =
+            PgPoolOptions::new().max_connections(max_connections).connect(database_url).await?;
+        // Executed as a simple query, since it consists of several statements
+        pool.execute(include_str!("postgres_job_store/queries/create_teloxide_jobs.sql")).await?;
+
+        Ok(Arc::new(Self { pool, serializer }))
+    }
+}
+
+impl<S> PostgresJobStore<S>
+where
+    S: Serializer<Job>,
+    <S as Serializer<Job>>::Error: Debug + Display,
+{
+    fn deserialize(
+        &self,
+        rows: Vec<(Vec<u8>,)>,
+    ) -> Result<Vec<Job>, PostgresJobStoreError<<S as Serializer<Job>>::Error>> {
+        rows.into_iter()
+            .map(|(job,)| {
+                self.serializer.deserialize(&job).map_err(PostgresJobStoreError::SerdeError)
+            })
+            .collect()
+    }
+}
+
+impl<S> JobStore for PostgresJobStore<S>
+where
+    S: Send + Sync + Serializer<Job> + 'static,
+    <S as Serializer<Job>>::Error: Debug + Display,
+{
+    type Error = PostgresJobStoreError<<S as Serializer<Job>>::Error>;
+
+    fn save_job(self: Arc<Self>, job: Job) -> BoxFuture<'static, Result<(), Self::Error>> {
+        Box::pin(async move {
+            let bytes =
+                self.serializer.serialize(&job).map_err(PostgresJobStoreError::SerdeError)?;
+            sqlx::query(include_str!("postgres_job_store/queries/save_job.sql"))
+                .bind(job.id.0)
+                .bind(job.next_run.timestamp_millis())
+                .bind(bytes)
+                .execute(&self.pool)
+                .await?;
+            Ok(())
+        })
+    }
+
+    fn remove_job(self: Arc<Self>, id: JobId) -> BoxFuture<'static, Result<bool, Self::Error>> {
+        Box::pin(async move {
+            let deleted_rows_count =
+                sqlx::query(include_str!("postgres_job_store/queries/remove_job.sql"))
+                    .bind(id.0)
+                    .execute(&self.pool)
+                    .await?
+                    .rows_affected();
+            Ok(deleted_rows_count != 0)
+        })
+    }
+
+    fn get_job(self: Arc<Self>, id: JobId) -> BoxFuture<'static, Result<Option<Job>, Self::Error>> {
+        Box::pin(async move {
+            let rows = sqlx::query_as(include_str!("postgres_job_store/queries/get_job.sql"))
+                .bind(id.0)
+                .fetch_all(&self.pool)
+                .await?;
+            Ok(self.deserialize(rows)?.pop())
+        })
+    }
+
+    fn due_jobs(
+        self: Arc<Self>,
+        now: DateTime<Utc>,
+    ) -> BoxFuture<'static, Result<Vec<Job>, Self::Error>> {
+        Box::pin(async move {
+            let rows = sqlx::query_as(include_str!("postgres_job_store/queries/due_jobs.sql"))
+                .bind(now.timestamp_millis())
+                .fetch_all(&self.pool)
+                .await?;
+            self.deserialize(rows)
+        })
+    }
+
+    fn next_run(self: Arc<Self>) -> BoxFuture<'static, Result<Option<DateTime<Utc>>, Self::Error>> {
+        Box::pin(async move {
+            let (next_run,): (Option<i64>,) =
+                sqlx::query_as(include_str!("postgres_job_store/queries/next_run.sql"))
+                    .fetch_one(&self.pool)
+                    .await?;
+            Ok(next_run.and_then(DateTime::from_timestamp_millis))
+        })
+    }
+}
@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS teloxide_jobs (
+    id TEXT PRIMARY KEY,
+    next_run BIGINT NOT NULL,
+    job BYTEA NOT NULL
+);
+CREATE INDEX IF NOT EXISTS teloxide_jobs_next_run ON teloxide_jobs (next_run);
@@ -0,0 +1 @@
+SELECT job FROM teloxide_jobs WHERE next_run <= $1 ORDER BY next_run
@@ -0,0 +1 @@
+SELECT job FROM teloxide_jobs WHERE id = $1
@@ -0,0 +1 @@
+SELECT MIN(next_run) FROM teloxide_jobs
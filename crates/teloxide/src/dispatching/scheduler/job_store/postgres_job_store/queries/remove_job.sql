@@ -0,0 +1 @@
+DELETE FROM teloxide_jobs WHERE id = $1
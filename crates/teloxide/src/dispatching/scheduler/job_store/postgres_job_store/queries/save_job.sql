@@ -0,0 +1,2 @@
+INSERT INTO teloxide_jobs VALUES ($1, $2, $3)
+ON CONFLICT(id) DO UPDATE SET next_run=excluded.next_run, job=excluded.job
@@ -0,0 +1,145 @@
+use std::{
+    convert::Infallible,
+    fmt::{Debug, Display},
+    sync::Arc,
+};
+
+use chrono::{DateTime, Utc};
+use deadpool_redis::{redis, CreatePoolError, PoolError, Runtime};
+use futures::future::BoxFuture;
+use redis::AsyncCommands;
+use thiserror::Error;
+
+use super::JobStore;
+use crate::dispatching::{
+    dialogue::Serializer,
+    scheduler::{Job, JobId},
+};
+
+/// A hash of serialized jobs by their identifiers.
+const JOBS_KEY: &str = "teloxide_jobs";
+/// A sorted set of job identifiers, scored by the next run in milliseconds.
+const SCHEDULE_KEY: &str = "teloxide_jobs_schedule";
+
+/// An error returned from [`RedisJobStore`].
+#[derive(Debug, Error)]
+pub enum RedisJobStoreError<SE>
+where
+    SE: Debug + Display,
+{
+    #[error("parsing/serializing error: {0}")]
+    SerdeError(SE),
+
+    #[error("error from Redis: {0}")]
+    RedisError(#[from] redis::RedisError),
+
+    #[error("error creating redis pool: {0}")]
+    CreatePoolError(#[from] CreatePoolError),
+
+    #[error("redis pool error: {0}")]
+    PoolError(#[from] PoolError),
+}
+
+/// A job store based on [Redis](https://redis.io/).
+pub struct RedisJobStore<S> {
+    pool: deadpool_redis::Pool,
+    serializer: S,
+}
+
+impl<S> RedisJobStore<S> {
+    pub async fn open(
+        url: &str,
+        serializer: S,
+    ) -> Result<Arc<Self>, RedisJobStoreError<Infallible>> {
+        let config = deadpool_redis::Config::from_url(url);
+        let pool = config.create_pool(Some(Runtime::Tokio1))?;
+
+        Ok(Arc::new(Self { pool, serializer }))
+    }
+}
+
+impl<S> RedisJobStore<S>
+where
+    S: Serializer<Job>,
+    <S as Serializer<Job>>::Error: Debug + Display,
+{
+    fn deserialize(
+        &self,
+        jobs: Vec<Option<Vec<u8>>>,
+    ) -> Result<Vec<Job>, RedisJobStoreError<<S as Serializer<Job>>::Error>> {
+        jobs.into_iter()
+            .flatten()
+            .map(|job| self.serializer.deserialize(&job).map_err(RedisJobStoreError::SerdeError))
+            .collect()
+    }
+}
+
+impl<S> JobStore for RedisJobStore<S>
+where
+    S: Send + Sync + Serializer<Job> + 'static,
+    <S as Serializer<Job>>::Error: Debug + Display,
+{
+    type Error = RedisJobStoreError<<S as Serializer<Job>>::Error>;
+
+    fn save_job(self: Arc<Self>, job: Job) -> BoxFuture<'static, Result<(), Self::Error>> {
+        Box::pin(async move {
+            let bytes = self.serializer.serialize(&job).map_err(RedisJobStoreError::SerdeError)?;
+            () = redis::pipe()
+                .atomic()
+                .hset(JOBS_KEY, &job.id.0, bytes)
+                .ignore()
+                .zadd(SCHEDULE_KEY, &job.id.0, job.next_run.timestamp_millis())
+                .ignore()
+                .query_async(&mut self.pool.get().await?)
+                .await?;
+            Ok(())
+        })
+    }
+
+    fn remove_job(self: Arc<Self>, id: JobId) -> BoxFuture<'static, Result<bool, Self::Error>> {
+        Box::pin(async move {
+            let (deleted_count,): (i64,) = redis::pipe()
+                .atomic()
+                .hdel(JOBS_KEY, &id.0)
+                .zrem(SCHEDULE_KEY, &id.0)
+                .ignore()
+                .query_async(&mut self.pool.get().await?)
+                .await?;
+            Ok(deleted_count != 0)
+        })
+    }
+
+    fn get_job(self: Arc<Self>, id: JobId) -> BoxFuture<'static, Result<Option<Job>, Self::Error>> {
+        Box::pin(async move {
+            let job = self.pool.get().await?.hget(JOBS_KEY, id.0).await?;
+            Ok(self.deserialize(vec![job])?.pop())
+        })
+    }
+
+    fn due_jobs(
+        self: Arc<Self>,
+        now: DateTime<Utc>,
+    ) -> BoxFuture<'static, Result<Vec<Job>, Self::Error>> {
+        Box::pin(async move {
+            let mut conn = self.pool.get().await?;
+            let ids: Vec<String> =
+                conn.zrangebyscore(SCHEDULE_KEY, "-inf", now.timestamp_millis()).await?;
+            if ids.is_empty() {
+                return Ok(Vec::new());
+            }
+
+            // `HMGET` returns `nil` for jobs removed in the meantime
+            let jobs: Vec<Option<Vec<u8>>> =
+                redis::cmd("HMGET").arg(JOBS_KEY).arg(&ids).query_async(&mut conn).await?;
+            self.deserialize(jobs)
+        })
+    }
+
+    fn next_run(self: Arc<Self>) -> BoxFuture<'static, Result<Option<DateTime<Utc>>, Self::Error>> {
+        Box::pin(async move {
+            let first: Vec<(String, i64)> =
+                self.pool.get().await?.zrange_withscores(SCHEDULE_KEY, 0, 0).await?;
+            Ok(first.first().and_then(|(_, next_run)| DateTime::from_timestamp_millis(*next_run)))
+        })
+    }
+}
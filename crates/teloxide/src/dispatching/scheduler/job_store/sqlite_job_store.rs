@@ -0,0 +1,148 @@
+use std::{
+    convert::Infallible,
+    fmt::{Debug, Display},
+    sync::Arc,
+};
+
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use sqlx::sqlite::SqlitePool;
+use thiserror::Error;
+
+use super::JobStore;
+use crate::dispatching::{
+    dialogue::Serializer,
+    scheduler::{Job, JobId},
+};
+
+/// A persistent job store based on [SQLite](https://www.sqlite.org/).
+pub struct SqliteJobStore<S> {
+    pool: SqlitePool,
+    serializer: S,
+}
+
+/// An error returned from [`SqliteJobStore`].
+#[derive(Debug, Error)]
+pub enum SqliteJobStoreError<SE>
+where
+    SE: Debug + Display,
+{
+    #[error("job serialization error: {0}")]
+    SerdeError(SE),
+
+    #[error("sqlite error: {0}")]
+    SqliteError(#[from] sqlx::Error),
+}
+
+impl<S> SqliteJobStore<S> {
+    /// Opens the database at `path` and creates the table for storing jobs.
+    pub async fn open(
+        path: &str,
+        serializer: S,
+    ) -> Result<Arc<Self>, SqliteJobStoreError<Infallible>> {
+        let pool = SqlitePool::connect(format!("sqlite:{path}?mode=rwc").as_str()).await?;
+        sqlx::query(
+            "
+CREATE TABLE IF NOT EXISTS teloxide_jobs (
+    id TEXT PRIMARY KEY,
+    next_run BIGINT NOT NULL,
+    job BLOB NOT NULL
+);
+CREATE INDEX IF NOT EXISTS teloxide_jobs_next_run ON teloxide_jobs (next_run);
+        ",
+        )
+        .execute(&pool)
+        .await?;
+
+        Ok(Arc::new(Self { pool, serializer }))
+    }
+}
+
+impl<S> SqliteJobStore<S>
+where
+    S: Serializer<Job>,
+    <S as Serializer<Job>>::Error: Debug + Display,
+{
+    fn deserialize(
+        &self,
+        rows: Vec<(Vec<u8>,)>,
+    ) -> Result<Vec<Job>, SqliteJobStoreError<<S as Serializer<Job>>::Error>> {
+        rows.into_iter()
+            .map(|(job,)| {
+                self.serializer.deserialize(&job).map_err(SqliteJobStoreError::SerdeError)
+            })
+            .collect()
+    }
+}
+
+impl<S> JobStore for SqliteJobStore<S>
+where
+    S: Send + Sync + Serializer<Job> + 'static,
+    <S as Serializer<Job>>::Error: Debug + Display,
+{
+    type Error = SqliteJobStoreError<<S as Serializer<Job>>::Error>;
+
+    fn save_job(self: Arc<Self>, job: Job) -> BoxFuture<'static, Result<(), Self::Error>> {
+        Box::pin(async move {
+            let bytes = self.serializer.serialize(&job).map_err(SqliteJobStoreError::SerdeError)?;
+            sqlx::query(
+                "
+            INSERT INTO teloxide_jobs VALUES (?, ?, ?)
+            ON CONFLICT(id) DO UPDATE SET next_run=excluded.next_run, job=excluded.job
+                ",
+            )
+            .bind(job.id.0)
+            .bind(job.next_run.timestamp_millis())
+            .bind(bytes)
+            .execute(&self.pool)
+            .await?;
+            Ok(())
+        })
+    }
+
+    fn remove_job(self: Arc<Self>, id: JobId) -> BoxFuture<'static, Result<bool, Self::Error>> {
+        Box::pin(async move {
+            let deleted_rows_count = sqlx::query("DELETE FROM teloxide_jobs WHERE id = ?")
+                .bind(id.0)
+                .execute(&self.pool)
+                .await?
+                .rows_affected();
+            Ok(deleted_rows_count != 0)
+        })
+    }
+
+    fn get_job(self: Arc<Self>, id: JobId) -> BoxFuture<'static, Result<Option<Job>, Self::Error>> {
+        Box::pin(async move {
+            let rows = sqlx::query_as("SELECT job FROM teloxide_jobs WHERE id = ?")
+                .bind(id.0)
+                .fetch_all(&self.pool)
+                .await?;
+            Ok(self.deserialize(rows)?.pop())
+        })
+    }
+
+    fn due_jobs(
+        self: Arc<Self>,
+        now: DateTime<Utc>,
+    ) -> BoxFuture<'static, Result<Vec<Job>, Self::Error>> {
+        Box::pin(async move {
+            let rows = sqlx::query_as(
+                "SELECT job FROM teloxide_jobs WHERE next_run <= ? ORDER BY next_run",
+            )
+            .bind(now.timestamp_millis())
+            .fetch_all(&self.pool)
+            .await?;
+            self.deserialize(rows)
+        })
+    }
+
+    fn next_run(self: Arc<Self>) -> BoxFuture<'static, Result<Option<DateTime<Utc>>, Self::Error>> {
+        Box::pin(async move {
+            let (next_run,): (Option<i64>,) =
+                sqlx::query_as("SELECT MIN(next_run) FROM teloxide_jobs")
+                    .fetch_one(&self.pool)
+                    .await?;
+            Ok(next_run.and_then(DateTime::from_timestamp_millis))
+        })
+    }
+}
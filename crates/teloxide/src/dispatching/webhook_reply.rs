@@ -0,0 +1,247 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use serde::Serialize;
+use tokio::sync::oneshot;
+
+use crate::{
+    requests::{Output, Payload, Request},
+    types::UpdateId,
+};
+
+/// Updates received by a webhook that can be answered right in the HTTP
+/// response.
+///
+/// Telegram [allows] to perform a request to the Bot API in the response to a
+/// webhook request, which saves a round trip per update. To use this:
+///
+///  1. Create `WebhookReplies` and pass it to both the webhook listener (see
+///     [`webhooks::Options::inline_replies`]) and the dispatcher (see
+///     [`DispatcherBuilder::webhook_replies`]).
+///  2. In handlers, use the [`WebhookReply`] dependency to send requests.
+///
+/// The webhook waits for a reply for at most [`deadline`] after receiving an
+/// update. If a handler replies later, or the bot doesn't use a webhook at
+/// all, [`WebhookReply::send`] falls back to sending the request as usual.
+///
+/// ## Examples
+///
+/// ```no_run
+/// # #[cfg(feature = "webhooks-axum")]
+/// # async fn run() -> Result<(), teloxide::RequestError> {
+/// use std::time::Duration;
+/// use teloxide::{
+///     dispatching::{WebhookReplies, WebhookReply},
+///     prelude::*,
+///     update_listeners::webhooks,
+/// };
+///
+/// let bot = Bot::from_env();
+/// let replies = WebhookReplies::new(Duration::from_secs(1));
+///
+/// let addr = ([127, 0, 0, 1], 8443).into();
+/// let url = "https://example.com/webhook".parse().unwrap();
+/// let options = webhooks::Options::new(addr, url).inline_replies(replies.clone());
+/// let listener = webhooks::axum(bot.clone(), options).await?;
+///
+/// let handler = Update::filter_message().endpoint(
+///     |bot: Bot, msg: Message, reply: WebhookReply| async move {
+///         reply.send(bot.send_message(msg.chat.id, "pong")).await?;
+///         respond(())
+///     },
+/// );
+///
+/// Dispatcher::builder(bot, handler)
+///     .webhook_replies(replies)
+///     .build()
+///     .dispatch_with_listener(listener, LoggingErrorHandler::new())
+///     .await;
+/// # Ok(()) }
+/// ```
+///
+/// [allows]: https://core.telegram.org/bots/api#making-requests-when-getting-updates
+/// [`webhooks::Options::inline_replies`]: crate::update_listeners::webhooks::Options::inline_replies
+/// [`DispatcherBuilder::webhook_replies`]: crate::dispatching::DispatcherBuilder::webhook_replies
+/// [`deadline`]: WebhookReplies::deadline
+#[derive(Clone)]
+pub struct WebhookReplies {
+    deadline: Duration,
+    slots: Arc<Mutex<HashMap<UpdateId, oneshot::Sender<String>>>>,
+}
+
+impl WebhookReplies {
+    /// Creates a new registry, webhook requests wait for a reply for at most
+    /// `deadline`.
+    ///
+    /// Telegram waits for the response for about a minute, but while it
+    /// waits, the connection can't be used to deliver other updates (see
+    /// [`webhooks::Options::max_connections`]), so the deadline should be
+    /// short, usually about a second.
+    ///
+    /// [`webhooks::Options::max_connections`]: crate::update_listeners::webhooks::Options::max_connections
+    #[must_use]
+    pub fn new(deadline: Duration) -> Self {
+        Self { deadline, slots: <_>::default() }
+    }
+
+    /// Returns the time webhook requests wait for a reply.
+    #[must_use]
+    pub fn deadline(&self) -> Duration {
+        self.deadline
+    }
+
+    /// Registers an update received by a webhook, returning a receiver of the
+    /// reply body.
+    ///
+    /// The receiver fails if the update was handled without a reply.
+    #[cfg_attr(not(feature = "webhooks-axum"), allow(dead_code))]
+    pub(crate) fn register(&self, id: UpdateId) -> oneshot::Receiver<String> {
+        let (tx, rx) = oneshot::channel();
+        self.slots.lock().unwrap().insert(id, tx);
+        rx
+    }
+
+    /// Unregisters an update after the webhook stopped waiting for a reply.
+    #[cfg_attr(not(feature = "webhooks-axum"), allow(dead_code))]
+    pub(crate) fn unregister(&self, id: UpdateId) {
+        self.slots.lock().unwrap().remove(&id);
+    }
+
+    /// Takes the reply slot of an update that is about to be handled.
+    pub(crate) fn take(&self, id: UpdateId) -> WebhookReply {
+        let tx = self.slots.lock().unwrap().remove(&id);
+        WebhookReply { slot: Arc::new(Mutex::new(tx)) }
+    }
+}
+
+/// A way to answer an update in the webhook HTTP response.
+///
+/// This is passed to handlers by [`Dispatcher`]. See [`WebhookReplies`] for
+/// the details.
+///
+/// [`Dispatcher`]: crate::dispatching::Dispatcher
+#[derive(Clone, Default)]
+pub struct WebhookReply {
+    slot: Arc<Mutex<Option<oneshot::Sender<String>>>>,
+}
+
+/// The body of a webhook response.
+#[derive(Serialize)]
+struct MethodCall<'a, P> {
+    method: &'static str,
+    #[serde(flatten)]
+    payload: &'a P,
+}
+
+impl WebhookReply {
+    /// Returns `true` if the update can still be answered in the webhook
+    /// response.
+    #[must_use]
+    pub fn is_available(&self) -> bool {
+        self.slot.lock().unwrap().as_ref().is_some_and(|tx| !tx.is_closed())
+    }
+
+    /// Sends `request` in the webhook response if possible, or as a usual
+    /// request otherwise.
+    ///
+    /// Returns `Ok(None)` if the request was sent in the webhook response.
+    /// Telegram doesn't report the result of such requests, not even errors.
+    ///
+    /// Only one request can be sent in the response, so subsequent calls
+    /// always send requests as usual. Requests which upload files can't be
+    /// sent in the response either.
+    ///
+    /// Note that the request is serialized directly, so bot adaptors (for
+    /// example, [`Throttle`]) don't affect requests sent in the response.
+    ///
+    /// [`Throttle`]: crate::adaptors::Throttle
+    pub async fn send<Req>(&self, request: Req) -> Result<Option<Output<Req>>, Req::Err>
+    where
+        Req: Request,
+        Req::Payload: Serialize,
+    {
+        if self.try_send(request.payload_ref()) {
+            return Ok(None);
+        }
+
+        request.send().await.map(Some)
+    }
+
+    fn try_send<P>(&self, payload: &P) -> bool
+    where
+        P: Payload + Serialize,
+    {
+        if !self.is_available() {
+            return false;
+        }
+
+        let body = match serde_json::to_string(&MethodCall { method: P::NAME, payload }) {
+            Ok(body) => body,
+            Err(err) => {
+                log::error!("Couldn't serialize a webhook reply: {err}");
+                return false;
+            }
+        };
+        // Files can only be uploaded with `multipart/form-data` requests
+        if body.contains("\"attach://") {
+            return false;
+        }
+
+        match self.slot.lock().unwrap().take() {
+            Some(tx) => tx.send(body).is_ok(),
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        payloads::{AnswerCallbackQuery, SendPhoto},
+        types::{ChatId, InputFile},
+    };
+
+    #[test]
+    fn reply_in_response() {
+        let replies = WebhookReplies::new(Duration::from_secs(1));
+        let mut rx = replies.register(UpdateId(1));
+
+        let reply = replies.take(UpdateId(1));
+        assert!(reply.is_available());
+        assert!(reply.try_send(&AnswerCallbackQuery::new("42")));
+        assert!(!reply.is_available());
+        assert!(!reply.try_send(&AnswerCallbackQuery::new("43")));
+
+        let body: serde_json::Value = serde_json::from_str(&rx.try_recv().unwrap()).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({ "method": "AnswerCallbackQuery", "callback_query_id": "42" })
+        );
+    }
+
+    #[test]
+    fn fallback() {
+        let replies = WebhookReplies::new(Duration::from_secs(1));
+
+        // Not registered
+        assert!(!replies.take(UpdateId(1)).is_available());
+        assert!(!WebhookReply::default().try_send(&AnswerCallbackQuery::new("42")));
+
+        // The webhook stopped waiting
+        let rx = replies.register(UpdateId(2));
+        let reply = replies.take(UpdateId(2));
+        drop(rx);
+        assert!(!reply.try_send(&AnswerCallbackQuery::new("42")));
+
+        // File uploads
+        let _rx = replies.register(UpdateId(3));
+        let reply = replies.take(UpdateId(3));
+        let photo = SendPhoto::new(ChatId(1), InputFile::memory(&b"photo"[..]));
+        assert!(!reply.try_send(&photo));
+        assert!(reply.is_available());
+    }
+}
@@ -1,7 +1,21 @@
 //! Convenient error handling.
 
+use dptree::di::DependencyMap;
 use futures::future::BoxFuture;
-use std::{convert::Infallible, fmt::Debug, future::Future, sync::Arc};
+use std::{
+    any::Any,
+    collections::HashMap,
+    convert::Infallible,
+    fmt::{Debug, Write as _},
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    requests::{Request, Requester},
+    types::{Recipient, Update},
+};
 
 /// An asynchronous handler of an error.
 ///
@@ -10,6 +24,127 @@ use std::{convert::Infallible, fmt::Debug, future::Future, sync::Arc};
 pub trait ErrorHandler<E> {
     #[must_use]
     fn handle_error(self: Arc<Self>, error: E) -> BoxFuture<'static, ()>;
+
+    /// Handles a panic of an update handler.
+    ///
+    /// [`Dispatcher`] catches panics of handlers, so a panic only affects the
+    /// update that caused it. By default, the panic is logged (even by
+    /// [`IgnoringErrorHandler`]).
+    ///
+    /// [`Dispatcher`]: crate::dispatching::Dispatcher
+    #[must_use]
+    fn handle_panic(self: Arc<Self>, panic: HandlerPanic) -> BoxFuture<'static, ()> {
+        log::error!("{panic}");
+        Box::pin(async {})
+    }
+}
+
+/// An asynchronous handler of an error returned by an update handler, which
+/// also receives the update and the dependencies.
+///
+/// Unlike [`ErrorHandler`], it can reply to the user who triggered the error
+/// or log the chat where it happened. The dependencies are the ones passed to
+/// [`DispatcherBuilder::dependencies`], plus the bot, [`Me`] and the
+/// [`Update`].
+///
+/// Set it with [`DispatcherBuilder::update_error_handler`].
+///
+/// ## Examples
+///
+/// ```no_run
+/// use std::sync::Arc;
+///
+/// use teloxide::{dptree::di::DependencySupplier, prelude::*, RequestError};
+///
+/// # async {
+/// let handler = Update::filter_message().endpoint(|bot: Bot, msg: Message| async move {
+///     bot.send_message(msg.chat.id, "Hi!").await?;
+///     respond(())
+/// });
+///
+/// Dispatcher::builder(Bot::from_env(), handler)
+///     .update_error_handler(Arc::new(
+///         |err: RequestError, update: Arc<Update>, deps: DependencyMap| async move {
+///             log::error!("An error in chat {:?}: {err}", update.chat().map(|chat| chat.id));
+///
+///             let bot: Arc<Bot> = deps.get();
+///             if let Some(chat) = update.chat() {
+///                 let _ = bot.send_message(chat.id, "Something went wrong").await;
+///             }
+///         },
+///     ))
+///     .build()
+///     .dispatch()
+///     .await;
+/// # };
+/// ```
+///
+/// [`DispatcherBuilder::dependencies`]: crate::dispatching::DispatcherBuilder::dependencies
+/// [`DispatcherBuilder::update_error_handler`]: crate::dispatching::DispatcherBuilder::update_error_handler
+/// [`Me`]: crate::types::Me
+pub trait UpdateErrorHandler<E> {
+    #[must_use]
+    fn handle_update_error(
+        self: Arc<Self>,
+        error: E,
+        update: Arc<Update>,
+        deps: DependencyMap,
+    ) -> BoxFuture<'static, ()>;
+
+    /// Handles a panic of an update handler.
+    ///
+    /// By default, the panic is logged. See [`ErrorHandler::handle_panic`].
+    #[must_use]
+    fn handle_update_panic(
+        self: Arc<Self>,
+        panic: HandlerPanic,
+        deps: DependencyMap,
+    ) -> BoxFuture<'static, ()> {
+        let _ = deps;
+        log::error!("{panic}");
+        Box::pin(async {})
+    }
+}
+
+impl<E, F, Fut> UpdateErrorHandler<E> for F
+where
+    F: Fn(E, Arc<Update>, DependencyMap) -> Fut + Send + Sync + 'static,
+    E: Send + 'static,
+    Fut: Future<Output = ()> + Send,
+{
+    fn handle_update_error(
+        self: Arc<Self>,
+        error: E,
+        update: Arc<Update>,
+        deps: DependencyMap,
+    ) -> BoxFuture<'static, ()> {
+        Box::pin(async move { self(error, update, deps).await })
+    }
+}
+
+/// A panic of an update handler, see [`ErrorHandler::handle_panic`].
+#[derive(Clone, Debug, thiserror::Error)]
+#[error("A handler panicked while processing update {}: {message}", .update.id.0)]
+pub struct HandlerPanic {
+    /// The update that caused the panic.
+    pub update: Arc<Update>,
+
+    /// The panic message.
+    pub message: String,
+}
+
+impl HandlerPanic {
+    pub(crate) fn new(update: Arc<Update>, payload: Box<dyn Any + Send>) -> Self {
+        let message = match payload.downcast::<String>() {
+            Ok(message) => *message,
+            Err(payload) => match payload.downcast::<&'static str>() {
+                Ok(message) => (*message).to_owned(),
+                Err(_) => "Box<dyn Any>".to_owned(),
+            },
+        };
+
+        Self { update, message }
+    }
 }
 
 impl<E, F, Fut> ErrorHandler<E> for F
@@ -210,3 +345,222 @@ where
         Box::pin(async {})
     }
 }
+
+/// A handler that logs all errors and reports them to an admin chat.
+///
+/// A report contains the error, the chat and the user of the update which
+/// caused it. Repeated errors (with the same [`Debug`] representation) are
+/// reported at most once per the deduplication window (1 hour by default),
+/// the next report mentions how many times the error was repeated. At most
+/// 1000 distinct errors are deduplicated at the same time; if an error is
+/// forgotten because of this limit, its unreported repetitions are only
+/// logged.
+///
+/// Panics of handlers are reported too.
+///
+/// ## Examples
+///
+/// ```no_run
+/// use teloxide::{error_handlers::AdminChatErrorHandler, prelude::*, types::ChatId};
+///
+/// # async {
+/// let bot = Bot::from_env();
+/// let handler = Update::filter_message().endpoint(|| async { respond(()) });
+///
+/// Dispatcher::builder(bot.clone(), handler)
+///     .update_error_handler(AdminChatErrorHandler::new(bot, ChatId(-1001234567890)))
+///     .build()
+///     .dispatch()
+///     .await;
+/// # };
+/// ```
+pub struct AdminChatErrorHandler<R> {
+    bot: R,
+    chat_id: Recipient,
+    dedup_window: Duration,
+    // Last reports and the number of suppressed repetitions, by error.
+    reported: Mutex<HashMap<String, (Instant, u32)>>,
+}
+
+/// The maximum number of errors remembered by [`AdminChatErrorHandler`].
+const MAX_TRACKED_ERRORS: usize = 1000;
+
+impl<R> AdminChatErrorHandler<R> {
+    /// Creates `AdminChatErrorHandler` reporting errors to `chat_id` with
+    /// `bot`.
+    #[must_use]
+    pub fn new<C>(bot: R, chat_id: C) -> Arc<Self>
+    where
+        C: Into<Recipient>,
+    {
+        Self::with_dedup_window(bot, chat_id, Duration::from_secs(60 * 60))
+    }
+
+    /// Creates `AdminChatErrorHandler` which reports the same error at most
+    /// once per `dedup_window`.
+    #[must_use]
+    pub fn with_dedup_window<C>(bot: R, chat_id: C, dedup_window: Duration) -> Arc<Self>
+    where
+        C: Into<Recipient>,
+    {
+        Arc::new(Self {
+            bot,
+            chat_id: chat_id.into(),
+            dedup_window,
+            reported: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Returns the number of suppressed repetitions of the error since its
+    /// last report, or `None` if it must not be reported now.
+    fn should_report(&self, error: &str) -> Option<u32> {
+        let mut reported = self.reported.lock().unwrap();
+
+        let res = match reported.get_mut(error) {
+            Some((at, suppressed)) if at.elapsed() < self.dedup_window => {
+                *suppressed += 1;
+                None
+            }
+            Some((at, suppressed)) => {
+                *at = Instant::now();
+                Some(std::mem::take(suppressed))
+            }
+            None => {
+                reported.insert(error.to_owned(), (Instant::now(), 0));
+                Some(0)
+            }
+        };
+
+        // Errors which didn't recur after the window are forgotten, so that
+        // errors containing e.g. ids don't pile up. Unreported repetitions are
+        // kept until the next report
+        reported.retain(|_, (at, suppressed)| *suppressed > 0 || at.elapsed() < self.dedup_window);
+        if reported.len() > MAX_TRACKED_ERRORS {
+            let oldest = reported.iter().min_by_key(|(_, (at, _))| *at).map(|(e, _)| e.clone());
+            if let Some((error, (_, suppressed))) = oldest.and_then(|e| reported.remove_entry(&e)) {
+                if suppressed > 0 {
+                    log::error!(
+                        "Error repeated {suppressed} more times since the last report: {error}"
+                    );
+                }
+            }
+        }
+
+        res
+    }
+
+    fn report(
+        self: Arc<Self>,
+        title: &str,
+        error: String,
+        update: &Update,
+    ) -> BoxFuture<'static, ()>
+    where
+        R: Requester + Send + Sync + 'static,
+    {
+        let Some(repeated) = self.should_report(&error) else {
+            return Box::pin(async {});
+        };
+
+        // Reports are sent as plain text, so nothing has to be escaped
+        let mut text = format!("{title} while handling update {}\n", update.id.0);
+        if let Some(chat) = update.chat() {
+            let _ = write!(text, "\nChat: {}", chat.id);
+            if let Some(username) = chat.username() {
+                let _ = write!(text, " (@{username})");
+            }
+        }
+        if let Some(user) = update.from() {
+            let _ = write!(text, "\nUser: {} ({})", user.id, user.full_name());
+        }
+        if repeated > 0 {
+            let _ = write!(text, "\nRepeated {repeated} more times since the last report");
+        }
+        let _ = write!(text, "\n\n{error}");
+
+        // The maximum length of a message
+        const MAX_LEN: usize = 4096;
+        if let Some((end, _)) = text.char_indices().nth(MAX_LEN) {
+            text.truncate(end);
+        }
+
+        Box::pin(async move {
+            if let Err(err) = self.bot.send_message(self.chat_id.clone(), text).send().await {
+                log::error!("Failed to report an error to the admin chat: {err}");
+            }
+        })
+    }
+}
+
+impl<R, E> UpdateErrorHandler<E> for AdminChatErrorHandler<R>
+where
+    R: Requester + Send + Sync + 'static,
+    E: Debug,
+{
+    fn handle_update_error(
+        self: Arc<Self>,
+        error: E,
+        update: Arc<Update>,
+        _: DependencyMap,
+    ) -> BoxFuture<'static, ()> {
+        log::error!("Error while handling update {}: {error:?}", update.id.0);
+        self.report("Error", format!("{error:?}"), &update)
+    }
+
+    fn handle_update_panic(
+        self: Arc<Self>,
+        panic: HandlerPanic,
+        _: DependencyMap,
+    ) -> BoxFuture<'static, ()> {
+        log::error!("{panic}");
+        self.report("Panic", panic.message, &panic.update)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use teloxide_core::{types::ChatId, Bot};
+
+    use super::*;
+
+    #[test]
+    fn admin_chat_dedup() {
+        let handler =
+            AdminChatErrorHandler::with_dedup_window(Bot::new(""), ChatId(1), Duration::ZERO);
+        assert_eq!(handler.should_report("a"), Some(0));
+        assert_eq!(handler.should_report("a"), Some(0));
+
+        let handler = AdminChatErrorHandler::new(Bot::new(""), ChatId(1));
+        assert_eq!(handler.should_report("a"), Some(0));
+        assert_eq!(handler.should_report("a"), None);
+        assert_eq!(handler.should_report("a"), None);
+        assert_eq!(handler.should_report("b"), Some(0));
+
+        // Pretend that the window has passed
+        handler.reported.lock().unwrap().get_mut("a").unwrap().0 -= Duration::from_secs(60 * 60);
+        assert_eq!(handler.should_report("a"), Some(2));
+        assert_eq!(handler.should_report("a"), None);
+
+        // Expired errors are forgotten, unless they have unreported repetitions
+        assert_eq!(handler.should_report("b"), None);
+        for error in ["a", "b"] {
+            handler.reported.lock().unwrap().get_mut(error).unwrap().0 -=
+                Duration::from_secs(60 * 60);
+        }
+        assert_eq!(handler.should_report("c"), Some(0));
+        assert_eq!(handler.should_report("b"), Some(1));
+        handler.reported.lock().unwrap().get_mut("b").unwrap().0 -= Duration::from_secs(60 * 60);
+        assert_eq!(handler.should_report("c"), None);
+        assert!(!handler.reported.lock().unwrap().contains_key("b"));
+        assert!(handler.reported.lock().unwrap().contains_key("a"));
+    }
+
+    #[test]
+    fn admin_chat_dedup_limit() {
+        let handler = AdminChatErrorHandler::new(Bot::new(""), ChatId(1));
+        for i in 0..MAX_TRACKED_ERRORS + 10 {
+            assert_eq!(handler.should_report(&i.to_string()), Some(0));
+        }
+        assert_eq!(handler.reported.lock().unwrap().len(), MAX_TRACKED_ERRORS);
+    }
+}
@@ -144,13 +144,16 @@ pub mod sugar;
 pub mod update_listeners;
 pub mod utils;
 
+#[cfg(test)]
+mod test_utils;
+
 #[doc(inline)]
 pub use teloxide_core::*;
 
 #[cfg(feature = "macros")]
 pub use teloxide_macros as macros;
 
-pub use dispatching::{filter_command, filter_mention_command};
+pub use dispatching::{filter_command, filter_command_with_help, filter_mention_command};
 pub use dptree::{self, case as handler};
 
 #[cfg(all(feature = "nightly", doctest))]
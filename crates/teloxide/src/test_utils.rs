@@ -0,0 +1,143 @@
+//! Helpers shared by unit tests.
+
+// Not every set of features uses every helper
+#![allow(dead_code)]
+
+use std::sync::{Arc, Mutex};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+
+use crate::types::{Me, Update};
+
+/// A mock Telegram Bot API server which answers all requests with the same
+/// response and records the requests.
+pub(crate) struct MockApi {
+    url: reqwest::Url,
+    requests: Arc<Mutex<Vec<MockRequest>>>,
+}
+
+/// A request received by [`MockApi`].
+#[derive(Clone, Debug)]
+pub(crate) struct MockRequest {
+    /// The name of the called method, e.g. `GetMe`.
+    pub(crate) method: String,
+    /// The JSON body of the request, `Null` if there is none.
+    pub(crate) body: serde_json::Value,
+}
+
+impl MockApi {
+    /// Starts a server which answers all requests with `response`, the whole
+    /// JSON body of a response.
+    pub(crate) async fn serve(response: impl Into<String>) -> Self {
+        let response = response.into();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap()).parse().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        tokio::spawn({
+            let requests = Arc::clone(&requests);
+            async move {
+                while let Ok((mut stream, _)) = listener.accept().await {
+                    if let Some(request) = read_request(&mut stream).await {
+                        requests.lock().unwrap().push(request);
+                    }
+
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: \
+                         {}\r\nConnection: close\r\n\r\n{response}",
+                        response.len()
+                    );
+                    let _ = stream.write_all(response.as_bytes()).await;
+                }
+            }
+        });
+
+        Self { url, requests }
+    }
+
+    /// Starts a server which answers all requests with a successful `getMe`
+    /// response, see [`me`].
+    pub(crate) async fn get_me(id: u64) -> Self {
+        Self::serve(format!(r#"{{"ok":true,"result":{}}}"#, me_json(id))).await
+    }
+
+    /// Returns the URL to pass to `Bot::set_api_url`.
+    pub(crate) fn url(&self) -> reqwest::Url {
+        self.url.clone()
+    }
+
+    /// Returns the requests received so far.
+    pub(crate) fn requests(&self) -> Vec<MockRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+// Reads a request with a body of `Content-Length` bytes.
+async fn read_request(stream: &mut tokio::net::TcpStream) -> Option<MockRequest> {
+    let mut request = Vec::new();
+    let mut buf = [0; 4096];
+    loop {
+        match stream.read(&mut buf).await {
+            Ok(0) | Err(_) => return None,
+            Ok(n) => request.extend_from_slice(&buf[..n]),
+        }
+
+        let request = String::from_utf8_lossy(&request);
+        let Some((headers, body)) = request.split_once("\r\n\r\n") else { continue };
+        let len = headers
+            .lines()
+            .find_map(|l| l.to_lowercase().strip_prefix("content-length:")?.trim().parse().ok())
+            .unwrap_or(0);
+        if body.len() < len {
+            continue;
+        }
+
+        // The request line is `POST /bot{token}/{method} HTTP/1.1`
+        let path = headers.split(' ').nth(1).unwrap_or_default();
+        let method = path.rsplit('/').next().unwrap_or_default().to_owned();
+        let body = serde_json::from_str(body).unwrap_or_default();
+        return Some(MockRequest { method, body });
+    }
+}
+
+fn me_json(id: u64) -> String {
+    format!(
+        r#"{{"id":{id},"is_bot":true,"first_name":"Bot","username":"bot{id}","can_join_groups":true,"can_read_all_group_messages":false,"supports_inline_queries":false,"can_connect_to_business":false}}"#
+    )
+}
+
+/// Returns information about a bot with the username `bot{id}`.
+pub(crate) fn me(id: u64) -> Me {
+    serde_json::from_str(&me_json(id)).unwrap()
+}
+
+/// Returns an update parsed from `json`.
+pub(crate) fn update(json: serde_json::Value) -> Update {
+    // `Update` can't be deserialized from `serde_json::Value` directly
+    serde_json::from_str(&json.to_string()).unwrap()
+}
+
+/// Returns an update with a text message from the user `user_id` in the chat
+/// `chat_id`, which is a private chat if the identifier is positive, and a
+/// supergroup otherwise.
+pub(crate) fn text_message(chat_id: i64, user_id: u64, text: &str) -> Update {
+    let chat = if chat_id > 0 {
+        serde_json::json!({ "id": chat_id, "first_name": "Test", "type": "private" })
+    } else {
+        serde_json::json!({ "id": chat_id, "title": "Test", "type": "supergroup" })
+    };
+
+    update(serde_json::json!({
+        "update_id": 1,
+        "message": {
+            "message_id": 1,
+            "date": 1567927221,
+            "chat": chat,
+            "from": { "id": user_id, "is_bot": false, "first_name": "Test" },
+            "text": text
+        }
+    }))
+}
@@ -1,7 +1,7 @@
 //!
 use std::net::SocketAddr;
 
-use crate::{requests::Requester, types::InputFile};
+use crate::{dispatching::WebhookReplies, requests::Requester, types::InputFile};
 
 /// Options related to setting up webhooks.
 #[must_use]
@@ -58,6 +58,12 @@ pub struct Options {
     ///
     /// Default - `teloxide` will generate a random token.
     pub secret_token: Option<String>,
+
+    /// Updates that can be answered right in the HTTP response, see
+    /// [`WebhookReplies`].
+    ///
+    /// Default - None.
+    pub inline_replies: Option<WebhookReplies>,
 }
 
 impl Options {
@@ -73,6 +79,7 @@ impl Options {
             max_connections: None,
             drop_pending_updates: false,
             secret_token: None,
+            inline_replies: None,
         }
     }
 
@@ -119,6 +126,17 @@ impl Options {
         Self { secret_token: Some(token), ..self }
     }
 
+    /// Allows handlers to answer updates in the HTTP response, see
+    /// [`WebhookReplies`].
+    ///
+    /// The same `replies` must be passed to
+    /// [`DispatcherBuilder::webhook_replies`].
+    ///
+    /// [`DispatcherBuilder::webhook_replies`]: crate::dispatching::DispatcherBuilder::webhook_replies
+    pub fn inline_replies(self, replies: WebhookReplies) -> Self {
+        Self { inline_replies: Some(replies), ..self }
+    }
+
     /// Returns `self.secret_token`, generating a new one if it's `None`.
     ///
     /// After a call to this function `self.secret_token` is always `Some(_)`.
@@ -2,11 +2,13 @@ use std::{convert::Infallible, future::Future};
 
 use axum::{
     extract::{FromRequestParts, State},
-    http::{request::Parts, status::StatusCode},
+    http::{header, request::Parts, status::StatusCode},
+    response::{IntoResponse, Response},
 };
 use tokio::sync::mpsc;
 
 use crate::{
+    dispatching::WebhookReplies,
     requests::Requester,
     stop::StopFlag,
     types::{Update, UpdateKind},
@@ -159,29 +161,29 @@ pub fn axum_no_setup(
         stop::{mk_stop_token, StopToken},
         update_listeners::{webhooks::tuple_first_mut, StatefulListener},
     };
-    use axum::{response::IntoResponse, routing::post};
+    use axum::routing::post;
     use tokio_stream::wrappers::UnboundedReceiverStream;
     use tower_http::trace::TraceLayer;
 
     let (tx, rx): (UpdateSender, _) = mpsc::unbounded_channel();
 
     async fn telegram_request(
-        State(WebhookState { secret, flag, mut tx }): State<WebhookState>,
+        State(WebhookState { secret, flag, mut tx, replies }): State<WebhookState>,
         secret_header: XTelegramBotApiSecretToken,
         input: String,
-    ) -> impl IntoResponse {
+    ) -> Response {
         // FIXME: use constant time comparison here
         if secret_header.0.as_deref() != secret.as_deref().map(str::as_bytes) {
-            return StatusCode::UNAUTHORIZED;
+            return StatusCode::UNAUTHORIZED.into_response();
         }
 
         let tx = match tx.get() {
-            None => return StatusCode::SERVICE_UNAVAILABLE,
+            None => return StatusCode::SERVICE_UNAVAILABLE.into_response(),
             // Do not process updates after `.stop()` is called even if the server is still
             // running (useful for when you need to stop the bot but can't stop the server).
             _ if flag.is_stopped() => {
                 tx.close();
-                return StatusCode::SERVICE_UNAVAILABLE;
+                return StatusCode::SERVICE_UNAVAILABLE.into_response();
             }
             Some(tx) => tx,
         };
@@ -194,7 +196,24 @@ pub fn axum_no_setup(
                     *value = serde_json::from_str(&input).unwrap_or_default();
                 }
 
-                tx.send(Ok(update)).expect("Cannot send an incoming update from the webhook")
+                let Some(replies) = replies else {
+                    tx.send(Ok(update)).expect("Cannot send an incoming update from the webhook");
+                    return StatusCode::OK.into_response();
+                };
+
+                let id = update.id;
+                let reply = replies.register(id);
+                tx.send(Ok(update)).expect("Cannot send an incoming update from the webhook");
+
+                match tokio::time::timeout(replies.deadline(), reply).await {
+                    Ok(Ok(body)) => {
+                        return ([(header::CONTENT_TYPE, "application/json")], body).into_response()
+                    }
+                    // The update was handled without a reply
+                    Ok(Err(_)) => {}
+                    // Handlers will send their requests as usual
+                    Err(_) => replies.unregister(id),
+                }
             }
             Err(error) => {
                 log::error!(
@@ -207,7 +226,7 @@ pub fn axum_no_setup(
             }
         };
 
-        StatusCode::OK
+        StatusCode::OK.into_response()
     }
 
     let (stop_token, stop_flag) = mk_stop_token();
@@ -219,6 +238,7 @@ pub fn axum_no_setup(
             tx: ClosableSender::new(tx),
             flag: stop_flag.clone(),
             secret: options.secret_token,
+            replies: options.inline_replies,
         });
 
     let stream = UnboundedReceiverStream::new(rx);
@@ -241,6 +261,7 @@ struct WebhookState {
     tx: UpdateCSender,
     flag: StopFlag,
     secret: Option<String>,
+    replies: Option<WebhookReplies>,
 }
 
 /// A terrible workaround to drop axum extension
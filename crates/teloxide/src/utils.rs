@@ -1,9 +1,13 @@
 //! Some useful utilities.
 
 pub mod command;
+#[cfg(feature = "deep-linking")]
+pub mod deep_linking;
 pub mod html;
 pub mod markdown;
 pub mod render;
 pub(crate) mod shutdown_token;
+#[cfg(feature = "webapp")]
+pub mod webapp;
 
 pub use teloxide_core::net::client_from_env;
@@ -51,8 +51,10 @@
 
 use core::fmt;
 use std::{
+    collections::{HashMap, HashSet, VecDeque},
     error::Error,
     fmt::{Display, Formatter, Write},
+    str::FromStr,
 };
 
 use teloxide_core::types::{BotCommand, Me};
@@ -134,6 +136,42 @@ pub use teloxide_macros::BotCommands;
 /// # }
 /// ```
 ///
+///  - `args` - splits a message into arguments in a shell-like manner (see
+///    [`split_args`]), so quoted arguments can contain spaces. Arguments of
+///    type `Option<T>` are optional and must follow the required ones, an
+///    argument of type `Vec<T>` must be the last one and takes the rest of the
+///    arguments. Named fields can also be turned into `--flags` and `--options`
+///    (see [field attributes](#field-attributes)). The usage of the arguments
+///    is shown in the help message.
+///
+/// ## Example
+/// ```
+/// # #[cfg(feature = "macros")] {
+/// use teloxide::utils::command::BotCommands;
+///
+/// #[derive(BotCommands, PartialEq, Debug)]
+/// #[command(rename_rule = "lowercase", parse_with = "args")]
+/// enum Command {
+///     /// Ban a user.
+///     Ban { user: String, days: Option<u32>, reasons: Vec<String> },
+/// }
+///
+/// let command = Command::parse(r#"/ban "John Doe" 7 spam flood"#, "").unwrap();
+/// assert_eq!(
+///     command,
+///     Command::Ban {
+///         user: "John Doe".to_string(),
+///         days: Some(7),
+///         reasons: vec!["spam".to_string(), "flood".to_string()]
+///     }
+/// );
+/// assert_eq!(
+///     Command::descriptions().to_string(),
+///     "/ban <user> [days] [reasons...] — Ban a user."
+/// );
+/// # }
+/// ```
+///
 /// 5. `#[command(separator = "sep")]` Specify separator used by the `split`
 ///    parser. It will be ignored when accompanied by another type of parsers.
 ///
@@ -208,6 +246,46 @@ pub use teloxide_macros::BotCommands;
 /// 8. `#[command(hide_aliases)]` Hide all aliases of a command from the help
 ///    message.
 ///
+/// 9. `#[command(subcommand)]` Parse arguments of a command as a subcommand,
+///    which is another enum deriving [`BotCommands`] (its prefix is ignored).
+///    The variant must have exactly one field of this type. Subcommands are
+///    shown in the help message after the command.
+///
+/// ## Example
+/// ```
+/// # #[cfg(feature = "macros")] {
+/// use teloxide::utils::command::BotCommands;
+///
+/// #[derive(BotCommands, PartialEq, Debug)]
+/// #[command(rename_rule = "lowercase")]
+/// enum Command {
+///     /// Manage settings.
+///     #[command(subcommand)]
+///     Config(ConfigCommand),
+/// }
+///
+/// #[derive(BotCommands, PartialEq, Debug)]
+/// #[command(rename_rule = "lowercase", parse_with = "args")]
+/// enum ConfigCommand {
+///     /// Set a value.
+///     Set { key: String, value: String },
+///     /// Get a value.
+///     Get { key: String },
+/// }
+///
+/// let command = Command::parse("/config set lang en", "").unwrap();
+/// assert_eq!(
+///     command,
+///     Command::Config(ConfigCommand::Set { key: "lang".to_string(), value: "en".to_string() })
+/// );
+/// assert_eq!(
+///     Command::descriptions().command_help("config").unwrap(),
+///     "/config — Manage settings.\n/config set <key> <value> — Set a value.\n/config get <key> \
+///      — Get a value."
+/// );
+/// # }
+/// ```
+///
 /// ## Example
 /// ```
 /// # #[cfg(feature = "macros")] {
@@ -243,6 +321,45 @@ pub use teloxide_macros::BotCommands;
 /// These attributes just override the corresponding `enum` attributes for a
 /// specific variant.
 ///
+/// # Field attributes
+/// Field attributes can only be used with the `args` parser and only on named
+/// fields. Underscores in field names are replaced with dashes.
+///
+///  1. `#[command(flag)]` Make a field a flag, which is `true` if `--name` was
+///     passed. The field must be of type `bool`.
+///
+///  2. `#[command(option)]` Make a field an option, passed as `--name=value` or
+///     `--name value`. The option is optional if its type is `Option<T>`.
+///
+/// ## Example
+/// ```
+/// # #[cfg(feature = "macros")] {
+/// use teloxide::utils::command::BotCommands;
+///
+/// #[derive(BotCommands, PartialEq, Debug)]
+/// #[command(rename_rule = "lowercase", parse_with = "args")]
+/// enum Command {
+///     Mute {
+///         user: String,
+///         #[command(flag)]
+///         silent: bool,
+///         #[command(option)]
+///         for_minutes: Option<u32>,
+///     },
+/// }
+///
+/// let command = Command::parse("/mute john --silent --for-minutes=5", "").unwrap();
+/// assert_eq!(
+///     command,
+///     Command::Mute { user: "john".to_string(), silent: true, for_minutes: Some(5) }
+/// );
+/// assert_eq!(
+///     Command::descriptions().to_string(),
+///     "/mute <user> [--silent] [--for-minutes=<for-minutes>]"
+/// );
+/// # }
+/// ```
+///
 /// [`FromStr`]: https://doc.rust-lang.org/std/str/trait.FromStr.html
 /// [`BotCommands`]: crate::utils::command::BotCommands
 pub trait BotCommands: Sized {
@@ -262,6 +379,16 @@ pub trait BotCommands: Sized {
     /// [`BotCommand`]: crate::types::BotCommand
     /// [`set_my_commands`]: crate::requests::Requester::set_my_commands
     fn bot_commands() -> Vec<BotCommand>;
+
+    /// Parses a subcommand, that is a command without a prefix and a bot
+    /// username, e.g. `set key value`.
+    ///
+    /// This is used to parse variants marked with `#[command(subcommand)]`.
+    /// The default implementation calls [`BotCommands::parse`], so it only
+    /// works for commands with an empty prefix.
+    fn parse_subcommand(s: &str) -> Result<Self, ParseError> {
+        Self::parse(s, "")
+    }
 }
 
 pub type PrefixedBotCommand = String;
@@ -288,6 +415,24 @@ pub enum ParseError {
     /// [`FromStr::from_str`]: https://doc.rust-lang.org/std/str/trait.FromStr.html#tymethod.from_str
     IncorrectFormat(Box<dyn Error + Send + Sync + 'static>),
 
+    /// An argument named `name` could not be parsed.
+    ///
+    /// Returned by [`CommandArgs`] (and thus by `parse_with = "args"`), which
+    /// knows argument names.
+    InvalidArgument {
+        name: String,
+        error: Box<dyn Error + Send + Sync + 'static>,
+    },
+
+    /// An `--option` that the command doesn't accept was passed.
+    UnknownOption(String),
+
+    /// A required `--option` was not passed.
+    MissingOption(String),
+
+    /// A quoted argument was not closed.
+    UnclosedQuote,
+
     UnknownCommand(PrefixedBotCommand),
     WrongBotName(BotName),
 
@@ -318,6 +463,56 @@ pub struct CommandDescription<'a> {
     pub aliases: &'a [&'a str],
     /// Human-readable description of the command.
     pub description: &'a str,
+    /// Usage of the command arguments, e.g. `<user> [days] [--silent]`.
+    ///
+    /// Empty if the command has no arguments or their usage is unknown.
+    pub usage: &'a str,
+    /// Descriptions of the subcommands, if this command has any.
+    pub subcommands: Option<fn() -> CommandDescriptions<'static>>,
+}
+
+/// Arguments of a command, split in a shell-like manner.
+///
+/// This is what `#[command(parse_with = "args")]` uses under the hood, but it
+/// can also be used directly in custom parsers.
+///
+/// Arguments are separated by whitespace. Single or double quotes group
+/// several words into one argument and a backslash escapes the next
+/// character. If the command accepts any flags or options, `--flag`,
+/// `--option=value` and `--option value` tokens are recognized as such,
+/// while everything after a bare `--` is positional.
+///
+/// ## Example
+/// ```
+/// use teloxide::utils::command::{CommandArgs, ParseError};
+///
+/// fn parse_ban(input: String) -> Result<(String, Option<u32>, bool), ParseError> {
+///     let mut args = CommandArgs::new(&input, 1, &["silent"], &[])?;
+///     let user = args.required("user")?;
+///     let days = args.optional("days")?;
+///     let silent = args.flag("silent");
+///     args.finish()?;
+///
+///     Ok((user, days, silent))
+/// }
+///
+/// assert_eq!(
+///     parse_ban(r#""John Doe" 7 --silent"#.to_owned()).unwrap(),
+///     ("John Doe".to_owned(), Some(7), true)
+/// );
+/// assert_eq!(parse_ban("john".to_owned()).unwrap(), ("john".to_owned(), None, false));
+/// assert!(matches!(
+///     parse_ban("john seven".to_owned()),
+///     Err(ParseError::InvalidArgument { name, .. }) if name == "days"
+/// ));
+/// ```
+#[derive(Debug, Clone)]
+pub struct CommandArgs {
+    positional: VecDeque<String>,
+    required: usize,
+    consumed: usize,
+    flags: HashSet<String>,
+    options: HashMap<String, String>,
 }
 
 impl<'a> CommandDescriptions<'a> {
@@ -350,12 +545,16 @@ impl<'a> CommandDescriptions<'a> {
     ///         command: "start",
     ///         description: "start this bot",
     ///         aliases: &[],
+    ///         usage: "",
+    ///         subcommands: None,
     ///     },
     ///     CommandDescription {
     ///         prefix: "/",
     ///         command: "help",
     ///         description: "show this message",
     ///         aliases: &[],
+    ///         usage: "",
+    ///         subcommands: None,
     ///     },
     /// ]);
     ///
@@ -381,6 +580,333 @@ impl<'a> CommandDescriptions<'a> {
     pub fn username_from_me(self, me: &'a Me) -> CommandDescriptions<'a> {
         self.username(me.user.username.as_deref().expect("Bots must have usernames"))
     }
+
+    /// Returns help for a particular command, including its subcommands.
+    ///
+    /// `command` is a command with or without a prefix and a bot username,
+    /// optionally followed by subcommands, e.g. `ban`, `/config set` or the
+    /// whole text of a message with a command. Words that don't name a
+    /// subcommand are ignored.
+    ///
+    /// Returns `None` if there is no such command.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use teloxide::utils::command::{CommandDescription, CommandDescriptions};
+    ///
+    /// let descriptions = CommandDescriptions::new(&[
+    ///     CommandDescription {
+    ///         prefix: "/",
+    ///         command: "start",
+    ///         description: "start this bot",
+    ///         aliases: &[],
+    ///         usage: "",
+    ///         subcommands: None,
+    ///     },
+    ///     CommandDescription {
+    ///         prefix: "/",
+    ///         command: "ban",
+    ///         description: "ban a user",
+    ///         aliases: &["b"],
+    ///         usage: "<user> [days]",
+    ///         subcommands: None,
+    ///     },
+    /// ]);
+    ///
+    /// assert_eq!(descriptions.command_help("ban").unwrap(), "/ban, /b <user> [days] — ban a user");
+    /// assert_eq!(
+    ///     descriptions.command_help("/b john seven").unwrap(),
+    ///     "/ban, /b <user> [days] — ban a user"
+    /// );
+    /// assert_eq!(descriptions.command_help("/mute"), None);
+    /// ```
+    #[must_use]
+    pub fn command_help(&self, command: &str) -> Option<String> {
+        let matches = |descr: &CommandDescription<'_>, word: &str| {
+            // Unwrap: split iterators always have at least one item
+            let word = word.split('@').next().unwrap();
+            [descr.command]
+                .iter()
+                .chain(descr.aliases)
+                .any(|name| word == *name || word.strip_prefix(descr.prefix) == Some(*name))
+        };
+
+        let mut words = command.split_whitespace();
+        let first = words.next()?;
+        let mut descr = self.descriptions.iter().find(|descr| matches(descr, first))?.clone();
+        let mut parent = None;
+
+        for word in words {
+            let Some(subcommands) = descr.subcommands else { break };
+            let Some(sub) = subcommands().descriptions.iter().find(|sub| matches(sub, word)) else {
+                break;
+            };
+
+            parent = Some(self.invocation(parent.as_deref(), descr.prefix, descr.command));
+            descr = sub.clone();
+        }
+
+        let mut help = String::new();
+        self.write_command(&mut help, parent.as_deref(), &descr).ok()?;
+        Some(help)
+    }
+
+    /// Returns how `command` is invoked, e.g. `/config@bot set` for the `set`
+    /// subcommand of `/config`.
+    fn invocation(&self, parent: Option<&str>, prefix: &str, command: &str) -> String {
+        match (parent, self.bot_username) {
+            (Some(parent), _) => format!("{parent} {command}"),
+            (None, Some(username)) => format!("{prefix}{command}@{username}"),
+            (None, None) => format!("{prefix}{command}"),
+        }
+    }
+
+    /// Writes a line describing `descr` followed by lines describing its
+    /// subcommands.
+    fn write_command<W>(
+        &self,
+        w: &mut W,
+        parent: Option<&str>,
+        descr: &CommandDescription<'_>,
+    ) -> fmt::Result
+    where
+        W: Write,
+    {
+        let &CommandDescription { prefix, command, aliases, description, usage, subcommands } =
+            descr;
+
+        w.write_str(&self.invocation(parent, prefix, command))?;
+        for alias in aliases {
+            w.write_str(", ")?;
+            w.write_str(&self.invocation(parent, prefix, alias))?;
+        }
+
+        if !usage.is_empty() {
+            w.write_char(' ')?;
+            w.write_str(usage)?;
+        }
+
+        if !description.is_empty() {
+            w.write_str(" — ")?;
+            w.write_str(description)?;
+        }
+
+        if let Some(subcommands) = subcommands {
+            let invocation = self.invocation(parent, prefix, command);
+            for sub in subcommands().descriptions {
+                w.write_char('\n')?;
+                self.write_command(w, Some(&invocation), sub)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl CommandArgs {
+    /// Splits `text` into arguments.
+    ///
+    /// `required` is the number of required positional arguments, it's used
+    /// for error reporting. `flags` and `options` are the names (without the
+    /// leading `--`) of the flags and options accepted by the command.
+    pub fn new(
+        text: &str,
+        required: usize,
+        flags: &[&str],
+        options: &[&str],
+    ) -> Result<Self, ParseError> {
+        let mut this = Self {
+            positional: VecDeque::new(),
+            required,
+            consumed: 0,
+            flags: HashSet::new(),
+            options: HashMap::new(),
+        };
+
+        let mut tokens = split_args(text)?.into_iter();
+
+        if flags.is_empty() && options.is_empty() {
+            this.positional.extend(tokens);
+            return Ok(this);
+        }
+
+        while let Some(token) = tokens.next() {
+            if token == "--" {
+                this.positional.extend(tokens);
+                break;
+            }
+
+            let option = match token.strip_prefix("--") {
+                Some(option) if !option.is_empty() => option,
+                _ => {
+                    this.positional.push_back(token);
+                    continue;
+                }
+            };
+
+            let (name, value) = match option.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_owned())),
+                None => (option, None),
+            };
+
+            if flags.contains(&name) {
+                if value.is_some() {
+                    return Err(ParseError::InvalidArgument {
+                        name: name.to_owned(),
+                        error: "flags don't take values".into(),
+                    });
+                }
+
+                this.flags.insert(name.to_owned());
+            } else if options.contains(&name) {
+                let value = value
+                    .or_else(|| tokens.next())
+                    .ok_or_else(|| ParseError::MissingOption(name.to_owned()))?;
+
+                this.options.insert(name.to_owned(), value);
+            } else {
+                return Err(ParseError::UnknownOption(token));
+            }
+        }
+
+        Ok(this)
+    }
+
+    /// Parses the next positional argument, failing if there is none.
+    pub fn required<T>(&mut self, name: &str) -> Result<T, ParseError>
+    where
+        T: FromStr,
+        T::Err: Into<Box<dyn Error + Send + Sync + 'static>>,
+    {
+        match self.positional.pop_front() {
+            Some(arg) => {
+                self.consumed += 1;
+                parse_arg(name, &arg)
+            }
+            None => Err(ParseError::TooFewArguments {
+                expected: self.required,
+                found: self.consumed,
+                message: format!("Missing argument `{name}`"),
+            }),
+        }
+    }
+
+    /// Parses the next positional argument, if there is one.
+    pub fn optional<T>(&mut self, name: &str) -> Result<Option<T>, ParseError>
+    where
+        T: FromStr,
+        T::Err: Into<Box<dyn Error + Send + Sync + 'static>>,
+    {
+        match self.positional.is_empty() {
+            true => Ok(None),
+            false => self.required(name).map(Some),
+        }
+    }
+
+    /// Parses all remaining positional arguments.
+    pub fn rest<T>(&mut self, name: &str) -> Result<Vec<T>, ParseError>
+    where
+        T: FromStr,
+        T::Err: Into<Box<dyn Error + Send + Sync + 'static>>,
+    {
+        self.consumed += self.positional.len();
+        self.positional.drain(..).map(|arg| parse_arg(name, &arg)).collect()
+    }
+
+    /// Returns `true` if `--name` was passed.
+    #[must_use]
+    pub fn flag(&self, name: &str) -> bool {
+        self.flags.contains(name)
+    }
+
+    /// Parses the value of `--name`, if it was passed.
+    pub fn option<T>(&self, name: &str) -> Result<Option<T>, ParseError>
+    where
+        T: FromStr,
+        T::Err: Into<Box<dyn Error + Send + Sync + 'static>>,
+    {
+        self.options.get(name).map(|value| parse_arg(name, value)).transpose()
+    }
+
+    /// Parses the value of `--name`, failing if it was not passed.
+    pub fn required_option<T>(&self, name: &str) -> Result<T, ParseError>
+    where
+        T: FromStr,
+        T::Err: Into<Box<dyn Error + Send + Sync + 'static>>,
+    {
+        self.option(name)?.ok_or_else(|| ParseError::MissingOption(name.to_owned()))
+    }
+
+    /// Checks that all positional arguments were consumed.
+    pub fn finish(self) -> Result<(), ParseError> {
+        match self.positional.front() {
+            Some(excess) => Err(ParseError::TooManyArguments {
+                expected: self.consumed,
+                found: self.consumed + self.positional.len(),
+                message: format!("Excess argument: {excess}"),
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Splits a string into arguments in a shell-like manner.
+///
+/// Whitespace separates arguments, single and double quotes group words into
+/// one argument and a backslash escapes the next character (except inside
+/// single quotes).
+///
+/// ## Example
+/// ```
+/// use teloxide::utils::command::split_args;
+///
+/// let args = split_args(r#"ban "John Doe" 'for spam' it\'s"#).unwrap();
+/// assert_eq!(args, vec!["ban", "John Doe", "for spam", "it's"]);
+/// ```
+pub fn split_args(text: &str) -> Result<Vec<String>, ParseError> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    // Whether `current` is a started argument, even if it's empty (`""`)
+    let mut in_arg = false;
+    let mut quote = None;
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('\''), c) => current.push(c),
+            (_, '\\') => {
+                in_arg = true;
+                current.push(chars.next().unwrap_or('\\'));
+            }
+            (Some(_), c) => current.push(c),
+            (None, '"' | '\'') => {
+                in_arg = true;
+                quote = Some(c);
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            }
+            (None, c) => {
+                in_arg = true;
+                current.push(c);
+            }
+        }
+    }
+
+    if quote.is_some() {
+        return Err(ParseError::UnclosedQuote);
+    }
+
+    if in_arg {
+        args.push(current);
+    }
+
+    Ok(args)
 }
 
 /// Parses a string into a command with args.
@@ -459,6 +985,15 @@ where
     Some((command, words.collect()))
 }
 
+fn parse_arg<T>(name: &str, arg: &str) -> Result<T, ParseError>
+where
+    T: FromStr,
+    T::Err: Into<Box<dyn Error + Send + Sync + 'static>>,
+{
+    T::from_str(arg)
+        .map_err(|e| ParseError::InvalidArgument { name: name.to_owned(), error: e.into() })
+}
+
 impl Display for ParseError {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
         match self {
@@ -471,6 +1006,12 @@ impl Display for ParseError {
                 "Too many arguments (expected {expected}, found {found}, message = '{message}')"
             ),
             ParseError::IncorrectFormat(e) => write!(f, "Incorrect format of command args: {e}"),
+            ParseError::InvalidArgument { name, error } => {
+                write!(f, "Invalid argument `{name}`: {error}")
+            }
+            ParseError::UnknownOption(o) => write!(f, "Unknown option: {o}"),
+            ParseError::MissingOption(o) => write!(f, "Missing option: --{o}"),
+            ParseError::UnclosedQuote => write!(f, "Unclosed quote in command args"),
             ParseError::UnknownCommand(e) => write!(f, "Unknown command: {e}"),
             ParseError::WrongBotName(n) => write!(f, "Wrong bot name: {n}"),
             ParseError::Custom(e) => write!(f, "{e}"),
@@ -487,39 +1028,11 @@ impl Display for CommandDescriptions<'_> {
             f.write_str("\n\n")?;
         }
 
-        let format_command = |command: &str, prefix: &str, formater: &mut fmt::Formatter<'_>| {
-            formater.write_str(prefix)?;
-            formater.write_str(command)?;
-            if let Some(username) = self.bot_username {
-                formater.write_char('@')?;
-                formater.write_str(username)?;
-            }
-            fmt::Result::Ok(())
-        };
-
-        let mut write = |&CommandDescription { prefix, command, aliases, description }, nls| {
-            if nls {
-                f.write_char('\n')?;
-            }
-
-            format_command(command, prefix, f)?;
-            for alias in aliases {
-                f.write_str(", ")?;
-                format_command(alias, prefix, f)?;
-            }
-
-            if !description.is_empty() {
-                f.write_str(" — ")?;
-                f.write_str(description)?;
-            }
-
-            fmt::Result::Ok(())
-        };
-
         if let Some(descr) = self.descriptions.first() {
-            write(descr, false)?;
+            self.write_command(f, None, descr)?;
             for descr in &self.descriptions[1..] {
-                write(descr, true)?;
+                f.write_char('\n')?;
+                self.write_command(f, None, descr)?;
             }
         }
 
@@ -548,4 +1061,52 @@ mod tests {
         let actual = parse_command(data, "");
         assert_eq!(actual, expected)
     }
+
+    #[test]
+    fn split_args_quotes() {
+        assert_eq!(split_args("  a  b ").unwrap(), vec!["a", "b"]);
+        assert_eq!(split_args(r#"a "b c" 'd "e"' """#).unwrap(), vec!["a", "b c", r#"d "e""#, ""]);
+        assert_eq!(split_args(r#"a\ b "c\"d" 'e\f'"#).unwrap(), vec!["a b", r#"c"d"#, r"e\f"]);
+        assert!(matches!(split_args(r#"a "b"#), Err(ParseError::UnclosedQuote)));
+    }
+
+    #[test]
+    fn command_args_options() {
+        let mut args =
+            CommandArgs::new("--silent a --reason=spam -- --b", 1, &["silent"], &["reason"])
+                .unwrap();
+        assert_eq!(args.required::<String>("x").unwrap(), "a");
+        assert_eq!(args.rest::<String>("rest").unwrap(), vec!["--b"]);
+        assert!(args.flag("silent"));
+        assert_eq!(args.option::<String>("reason").unwrap().as_deref(), Some("spam"));
+        args.finish().unwrap();
+
+        let args = CommandArgs::new("--reason spam", 0, &[], &["reason"]).unwrap();
+        assert_eq!(args.required_option::<String>("reason").unwrap(), "spam");
+
+        assert!(matches!(
+            CommandArgs::new("--what", 0, &["silent"], &[]),
+            Err(ParseError::UnknownOption(o)) if o == "--what"
+        ));
+        assert!(matches!(
+            CommandArgs::new("--reason", 0, &[], &["reason"]),
+            Err(ParseError::MissingOption(o)) if o == "reason"
+        ));
+    }
+
+    #[test]
+    fn command_args_errors() {
+        let mut args = CommandArgs::new("1 2", 2, &[], &[]).unwrap();
+        assert_eq!(args.required::<u8>("a").unwrap(), 1);
+        assert!(matches!(
+            args.clone().required::<bool>("b"),
+            Err(ParseError::InvalidArgument { name, .. }) if name == "b"
+        ));
+        assert!(matches!(args.clone().finish(), Err(ParseError::TooManyArguments { .. })));
+        assert_eq!(args.required::<u8>("b").unwrap(), 2);
+        assert!(matches!(
+            args.required::<u8>("c"),
+            Err(ParseError::TooFewArguments { expected: 2, found: 2, .. })
+        ));
+    }
 }
@@ -0,0 +1,456 @@
+//! Utilities for [deep linking].
+//!
+//! Deep links allow to start a bot with a parameter, add it to a group or a
+//! channel with the specified administrator rights, or open its Main Mini App:
+//!
+//!  - [`start_link`] builds `https://t.me/<bot>?start=<payload>`,
+//!  - [`start_group_link`] builds `https://t.me/<bot>?startgroup=<payload>`,
+//!  - [`start_channel_link`] builds `https://t.me/<bot>?startchannel&admin=...`,
+//!  - [`start_app_link`] builds `https://t.me/<bot>?startapp=<payload>` or `https://t.me/<bot>/<app>?startapp=<payload>`.
+//!
+//! All of them require [`Me`] to know the username of the bot. Inside
+//! handlers, [`Me`] is always available as a dependency; outside of them use
+//! [`Requester::get_me`], wrapping the bot into the [`CacheMe`] adaptor to
+//! avoid requesting it every time.
+//!
+//! `start` and `startgroup` parameters are limited to 64 characters out of
+//! `A-Z`, `a-z`, `0-9`, `_` and `-`. [`PayloadCodec`] fits arbitrary
+//! serializable values into this format by serializing them to JSON and
+//! encoding the result with URL-safe base64. Since users are free to edit
+//! links, [`PayloadCodec::signed`] additionally appends a truncated HMAC-SHA256
+//! of the payload, so that it can't be forged without knowing the key.
+//!
+//! Payloads of incoming `/start <payload>` messages are decoded with
+//! [`filter_start_payload`] (or [`HandlerExt::filter_start_payload`]):
+//!
+//! ```no_run
+//! use serde::{Deserialize, Serialize};
+//! use teloxide::{prelude::*, utils::deep_linking::PayloadCodec};
+//!
+//! #[derive(Serialize, Deserialize, Clone)]
+//! struct Referral {
+//!     from: UserId,
+//! }
+//!
+//! # async fn run(bot: Bot) {
+//! let codec = PayloadCodec::signed(bot.token());
+//!
+//! let handler = Update::filter_message()
+//!     .filter_start_payload::<Referral>(codec.clone())
+//!     .endpoint(|bot: Bot, msg: Message, referral: Referral| async move {
+//!         bot.send_message(msg.chat.id, format!("You were invited by {}", referral.from)).await?;
+//!         respond(())
+//!     });
+//!
+//! Dispatcher::builder(bot, handler).build().dispatch().await;
+//! # }
+//! ```
+//!
+//! Plain `/start` commands and commands with a payload that fails to decode
+//! are not accepted by the filter, so a [`BotCommands`] handler placed after it
+//! can still handle them.
+//!
+//! [deep linking]: https://core.telegram.org/api/links#bot-links
+//! [`Requester::get_me`]: crate::requests::Requester::get_me
+//! [`CacheMe`]: crate::adaptors::CacheMe
+//! [`HandlerExt::filter_start_payload`]: crate::dispatching::HandlerExt::filter_start_payload
+//! [`BotCommands`]: crate::utils::command::BotCommands
+
+use std::{fmt, sync::Arc};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use dptree::{di::DependencyMap, Handler};
+use hmac::{Hmac, Mac};
+use serde::{de::DeserializeOwned, Serialize};
+use sha2::Sha256;
+use url::Url;
+
+use crate::{
+    dispatching::DpHandlerDescription,
+    types::{ChatAdministratorRights, Me, Message},
+    utils::command::parse_command,
+};
+
+/// The maximum length of `start` and `startgroup` parameters.
+pub const MAX_START_PAYLOAD_LEN: usize = 64;
+
+/// The maximum length of `startapp` parameters.
+pub const MAX_START_APP_PAYLOAD_LEN: usize = 512;
+
+/// The length of a signature appended by [`PayloadCodec::signed`], in bytes.
+const SIGNATURE_LEN: usize = 8;
+
+/// Errors that can occur while building or decoding deep links.
+#[derive(Debug, thiserror::Error)]
+pub enum DeepLinkError {
+    /// The payload is longer than allowed.
+    #[error("The payload is too long: {len} characters, at most {max} are allowed")]
+    TooLong { len: usize, max: usize },
+
+    /// The payload contains a character not allowed in deep links.
+    #[error("The payload contains a forbidden character: {0:?}")]
+    ForbiddenCharacter(char),
+
+    /// The payload is not valid URL-safe base64.
+    #[error("The payload is not valid base64: {0}")]
+    Base64(#[from] base64::DecodeError),
+
+    /// The payload signature is missing or doesn't match.
+    #[error("The payload signature is invalid")]
+    InvalidSignature,
+
+    /// The payload can't be (de)serialized.
+    #[error("The payload can't be (de)serialized: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// Encodes and decodes typed deep link payloads.
+///
+/// See the [module-level documentation](self) for the details.
+#[derive(Clone, Default)]
+pub struct PayloadCodec {
+    key: Option<Arc<[u8]>>,
+}
+
+impl PayloadCodec {
+    /// Creates a codec which doesn't sign payloads.
+    #[must_use]
+    pub fn new() -> Self {
+        Self { key: None }
+    }
+
+    /// Creates a codec which signs payloads with HMAC-SHA256 using `key`, and
+    /// rejects payloads with an invalid signature.
+    ///
+    /// The signature takes 8 bytes, or 11 characters of the 64 allowed. The
+    /// bot token is a reasonable key, unless it is going to be revoked.
+    #[must_use]
+    pub fn signed<K>(key: K) -> Self
+    where
+        K: AsRef<[u8]>,
+    {
+        Self { key: Some(key.as_ref().into()) }
+    }
+
+    /// Encodes `payload` into a string suitable for [`start_link`] and
+    /// [`start_group_link`].
+    ///
+    /// Returns [`DeepLinkError::TooLong`] if the encoded payload is longer than
+    /// [`MAX_START_PAYLOAD_LEN`].
+    pub fn encode<T>(&self, payload: &T) -> Result<String, DeepLinkError>
+    where
+        T: Serialize + ?Sized,
+    {
+        let mut bytes = serde_json::to_vec(payload)?;
+        if let Some(signature) = self.signature(&bytes) {
+            bytes.extend_from_slice(&signature[..SIGNATURE_LEN]);
+        }
+
+        let encoded = URL_SAFE_NO_PAD.encode(bytes);
+        check_payload(&encoded, MAX_START_PAYLOAD_LEN)?;
+
+        Ok(encoded)
+    }
+
+    /// Decodes a payload previously encoded with [`PayloadCodec::encode`].
+    pub fn decode<T>(&self, payload: &str) -> Result<T, DeepLinkError>
+    where
+        T: DeserializeOwned,
+    {
+        let mut bytes = URL_SAFE_NO_PAD.decode(payload)?;
+
+        if let Some(key) = &self.key {
+            let split =
+                bytes.len().checked_sub(SIGNATURE_LEN).ok_or(DeepLinkError::InvalidSignature)?;
+            let signature = bytes.split_off(split);
+
+            let mut mac =
+                Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+            mac.update(&bytes);
+            mac.verify_truncated_left(&signature).map_err(|_| DeepLinkError::InvalidSignature)?;
+        }
+
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+
+    fn signature(&self, data: &[u8]) -> Option<[u8; 32]> {
+        let key = self.key.as_ref()?;
+        let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+        mac.update(data);
+
+        Some(mac.finalize().into_bytes().into())
+    }
+}
+
+impl fmt::Debug for PayloadCodec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PayloadCodec").field("signed", &self.key.is_some()).finish()
+    }
+}
+
+/// Builds a link which starts a private chat with the bot and sends
+/// `/start <payload>`.
+pub fn start_link(me: &Me, payload: &str) -> Result<Url, DeepLinkError> {
+    check_payload(payload, MAX_START_PAYLOAD_LEN)?;
+
+    Ok(link(me, &format!("start={payload}")))
+}
+
+/// Builds a link which prompts the user to add the bot to a group, sending
+/// `/start <payload>` there if `payload` is specified.
+///
+/// If `rights` are specified, the user is also suggested to make the bot an
+/// administrator with these rights.
+pub fn start_group_link(
+    me: &Me,
+    payload: Option<&str>,
+    rights: Option<&ChatAdministratorRights>,
+) -> Result<Url, DeepLinkError> {
+    let mut query = match payload {
+        Some(payload) => {
+            check_payload(payload, MAX_START_PAYLOAD_LEN)?;
+            format!("startgroup={payload}")
+        }
+        None => "startgroup".to_owned(),
+    };
+    if let Some(rights) = rights {
+        query.push_str("&admin=");
+        query.push_str(&admin_rights(rights));
+    }
+
+    Ok(link(me, &query))
+}
+
+/// Builds a link which prompts the user to add the bot to a channel as an
+/// administrator with the specified `rights`.
+#[must_use]
+pub fn start_channel_link(me: &Me, rights: &ChatAdministratorRights) -> Url {
+    link(me, &format!("startchannel&admin={}", admin_rights(rights)))
+}
+
+/// Builds a link which opens a Mini App of the bot, passing `payload` in the
+/// `start_param` field of the init data.
+///
+/// If `app_name` is `None`, the link opens the Main Mini App of the bot.
+pub fn start_app_link(
+    me: &Me,
+    app_name: Option<&str>,
+    payload: Option<&str>,
+) -> Result<Url, DeepLinkError> {
+    let mut url = me.tme_url();
+    if let Some(app_name) = app_name {
+        url.path_segments_mut().expect("t.me URLs have a path").push(app_name);
+    }
+
+    match payload {
+        Some(payload) => {
+            check_payload(payload, MAX_START_APP_PAYLOAD_LEN)?;
+            url.set_query(Some(&format!("startapp={payload}")));
+        }
+        None => url.set_query(Some("startapp")),
+    }
+
+    Ok(url)
+}
+
+/// Returns a handler that accepts a payload of `/start <payload>` decoded with
+/// `codec`.
+///
+/// Messages with a plain `/start` or with a payload that fails to decode are
+/// not accepted.
+///
+/// A call to this function is the same as
+/// `dptree::entry().filter_start_payload::<T>(codec)`.
+///
+/// ## Dependency requirements
+///
+///  - [`crate::types::Message`]
+///  - [`crate::types::Me`]
+#[must_use]
+pub fn filter_start_payload<T, Output>(
+    codec: PayloadCodec,
+) -> Handler<'static, DependencyMap, Output, DpHandlerDescription>
+where
+    T: DeserializeOwned + Send + Sync + 'static,
+    Output: Send + Sync + 'static,
+{
+    dptree::filter_map(move |message: Message, me: Me| {
+        let (command, args) = parse_command(message.text()?, me.username())?;
+        let [payload] = args.as_slice() else { return None };
+        if !command.eq_ignore_ascii_case("start") {
+            return None;
+        }
+
+        match codec.decode::<T>(payload) {
+            Ok(payload) => Some(payload),
+            Err(err) => {
+                log::debug!("Failed to decode a deep link payload {payload:?}: {err}");
+                None
+            }
+        }
+    })
+}
+
+fn link(me: &Me, query: &str) -> Url {
+    let mut url = me.tme_url();
+    url.set_query(Some(query));
+    url
+}
+
+fn check_payload(payload: &str, max: usize) -> Result<(), DeepLinkError> {
+    if let Some(c) = payload.chars().find(|&c| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+    {
+        return Err(DeepLinkError::ForbiddenCharacter(c));
+    }
+    if payload.len() > max {
+        return Err(DeepLinkError::TooLong { len: payload.len(), max });
+    }
+
+    Ok(())
+}
+
+fn admin_rights(rights: &ChatAdministratorRights) -> String {
+    let ChatAdministratorRights {
+        is_anonymous,
+        can_manage_chat,
+        can_delete_messages,
+        can_manage_video_chats,
+        can_restrict_members,
+        can_promote_members,
+        can_change_info,
+        can_invite_users,
+        can_post_messages,
+        can_edit_messages,
+        can_pin_messages,
+        can_post_stories,
+        can_edit_stories,
+        can_delete_stories,
+        can_manage_topics,
+    } = *rights;
+
+    let names = [
+        (can_change_info, "change_info"),
+        (can_post_messages.unwrap_or(false), "post_messages"),
+        (can_edit_messages.unwrap_or(false), "edit_messages"),
+        (can_delete_messages, "delete_messages"),
+        (can_restrict_members, "restrict_members"),
+        (can_invite_users, "invite_users"),
+        (can_pin_messages.unwrap_or(false), "pin_messages"),
+        (can_manage_topics.unwrap_or(false), "manage_topics"),
+        (can_promote_members, "promote_members"),
+        (can_manage_video_chats, "manage_video_chats"),
+        (is_anonymous, "anonymous"),
+        (can_manage_chat, "manage_chat"),
+        (can_post_stories.unwrap_or(false), "post_stories"),
+        (can_edit_stories.unwrap_or(false), "edit_stories"),
+        (can_delete_stories.unwrap_or(false), "delete_stories"),
+    ];
+
+    names.iter().filter(|(has, _)| *has).map(|(_, name)| *name).collect::<Vec<_>>().join("+")
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::types::{User, UserId};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Referral {
+        from: u64,
+        campaign: String,
+    }
+
+    fn me() -> Me {
+        Me {
+            user: User {
+                id: UserId(42),
+                is_bot: true,
+                first_name: "Test".to_owned(),
+                last_name: None,
+                username: Some("test_bot".to_owned()),
+                language_code: None,
+                is_premium: false,
+                added_to_attachment_menu: false,
+            },
+            can_join_groups: true,
+            can_read_all_group_messages: false,
+            supports_inline_queries: false,
+            can_connect_to_business: false,
+        }
+    }
+
+    #[test]
+    fn payload_roundtrip() {
+        let referral = Referral { from: 218_485_655, campaign: "summer".to_owned() };
+
+        let plain = PayloadCodec::new();
+        let encoded = plain.encode(&referral).unwrap();
+        assert_eq!(plain.decode::<Referral>(&encoded).unwrap(), referral);
+
+        let signed = PayloadCodec::signed("secret");
+        let encoded = signed.encode(&referral).unwrap();
+        assert!(encoded.len() <= MAX_START_PAYLOAD_LEN);
+        assert_eq!(signed.decode::<Referral>(&encoded).unwrap(), referral);
+
+        // Wrong key
+        assert!(matches!(
+            PayloadCodec::signed("other").decode::<Referral>(&encoded),
+            Err(DeepLinkError::InvalidSignature)
+        ));
+        // Unsigned payload
+        assert!(signed.decode::<Referral>(&plain.encode(&referral).unwrap()).is_err());
+        // Too long
+        assert!(matches!(plain.encode(&"a".repeat(64)), Err(DeepLinkError::TooLong { .. })));
+    }
+
+    #[test]
+    fn links() {
+        let me = me();
+
+        assert_eq!(
+            start_link(&me, "abc_DEF-1").unwrap().as_str(),
+            "https://t.me/test_bot?start=abc_DEF-1"
+        );
+        assert!(matches!(start_link(&me, "a b"), Err(DeepLinkError::ForbiddenCharacter(' '))));
+
+        let rights = ChatAdministratorRights {
+            is_anonymous: false,
+            can_manage_chat: true,
+            can_delete_messages: true,
+            can_manage_video_chats: false,
+            can_restrict_members: false,
+            can_promote_members: false,
+            can_change_info: true,
+            can_invite_users: false,
+            can_post_messages: Some(true),
+            can_edit_messages: None,
+            can_pin_messages: None,
+            can_post_stories: None,
+            can_edit_stories: None,
+            can_delete_stories: None,
+            can_manage_topics: None,
+        };
+        assert_eq!(
+            start_group_link(&me, Some("hi"), Some(&rights)).unwrap().as_str(),
+            "https://t.me/test_bot?startgroup=hi&admin=change_info+post_messages+delete_messages+manage_chat"
+        );
+        assert_eq!(
+            start_group_link(&me, None, None).unwrap().as_str(),
+            "https://t.me/test_bot?startgroup"
+        );
+        assert_eq!(
+            start_channel_link(&me, &rights).as_str(),
+            "https://t.me/test_bot?startchannel&admin=change_info+post_messages+delete_messages+manage_chat"
+        );
+        assert_eq!(
+            start_app_link(&me, Some("game"), Some("level1")).unwrap().as_str(),
+            "https://t.me/test_bot/game?startapp=level1"
+        );
+        assert_eq!(
+            start_app_link(&me, None, None).unwrap().as_str(),
+            "https://t.me/test_bot?startapp"
+        );
+    }
+}
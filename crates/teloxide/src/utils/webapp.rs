@@ -0,0 +1,568 @@
+//! Validation of [Web App] init data and [Login Widget] data.
+//!
+//! When a [Web App] is opened, it receives `Telegram.WebApp.initData`, which
+//! it can pass to the bot's backend. Before trusting this data, the backend
+//! must check that it was really sent by Telegram. [`WebAppInitData`] does
+//! this either by checking the HMAC-SHA256 `hash` with the bot token, or by
+//! checking the Ed25519 `signature` with Telegram's public key (the latter
+//! doesn't require the bot token, so third parties can validate data too).
+//!
+//! Data received from the [Login Widget] (and from [`LoginUrl`] buttons) is
+//! validated in a similar way with [`LoginWidgetData`].
+//!
+//! ## Example
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use teloxide::{prelude::*, types::InlineQueryResult, utils::webapp::WebAppInitData};
+//!
+//! # async fn handle(bot: Bot, init_data: String, result: InlineQueryResult) -> Result<(), Box<dyn std::error::Error>> {
+//! // `init_data` is `Telegram.WebApp.initData`, sent to the backend by the Web App
+//! let data = WebAppInitData::validate(&init_data, bot.token(), Duration::from_secs(60 * 60))?;
+//! let user = data.user.as_ref().ok_or("no user")?;
+//! log::info!("{} opened the Web App", user.first_name);
+//!
+//! if let Some(request) = data.answer_web_app_query(&bot, result) {
+//!     let sent = request.await?;
+//!     log::info!("Sent an inline message {:?}", sent.inline_message_id);
+//! }
+//! # Ok(()) }
+//! ```
+//!
+//! [Web App]: https://core.telegram.org/bots/webapps
+//! [Login Widget]: https://core.telegram.org/widgets/login
+//! [`LoginUrl`]: crate::types::LoginUrl
+
+use std::{
+    error::Error,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use hmac::{Hmac, Mac};
+use serde::{de::value::StrDeserializer, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use teloxide_core::{
+    requests::Requester,
+    types::{ChatId, ChatType, InlineQueryResult, UserId},
+};
+use thiserror::Error;
+
+/// Telegram's Ed25519 public key used to sign Web App init data in the
+/// production environment.
+pub const TELEGRAM_PUBLIC_KEY: [u8; 32] = [
+    0xe7, 0xbf, 0x03, 0xa2, 0xfa, 0x46, 0x02, 0xaf, 0x45, 0x80, 0x70, 0x3d, 0x88, 0xdd, 0xa5, 0xbb,
+    0x59, 0xf3, 0x2e, 0xd8, 0xb0, 0x2a, 0x56, 0xc1, 0x87, 0xfe, 0x7d, 0x34, 0xca, 0xed, 0x24, 0x2d,
+];
+
+/// Telegram's Ed25519 public key used to sign Web App init data in the test
+/// environment.
+pub const TELEGRAM_TEST_PUBLIC_KEY: [u8; 32] = [
+    0x40, 0x05, 0x50, 0x58, 0xa4, 0xee, 0x38, 0x15, 0x6a, 0x06, 0x56, 0x2e, 0x52, 0xee, 0xce, 0x92,
+    0xa7, 0x71, 0xbc, 0xd8, 0x34, 0x6a, 0x8c, 0x46, 0x15, 0xcb, 0x73, 0x76, 0xed, 0xdf, 0x72, 0xec,
+];
+
+/// Data passed to a [Web App] when it's opened ([`WebAppInitData`] in the
+/// Telegram docs).
+///
+/// [Web App]: https://core.telegram.org/bots/webapps
+/// [`WebAppInitData`]: https://core.telegram.org/bots/webapps#webappinitdata
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WebAppInitData {
+    /// A unique identifier for the Web App session, required for sending
+    /// messages via [`answer_web_app_query`].
+    ///
+    /// [`answer_web_app_query`]: WebAppInitData::answer_web_app_query
+    pub query_id: Option<String>,
+
+    /// The user who opened the Web App.
+    pub user: Option<WebAppUser>,
+
+    /// The chat partner of the current user in the chat where the bot was
+    /// launched via the attachment menu. Returned only for private chats and
+    /// only for Web Apps launched via the attachment menu.
+    pub receiver: Option<WebAppUser>,
+
+    /// The chat where the bot was launched via the attachment menu. Returned
+    /// for supergroups, channels and group chats – only for Web Apps launched
+    /// via the attachment menu.
+    pub chat: Option<WebAppChat>,
+
+    /// Type of the chat from which the Web App was opened.
+    pub chat_type: Option<ChatType>,
+
+    /// Global identifier, uniquely corresponding to the chat from which the
+    /// Web App was opened. Returned only for Web Apps launched from a direct
+    /// link.
+    pub chat_instance: Option<String>,
+
+    /// The value of the `startattach` or `startapp` parameter, passed via
+    /// link.
+    pub start_param: Option<String>,
+
+    /// Time in seconds, after which a message can be sent via
+    /// [`answer_web_app_query`].
+    ///
+    /// [`answer_web_app_query`]: WebAppInitData::answer_web_app_query
+    pub can_send_after: Option<u32>,
+
+    /// Time when the form was opened.
+    pub auth_date: DateTime<Utc>,
+
+    /// A hash of all passed parameters, which the bot server can use to check
+    /// their validity.
+    pub hash: String,
+
+    /// A signature of all passed parameters (except `hash`), which third
+    /// parties can use to check their validity.
+    pub signature: Option<String>,
+}
+
+/// A user in [`WebAppInitData`] ([`WebAppUser`] in the Telegram docs).
+///
+/// [`WebAppUser`]: https://core.telegram.org/bots/webapps#webappuser
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct WebAppUser {
+    /// Unique identifier for this user or bot.
+    pub id: UserId,
+
+    /// `true`, if this user is a bot. Returned in the `receiver` field only.
+    #[serde(default)]
+    pub is_bot: bool,
+
+    /// First name of the user or bot.
+    pub first_name: String,
+
+    /// Last name of the user or bot.
+    pub last_name: Option<String>,
+
+    /// Username of the user or bot.
+    pub username: Option<String>,
+
+    /// [IETF language tag] of the user's language. Returns in the `user` field
+    /// only.
+    ///
+    /// [IETF language tag]: https://en.wikipedia.org/wiki/IETF_language_tag
+    pub language_code: Option<String>,
+
+    /// `true`, if this user is a Telegram Premium user.
+    #[serde(default)]
+    pub is_premium: bool,
+
+    /// `true`, if this user added the bot to the attachment menu.
+    #[serde(default)]
+    pub added_to_attachment_menu: bool,
+
+    /// `true`, if this user allowed the bot to message them.
+    #[serde(default)]
+    pub allows_write_to_pm: bool,
+
+    /// URL of the user’s profile photo.
+    pub photo_url: Option<String>,
+}
+
+/// A chat in [`WebAppInitData`] ([`WebAppChat`] in the Telegram docs).
+///
+/// [`WebAppChat`]: https://core.telegram.org/bots/webapps#webappchat
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct WebAppChat {
+    /// Unique identifier for this chat.
+    pub id: ChatId,
+
+    /// Type of the chat.
+    #[serde(rename = "type")]
+    pub kind: ChatType,
+
+    /// Title of the chat.
+    pub title: String,
+
+    /// Username of the chat.
+    pub username: Option<String>,
+
+    /// URL of the chat’s photo.
+    pub photo_url: Option<String>,
+}
+
+/// Data received from the [Login Widget] or a [`LoginUrl`] button.
+///
+/// [Login Widget]: https://core.telegram.org/widgets/login
+/// [`LoginUrl`]: crate::types::LoginUrl
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LoginWidgetData {
+    /// Unique identifier of the user.
+    pub id: UserId,
+
+    /// First name of the user.
+    pub first_name: String,
+
+    /// Last name of the user.
+    pub last_name: Option<String>,
+
+    /// Username of the user.
+    pub username: Option<String>,
+
+    /// URL of the user’s profile photo.
+    pub photo_url: Option<String>,
+
+    /// Time when the user logged in.
+    pub auth_date: DateTime<Utc>,
+
+    /// A hash of all passed parameters, which the bot server can use to check
+    /// their validity.
+    pub hash: String,
+}
+
+/// An error returned when Web App or Login Widget data is invalid.
+#[derive(Debug, Error)]
+pub enum ValidationError {
+    #[error("field `{0}` is missing")]
+    MissingField(&'static str),
+
+    #[error("field `{name}` is malformed: {source}")]
+    MalformedField { name: &'static str, source: Box<dyn Error + Send + Sync + 'static> },
+
+    /// Returned when `hash` doesn't match the data.
+    #[error("hash is invalid")]
+    InvalidHash,
+
+    /// Returned when `signature` doesn't match the data.
+    #[error("signature is invalid")]
+    InvalidSignature,
+
+    /// Returned when `auth_date` is older than allowed.
+    #[error("data is outdated")]
+    Outdated,
+}
+
+type HmacSha256 = Hmac<Sha256>;
+
+impl WebAppInitData {
+    /// Validates `init_data` (the `Telegram.WebApp.initData` string) with the
+    /// bot token and parses it.
+    ///
+    /// Data older than `max_age` is rejected, pass [`Duration::MAX`] to accept
+    /// data of any age.
+    ///
+    /// The bot token can be obtained with [`Bot::token`].
+    ///
+    /// [`Bot::token`]: crate::Bot::token
+    pub fn validate(
+        init_data: &str,
+        bot_token: &str,
+        max_age: Duration,
+    ) -> Result<Self, ValidationError> {
+        let pairs = pairs(init_data);
+
+        let secret_key = HmacSha256::new_from_slice(b"WebAppData")
+            .expect("HMAC can take key of any size")
+            .chain_update(bot_token)
+            .finalize()
+            .into_bytes();
+        check_hash(&pairs, &secret_key)?;
+
+        let this = Self::parse(init_data)?;
+        check_age(this.auth_date, max_age)?;
+        Ok(this)
+    }
+
+    /// Validates `init_data` (the `Telegram.WebApp.initData` string) with the
+    /// Ed25519 `public_key` and parses it.
+    ///
+    /// Unlike [`validate`], this doesn't require the bot token, so third
+    /// parties, knowing only the bot's ID, can validate the data. Use
+    /// [`TELEGRAM_PUBLIC_KEY`] (or [`TELEGRAM_TEST_PUBLIC_KEY`] for the test
+    /// environment) as the `public_key`.
+    ///
+    /// Data older than `max_age` is rejected, pass [`Duration::MAX`] to accept
+    /// data of any age.
+    ///
+    /// [`validate`]: WebAppInitData::validate
+    pub fn validate_signature(
+        init_data: &str,
+        bot_id: UserId,
+        public_key: &[u8; 32],
+        max_age: Duration,
+    ) -> Result<Self, ValidationError> {
+        let pairs = pairs(init_data);
+
+        let signature = find(&pairs, "signature")?;
+        let signature = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(signature.trim_end_matches('='))
+            .map_err(|_| ValidationError::InvalidSignature)?;
+        let signature =
+            Signature::from_slice(&signature).map_err(|_| ValidationError::InvalidSignature)?;
+
+        let data_check_string =
+            format!("{bot_id}:WebAppData\n{}", data_check_string(&pairs, &["hash", "signature"]));
+        VerifyingKey::from_bytes(public_key)
+            .and_then(|key| key.verify(data_check_string.as_bytes(), &signature))
+            .map_err(|_| ValidationError::InvalidSignature)?;
+
+        let this = Self::parse(init_data)?;
+        check_age(this.auth_date, max_age)?;
+        Ok(this)
+    }
+
+    /// Parses `init_data` (the `Telegram.WebApp.initData` string) **without**
+    /// validating it.
+    ///
+    /// The returned data must not be trusted, use [`validate`] or
+    /// [`validate_signature`] instead, unless the data was validated by other
+    /// means.
+    ///
+    /// [`validate`]: WebAppInitData::validate
+    /// [`validate_signature`]: WebAppInitData::validate_signature
+    pub fn parse(init_data: &str) -> Result<Self, ValidationError> {
+        let pairs = pairs(init_data);
+        let get = |name| pairs.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str());
+
+        Ok(Self {
+            query_id: get("query_id").map(ToOwned::to_owned),
+            user: get("user").map(|v| parse_json("user", v)).transpose()?,
+            receiver: get("receiver").map(|v| parse_json("receiver", v)).transpose()?,
+            chat: get("chat").map(|v| parse_json("chat", v)).transpose()?,
+            chat_type: get("chat_type").map(parse_chat_type).transpose()?,
+            chat_instance: get("chat_instance").map(ToOwned::to_owned),
+            start_param: get("start_param").map(ToOwned::to_owned),
+            can_send_after: get("can_send_after")
+                .map(|v| v.parse().map_err(|e| malformed("can_send_after", e)))
+                .transpose()?,
+            auth_date: parse_auth_date(find(&pairs, "auth_date")?)?,
+            hash: find(&pairs, "hash")?.to_owned(),
+            signature: get("signature").map(ToOwned::to_owned),
+        })
+    }
+
+    /// Sends a message on behalf of the user who opened the Web App, using
+    /// [`answer_web_app_query`].
+    ///
+    /// The request resolves to [`SentWebAppMessage`]. Returns `None` if there
+    /// is no `query_id` (i.e. the Web App wasn't opened from an inline
+    /// button or the attachment menu).
+    ///
+    /// [`answer_web_app_query`]: crate::requests::Requester::answer_web_app_query
+    /// [`SentWebAppMessage`]: crate::types::SentWebAppMessage
+    #[must_use]
+    pub fn answer_web_app_query<R>(
+        &self,
+        bot: &R,
+        result: InlineQueryResult,
+    ) -> Option<R::AnswerWebAppQuery>
+    where
+        R: Requester,
+    {
+        self.query_id.as_ref().map(|query_id| bot.answer_web_app_query(query_id, result))
+    }
+}
+
+impl LoginWidgetData {
+    /// Validates `query` (URL query string with the Login Widget data, e.g.
+    /// `id=1&first_name=John&auth_date=1662771648&hash=...`) with the bot token
+    /// and parses it.
+    ///
+    /// Data older than `max_age` is rejected, pass [`Duration::MAX`] to accept
+    /// data of any age.
+    ///
+    /// The bot token can be obtained with [`Bot::token`].
+    ///
+    /// [`Bot::token`]: crate::Bot::token
+    pub fn validate(
+        query: &str,
+        bot_token: &str,
+        max_age: Duration,
+    ) -> Result<Self, ValidationError> {
+        let pairs = pairs(query);
+
+        let secret_key = Sha256::digest(bot_token);
+        check_hash(&pairs, &secret_key)?;
+
+        let get = |name| pairs.iter().find(|(k, _)| k == name).map(|(_, v)| v.to_owned());
+        let this = Self {
+            id: find(&pairs, "id")?.parse().map(UserId).map_err(|e| malformed("id", e))?,
+            first_name: find(&pairs, "first_name")?.to_owned(),
+            last_name: get("last_name"),
+            username: get("username"),
+            photo_url: get("photo_url"),
+            auth_date: parse_auth_date(find(&pairs, "auth_date")?)?,
+            hash: find(&pairs, "hash")?.to_owned(),
+        };
+
+        check_age(this.auth_date, max_age)?;
+        Ok(this)
+    }
+}
+
+fn pairs(query: &str) -> Vec<(String, String)> {
+    url::form_urlencoded::parse(query.as_bytes()).into_owned().collect()
+}
+
+fn find<'a>(pairs: &'a [(String, String)], name: &'static str) -> Result<&'a str, ValidationError> {
+    pairs
+        .iter()
+        .find(|(k, _)| k == name)
+        .map(|(_, v)| v.as_str())
+        .ok_or(ValidationError::MissingField(name))
+}
+
+/// Returns alphabetically sorted `key=value` pairs (except `excluded` keys)
+/// joined with line feeds.
+fn data_check_string(pairs: &[(String, String)], excluded: &[&str]) -> String {
+    let mut pairs: Vec<_> = pairs
+        .iter()
+        .filter(|(k, _)| !excluded.contains(&k.as_str()))
+        .map(|(k, v)| format!("{k}={v}"))
+        .collect();
+    pairs.sort_unstable();
+    pairs.join("\n")
+}
+
+fn check_hash(pairs: &[(String, String)], secret_key: &[u8]) -> Result<(), ValidationError> {
+    let hash = hex::decode(find(pairs, "hash")?).map_err(|_| ValidationError::InvalidHash)?;
+
+    HmacSha256::new_from_slice(secret_key)
+        .expect("HMAC can take key of any size")
+        .chain_update(data_check_string(pairs, &["hash"]))
+        .verify_slice(&hash)
+        .map_err(|_| ValidationError::InvalidHash)
+}
+
+fn check_age(auth_date: DateTime<Utc>, max_age: Duration) -> Result<(), ValidationError> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let auth_date = Duration::from_secs(auth_date.timestamp().try_into().unwrap_or_default());
+
+    match now.saturating_sub(auth_date) > max_age {
+        true => Err(ValidationError::Outdated),
+        false => Ok(()),
+    }
+}
+
+fn parse_auth_date(value: &str) -> Result<DateTime<Utc>, ValidationError> {
+    let timestamp = value.parse().map_err(|e| malformed("auth_date", e))?;
+    DateTime::from_timestamp(timestamp, 0).ok_or_else(|| malformed("auth_date", "out of range"))
+}
+
+fn parse_chat_type(value: &str) -> Result<ChatType, ValidationError> {
+    ChatType::deserialize(StrDeserializer::<serde::de::value::Error>::new(value))
+        .map_err(|e| malformed("chat_type", e))
+}
+
+fn parse_json<T>(name: &'static str, value: &str) -> Result<T, ValidationError>
+where
+    T: serde::de::DeserializeOwned,
+{
+    serde_json::from_str(value).map_err(|e| malformed(name, e))
+}
+
+fn malformed<E>(name: &'static str, source: E) -> ValidationError
+where
+    E: Into<Box<dyn Error + Send + Sync + 'static>>,
+{
+    ValidationError::MalformedField { name, source: source.into() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKEN: &str = "123456:ABC-DEF1234ghIkl-zyx57W2v1u123ew11";
+
+    const INIT_DATA: &str = "query_id=AAHdF6IQAAAAAN0XohDhrOrc&user=%7B%22id%22%3A279058397%2C%\
+                             22first_name%22%3A%22Vladislav%22%2C%22last_name%22%3A%22Kibenko%22%\
+                             2C%22username%22%3A%22vdkfrost%22%2C%22language_code%22%3A%22ru%22%\
+                             2C%22is_premium%22%3Atrue%2C%22allows_write_to_pm%22%3Atrue%7D&\
+                             auth_date=1662771648&start_param=ref42&chat_type=sender&\
+                             chat_instance=-4308371183283224562&\
+                             hash=1c39dc157366f5ebd4c14e962ba72c51db1fc3dc3ded651555aebc67631215b8";
+
+    #[test]
+    fn validate_init_data() {
+        let data = WebAppInitData::validate(INIT_DATA, TOKEN, Duration::MAX).unwrap();
+        assert_eq!(data.query_id.as_deref(), Some("AAHdF6IQAAAAAN0XohDhrOrc"));
+        assert_eq!(data.start_param.as_deref(), Some("ref42"));
+        assert_eq!(data.chat_type, Some(ChatType::Sender));
+        assert_eq!(data.auth_date.timestamp(), 1662771648);
+
+        let user = data.user.unwrap();
+        assert_eq!(user.id, UserId(279058397));
+        assert_eq!(user.username.as_deref(), Some("vdkfrost"));
+        assert!(user.is_premium && user.allows_write_to_pm && !user.is_bot);
+
+        assert!(matches!(
+            WebAppInitData::validate(INIT_DATA, "123456:wrong", Duration::MAX),
+            Err(ValidationError::InvalidHash)
+        ));
+        assert!(matches!(
+            WebAppInitData::validate(&INIT_DATA.replace("ref42", "ref43"), TOKEN, Duration::MAX),
+            Err(ValidationError::InvalidHash)
+        ));
+        assert!(matches!(
+            WebAppInitData::validate(INIT_DATA, TOKEN, Duration::from_secs(60)),
+            Err(ValidationError::Outdated)
+        ));
+    }
+
+    #[test]
+    fn validate_init_data_signature() {
+        const PUBLIC_KEY: [u8; 32] = [
+            0xea, 0x4a, 0x6c, 0x63, 0xe2, 0x9c, 0x52, 0x0a, 0xbe, 0xf5, 0x50, 0x7b, 0x13, 0x2e,
+            0xc5, 0xf9, 0x95, 0x47, 0x76, 0xae, 0xbe, 0xbe, 0x7b, 0x92, 0x42, 0x1e, 0xea, 0x69,
+            0x14, 0x46, 0xd2, 0x2c,
+        ];
+        const INIT_DATA: &str = "user=%7B%22id%22%3A279058397%2C%22first_name%22%3A%22Vladislav%\
+                                 22%7D&auth_date=1662771648&chat_type=private&chat_instance=42&\
+                                 signature=LGdxS82sn7Zg30NgZV6BT7fN8iz34pMQpDGdxR177dbWTt-\
+                                 An2m1VBVE3T11wV9YPKXQ85N1xAwNwqaPA17SAQ&hash=00";
+
+        let data = WebAppInitData::validate_signature(
+            INIT_DATA,
+            UserId(123456),
+            &PUBLIC_KEY,
+            Duration::MAX,
+        )
+        .unwrap();
+        assert_eq!(data.chat_type, Some(ChatType::Private));
+        assert_eq!(data.user.unwrap().first_name, "Vladislav");
+
+        assert!(matches!(
+            WebAppInitData::validate_signature(
+                INIT_DATA,
+                UserId(654321),
+                &PUBLIC_KEY,
+                Duration::MAX
+            ),
+            Err(ValidationError::InvalidSignature)
+        ));
+        assert!(matches!(
+            WebAppInitData::validate_signature(
+                INIT_DATA,
+                UserId(123456),
+                &TELEGRAM_PUBLIC_KEY,
+                Duration::MAX
+            ),
+            Err(ValidationError::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn validate_login_widget_data() {
+        const QUERY: &str = "id=279058397&first_name=Vladislav&username=vdkfrost&photo_url=https%\
+                             3A%2F%2Ft.me%2Fi%2Fuserpic%2F320%2Fx.jpg&auth_date=1662771648&\
+                             hash=8517da4e5195f60631969fd6fee6fb536e0f96b8dd177ce5a8969baefb6d9f75";
+
+        let data = LoginWidgetData::validate(QUERY, TOKEN, Duration::MAX).unwrap();
+        assert_eq!(data.id, UserId(279058397));
+        assert_eq!(data.last_name, None);
+        assert_eq!(data.photo_url.as_deref(), Some("https://t.me/i/userpic/320/x.jpg"));
+
+        assert!(matches!(
+            LoginWidgetData::validate(QUERY, "123456:wrong", Duration::MAX),
+            Err(ValidationError::InvalidHash)
+        ));
+        assert!(matches!(
+            LoginWidgetData::validate("id=1", TOKEN, Duration::MAX),
+            Err(ValidationError::MissingField("hash"))
+        ));
+    }
+}
@@ -270,6 +270,49 @@ fn parse_custom_parser() {
     );
 }
 
+#[test]
+#[cfg(feature = "macros")]
+fn parse_custom_parser_any_field_order() {
+    use teloxide::utils::command::ParseError;
+
+    // The order of arguments is only checked for `parse_with = "args"`
+    fn parse_rest_first(s: String) -> Result<(Vec<String>, u32), ParseError> {
+        let mut words: Vec<_> = s.split_whitespace().map(ToOwned::to_owned).collect();
+        let last = words.pop().ok_or(ParseError::TooFewArguments {
+            expected: 1,
+            found: 0,
+            message: s.clone(),
+        })?;
+        let last = last.parse().map_err(|e| ParseError::IncorrectFormat(Box::new(e)))?;
+        Ok((words, last))
+    }
+
+    fn parse_optional_first(s: String) -> Result<(Option<u8>, String), ParseError> {
+        match s.split_once(' ') {
+            Some((n, rest)) => Ok((n.parse().ok(), rest.to_owned())),
+            None => Ok((None, s)),
+        }
+    }
+
+    #[derive(BotCommands, Debug, PartialEq)]
+    #[command(rename_rule = "lowercase")]
+    enum DefaultCommands {
+        #[command(parse_with = parse_rest_first)]
+        Foo(Vec<String>, u32),
+        #[command(parse_with = parse_optional_first)]
+        Bar(Option<u8>, String),
+    }
+
+    assert_eq!(
+        DefaultCommands::Foo(vec!["a".to_owned(), "b".to_owned()], 3),
+        DefaultCommands::parse("/foo a b 3", "").unwrap()
+    );
+    assert_eq!(
+        DefaultCommands::Bar(Some(1), "x".to_owned()),
+        DefaultCommands::parse("/bar 1 x", "").unwrap()
+    );
+}
+
 #[test]
 #[cfg(feature = "macros")]
 fn parse_named_fields() {
@@ -586,3 +629,222 @@ fn custom_result() {
     #[allow(dead_code)]
     enum DefaultCommands {}
 }
+
+#[test]
+#[cfg(feature = "macros")]
+fn parse_with_args() {
+    #[derive(BotCommands, Debug, PartialEq)]
+    #[command(rename_rule = "lowercase", parse_with = "args")]
+    enum DefaultCommands {
+        Ban { user: String, days: Option<u32>, reasons: Vec<String> },
+        Sum(Vec<i32>),
+        Help,
+    }
+
+    assert_eq!(
+        DefaultCommands::Ban { user: "John Doe".to_owned(), days: None, reasons: vec![] },
+        DefaultCommands::parse(r#"/ban "John Doe""#, "").unwrap()
+    );
+    assert_eq!(
+        DefaultCommands::Ban {
+            user: "john".to_owned(),
+            days: Some(7),
+            reasons: vec!["spam".to_owned(), "flood in chat".to_owned()]
+        },
+        DefaultCommands::parse("/ban john 7 spam 'flood in chat'", "").unwrap()
+    );
+    assert_eq!(
+        DefaultCommands::Sum(vec![1, -2, 3]),
+        DefaultCommands::parse("/sum 1 -2 3", "").unwrap()
+    );
+    assert_eq!(DefaultCommands::Sum(vec![]), DefaultCommands::parse("/sum", "").unwrap());
+}
+
+#[test]
+#[cfg(feature = "macros")]
+fn parse_with_args_options() {
+    #[derive(BotCommands, Debug, PartialEq)]
+    #[command(rename_rule = "lowercase", parse_with = "args")]
+    enum DefaultCommands {
+        Mute {
+            user: String,
+            #[command(flag)]
+            silent: bool,
+            #[command(option)]
+            for_minutes: Option<u32>,
+            #[command(option)]
+            reason: String,
+        },
+    }
+
+    assert_eq!(
+        DefaultCommands::Mute {
+            user: "john".to_owned(),
+            silent: true,
+            for_minutes: Some(5),
+            reason: "spam".to_owned()
+        },
+        DefaultCommands::parse("/mute --silent --for-minutes 5 john --reason=spam", "").unwrap()
+    );
+    assert_eq!(
+        DefaultCommands::Mute {
+            user: "--john".to_owned(),
+            silent: false,
+            for_minutes: None,
+            reason: "a b".to_owned()
+        },
+        DefaultCommands::parse("/mute --reason 'a b' -- --john", "").unwrap()
+    );
+}
+
+#[test]
+#[cfg(feature = "macros")]
+fn parse_with_args_errors() {
+    use teloxide::utils::command::ParseError;
+
+    #[derive(BotCommands, Debug, PartialEq)]
+    #[command(rename_rule = "lowercase", parse_with = "args")]
+    enum DefaultCommands {
+        Ban {
+            user: String,
+            days: Option<u32>,
+            #[command(option)]
+            reason: String,
+        },
+    }
+
+    assert!(matches!(
+        DefaultCommands::parse("/ban --reason=spam", ""),
+        Err(ParseError::TooFewArguments { expected: 1, found: 0, .. })
+    ));
+    assert!(matches!(
+        DefaultCommands::parse("/ban john week --reason=spam", ""),
+        Err(ParseError::InvalidArgument { name, .. }) if name == "days"
+    ));
+    assert!(matches!(
+        DefaultCommands::parse("/ban john 7 8 --reason=spam", ""),
+        Err(ParseError::TooManyArguments { expected: 2, found: 3, .. })
+    ));
+    assert!(matches!(
+        DefaultCommands::parse("/ban john", ""),
+        Err(ParseError::MissingOption(o)) if o == "reason"
+    ));
+    assert!(matches!(
+        DefaultCommands::parse("/ban john --silent", ""),
+        Err(ParseError::UnknownOption(o)) if o == "--silent"
+    ));
+    assert!(matches!(
+        DefaultCommands::parse("/ban 'john --reason=spam", ""),
+        Err(ParseError::UnclosedQuote)
+    ));
+}
+
+#[test]
+#[cfg(feature = "macros")]
+fn args_usage() {
+    #[derive(BotCommands, Debug, PartialEq)]
+    #[command(rename_rule = "lowercase", parse_with = "args")]
+    enum DefaultCommands {
+        /// Ban a user.
+        #[command(alias = "b")]
+        Ban {
+            user: String,
+            days: Option<u32>,
+            #[command(flag)]
+            dry_run: bool,
+            #[command(option)]
+            reason: Option<String>,
+        },
+        /// Sum numbers.
+        Sum(i32, Vec<i32>),
+        /// Show help.
+        Help,
+        #[command(parse_with = "split")]
+        Split(u8, u8),
+    }
+
+    assert_eq!(
+        DefaultCommands::descriptions().to_string(),
+        "/ban, /b <user> [days] [--dry-run] [--reason=<reason>] — Ban a user.\n/sum <arg1> \
+         [arg2...] — Sum numbers.\n/help — Show help.\n/split"
+    );
+}
+
+#[test]
+#[cfg(feature = "macros")]
+fn subcommands() {
+    use teloxide::utils::command::ParseError;
+
+    #[derive(BotCommands, Debug, PartialEq)]
+    #[command(rename_rule = "lowercase")]
+    enum DefaultCommands {
+        /// Show help.
+        Help,
+        /// Manage settings.
+        #[command(subcommand)]
+        Config(ConfigCommand),
+    }
+
+    #[derive(BotCommands, Debug, PartialEq)]
+    #[command(rename_rule = "lowercase", parse_with = "args")]
+    enum ConfigCommand {
+        /// Set a value.
+        #[command(alias = "s")]
+        Set { key: String, value: String },
+        /// Get a value.
+        Get { key: String },
+        /// Reset everything.
+        Reset,
+    }
+
+    assert_eq!(
+        DefaultCommands::Config(ConfigCommand::Set {
+            key: "lang".to_owned(),
+            value: "en".to_owned()
+        }),
+        DefaultCommands::parse("/config set lang en", "").unwrap()
+    );
+    assert_eq!(
+        DefaultCommands::Config(ConfigCommand::Set {
+            key: "lang".to_owned(),
+            value: "en".to_owned()
+        }),
+        DefaultCommands::parse("/config@bot s lang en", "bot").unwrap()
+    );
+    assert_eq!(
+        DefaultCommands::Config(ConfigCommand::Reset),
+        DefaultCommands::parse("/config reset", "").unwrap()
+    );
+    assert!(matches!(
+        DefaultCommands::parse("/config", ""),
+        Err(ParseError::TooFewArguments { expected: 1, found: 0, .. })
+    ));
+    assert!(matches!(
+        DefaultCommands::parse("/config delete lang", ""),
+        Err(ParseError::IncorrectFormat(_))
+    ));
+    assert!(matches!(
+        DefaultCommands::parse("/config get", ""),
+        Err(ParseError::TooFewArguments { .. })
+    ));
+
+    assert_eq!(
+        DefaultCommands::descriptions().to_string(),
+        "/help — Show help.\n/config — Manage settings.\n/config set, /config s <key> <value> — \
+         Set a value.\n/config get <key> — Get a value.\n/config reset — Reset everything."
+    );
+    assert_eq!(
+        DefaultCommands::descriptions().command_help("config").unwrap(),
+        "/config — Manage settings.\n/config set, /config s <key> <value> — Set a value.\n/config \
+         get <key> — Get a value.\n/config reset — Reset everything."
+    );
+    assert_eq!(
+        DefaultCommands::descriptions().username("bot").command_help("/config@bot s lang").unwrap(),
+        "/config@bot set, /config@bot s <key> <value> — Set a value."
+    );
+    assert_eq!(
+        DefaultCommands::descriptions().command_help("/help").unwrap(),
+        "/help — Show help."
+    );
+    assert_eq!(DefaultCommands::descriptions().command_help("/start"), None);
+}
@@ -0,0 +1,142 @@
+//! Tests shared by the dialogue storages.
+
+// Not every storage test uses every helper
+#![allow(dead_code)]
+
+use std::{fmt::Debug, sync::Arc};
+use teloxide::{
+    dispatching::dialogue::{CasStorage, EnumerableStorage, Storage},
+    types::ChatId,
+};
+
+pub type Dialogue = String;
+
+macro_rules! test_dialogues {
+    ($storage:expr, $_0:expr, $_1:expr, $_2:expr) => {
+        assert_eq!(Arc::clone(&$storage).get_dialogue(ChatId(1)).await.unwrap(), $_0);
+        assert_eq!(Arc::clone(&$storage).get_dialogue(ChatId(11)).await.unwrap(), $_1);
+        assert_eq!(Arc::clone(&$storage).get_dialogue(ChatId(256)).await.unwrap(), $_2);
+    };
+}
+
+pub async fn test_enumerable<St>(storage: Arc<St>)
+where
+    St: EnumerableStorage<Dialogue> + Send + Sync + 'static,
+    St::Error: Debug + Send,
+{
+    // More than one page of dialogues
+    let dialogues: Vec<_> =
+        (0..250).map(|i| (ChatId(i - 100), format!("state {}", i % 3))).collect();
+
+    Arc::clone(&storage).remove_dialogues_where(|_, _| true).await.unwrap();
+    assert_eq!(Arc::clone(&storage).import_dialogues(dialogues.clone()).await.unwrap(), 250);
+    assert_eq!(Arc::clone(&storage).count_dialogues().await.unwrap(), 250);
+
+    let mut exported = Arc::clone(&storage).export_dialogues().await.unwrap();
+    exported.sort();
+    assert_eq!(exported, dialogues);
+
+    let removed = Arc::clone(&storage).remove_dialogues_where(|_, d| d == "state 0").await.unwrap();
+    assert_eq!(removed, 84);
+    assert_eq!(Arc::clone(&storage).count_dialogues().await.unwrap(), 166);
+    assert_eq!(Arc::clone(&storage).get_dialogue(ChatId(-100)).await.unwrap(), None);
+    assert_eq!(
+        Arc::clone(&storage).get_dialogue(ChatId(-99)).await.unwrap(),
+        Some("state 1".to_owned())
+    );
+
+    assert_eq!(storage.remove_dialogues_where(|_, _| true).await.unwrap(), 166);
+}
+
+pub async fn test_cas<St>(storage: Arc<St>)
+where
+    St: CasStorage<Dialogue> + Send + Sync + 'static,
+    St::Error: Debug,
+{
+    let chat_id = ChatId(2001);
+    let _ = Arc::clone(&storage).remove_dialogue(chat_id).await;
+    assert_eq!(Arc::clone(&storage).get_dialogue_versioned(chat_id).await.unwrap(), None);
+
+    let update_if = |expected, dialogue: &str| {
+        Arc::clone(&storage).update_dialogue_if_version(chat_id, expected, dialogue.to_owned())
+    };
+    let v1 = update_if(None, "a").await.unwrap().unwrap();
+    assert_eq!(update_if(None, "b").await.unwrap(), None);
+    assert_eq!(
+        Arc::clone(&storage).get_dialogue_versioned(chat_id).await.unwrap(),
+        Some(("a".to_owned(), v1))
+    );
+    let v2 = update_if(Some(v1), "b").await.unwrap().unwrap();
+    assert_ne!(v1, v2);
+    assert_eq!(update_if(Some(v1), "c").await.unwrap(), None);
+
+    Arc::clone(&storage).update_dialogue(chat_id, "c".to_owned()).await.unwrap();
+    let (dialogue, v3) =
+        Arc::clone(&storage).get_dialogue_versioned(chat_id).await.unwrap().unwrap();
+    assert_eq!(dialogue, "c");
+    assert_ne!(v2, v3);
+
+    teloxide::dispatching::dialogue::Dialogue::new(Arc::clone(&storage), chat_id)
+        .transition(|dialogue: Option<String>| dialogue.unwrap() + "d")
+        .await
+        .unwrap();
+    assert_eq!(Arc::clone(&storage).get_dialogue(chat_id).await.unwrap(), Some("cd".to_owned()));
+
+    storage.remove_dialogue(chat_id).await.unwrap();
+}
+
+pub async fn test_namespaces<St, const N: usize>(default: Arc<St>, namespaced: [Arc<St>; N])
+where
+    St: Storage<Dialogue>,
+    St::Error: Debug,
+{
+    let chat_id = ChatId(1001);
+
+    Arc::clone(&default).update_dialogue(chat_id, "default".to_owned()).await.unwrap();
+    for (i, storage) in namespaced.iter().enumerate() {
+        Arc::clone(storage).update_dialogue(chat_id, i.to_string()).await.unwrap();
+    }
+
+    assert_eq!(
+        Arc::clone(&default).get_dialogue(chat_id).await.unwrap(),
+        Some("default".to_owned())
+    );
+    for (i, storage) in namespaced.iter().enumerate() {
+        assert_eq!(Arc::clone(storage).get_dialogue(chat_id).await.unwrap(), Some(i.to_string()));
+    }
+}
+
+#[cfg(feature = "scheduler")]
+pub async fn test_jobs<St>(store: Arc<St>)
+where
+    St: teloxide::dispatching::scheduler::JobStore,
+    St::Error: Debug,
+{
+    use std::time::Duration;
+    use teloxide::dispatching::scheduler::Job;
+
+    let now = chrono::Utc::now();
+    let due = Job::after("remind", Duration::ZERO).with_data("ABC").unwrap();
+    let later = Job::after("remind", Duration::from_secs(60));
+    let cron = Job::cron("digest", "0 0 9 * * *").unwrap().with_id("digest");
+
+    assert_eq!(Arc::clone(&store).next_run().await.unwrap(), None);
+    for job in [&due, &later, &cron] {
+        Arc::clone(&store).save_job(job.clone()).await.unwrap();
+    }
+
+    let due_jobs = Arc::clone(&store).due_jobs(now + chrono::Duration::seconds(1)).await.unwrap();
+    assert_eq!(due_jobs, [due.clone()]);
+    assert_eq!(due_jobs[0].data::<String>().unwrap(), "ABC");
+    assert_eq!(
+        Arc::clone(&store).next_run().await.unwrap().map(|t| t.timestamp_millis()),
+        Some(due.next_run().timestamp_millis())
+    );
+    assert_eq!(Arc::clone(&store).get_job(cron.id().clone()).await.unwrap(), Some(cron.clone()));
+
+    for job in [&due, &later, &cron] {
+        assert!(Arc::clone(&store).remove_job(job.id().clone()).await.unwrap());
+    }
+    assert!(!Arc::clone(&store).remove_job(due.id().clone()).await.unwrap());
+    assert_eq!(Arc::clone(&store).get_job(due.id().clone()).await.unwrap(), None);
+}
@@ -0,0 +1,183 @@
+use std::{
+    fmt::{Debug, Display},
+    fs,
+    sync::Arc,
+};
+use teloxide::{
+    dispatching::dialogue::{
+        serializer::{Json, Versioned},
+        RedbStorage, RedbStorageError, Serializer, Storage, StorageNamespace,
+    },
+    types::{ChatId, UserId},
+};
+
+#[macro_use]
+mod common;
+use common::{test_cas, test_enumerable, test_namespaces, Dialogue};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_redb_json() {
+    fs::create_dir("./test_redb1").unwrap();
+    let storage = RedbStorage::open(
+        "./test_redb1/test_redb1.redb",
+        teloxide::dispatching::dialogue::serializer::Json,
+    )
+    .await
+    .unwrap();
+    test_redb(storage).await;
+    fs::remove_dir_all("./test_redb1").unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_redb_bincode() {
+    fs::create_dir("./test_redb2").unwrap();
+    let storage = RedbStorage::open(
+        "./test_redb2/test_redb2.redb",
+        teloxide::dispatching::dialogue::serializer::Bincode,
+    )
+    .await
+    .unwrap();
+    test_redb(storage).await;
+    fs::remove_dir_all("./test_redb2").unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_redb_cbor() {
+    fs::create_dir("./test_redb3").unwrap();
+    let storage = RedbStorage::open(
+        "./test_redb3/test_redb3.redb",
+        teloxide::dispatching::dialogue::serializer::Cbor,
+    )
+    .await
+    .unwrap();
+    test_redb(storage).await;
+    fs::remove_dir_all("./test_redb3").unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_redb_namespaces() {
+    fs::create_dir("./test_redb4").unwrap();
+    let path = "./test_redb4/test_redb4.redb";
+    let default = RedbStorage::open(path, Json).await.unwrap();
+    let db = default.database();
+    let open = |namespace| RedbStorage::open_with_database(Arc::clone(db), Json, namespace);
+
+    let bot1 = open(StorageNamespace::new().bot_id(UserId(1))).await.unwrap();
+    let bot2 = open(StorageNamespace::new().bot_id(UserId(2))).await.unwrap();
+    let named = open(StorageNamespace::new().name("registration")).await.unwrap();
+
+    test_redb(Arc::clone(&bot1)).await;
+    test_redb(Arc::clone(&named)).await;
+    test_namespaces(Arc::clone(&default), [Arc::clone(&bot1), bot2, named]).await;
+
+    // Dialogues which already exist in the namespace are kept
+    Arc::clone(&default).update_dialogue(ChatId(1002), "legacy".to_owned()).await.unwrap();
+    assert_eq!(bot1.migrate_from_default().await.unwrap(), 1);
+    assert_eq!(Arc::clone(&bot1).get_dialogue(ChatId(1001)).await.unwrap(), Some("0".to_owned()));
+    assert_eq!(
+        Arc::clone(&bot1).get_dialogue(ChatId(1002)).await.unwrap(),
+        Some("legacy".to_owned())
+    );
+    assert_eq!(Arc::clone(&default).get_dialogue(ChatId(1001)).await.unwrap(), None::<Dialogue>);
+    assert_eq!(Arc::clone(&default).get_dialogue(ChatId(1002)).await.unwrap(), None::<Dialogue>);
+
+    fs::remove_dir_all("./test_redb4").unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_redb_versioned() {
+    fs::create_dir("./test_redb5").unwrap();
+    let path = "./test_redb5/test_redb5.redb";
+
+    let storage = RedbStorage::open(path, Json).await.unwrap();
+    Arc::clone(&storage).update_dialogue(ChatId(1), "legacy".to_owned()).await.unwrap();
+    Arc::clone(&storage).update_dialogue(ChatId(2), 42).await.unwrap();
+
+    let serializer = Versioned::new(Json)
+        .migration(|old: String| (old, 0))
+        .on_invalid_state(|_, _| Some(("invalid".to_owned(), 0)));
+    let db = Arc::clone(storage.database());
+    let storage =
+        RedbStorage::open_with_database(db, serializer, StorageNamespace::new()).await.unwrap();
+    assert_eq!(
+        Arc::clone(&storage).get_dialogue(ChatId(1)).await.unwrap(),
+        Some(("legacy".to_owned(), 0))
+    );
+    assert_eq!(
+        Arc::clone(&storage).get_dialogue(ChatId(2)).await.unwrap(),
+        Some(("invalid".to_owned(), 0))
+    );
+
+    Arc::clone(&storage).update_dialogue(ChatId(1), ("updated".to_owned(), 1)).await.unwrap();
+    assert_eq!(
+        Arc::clone(&storage).get_dialogue(ChatId(1)).await.unwrap(),
+        Some(("updated".to_owned(), 1))
+    );
+
+    fs::remove_dir_all("./test_redb5").unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_redb_enumerable() {
+    fs::create_dir("./test_redb6").unwrap();
+    let path = "./test_redb6/test_redb6.redb";
+
+    let storage = RedbStorage::open(path, Json).await.unwrap();
+    let namespace = StorageNamespace::new().bot_id(UserId(1));
+    let bot_storage =
+        RedbStorage::open_with_database(Arc::clone(storage.database()), Json, namespace)
+            .await
+            .unwrap();
+    test_enumerable(storage).await;
+    test_enumerable(bot_storage).await;
+
+    fs::remove_dir_all("./test_redb6").unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_redb_cas() {
+    fs::create_dir("./test_redb7").unwrap();
+    let path = "./test_redb7/test_redb7.redb";
+
+    let storage = RedbStorage::open(path, Json).await.unwrap();
+    let namespace = StorageNamespace::new().bot_id(UserId(1));
+    let bot_storage =
+        RedbStorage::open_with_database(Arc::clone(storage.database()), Json, namespace)
+            .await
+            .unwrap();
+    test_cas(storage).await;
+    test_cas(bot_storage).await;
+
+    fs::remove_dir_all("./test_redb7").unwrap();
+}
+
+async fn test_redb<S>(storage: Arc<RedbStorage<S>>)
+where
+    S: Send + Sync + Serializer<Dialogue> + 'static,
+    <S as Serializer<Dialogue>>::Error: Debug + Display,
+{
+    test_dialogues!(storage, None, None, None);
+
+    Arc::clone(&storage).update_dialogue(ChatId(1), "ABC".to_owned()).await.unwrap();
+    Arc::clone(&storage).update_dialogue(ChatId(11), "DEF".to_owned()).await.unwrap();
+    Arc::clone(&storage).update_dialogue(ChatId(256), "GHI".to_owned()).await.unwrap();
+
+    test_dialogues!(
+        storage,
+        Some("ABC".to_owned()),
+        Some("DEF".to_owned()),
+        Some("GHI".to_owned())
+    );
+
+    Arc::clone(&storage).remove_dialogue(ChatId(1)).await.unwrap();
+    Arc::clone(&storage).remove_dialogue(ChatId(11)).await.unwrap();
+    Arc::clone(&storage).remove_dialogue(ChatId(256)).await.unwrap();
+
+    test_dialogues!(storage, None, None, None);
+
+    // Check that a try to remove a non-existing dialogue results in an error.
+    assert!(matches!(
+        Arc::clone(&storage).remove_dialogue(ChatId(1)).await.unwrap_err(),
+        RedbStorageError::DialogueNotFound
+    ));
+}
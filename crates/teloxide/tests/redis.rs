@@ -3,10 +3,19 @@ use std::{
     sync::Arc,
 };
 use teloxide::{
-    dispatching::dialogue::{RedisStorage, RedisStorageError, Serializer, Storage},
-    types::ChatId,
+    dispatching::dialogue::{
+        serializer::Json, RedisPoolConfig, RedisStorage, RedisStorageError, Serializer, Storage,
+        StorageNamespace,
+    },
+    types::{ChatId, UserId},
 };
 
+#[macro_use]
+mod common;
+#[cfg(feature = "scheduler")]
+use common::test_jobs;
+use common::{test_cas, test_enumerable, test_namespaces, Dialogue};
+
 #[tokio::test]
 #[cfg_attr(not(CI_REDIS), ignore)]
 async fn test_redis_json() {
@@ -43,14 +52,76 @@ async fn test_redis_cbor() {
     test_redis(storage).await;
 }
 
-type Dialogue = String;
+#[tokio::test]
+#[cfg_attr(not(CI_REDIS), ignore)]
+async fn test_redis_namespaces() {
+    // A separate database, because the migration moves all dialogues
+    let url = "redis://127.0.0.1:7777/1";
+    let open = |namespace| RedisStorage::open_with_namespace(url, Json, namespace);
 
-macro_rules! test_dialogues {
-    ($storage:expr, $_0:expr, $_1:expr, $_2:expr) => {
-        assert_eq!(Arc::clone(&$storage).get_dialogue(ChatId(1)).await.unwrap(), $_0);
-        assert_eq!(Arc::clone(&$storage).get_dialogue(ChatId(11)).await.unwrap(), $_1);
-        assert_eq!(Arc::clone(&$storage).get_dialogue(ChatId(256)).await.unwrap(), $_2);
-    };
+    let default = RedisStorage::open(url, Json).await.unwrap();
+    let bot1 = open(StorageNamespace::new().bot_id(UserId(1))).await.unwrap();
+    let bot2 = open(StorageNamespace::new().bot_id(UserId(2))).await.unwrap();
+    let named = open(StorageNamespace::new().name("registration")).await.unwrap();
+
+    test_redis(Arc::clone(&bot1)).await;
+    test_redis(Arc::clone(&named)).await;
+    test_namespaces(
+        Arc::clone(&default),
+        [Arc::clone(&bot1), Arc::clone(&bot2), Arc::clone(&named)],
+    )
+    .await;
+
+    // Dialogues which already exist in the namespace are kept, keys which aren't
+    // dialogues are left intact
+    Arc::clone(&default).update_dialogue(ChatId(1002), "legacy".to_owned()).await.unwrap();
+    Arc::clone(&default).update_dialogue(ChatId(1003), 42).await.unwrap();
+    assert_eq!(bot1.migrate_from_default::<Dialogue>().await.unwrap(), 1);
+    assert_eq!(Arc::clone(&bot1).get_dialogue(ChatId(1001)).await.unwrap(), Some("0".to_owned()));
+    assert_eq!(
+        Arc::clone(&bot1).get_dialogue(ChatId(1002)).await.unwrap(),
+        Some("legacy".to_owned())
+    );
+    assert_eq!(Arc::clone(&default).get_dialogue(ChatId(1001)).await.unwrap(), None::<Dialogue>);
+    assert_eq!(Arc::clone(&default).get_dialogue(ChatId(1002)).await.unwrap(), None::<Dialogue>);
+    assert_eq!(Arc::clone(&default).get_dialogue(ChatId(1003)).await.unwrap(), Some(42));
+    Storage::<i32>::remove_dialogue(Arc::clone(&default), ChatId(1003)).await.unwrap();
+
+    for chat_id in [ChatId(1001), ChatId(1002)] {
+        Storage::<Dialogue>::remove_dialogue(Arc::clone(&bot1), chat_id).await.unwrap();
+    }
+    Storage::<Dialogue>::remove_dialogue(bot2, ChatId(1001)).await.unwrap();
+    Storage::<Dialogue>::remove_dialogue(named, ChatId(1001)).await.unwrap();
+}
+
+#[tokio::test]
+#[cfg_attr(not(CI_REDIS), ignore)]
+async fn test_redis_enumerable() {
+    // A separate database, because all integer keys are dialogues in the default
+    // namespace
+    let url = "redis://127.0.0.1:7777/2";
+
+    test_enumerable(RedisStorage::open(url, Json).await.unwrap()).await;
+    let namespace = StorageNamespace::new().name("enumerable").bot_id(UserId(1));
+    test_enumerable(RedisStorage::open_with_namespace(url, Json, namespace).await.unwrap()).await;
+}
+
+#[tokio::test]
+#[cfg_attr(not(CI_REDIS), ignore)]
+async fn test_redis_cas() {
+    let url = "redis://127.0.0.1:7777";
+
+    test_cas(RedisStorage::open(url, Json).await.unwrap()).await;
+    let namespace = StorageNamespace::new().name("cas").bot_id(UserId(1));
+    test_cas(RedisStorage::open_with_namespace(url, Json, namespace).await.unwrap()).await;
+}
+
+#[tokio::test]
+#[cfg_attr(not(CI_REDIS), ignore)]
+async fn test_redis_pool() {
+    let pool = RedisPoolConfig::from_url("redis://127.0.0.1:7777/3").create_pool(None).unwrap();
+    let storage = RedisStorage::open_with_pool(pool, Json, StorageNamespace::new());
+    test_redis(storage).await;
 }
 
 async fn test_redis<S>(storage: Arc<RedisStorage<S>>)
@@ -83,3 +154,34 @@ where
         RedisStorageError::DialogueNotFound
     ));
 }
+
+#[cfg(feature = "scheduler")]
+#[tokio::test]
+#[cfg_attr(not(CI_REDIS), ignore)]
+async fn test_redis_jobs() {
+    use teloxide::dispatching::{dialogue::serializer::Json, scheduler::RedisJobStore};
+
+    let store = RedisJobStore::open("redis://127.0.0.1:7777", Json).await.unwrap();
+    test_jobs(store).await;
+}
+
+#[tokio::test]
+#[cfg_attr(not(CI_REDIS), ignore)]
+async fn test_redis_rate_limit() {
+    use std::time::Duration;
+    use teloxide::dispatching::rate_limit::{
+        Bucket, Decision, RateLimitStore, RedisRateLimitStore,
+    };
+
+    let store = RedisRateLimitStore::open("redis://127.0.0.1:7777").await.unwrap();
+    let bucket = Bucket { capacity: 2, window: Duration::from_secs(60) };
+    let take = || Arc::clone(&store).take_token("test_redis_rate_limit".to_owned(), bucket);
+
+    assert_eq!(take().await.unwrap(), Decision::Allowed);
+    assert_eq!(take().await.unwrap(), Decision::Allowed);
+    let Decision::Limited(exceeded) = take().await.unwrap() else { panic!("must be limited") };
+    assert!(exceeded.first);
+    assert!(exceeded.retry_after <= Duration::from_secs(30));
+    let Decision::Limited(exceeded) = take().await.unwrap() else { panic!("must be limited") };
+    assert!(!exceeded.first);
+}
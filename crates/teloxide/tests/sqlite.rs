@@ -4,10 +4,19 @@ use std::{
     sync::Arc,
 };
 use teloxide::{
-    dispatching::dialogue::{Serializer, SqliteStorage, SqliteStorageError, Storage},
-    types::ChatId,
+    dispatching::dialogue::{
+        serializer::{Json, Versioned},
+        Serializer, SqlitePool, SqliteStorage, SqliteStorageError, Storage, StorageNamespace,
+    },
+    types::{ChatId, UserId},
 };
 
+#[macro_use]
+mod common;
+#[cfg(feature = "scheduler")]
+use common::test_jobs;
+use common::{test_cas, test_enumerable, test_namespaces, Dialogue};
+
 #[tokio::test(flavor = "multi_thread")]
 async fn test_sqlite_json() {
     fs::create_dir("./test_db1").unwrap();
@@ -47,14 +56,141 @@ async fn test_sqlite_cbor() {
     fs::remove_dir_all("./test_db3").unwrap();
 }
 
-type Dialogue = String;
+#[tokio::test(flavor = "multi_thread")]
+async fn test_sqlite_namespaces() {
+    fs::create_dir("./test_db6").unwrap();
+    let path = "./test_db6/test_db6.sqlite";
+    let open = |namespace| SqliteStorage::open_with_namespace(path, Json, namespace);
+
+    let default = SqliteStorage::open(path, Json).await.unwrap();
+    let bot1 = open(StorageNamespace::new().bot_id(UserId(1))).await.unwrap();
+    let bot2 = open(StorageNamespace::new().bot_id(UserId(2))).await.unwrap();
+    let named = open(StorageNamespace::new().name("registration")).await.unwrap();
+    // Tables with and without bot IDs have different schemas, so they are separate
+    let named_bot1 =
+        open(StorageNamespace::new().name("registration").bot_id(UserId(1))).await.unwrap();
+
+    test_sqlite(Arc::clone(&bot1)).await;
+    test_sqlite(Arc::clone(&named)).await;
+    test_sqlite(Arc::clone(&named_bot1)).await;
+    test_namespaces(Arc::clone(&default), [Arc::clone(&bot1), bot2, named, named_bot1]).await;
+
+    // Names are quoted, so reserved words can be used as well
+    test_sqlite(open(StorageNamespace::new().name("order")).await.unwrap()).await;
+
+    // Dialogues which already exist in the namespace are kept
+    Arc::clone(&default).update_dialogue(ChatId(1002), "legacy".to_owned()).await.unwrap();
+    assert_eq!(bot1.migrate_from_default().await.unwrap(), 1);
+    assert_eq!(Arc::clone(&bot1).get_dialogue(ChatId(1001)).await.unwrap(), Some("0".to_owned()));
+    assert_eq!(
+        Arc::clone(&bot1).get_dialogue(ChatId(1002)).await.unwrap(),
+        Some("legacy".to_owned())
+    );
+    assert_eq!(Arc::clone(&default).get_dialogue(ChatId(1001)).await.unwrap(), None::<Dialogue>);
+    assert_eq!(Arc::clone(&default).get_dialogue(ChatId(1002)).await.unwrap(), None::<Dialogue>);
+
+    fs::remove_dir_all("./test_db6").unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_sqlite_versioned() {
+    fs::create_dir("./test_db7").unwrap();
+    let path = "./test_db7/test_db7.sqlite";
+
+    let storage = SqliteStorage::open(path, Json).await.unwrap();
+    Arc::clone(&storage).update_dialogue(ChatId(1), "legacy".to_owned()).await.unwrap();
+    Arc::clone(&storage).update_dialogue(ChatId(2), 42).await.unwrap();
+
+    let serializer = Versioned::new(Json)
+        .migration(|old: String| (old, 0))
+        .on_invalid_state(|_, _| Some(("invalid".to_owned(), 0)));
+    let storage = SqliteStorage::open(path, serializer).await.unwrap();
+    assert_eq!(
+        Arc::clone(&storage).get_dialogue(ChatId(1)).await.unwrap(),
+        Some(("legacy".to_owned(), 0))
+    );
+    assert_eq!(
+        Arc::clone(&storage).get_dialogue(ChatId(2)).await.unwrap(),
+        Some(("invalid".to_owned(), 0))
+    );
+
+    Arc::clone(&storage).update_dialogue(ChatId(1), ("updated".to_owned(), 1)).await.unwrap();
+    assert_eq!(
+        Arc::clone(&storage).get_dialogue(ChatId(1)).await.unwrap(),
+        Some(("updated".to_owned(), 1))
+    );
+
+    fs::remove_dir_all("./test_db7").unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_sqlite_enumerable() {
+    fs::create_dir("./test_db8").unwrap();
+    let path = "./test_db8/test_db8.sqlite";
+
+    test_enumerable(SqliteStorage::open(path, Json).await.unwrap()).await;
+    let namespace = StorageNamespace::new().bot_id(UserId(1));
+    test_enumerable(SqliteStorage::open_with_namespace(path, Json, namespace).await.unwrap()).await;
+
+    fs::remove_dir_all("./test_db8").unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_sqlite_cas() {
+    fs::create_dir("./test_db9").unwrap();
+    let path = "./test_db9/test_db9.sqlite";
+
+    test_cas(SqliteStorage::open(path, Json).await.unwrap()).await;
+    let namespace = StorageNamespace::new().bot_id(UserId(1));
+    test_cas(SqliteStorage::open_with_namespace(path, Json, namespace).await.unwrap()).await;
+
+    fs::remove_dir_all("./test_db9").unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_sqlite_data() {
+    use teloxide::dispatching::data::{ChatData, DataStorage, UserData};
+
+    fs::create_dir("./test_db10").unwrap();
+    let path = "./test_db10/test_db10.sqlite";
+
+    let dialogues = SqliteStorage::open(path, Json).await.unwrap();
+    let namespace = StorageNamespace::new().name("user_settings");
+    let users = SqliteStorage::open_with_namespace(path, Json, namespace).await.unwrap();
+    let namespace = StorageNamespace::new().name("chat_settings");
+    let chats = SqliteStorage::open_with_namespace(path, Json, namespace).await.unwrap();
+    let settings = DataStorage::<Vec<String>, _>::new(users, chats);
+
+    let user = UserData::new(Arc::clone(&settings), UserId(1));
+    let chat = ChatData::new(settings, ChatId(-1));
+    user.update(|languages| languages.push("en".to_owned())).await.unwrap();
+    user.update(|languages| languages.push("de".to_owned())).await.unwrap();
+    chat.set(vec!["fr".to_owned()]).await.unwrap();
+
+    assert_eq!(user.get().await.unwrap(), Some(vec!["en".to_owned(), "de".to_owned()]));
+    assert_eq!(chat.get().await.unwrap(), Some(vec!["fr".to_owned()]));
+    assert_eq!(dialogues.get_dialogue(ChatId(1)).await.unwrap(), None::<Dialogue>);
 
-macro_rules! test_dialogues {
-    ($storage:expr, $_0:expr, $_1:expr, $_2:expr) => {
-        assert_eq!(Arc::clone(&$storage).get_dialogue(ChatId(1)).await.unwrap(), $_0);
-        assert_eq!(Arc::clone(&$storage).get_dialogue(ChatId(11)).await.unwrap(), $_1);
-        assert_eq!(Arc::clone(&$storage).get_dialogue(ChatId(256)).await.unwrap(), $_2);
-    };
+    fs::remove_dir_all("./test_db10").unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_sqlite_pool() {
+    fs::create_dir("./test_db11").unwrap();
+    let pool = SqlitePool::connect("sqlite:./test_db11/test_db11.sqlite?mode=rwc").await.unwrap();
+
+    let storage =
+        SqliteStorage::open_with_pool(pool.clone(), Json, StorageNamespace::new()).await.unwrap();
+    Arc::clone(&storage).update_dialogue(ChatId(1), "ABC".to_owned()).await.unwrap();
+
+    // The pool is shared with another storage
+    let namespace = StorageNamespace::new().name("pool_dialogues");
+    let other = SqliteStorage::open_with_pool(pool, Json, namespace).await.unwrap();
+    assert_eq!(Arc::clone(&other).get_dialogue(ChatId(1)).await.unwrap(), None::<Dialogue>);
+    assert_eq!(Arc::clone(&storage).get_dialogue(ChatId(1)).await.unwrap(), Some("ABC".to_owned()));
+
+    Storage::<Dialogue>::remove_dialogue(storage, ChatId(1)).await.unwrap();
+    fs::remove_dir_all("./test_db11").unwrap();
 }
 
 async fn test_sqlite<S>(storage: Arc<SqliteStorage<S>>)
@@ -87,3 +223,16 @@ where
         SqliteStorageError::DialogueNotFound
     ));
 }
+
+#[cfg(feature = "scheduler")]
+#[tokio::test(flavor = "multi_thread")]
+async fn test_sqlite_jobs() {
+    use teloxide::dispatching::{dialogue::serializer::Bincode, scheduler::SqliteJobStore};
+
+    fs::create_dir("./test_db4").unwrap();
+    let store = SqliteJobStore::open("./test_db4/test_db4.sqlite", Json).await.unwrap();
+    test_jobs(store).await;
+    let store = SqliteJobStore::open("./test_db4/test_db5.sqlite", Bincode).await.unwrap();
+    test_jobs(store).await;
+    fs::remove_dir_all("./test_db4").unwrap();
+}